@@ -0,0 +1,65 @@
+//! Times `GameBoy::run_frame`, as a percentage of the speed of the real thing. Run with
+//! `cargo bench --bench run_frame`.
+
+use std::time::Instant;
+
+use yeahboy::{asm, mmu::CYCLES_PER_FRAME, GameBoy};
+
+/// Frames the real thing runs in a second
+const FRAMES_PER_SECOND: f64 = 4_194_304.0 / CYCLES_PER_FRAME as f64;
+
+const FRAMES: u32 = 1200;
+
+/// Copies memory around without a break, so the CPU does as much as it can in a frame
+const BUSY: &str = "
+    Start:
+        ld hl, $C000
+        ld de, $D000
+        ld bc, $1000
+    Copy:
+        ld a, [hl+]
+        ld [de], a
+        inc de
+        dec bc
+        ld a, b
+        or c
+        jr nz, Copy
+        jr Start
+    ";
+
+/// Waits in HALT for each VBlank, as most games do once they're done with a frame
+const HALTING: &str = "
+    SECTION \"VBlank\", ROM0[$0040]
+        reti
+
+    SECTION \"Main\", ROM0[$0100]
+        ld a, $01
+        ld [$FFFF], a
+        ei
+    Wait:
+        halt
+        jr Wait
+    ";
+
+fn bench(name: &str, source: &str) {
+    let mut gameboy = GameBoy::new();
+    gameboy.load_rom(asm::assemble(source).unwrap()).unwrap();
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        gameboy.run_frame().unwrap();
+    }
+    let per_second = FRAMES as f64 / start.elapsed().as_secs_f64();
+
+    println!(
+        "{:<8} {:>8.0} frames/s {:>8.0}% speed",
+        name,
+        per_second,
+        per_second / FRAMES_PER_SECOND * 100.0
+    );
+}
+
+fn main() {
+    bench("busy", BUSY);
+    bench("halting", HALTING);
+}
@@ -0,0 +1,1003 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    flow,
+    instructions::{
+        IAction, IFlag, ILocation, IRegister, Instruction, CB_PREFIXED_INSTRUCTIONS,
+        UNPREFIXED_INSTRUCTIONS,
+    },
+    io,
+    symbols::SymbolTable,
+};
+
+/// Where code goes when the source doesn't have a `SECTION`: the cartridge entry point
+pub const DEFAULT_ORIGIN: u16 = 0x0100;
+
+#[derive(Debug)]
+pub struct AsmError {
+    /// 1-based source line, or 0 when assembling a single line
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            0 => write!(f, "{}", self.message),
+            line => write!(f, "line {}: {}", line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+fn error<T>(message: String) -> Result<T, AsmError> {
+    Err(AsmError { line: 0, message })
+}
+
+/// A constant expression: numbers and symbols added together
+#[derive(Clone)]
+struct Expr {
+    terms: Vec<(i64, Term)>,
+}
+
+#[derive(Clone)]
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+impl Expr {
+    fn parse(text: &str, scope: &str) -> Result<Self, AsmError> {
+        let mut terms = Vec::new();
+        let mut sign = 1;
+        let mut current = String::new();
+
+        let mut push = |sign: i64, text: &str| -> Result<(), AsmError> {
+            let text = text.trim();
+            if text.is_empty() {
+                return error("missing operand in expression".to_string());
+            }
+            terms.push((sign, Term::parse(text, scope)?));
+            Ok(())
+        };
+
+        for c in text.trim().chars() {
+            match c {
+                '+' | '-' if current.trim().is_empty() => {
+                    // Unary sign
+                    if c == '-' {
+                        sign = -sign;
+                    }
+                }
+                '+' | '-' => {
+                    push(sign, &current)?;
+                    current.clear();
+                    sign = if c == '-' { -1 } else { 1 };
+                }
+                _ => current.push(c),
+            }
+        }
+        push(sign, &current)?;
+
+        Ok(Self { terms })
+    }
+
+    fn eval<F: Fn(&str) -> Option<i64>>(&self, resolve: &F) -> Result<i64, AsmError> {
+        let mut value = 0;
+
+        for (sign, term) in self.terms.iter() {
+            let term = match term {
+                Term::Number(n) => *n,
+                Term::Symbol(name) => match resolve(name) {
+                    Some(n) => n,
+                    None => return error(format!("unknown symbol {}", name)),
+                },
+            };
+            value += sign * term;
+        }
+
+        Ok(value)
+    }
+}
+
+impl Term {
+    fn parse(text: &str, scope: &str) -> Result<Self, AsmError> {
+        let number = |digits: &str, radix| {
+            i64::from_str_radix(&digits.replace('_', ""), radix)
+                .or_else(|_| error(format!("invalid number {}", text)))
+        };
+
+        if let Some(hex) = text.strip_prefix('$') {
+            return Ok(Term::Number(number(hex, 16)?));
+        }
+        if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            return Ok(Term::Number(number(hex, 16)?));
+        }
+        if let Some(bin) = text.strip_prefix('%') {
+            return Ok(Term::Number(number(bin, 2)?));
+        }
+
+        let first = text.chars().next().unwrap_or(' ');
+        if first.is_ascii_digit() {
+            return match text.strip_suffix('h').or_else(|| text.strip_suffix('H')) {
+                Some(hex) => Ok(Term::Number(number(hex, 16)?)),
+                None => Ok(Term::Number(number(text, 10)?)),
+            };
+        }
+
+        let valid = text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.#@".contains(c));
+        if !valid {
+            return error(format!("invalid expression {}", text));
+        }
+
+        // Local labels belong to the last global label
+        match text.starts_with('.') {
+            true => Ok(Term::Symbol(format!("{}{}", scope, text))),
+            false => Ok(Term::Symbol(text.to_string())),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Operand {
+    Register(IRegister),
+    Indirect(IRegister),
+    Condition(IFlag),
+    Value(Expr),
+    IndirectValue(Expr),
+    StackOffset(Expr),
+}
+
+fn register(name: &str) -> Option<IRegister> {
+    let reg = match name {
+        "A" => IRegister::A,
+        "B" => IRegister::B,
+        "C" => IRegister::C,
+        "D" => IRegister::D,
+        "E" => IRegister::E,
+        "H" => IRegister::H,
+        "L" => IRegister::L,
+        "AF" => IRegister::AF,
+        "BC" => IRegister::BC,
+        "DE" => IRegister::DE,
+        "HL" => IRegister::HL,
+        "SP" => IRegister::SP,
+        "HL+" | "HLI" => IRegister::HL_INC,
+        "HL-" | "HLD" => IRegister::HL_DEC,
+        _ => return None,
+    };
+
+    Some(reg)
+}
+
+impl Operand {
+    fn parse(text: &str, scope: &str) -> Result<Self, AsmError> {
+        let text = text.trim();
+        let upper = text.to_uppercase().replace(' ', "");
+
+        let inner = upper
+            .strip_prefix('[')
+            .and_then(|inner| inner.strip_suffix(']'))
+            .or_else(|| {
+                upper
+                    .strip_prefix('(')
+                    .and_then(|inner| inner.strip_suffix(')'))
+            });
+
+        if let Some(inner) = inner {
+            if let Some(reg) = register(inner) {
+                return Ok(Operand::Indirect(reg));
+            }
+            if let "$FF00+C" | "0XFF00+C" | "FF00+C" = inner {
+                return Ok(Operand::Indirect(IRegister::C));
+            }
+
+            // Keep the original case for symbols
+            let inner = &text.trim()[1..text.trim().len() - 1];
+            return Ok(Operand::IndirectValue(Expr::parse(inner, scope)?));
+        }
+
+        if let Some(reg) = register(&upper).filter(|reg| !matches!(reg, IRegister::HL_INC)) {
+            return Ok(Operand::Register(reg));
+        }
+
+        match upper.as_str() {
+            "Z" => return Ok(Operand::Condition(IFlag::Z)),
+            "NZ" => return Ok(Operand::Condition(IFlag::NZ)),
+            "CY" => return Ok(Operand::Condition(IFlag::CY)),
+            "NC" | "NCY" => return Ok(Operand::Condition(IFlag::NCY)),
+            _ => (),
+        }
+
+        if upper.starts_with("SP+") || upper.starts_with("SP-") {
+            let offset = &text.trim()[2..];
+            return Ok(Operand::StackOffset(Expr::parse(offset, scope)?));
+        }
+
+        Ok(Operand::Value(Expr::parse(text, scope)?))
+    }
+}
+
+/// How an action's operands are written in source
+#[derive(Clone, Copy)]
+enum Param {
+    Loc(ILocation),
+    Flag(IFlag),
+    Bit(u8),
+    Vector(u8),
+}
+
+/// Mnemonic and operands of an action; the inverse of what we parse
+fn signature(action: IAction) -> Option<(&'static str, Vec<Param>)> {
+    use Param::*;
+
+    let conditional = |flag, rest: Vec<Param>| match flag {
+        IFlag::TRUE => rest,
+        _ => [vec![Flag(flag)], rest].concat(),
+    };
+
+    let signature = match action {
+        IAction::NOP => ("NOP", vec![]),
+        IAction::LD(dst, src) | IAction::LD16(dst, src) => ("LD", vec![Loc(dst), Loc(src)]),
+        IAction::JP(flag, loc) => ("JP", conditional(flag, vec![Loc(loc)])),
+        IAction::JR(flag, loc) => ("JR", conditional(flag, vec![Loc(loc)])),
+        IAction::CALL(flag, loc) => ("CALL", conditional(flag, vec![Loc(loc)])),
+        IAction::RET(flag) => ("RET", conditional(flag, vec![])),
+        IAction::RETI => ("RETI", vec![]),
+        IAction::RST(vector) => ("RST", vec![Vector(vector)]),
+        IAction::PUSH(loc) => ("PUSH", vec![Loc(loc)]),
+        IAction::POP(loc) => ("POP", vec![Loc(loc)]),
+        IAction::CPL => ("CPL", vec![]),
+        IAction::CCF => ("CCF", vec![]),
+        IAction::SCF => ("SCF", vec![]),
+        IAction::DAA => ("DAA", vec![]),
+        IAction::RLCA => ("RLCA", vec![]),
+        IAction::RRCA => ("RRCA", vec![]),
+        IAction::RLA => ("RLA", vec![]),
+        IAction::RRA => ("RRA", vec![]),
+        IAction::HALT => ("HALT", vec![]),
+        IAction::STOP => ("STOP", vec![]),
+        IAction::DI => ("DI", vec![]),
+        IAction::EI => ("EI", vec![]),
+        IAction::INC(loc) | IAction::INC16(loc) => ("INC", vec![Loc(loc)]),
+        IAction::DEC(loc) | IAction::DEC16(loc) => ("DEC", vec![Loc(loc)]),
+        IAction::ADD(dst, src) | IAction::ADD16(dst, src) => ("ADD", vec![Loc(dst), Loc(src)]),
+        IAction::ADC(dst, src) => ("ADC", vec![Loc(dst), Loc(src)]),
+        IAction::SUB(dst, src) => ("SUB", vec![Loc(dst), Loc(src)]),
+        IAction::SBC(dst, src) => ("SBC", vec![Loc(dst), Loc(src)]),
+        IAction::AND(dst, src) => ("AND", vec![Loc(dst), Loc(src)]),
+        IAction::XOR(dst, src) => ("XOR", vec![Loc(dst), Loc(src)]),
+        IAction::OR(dst, src) => ("OR", vec![Loc(dst), Loc(src)]),
+        IAction::CP(dst, src) => ("CP", vec![Loc(dst), Loc(src)]),
+        IAction::RLC(loc) => ("RLC", vec![Loc(loc)]),
+        IAction::RRC(loc) => ("RRC", vec![Loc(loc)]),
+        IAction::RL(loc) => ("RL", vec![Loc(loc)]),
+        IAction::RR(loc) => ("RR", vec![Loc(loc)]),
+        IAction::SLA(loc) => ("SLA", vec![Loc(loc)]),
+        IAction::SRA(loc) => ("SRA", vec![Loc(loc)]),
+        IAction::SWAP(loc) => ("SWAP", vec![Loc(loc)]),
+        IAction::SRL(loc) => ("SRL", vec![Loc(loc)]),
+        IAction::BIT(bit, loc) => ("BIT", vec![Bit(bit), Loc(loc)]),
+        IAction::RES(bit, loc) => ("RES", vec![Bit(bit), Loc(loc)]),
+        IAction::SET(bit, loc) => ("SET", vec![Bit(bit), Loc(loc)]),
+        IAction::PREFIX | IAction::ILLEGAL => return None,
+    };
+
+    Some(signature)
+}
+
+/// An operand value that still has to be encoded
+enum Immediate {
+    Byte(Expr),
+    Word(Expr),
+    Signed(Expr),
+    /// JR target, encoded relative to the next instruction
+    Relative(Expr),
+    /// LDH address, encoded as an offset into the 0xFF00 page
+    High(Expr),
+}
+
+fn match_location(loc: ILocation, operand: &Operand, ldh: bool) -> Option<Option<Immediate>> {
+    let immediate = match (loc, operand) {
+        (ILocation::Register(reg), Operand::Register(other)) if reg == *other => None,
+        (
+            ILocation::RegisterIndirectByte(reg) | ILocation::RegisterIndirectWord(reg),
+            Operand::Indirect(other),
+        ) if reg == *other => None,
+        (ILocation::ImmediateByte, Operand::Value(expr)) => Some(Immediate::Byte(expr.clone())),
+        (ILocation::ImmediateWord, Operand::Value(expr)) => Some(Immediate::Word(expr.clone())),
+        (ILocation::ImmediateSignedByte, Operand::Value(expr)) => {
+            Some(Immediate::Signed(expr.clone()))
+        }
+        (ILocation::StackPointerOffset, Operand::StackOffset(expr)) => {
+            Some(Immediate::Signed(expr.clone()))
+        }
+        (ILocation::ImmediateByteIndirectByte, Operand::IndirectValue(expr)) if ldh => {
+            Some(Immediate::High(expr.clone()))
+        }
+        (
+            ILocation::ImmediateWordIndirectByte | ILocation::ImmediateWordIndirectWord,
+            Operand::IndirectValue(expr),
+        ) if !ldh => Some(Immediate::Word(expr.clone())),
+        _ => return None,
+    };
+
+    Some(immediate)
+}
+
+/// An instruction matched against the opcode tables
+struct Matched {
+    opcode: Vec<u8>,
+    length: u8,
+    immediate: Option<Immediate>,
+}
+
+impl Matched {
+    fn encode<F: Fn(&str) -> Option<i64>>(
+        &self,
+        addr: u16,
+        resolve: &F,
+    ) -> Result<Vec<u8>, AsmError> {
+        let mut bytes = self.opcode.clone();
+
+        let in_range = |value: i64, min: i64, max: i64| match (min..=max).contains(&value) {
+            true => Ok(value),
+            false => error(format!("value {} out of range", value)),
+        };
+
+        match &self.immediate {
+            None => (),
+            Some(Immediate::Byte(expr)) => {
+                bytes.push(in_range(expr.eval(resolve)?, -128, 0xFF)? as u8)
+            }
+            Some(Immediate::Signed(expr)) => {
+                bytes.push(in_range(expr.eval(resolve)?, -128, 127)? as u8)
+            }
+            Some(Immediate::Word(expr)) => {
+                let value = in_range(expr.eval(resolve)?, -0x8000, 0xFFFF)? as u16;
+                bytes.extend(value.to_le_bytes());
+            }
+            Some(Immediate::Relative(expr)) => {
+                let next = addr as i64 + self.length as i64;
+                let offset = expr.eval(resolve)? - next;
+                match (-128..=127).contains(&offset) {
+                    true => bytes.push(offset as u8),
+                    false => return error(format!("jump target is {} bytes away", offset)),
+                }
+            }
+            Some(Immediate::High(expr)) => {
+                let value = expr.eval(resolve)?;
+                match value {
+                    0xFF00..=0xFFFF => bytes.push(value as u8),
+                    0x00..=0xFF => bytes.push(value as u8),
+                    _ => return error(format!("LDH address ${:04X} out of range", value)),
+                }
+            }
+        }
+
+        // Instructions like STOP have padding bytes that aren't written in source
+        bytes.resize(self.length as usize, 0x00);
+
+        Ok(bytes)
+    }
+}
+
+/// Splits an instruction into its mnemonic and operands, normalising aliases
+fn parse_instruction(text: &str, scope: &str) -> Result<(String, Vec<Operand>), AsmError> {
+    let text = text.trim();
+    let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let mut mnemonic = mnemonic.to_uppercase();
+
+    let mut operands = split_operands(rest)
+        .iter()
+        .map(|operand| Operand::parse(operand, scope))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // LDI/LDD are LD with HL+/HL-
+    if mnemonic == "LDI" || mnemonic == "LDD" {
+        let post_op = match mnemonic.as_str() {
+            "LDI" => IRegister::HL_INC,
+            _ => IRegister::HL_DEC,
+        };
+        for operand in operands.iter_mut() {
+            if let Operand::Indirect(IRegister::HL) = operand {
+                *operand = Operand::Indirect(post_op);
+            }
+        }
+        mnemonic = "LD".to_string();
+    }
+
+    // `SUB B` is short for `SUB A, B`
+    let alu = ["ADD", "ADC", "SUB", "SBC", "AND", "XOR", "OR", "CP"];
+    if alu.contains(&mnemonic.as_str()) && operands.len() == 1 {
+        operands.insert(0, Operand::Register(IRegister::A));
+    }
+
+    // `JP [HL]` is another way of writing `JP HL`
+    if mnemonic == "JP" {
+        if let [Operand::Indirect(IRegister::HL)] = operands.as_slice() {
+            operands = vec![Operand::Register(IRegister::HL)];
+        }
+    }
+
+    Ok((mnemonic, operands))
+}
+
+/// Splits on commas that aren't inside brackets or quotes
+fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut quoted = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '[' | '(' if !quoted => depth += 1,
+            ']' | ')' if !quoted => depth -= 1,
+            ',' if depth == 0 && !quoted => {
+                operands.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => (),
+        }
+        current.push(c);
+    }
+
+    if !current.trim().is_empty() {
+        operands.push(current.trim().to_string());
+    }
+
+    operands
+}
+
+/// Finds the opcode for an instruction. Symbols are only resolved for operands that select the
+/// opcode (RST vectors and bit numbers), so this works before labels are known.
+fn match_instruction<F: Fn(&str) -> Option<i64>>(
+    mnemonic: &str,
+    operands: &[Operand],
+    resolve: &F,
+) -> Result<Matched, AsmError> {
+    let ldh = mnemonic == "LDH";
+    let high_page = |loc| {
+        matches!(
+            loc,
+            ILocation::ImmediateByteIndirectByte | ILocation::RegisterIndirectByte(IRegister::C)
+        )
+    };
+
+    let tables: [(&[u8], &Vec<Instruction>); 2] = [
+        (&[], &UNPREFIXED_INSTRUCTIONS),
+        (&[0xCB], &CB_PREFIXED_INSTRUCTIONS),
+    ];
+
+    for (prefix, table) in tables {
+        for (opcode, instruction) in table.iter().enumerate() {
+            let (name, params) = match signature(instruction.action) {
+                Some(signature) => signature,
+                None => continue,
+            };
+
+            // LDH only covers the 0xFF00 page, and LD never does apart from LD [C]
+            let name_matches = match instruction.action {
+                IAction::LD(dst, src) if ldh => high_page(dst) || high_page(src),
+                IAction::LD(ILocation::ImmediateByteIndirectByte, _)
+                | IAction::LD(_, ILocation::ImmediateByteIndirectByte) => false,
+                _ => name == mnemonic,
+            };
+
+            if !name_matches || params.len() != operands.len() {
+                continue;
+            }
+
+            let mut immediate = None;
+            let mut matches = true;
+
+            for (param, operand) in params.iter().zip(operands.iter()) {
+                let ok = match (param, operand) {
+                    (Param::Flag(flag), Operand::Condition(other)) => flag == other,
+                    (Param::Flag(IFlag::CY), Operand::Register(IRegister::C)) => true,
+                    (Param::Bit(n), Operand::Value(expr))
+                    | (Param::Vector(n), Operand::Value(expr)) => expr.eval(resolve)? == *n as i64,
+                    (Param::Loc(loc), operand) => match match_location(*loc, operand, ldh) {
+                        Some(value) => {
+                            immediate = value.or(immediate.take());
+                            true
+                        }
+                        None => false,
+                    },
+                    _ => false,
+                };
+
+                if !ok {
+                    matches = false;
+                    break;
+                }
+            }
+
+            if !matches {
+                continue;
+            }
+
+            if let (IAction::JR(_, _), Some(Immediate::Signed(expr))) =
+                (instruction.action, &immediate)
+            {
+                immediate = Some(Immediate::Relative(expr.clone()));
+            }
+
+            let mut opcode_bytes = prefix.to_vec();
+            opcode_bytes.push(opcode as u8);
+
+            return Ok(Matched {
+                opcode: opcode_bytes,
+                length: instruction.length,
+                immediate,
+            });
+        }
+    }
+
+    error(format!("no instruction matches {}", mnemonic))
+}
+
+/// Assembles a single instruction at `addr`, resolving symbols through `symbols` and the IO
+/// register names. `.local` labels belong to the nearest global label before `addr` in `bank`,
+/// the bank mapped there. This is what the debugger uses to patch code.
+pub fn assemble_line(
+    line: &str,
+    bank: u16,
+    addr: u16,
+    symbols: &SymbolTable,
+) -> Result<Vec<u8>, AsmError> {
+    let resolve = |name: &str| {
+        symbols
+            .address(name)
+            .map(|(_, addr)| addr)
+            .or_else(|| io::register_address(name))
+            .map(|addr| addr as i64)
+    };
+
+    let code = line.split(';').next().unwrap_or("");
+    let scope = symbols.scope(addr, bank).unwrap_or("");
+    let (mnemonic, operands) = parse_instruction(code, scope)?;
+
+    match_instruction(&mnemonic, &operands, &resolve)?.encode(addr, &resolve)
+}
+
+/// A line of source after the first pass
+enum Statement {
+    Instruction(Matched),
+    Data(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+/// Assembles a source file into a ROM image. Supports labels (including `.local` ones),
+/// `SECTION` with a fixed ROM0/ROMX address, `DEF name EQU value`, `DB` and `DW`. Code before
+/// any `SECTION` starts at the cartridge entry point.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut symbols: HashMap<String, i64> = HashMap::new();
+    let mut statements = Vec::new();
+
+    let mut scope = String::new();
+    let mut bank = 0;
+    let mut addr = DEFAULT_ORIGIN;
+
+    // First pass: work out where everything goes
+    for (index, line) in source.lines().enumerate() {
+        let with_line = |e: AsmError| AsmError {
+            line: index + 1,
+            message: e.message,
+        };
+        let mut text = strip_comment(line).trim();
+
+        // Labels
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            let is_label =
+                !label.is_empty() && !label.contains(char::is_whitespace) && !label.contains('"');
+            if is_label {
+                let name = match label.starts_with('.') {
+                    true => format!("{}{}", scope, label),
+                    false => {
+                        scope = label.to_string();
+                        label.to_string()
+                    }
+                };
+                symbols.insert(name, addr as i64);
+                text = rest.trim_start_matches(':').trim();
+            }
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let resolve = |name: &str| lookup(&symbols, name);
+        let (keyword, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let keyword = keyword.to_uppercase();
+
+        match keyword.as_str() {
+            "SECTION" => {
+                let (section_bank, section_addr) =
+                    parse_section(rest, &resolve).map_err(with_line)?;
+                bank = section_bank;
+                addr = section_addr;
+            }
+            "DEF" => {
+                let (name, value) = parse_def(rest).map_err(with_line)?;
+                let value = Expr::parse(value, &scope)
+                    .and_then(|expr| expr.eval(&resolve))
+                    .map_err(with_line)?;
+                symbols.insert(name.to_string(), value);
+            }
+            "DB" => {
+                let mut data = Vec::new();
+                for item in split_operands(rest) {
+                    match item.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                        Some(string) => data.extend(string.bytes().map(|byte| Expr {
+                            terms: vec![(1, Term::Number(byte as i64))],
+                        })),
+                        None => data.push(Expr::parse(&item, &scope).map_err(with_line)?),
+                    }
+                }
+                let len = data.len();
+                statements.push((index, bank, addr, Statement::Data(data)));
+                addr = advance(bank, addr, len).map_err(with_line)?;
+            }
+            "DW" => {
+                let words = split_operands(rest)
+                    .iter()
+                    .map(|item| Expr::parse(item, &scope))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(with_line)?;
+                let len = words.len() * 2;
+                statements.push((index, bank, addr, Statement::Words(words)));
+                addr = advance(bank, addr, len).map_err(with_line)?;
+            }
+            _ => {
+                let (mnemonic, operands) = parse_instruction(text, &scope).map_err(with_line)?;
+                let matched =
+                    match_instruction(&mnemonic, &operands, &resolve).map_err(with_line)?;
+                let len = matched.length;
+                statements.push((index, bank, addr, Statement::Instruction(matched)));
+                addr = advance(bank, addr, len as usize).map_err(with_line)?;
+            }
+        }
+    }
+
+    // Second pass: encode with every label known
+    let resolve = |name: &str| lookup(&symbols, name);
+
+    let mut rom = vec![0x00; 0x8000];
+
+    for (index, bank, addr, statement) in statements.iter() {
+        let with_line = |e: AsmError| AsmError {
+            line: index + 1,
+            message: e.message,
+        };
+
+        let bytes = match statement {
+            Statement::Instruction(matched) => {
+                matched.encode(*addr, &resolve).map_err(with_line)?
+            }
+            Statement::Data(data) => data
+                .iter()
+                .map(|expr| expr.eval(&resolve).map(|value| value as u8))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(with_line)?,
+            Statement::Words(words) => words
+                .iter()
+                .map(|expr| {
+                    expr.eval(&resolve)
+                        .map(|value| (value as u16).to_le_bytes())
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(with_line)?
+                .concat(),
+        };
+
+        for (i, byte) in bytes.iter().enumerate() {
+            let offset = flow::rom_offset(*bank, addr.wrapping_add(i as u16));
+            if offset >= rom.len() {
+                // Round up to a whole number of banks
+                rom.resize((offset / 0x4000 + 1) * 0x4000, 0x00);
+            }
+            rom[offset] = *byte;
+        }
+    }
+
+    Ok(rom)
+}
+
+/// The address after `len` bytes at `addr`, which have to fit in the ROM0 or ROMX section
+/// they're in
+fn advance(bank: u16, addr: u16, len: usize) -> Result<u16, AsmError> {
+    let (name, end) = match bank {
+        0 => ("ROM0".to_string(), 0x4000),
+        _ => (format!("ROMX bank {}", bank), 0x8000),
+    };
+
+    match addr as usize + len {
+        next if next <= end => Ok(next as u16),
+        _ => error(format!("section overflows {}", name)),
+    }
+}
+
+/// Looks up a label or constant, falling back to the IO register names
+fn lookup(symbols: &HashMap<String, i64>, name: &str) -> Option<i64> {
+    symbols
+        .get(name)
+        .copied()
+        .or_else(|| io::register_address(name).map(|addr| addr as i64))
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => (),
+        }
+    }
+
+    line
+}
+
+/// Parses `"name", ROM0[$0000]` or `"name", ROMX[$4000], BANK[n]` into a bank and address
+fn parse_section<F: Fn(&str) -> Option<i64>>(
+    text: &str,
+    resolve: &F,
+) -> Result<(u16, u16), AsmError> {
+    let parts = split_operands(text);
+    let bracketed = |part: &str, keyword: &str| -> Result<Option<i64>, AsmError> {
+        let upper = part.to_uppercase();
+        match upper.strip_prefix(keyword) {
+            Some(rest) => {
+                let inner = rest.trim().trim_start_matches('[').trim_end_matches(']');
+                Ok(Some(Expr::parse(inner, "")?.eval(resolve)?))
+            }
+            None => Ok(None),
+        }
+    };
+
+    let kind = parts.get(1).map(|part| part.trim()).unwrap_or("");
+    let bank = match parts.get(2) {
+        Some(part) => bracketed(part, "BANK")?,
+        None => None,
+    };
+
+    if let Some(addr) = bracketed(kind, "ROM0")? {
+        return match addr {
+            0x0000..=0x3FFF => Ok((0, addr as u16)),
+            _ => error(format!("ROM0 section at ${:X} isn't in $0000-$3FFF", addr)),
+        };
+    }
+    if let Some(addr) = bracketed(kind, "ROMX")? {
+        let bank = bank.unwrap_or(1);
+        return match (bank, addr) {
+            (1..=0x1FF, 0x4000..=0x7FFF) => Ok((bank as u16, addr as u16)),
+            (1..=0x1FF, _) => error(format!("ROMX section at ${:X} isn't in $4000-$7FFF", addr)),
+            _ => error(format!("ROMX section in bank {}", bank)),
+        };
+    }
+
+    error(format!("unsupported section {}", text))
+}
+
+/// Parses `name EQU value`
+fn parse_def(text: &str) -> Result<(&str, &str), AsmError> {
+    let (name, rest) = text
+        .trim()
+        .split_once(char::is_whitespace)
+        .unwrap_or((text, ""));
+    let rest = rest.trim();
+
+    match rest.get(..3).map(|equ| equ.eq_ignore_ascii_case("EQU")) {
+        Some(true) => Ok((name, &rest[3..])),
+        _ => error(format!("expected EQU in DEF {}", text)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, process::Command};
+
+    use super::{assemble, assemble_line};
+    use crate::{listing::Listing, symbols::SymbolTable};
+
+    fn line(text: &str) -> Vec<u8> {
+        assemble_line(text, 1, 0x0150, &SymbolTable::new()).unwrap()
+    }
+
+    #[test]
+    fn instructions() {
+        assert_eq!(line("nop"), [0x00]);
+        assert_eq!(line("ld a, [hl+]"), [0x2A]);
+        assert_eq!(line("LD [HL-], A"), [0x32]);
+        assert_eq!(line("ldi a, (hl)"), [0x2A]);
+        assert_eq!(line("ld a, $3E"), [0x3E, 0x3E]);
+        assert_eq!(line("ld hl, $C000"), [0x21, 0x00, 0xC0]);
+        assert_eq!(line("ld [$C000], a"), [0xEA, 0x00, 0xC0]);
+        assert_eq!(line("ld [$1234], sp"), [0x08, 0x34, 0x12]);
+        assert_eq!(line("jp hl"), [0xE9]);
+        assert_eq!(line("rst $38"), [0xFF]);
+        assert_eq!(line("bit 7, h"), [0xCB, 0x7C]);
+        assert_eq!(line("swap [hl]"), [0xCB, 0x36]);
+        assert_eq!(line("sub b"), [0x90]);
+        assert_eq!(line("cp a, 144"), [0xFE, 0x90]);
+        assert_eq!(line("stop"), [0x10, 0x00]);
+    }
+
+    #[test]
+    fn conditions_and_jumps() {
+        assert_eq!(line("jr nz, $0150"), [0x20, 0xFE]);
+        assert_eq!(line("jr c, $0162"), [0x38, 0x10]);
+        assert_eq!(line("ret c"), [0xD8]);
+        assert_eq!(line("ret nc"), [0xD0]);
+        assert_eq!(line("call z, $4000"), [0xCC, 0x00, 0x40]);
+        assert!(assemble_line("jr $0300", 1, 0x0150, &SymbolTable::new()).is_err());
+    }
+
+    #[test]
+    fn high_page_and_offsets() {
+        assert_eq!(line("ldh [rLCDC], a"), [0xE0, 0x40]);
+        assert_eq!(line("ldh a, [$FF00+$44]"), [0xF0, 0x44]);
+        assert_eq!(line("ldh [c], a"), [0xE2]);
+        assert_eq!(line("ld a, [$ff00+c]"), [0xF2]);
+        assert_eq!(line("add sp, -2"), [0xE8, 0xFE]);
+        assert_eq!(line("ld hl, sp+5"), [0xF8, 0x05]);
+        assert_eq!(line("ld hl, sp-$02"), [0xF8, 0xFE]);
+    }
+
+    #[test]
+    fn symbols() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0, 0x0200, "Sub");
+
+        let bytes = assemble_line("call Sub", 1, 0x0150, &symbols).unwrap();
+        assert_eq!(bytes, [0xCD, 0x00, 0x02]);
+        assert!(assemble_line("call Nope", 1, 0x0150, &symbols).is_err());
+    }
+
+    #[test]
+    fn local_labels_in_patches() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0, 0x0150, "Main");
+        symbols.insert(0, 0x0152, "Main.loop");
+        symbols.insert(2, 0x4000, "Other");
+        symbols.insert(2, 0x4002, "Other.loop");
+
+        let bytes = assemble_line("jr nz, .loop", 1, 0x0154, &symbols).unwrap();
+        assert_eq!(bytes, [0x20, 0xFC]);
+        let bytes = assemble_line("jr nz, .loop", 2, 0x4004, &symbols).unwrap();
+        assert_eq!(bytes, [0x20, 0xFC]);
+        assert!(assemble_line("jr nz, .loop", 3, 0x4004, &symbols).is_err());
+    }
+
+    #[test]
+    fn section_overflow() {
+        let error = assemble("SECTION \"a\", ROM0[$3FFE]\nnop\nnop\nnop\n").unwrap_err();
+        assert_eq!(error.line, 4);
+        assert_eq!(error.message, "section overflows ROM0");
+
+        let error = assemble("SECTION \"a\", ROMX[$7FFF], BANK[2]\ndw 0\n").unwrap_err();
+        assert_eq!(error.message, "section overflows ROMX bank 2");
+
+        assert!(assemble("SECTION \"a\", ROM0[$4000]\n").is_err());
+        assert!(assemble("SECTION \"a\", ROMX[$0150]\n").is_err());
+        assert!(assemble("SECTION \"a\", ROM0[$3FFF]\nnop\n").is_ok());
+    }
+
+    #[test]
+    fn program() {
+        let rom = assemble(
+            "
+            DEF COUNT EQU 3
+            Main:
+                ld b, COUNT
+            .loop:
+                dec b
+                jr nz, .loop ; back we go
+                call Sub
+                halt
+            Sub:
+                ret
+                db \"HI\", $01
+                dw Sub
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(rom.len(), 0x8000);
+        assert_eq!(
+            &rom[0x0100..0x010F],
+            [
+                0x06, 0x03, 0x05, 0x20, 0xFD, 0xCD, 0x09, 0x01, 0x76, 0xC9, 0x48, 0x49, 0x01, 0x09,
+                0x01
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let error = assemble("nop\nfoo a, b\n").unwrap_err();
+        assert_eq!(error.line, 2);
+    }
+
+    /// A ROM with code in two banks, including instructions the listing has to write as `DB`
+    fn round_trip_rom() -> Vec<u8> {
+        let mut rom = vec![0xFF; 0x10000];
+        let code: &[(usize, &[u8])] = &[
+            (0x0100, &[0x00, 0xC3, 0x50, 0x01]),
+            (0x0134, b"ROUNDTRIP"),
+            (
+                0x0150,
+                &[
+                    0x31, 0xFE, 0xFF, 0xCD, 0x00, 0x40, 0xE0, 0x40, 0xF0, 0x44, 0xFE, 0x90, 0x20,
+                    0xFA, 0x76, 0x10, 0x01, 0x18, 0xFE,
+                ],
+            ),
+            (
+                0x4000,
+                &[
+                    0x21, 0x00, 0xC0, 0x22, 0xEA, 0x40, 0xFF, 0xF8, 0xFB, 0xE8, 0x05, 0xCB, 0x7C,
+                    0x08, 0x00, 0xC0, 0xE2, 0xF2, 0xC9,
+                ],
+            ),
+        ];
+        for (offset, bytes) in code {
+            rom[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+
+        rom
+    }
+
+    #[test]
+    fn listing_round_trip() {
+        let rom = round_trip_rom();
+        let listing = Listing::new(&rom, &SymbolTable::new()).write();
+        let reassembled = assemble(&listing).unwrap();
+
+        assert!(reassembled == rom);
+    }
+
+    /// The same through rgbasm and rgblink, which the listing is written for. Skipped when
+    /// they aren't installed.
+    #[test]
+    fn listing_round_trip_with_rgbds() {
+        if Command::new("rgbasm").arg("--version").output().is_err() {
+            eprintln!("skipping: rgbasm isn't installed");
+            return;
+        }
+
+        let rom = round_trip_rom();
+        let dir = std::env::temp_dir().join(format!("yeahboy-rgbds-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (source, object, output) = (
+            dir.join("listing.asm"),
+            dir.join("listing.o"),
+            dir.join("listing.gb"),
+        );
+        fs::write(&source, Listing::new(&rom, &SymbolTable::new()).write()).unwrap();
+
+        let run = |command: &mut Command| {
+            let result = command.output().unwrap();
+            assert!(
+                result.status.success(),
+                "{}",
+                String::from_utf8_lossy(&result.stderr)
+            );
+        };
+        run(Command::new("rgbasm").arg("-o").arg(&object).arg(&source));
+        run(Command::new("rgblink").arg("-o").arg(&output).arg(&object));
+        let reassembled = fs::read(&output).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(reassembled == rom);
+    }
+}
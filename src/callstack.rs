@@ -0,0 +1,150 @@
+/// Most anomalies to keep around
+const MAX_ANOMALIES: usize = 64;
+
+/// A call that hasn't returned yet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CallFrame {
+    /// Address of the CALL/RST, or the PC when the interrupt was taken
+    pub caller: u16,
+    pub target: u16,
+    /// SP after pushing the return address, i.e. where the return address lives
+    pub sp: u16,
+    /// ROM bank mapped at the target
+    pub bank: u16,
+    pub interrupt: bool,
+}
+
+/// A return that didn't match the shadow stack
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Anomaly {
+    /// Address of the RET/RETI
+    pub pc: u16,
+    pub message: String,
+}
+
+/// Shadow call stack, built from the calls and returns the CPU executes. It doesn't read
+/// the real stack, so code that moves SP around can't corrupt it; mismatches are recorded as
+/// anomalies instead.
+#[derive(Default)]
+pub struct CallStack {
+    frames: Vec<CallFrame>,
+    anomalies: Vec<Anomaly>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    /// Innermost call last
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    /// Oldest first
+    pub fn anomalies(&self) -> &[Anomaly] {
+        &self.anomalies
+    }
+
+    pub fn call(&mut self, frame: CallFrame) {
+        // Frames at or below the new return address can't be returned to any more, e.g. after
+        // the stack pointer was reset
+        while let Some(top) = self.frames.last() {
+            if top.sp > frame.sp {
+                break;
+            }
+            self.frames.pop();
+        }
+
+        self.frames.push(frame);
+    }
+
+    /// Records a return executed at `pc` while SP was `sp`, before popping the return address
+    pub fn ret(&mut self, pc: u16, sp: u16, reti: bool) {
+        let index = match self.frames.iter().rposition(|frame| frame.sp == sp) {
+            Some(index) => index,
+            None => {
+                self.anomaly(pc, format!("return with no matching call (SP {:04x})", sp));
+                return;
+            }
+        };
+
+        let skipped = self.frames.len() - 1 - index;
+        if skipped > 0 {
+            self.anomaly(pc, format!("return skipped {} frame(s)", skipped));
+        }
+
+        let frame = self.frames[index];
+        if reti && !frame.interrupt {
+            self.anomaly(
+                pc,
+                format!("RETI from non-interrupt call to {:04x}", frame.target),
+            );
+        }
+
+        self.frames.truncate(index);
+    }
+
+    fn anomaly(&mut self, pc: u16, message: String) {
+        log::debug!("call stack anomaly at {:04x}: {}", pc, message);
+
+        if self.anomalies.len() == MAX_ANOMALIES {
+            self.anomalies.remove(0);
+        }
+        self.anomalies.push(Anomaly { pc, message });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CallFrame, CallStack};
+
+    fn frame(target: u16, sp: u16) -> CallFrame {
+        CallFrame {
+            caller: 0x0150,
+            target,
+            sp,
+            bank: 1,
+            interrupt: false,
+        }
+    }
+
+    #[test]
+    fn balanced() {
+        let mut stack = CallStack::new();
+        stack.call(frame(0x0200, 0xFFFC));
+        stack.call(frame(0x0300, 0xFFFA));
+        assert_eq!(stack.frames().len(), 2);
+
+        stack.ret(0x0305, 0xFFFA, false);
+        stack.ret(0x0205, 0xFFFC, false);
+        assert!(stack.frames().is_empty());
+        assert!(stack.anomalies().is_empty());
+    }
+
+    #[test]
+    fn unbalanced() {
+        let mut stack = CallStack::new();
+        stack.call(frame(0x0200, 0xFFFC));
+        stack.call(frame(0x0300, 0xFFFA));
+
+        // Return address pushed by hand
+        stack.ret(0x0305, 0xFFF8, false);
+        assert_eq!(stack.frames().len(), 2);
+
+        // Dropped the inner return address and returned from the outer call
+        stack.ret(0x0305, 0xFFFC, true);
+        assert!(stack.frames().is_empty());
+        assert_eq!(stack.anomalies().len(), 3);
+    }
+
+    #[test]
+    fn stack_reset() {
+        let mut stack = CallStack::new();
+        stack.call(frame(0x0200, 0xDFF0));
+        stack.call(frame(0x0300, 0xFFFC));
+        assert_eq!(stack.frames(), [frame(0x0300, 0xFFFC)]);
+    }
+}
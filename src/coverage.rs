@@ -0,0 +1,124 @@
+use bitflags::bitflags;
+
+/// How the CPU touched an address
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+bitflags! {
+    /// What a ROM byte has been used as. Saved one byte per ROM byte, so the flags are the file
+    /// format too.
+    pub struct Coverage: u8 {
+        /// Fetched as part of an instruction
+        const EXECUTED = 0b0000_0001;
+        /// Read as data by an instruction
+        const READ = 0b0000_0010;
+    }
+}
+
+/// Per-address access counts for the whole address space, plus which ROM bytes were ever
+/// executed or read
+pub struct AccessLog {
+    /// Reads, writes and executes of each address, indexed by `Access`
+    counts: Vec<[u32; 3]>,
+    /// How recently each address was accessed, 255 being just now
+    heat: Vec<[u8; 3]>,
+    /// Indexed by ROM offset, so every bank is covered separately
+    rom: Vec<Coverage>,
+}
+
+impl AccessLog {
+    pub fn new(rom_len: usize) -> Self {
+        Self {
+            counts: vec![[0; 3]; 0x10000],
+            heat: vec![[0; 3]; 0x10000],
+            rom: vec![Coverage::empty(); rom_len],
+        }
+    }
+
+    /// Records an access to `addr`, which is `rom_offset` into the ROM if it's mapped to ROM
+    pub fn record(&mut self, access: Access, addr: u16, rom_offset: Option<usize>) {
+        let counts = &mut self.counts[addr as usize][access as usize];
+        *counts = counts.saturating_add(1);
+        self.heat[addr as usize][access as usize] = u8::MAX;
+
+        let flag = match access {
+            Access::Read => Coverage::READ,
+            Access::Execute => Coverage::EXECUTED,
+            // Writes to ROM go to the MBC, they don't use the byte
+            Access::Write => return,
+        };
+        if let Some(coverage) = rom_offset.and_then(|offset| self.rom.get_mut(offset)) {
+            coverage.insert(flag);
+        }
+    }
+
+    pub fn count(&self, access: Access, addr: u16) -> u32 {
+        self.counts[addr as usize][access as usize]
+    }
+
+    /// Whether `addr` was ever accessed at all
+    pub fn touched(&self, addr: u16) -> bool {
+        self.counts[addr as usize].iter().any(|&count| count > 0)
+    }
+
+    pub fn heat(&self, access: Access, addr: u16) -> u8 {
+        self.heat[addr as usize][access as usize]
+    }
+
+    /// Cools every address down by `amount`
+    pub fn fade(&mut self, amount: u8) {
+        for heat in self.heat.iter_mut().flatten() {
+            *heat = heat.saturating_sub(amount);
+        }
+    }
+
+    /// `Coverage` flags for each ROM byte, in the format coverage files are saved in
+    pub fn rom_coverage(&self) -> Vec<u8> {
+        self.rom.iter().map(|coverage| coverage.bits()).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Access, AccessLog, Coverage};
+
+    #[test]
+    fn counts_and_heat() {
+        let mut log = AccessLog::new(0x8000);
+        log.record(Access::Read, 0xC000, None);
+        log.record(Access::Read, 0xC000, None);
+        log.record(Access::Write, 0xC001, None);
+
+        assert_eq!(log.count(Access::Read, 0xC000), 2);
+        assert_eq!(log.count(Access::Write, 0xC000), 0);
+        assert!(log.touched(0xC001));
+        assert!(!log.touched(0xC002));
+
+        log.fade(100);
+        assert_eq!(log.heat(Access::Read, 0xC000), 155);
+        log.fade(200);
+        assert_eq!(log.heat(Access::Read, 0xC000), 0);
+        assert_eq!(log.count(Access::Read, 0xC000), 2);
+    }
+
+    #[test]
+    fn rom_coverage() {
+        let mut log = AccessLog::new(0x8000);
+        log.record(Access::Execute, 0x0150, Some(0x0150));
+        log.record(Access::Read, 0x4000, Some(0x4000));
+        log.record(Access::Read, 0x0150, Some(0x0150));
+        log.record(Access::Write, 0x2000, Some(0x2000));
+
+        let coverage = log.rom_coverage();
+        assert_eq!(
+            coverage[0x0150],
+            (Coverage::EXECUTED | Coverage::READ).bits()
+        );
+        assert_eq!(coverage[0x4000], Coverage::READ.bits());
+        assert_eq!(coverage[0x2000], 0);
+    }
+}
@@ -1,10 +1,24 @@
 use crate::{
     bits,
+    callstack::{CallFrame, CallStack},
+    error::EmulatorError,
     instructions::{DecodedInstruction, IAction, IFlag, ILocation, IRegister},
+    io::{self, Interrupts},
     mmu::Mmu,
+    model::Model,
+    profiler::{Location, Profiler},
+    script::Script,
+    state::{Reader, Writer},
+    trace::Tracer,
 };
 use bitflags::bitflags;
 
+/// Clock cycles in an M-cycle, the time the CPU takes for each memory access
+const M_CYCLE: u64 = 4;
+
+/// M-cycles the CPU sits out for after STOP switches speed
+const SPEED_SWITCH_CYCLES: u64 = 2050;
+
 bitflags! {
     pub struct Flags: u8 {
         const ZERO = 0b1000_0000;
@@ -155,9 +169,9 @@ impl Registers {
 
     pub fn set_flag(&mut self, flag: Flags, value: bool) {
         if value {
-            self.f = self.f | flag
+            self.f |= flag
         } else {
-            self.f = self.f & (!flag)
+            self.f &= !flag
         }
     }
 
@@ -166,7 +180,49 @@ impl Registers {
     // }
 
     pub fn flip_flag(&mut self, flag: Flags) {
-        self.f = self.f ^ flag
+        self.f ^= flag
+    }
+}
+
+impl Registers {
+    /// What `model`'s boot ROM leaves in the registers. The DMG and MGB boot ROMs set H and C
+    /// unless the header checksum at 0x014D is zero.
+    pub fn post_boot(model: Model, header_checksum: u8) -> Self {
+        let checksum_flags = if header_checksum == 0 { 0x80 } else { 0xB0 };
+
+        let [a, f, b, c, d, e, h, l] = match model {
+            Model::Dmg0 => [0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03],
+            Model::Dmg => [0x01, checksum_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::Mgb => [0xFF, checksum_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::Sgb => [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+            Model::Cgb => [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+            Model::Agb => [0x11, 0x00, 0x01, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+        };
+
+        Self {
+            a,
+            f: f.into(),
+            b,
+            c,
+            d,
+            e,
+            h,
+            l,
+        }
+    }
+
+    /// Everything cleared, as at reset before a boot ROM runs
+    fn reset() -> Self {
+        Self {
+            a: 0,
+            f: Flags::empty(),
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            h: 0,
+            l: 0,
+        }
     }
 }
 
@@ -190,15 +246,95 @@ pub struct Cpu {
     mmu: Mmu,
     sp: u16,
     pc: u16,
+
+    /// Instructions executed so far
+    steps: u64,
+
+    /// Interrupt master enable
+    ime: bool,
+
+    /// EI was just executed. IME is only set once the instruction after it is done.
+    ei_delay: bool,
+
+    /// Hung by an illegal opcode, as only a reset gets it going again
+    locked: bool,
+
+    /// Waiting in HALT for an interrupt to be requested
+    halted: bool,
+
+    /// Waiting in STOP for a button to be pressed
+    stopped: bool,
+
+    /// HALT ran with IME off and an interrupt already pending, so the next opcode is read
+    /// without PC moving past it
+    halt_bug: bool,
+
+    /// Return an error for illegal opcodes instead of locking up, to stop in the debugger
+    break_on_illegal: bool,
+
+    /// M-cycles fetching the instruction being executed that haven't been clocked yet. They
+    /// are before its first memory access, or at the end, so one that can't be run takes no time.
+    pending_fetch: u16,
+
+    call_stack: CallStack,
+
+    tracer: Option<Tracer>,
+
+    profiler: Option<Profiler>,
+
+    script: Option<Script>,
+
+    /// The script's callbacks ran for the instruction here, which then couldn't be run, so
+    /// they don't run again when it's retried
+    script_ran_at: Option<u16>,
 }
 
 impl Cpu {
+    /// A DMG that's just finished booting, with `rom` in
     pub fn new(rom: Vec<u8>) -> Self {
+        Self::with_model(rom, Model::Dmg)
+    }
+
+    /// Skips `model`'s boot ROM, starting the cartridge in the state it would have left
+    pub fn with_model(rom: Vec<u8>, model: Model) -> Self {
+        let header_checksum = rom.get(0x014D).copied().unwrap_or(0);
+        let reg = Registers::post_boot(model, header_checksum);
+
+        Self::power_on(Mmu::with_model(rom, model), reg, 0x0100, 0xFFFE)
+    }
+
+    /// Starts from reset with `model`'s `boot_rom` mapped over the cartridge, as hardware does.
+    /// Fails if it's the wrong size for the model.
+    pub fn with_boot_rom(
+        rom: Vec<u8>,
+        model: Model,
+        boot_rom: Vec<u8>,
+    ) -> Result<Self, EmulatorError> {
+        let mmu = Mmu::with_boot_rom(rom, model, boot_rom)?;
+
+        Ok(Self::power_on(mmu, Registers::reset(), 0x0000, 0x0000))
+    }
+
+    fn power_on(mmu: Mmu, reg: Registers, pc: u16, sp: u16) -> Self {
         Self {
-            reg: Registers::new(),
-            mmu: Mmu::new(rom),
-            sp: 0xFFFE,
-            pc: 0x0100,
+            reg,
+            mmu,
+            sp,
+            pc,
+            steps: 0,
+            ime: false,
+            ei_delay: false,
+            locked: false,
+            halted: false,
+            stopped: false,
+            halt_bug: false,
+            break_on_illegal: false,
+            pending_fetch: 0,
+            call_stack: CallStack::new(),
+            tracer: None,
+            profiler: None,
+            script: None,
+            script_ran_at: None,
         }
     }
 
@@ -210,6 +346,10 @@ impl Cpu {
         &self.mmu
     }
 
+    pub fn mmu_mut(&mut self) -> &mut Mmu {
+        &mut self.mmu
+    }
+
     pub fn sp(&self) -> u16 {
         self.sp
     }
@@ -218,24 +358,205 @@ impl Cpu {
         self.pc
     }
 
-    fn read_register(&mut self, reg: IRegister) -> u8 {
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The hardware being emulated
+    pub fn model(&self) -> Model {
+        self.mmu.model()
+    }
+
+    /// Clock cycles since power on
+    pub fn cycles(&self) -> u64 {
+        self.mmu.scheduler().now()
+    }
+
+    /// Whether an illegal opcode has locked up the CPU
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn break_on_illegal(&self) -> bool {
+        self.break_on_illegal
+    }
+
+    /// Makes illegal opcodes return `EmulatorError::IllegalOpcode` from `step`, leaving PC on
+    /// them, rather than lock up the CPU as hardware does
+    pub fn set_break_on_illegal(&mut self, break_on_illegal: bool) {
+        self.break_on_illegal = break_on_illegal;
+    }
+
+    /// Starts writing a trace line before every step, or stops if `tracer` is `None`
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    /// Starts accumulating cycle counts, or stops and discards them if `profiler` is `None`
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Writes the registers and memory for a save state
+    pub fn save_state(&self, writer: &mut Writer) {
+        for reg in [
+            self.reg.a,
+            self.reg.f.into(),
+            self.reg.b,
+            self.reg.c,
+            self.reg.d,
+            self.reg.e,
+            self.reg.h,
+            self.reg.l,
+        ] {
+            writer.u8(reg);
+        }
+        writer.u16(self.sp);
+        writer.u16(self.pc);
+        writer.u64(self.steps);
+        writer.bool(self.ime);
+        writer.bool(self.ei_delay);
+        writer.bool(self.locked);
+        writer.bool(self.halted);
+        writer.bool(self.stopped);
+        writer.bool(self.halt_bug);
+
+        self.mmu.save_state(writer);
+    }
+
+    /// Reads back what `save_state` wrote, leaving everything as it was if that fails. The call
+    /// stack starts over, as it can't be known.
+    pub fn load_state(&mut self, reader: &mut Reader) -> Result<(), EmulatorError> {
+        let mut reg = [0; 8];
+        for byte in reg.iter_mut() {
+            *byte = reader.u8()?;
+        }
+        let sp = reader.u16()?;
+        let pc = reader.u16()?;
+        let steps = reader.u64()?;
+        let ime = reader.bool()?;
+        let ei_delay = reader.bool()?;
+        let locked = reader.bool()?;
+        let halted = reader.bool()?;
+        let stopped = reader.bool()?;
+        let halt_bug = reader.bool()?;
+
+        self.mmu.load_state(reader)?;
+
+        let [a, f, b, c, d, e, h, l] = reg;
+        self.reg = Registers {
+            a,
+            f: f.into(),
+            b,
+            c,
+            d,
+            e,
+            h,
+            l,
+        };
+        self.sp = sp;
+        self.pc = pc;
+        self.steps = steps;
+        self.ime = ime;
+        self.ei_delay = ei_delay;
+        self.locked = locked;
+        self.halted = halted;
+        self.stopped = stopped;
+        self.halt_bug = halt_bug;
+        self.call_stack = CallStack::new();
+
+        Ok(())
+    }
+
+    /// Starts running `script`'s callbacks as the CPU steps, or stops if `script` is `None`
+    pub fn set_script(&mut self, script: Option<Script>) {
+        self.script = script;
+        self.script_ran_at = None;
+    }
+
+    pub fn script_mut(&mut self) -> Option<&mut Script> {
+        self.script.as_mut()
+    }
+
+    pub fn ime(&self) -> bool {
+        self.ime
+    }
+
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    /// Sets any register the instructions can name. 8-bit registers take the low byte of
+    /// `value`; writing AF goes through `set_f`, so the low nibble of F stays zero. HL+ and HL-
+    /// set HL.
+    pub fn set_register(&mut self, reg: IRegister, value: u16) {
+        match reg {
+            IRegister::AF => {
+                self.reg.set_a((value >> 8) as u8);
+                self.set_f(value as u8);
+            }
+            IRegister::BC => self.reg.set_bc(value),
+            IRegister::DE => self.reg.set_de(value),
+            IRegister::HL | IRegister::HL_INC | IRegister::HL_DEC => self.reg.set_hl(value),
+            IRegister::SP => self.sp = value,
+            IRegister::A
+            | IRegister::B
+            | IRegister::C
+            | IRegister::D
+            | IRegister::E
+            | IRegister::H
+            | IRegister::L => {
+                if let Some(register) = self.register_u8(reg) {
+                    *register = value as u8;
+                }
+            }
+        }
+    }
+
+    /// Sets F from a raw byte. The low nibble doesn't exist in hardware and always reads zero.
+    pub fn set_f(&mut self, value: u8) {
+        if value & 0x0F != 0 {
+            log::debug!("dropping low nibble of F value {:02x}", value);
+        }
+        self.reg.set_f(Flags::from(value));
+    }
+
+    pub fn set_flag(&mut self, flag: Flags, value: bool) {
+        self.reg.set_flag(flag, value)
+    }
+
+    pub fn set_pc(&mut self, value: u16) {
+        self.pc = value
+    }
+
+    /// The 8-bit register `reg`, if it is one
+    fn register_u8(&mut self, reg: IRegister) -> Option<&mut u8> {
         match reg {
-            IRegister::A => self.reg.a(),
-            IRegister::B => self.reg.b(),
-            IRegister::C => self.reg.c(),
-            IRegister::D => self.reg.d(),
-            IRegister::E => self.reg.e(),
-            IRegister::H => self.reg.h(),
-            IRegister::L => self.reg.l(),
+            IRegister::A => Some(&mut self.reg.a),
+            IRegister::B => Some(&mut self.reg.b),
+            IRegister::C => Some(&mut self.reg.c),
+            IRegister::D => Some(&mut self.reg.d),
+            IRegister::E => Some(&mut self.reg.e),
+            IRegister::H => Some(&mut self.reg.h),
+            IRegister::L => Some(&mut self.reg.l),
             IRegister::AF
             | IRegister::BC
             | IRegister::DE
             | IRegister::HL
             | IRegister::HL_INC
             | IRegister::HL_DEC
-            | IRegister::SP => {
-                panic!("attempted to read 16-bit register {} as a u8!", reg)
-            }
+            | IRegister::SP => None,
+        }
+    }
+
+    fn read_register(&mut self, reg: IRegister) -> Result<u8, String> {
+        match self.register_u8(reg) {
+            Some(register) => Ok(*register),
+            None => Err(format!("attempted to read 16-bit register {} as a u8", reg)),
         }
     }
 
@@ -246,13 +567,13 @@ impl Cpu {
             IRegister::DE => self.reg.de(),
             IRegister::HL => self.reg.hl(),
             IRegister::HL_INC => {
-                let value = self.reg.hl() as u16;
-                self.reg.set_hl(value + 1);
+                let value = self.reg.hl();
+                self.reg.set_hl(value.wrapping_add(1));
                 value
             }
             IRegister::HL_DEC => {
-                let value = self.reg.hl() as u16;
-                self.reg.set_hl(value - 1);
+                let value = self.reg.hl();
+                self.reg.set_hl(value.wrapping_sub(1));
                 value
             }
             // We want to accept 8-bit registers as u16 as well, in case we use them for
@@ -263,33 +584,25 @@ impl Cpu {
             IRegister::C => self.reg.c() as u16,
             IRegister::D => self.reg.d() as u16,
             IRegister::E => self.reg.e() as u16,
-            IRegister::H => self.reg.e() as u16,
+            IRegister::H => self.reg.h() as u16,
             IRegister::L => self.reg.l() as u16,
         }
     }
 
-    fn write_register(&mut self, reg: IRegister, value: u8) {
-        match reg {
-            IRegister::A => self.reg.set_a(value),
-            IRegister::B => self.reg.set_b(value),
-            IRegister::C => self.reg.set_c(value),
-            IRegister::D => self.reg.set_d(value),
-            IRegister::E => self.reg.set_e(value),
-            IRegister::H => self.reg.set_h(value),
-            IRegister::L => self.reg.set_l(value),
-            IRegister::AF
-            | IRegister::BC
-            | IRegister::DE
-            | IRegister::HL
-            | IRegister::HL_INC
-            | IRegister::HL_DEC
-            | IRegister::SP => {
-                panic!("attempted to write a u8 to 16-bit register {}", reg)
+    fn write_register(&mut self, reg: IRegister, value: u8) -> Result<(), String> {
+        match self.register_u8(reg) {
+            Some(register) => {
+                *register = value;
+                Ok(())
             }
+            None => Err(format!(
+                "attempted to write a u8 to 16-bit register {}",
+                reg
+            )),
         }
     }
 
-    fn write_register_u16(&mut self, reg: IRegister, value: u16) {
+    fn write_register_u16(&mut self, reg: IRegister, value: u16) -> Result<(), String> {
         match reg {
             IRegister::AF => self.reg.set_af(value),
             IRegister::BC => self.reg.set_bc(value),
@@ -305,101 +618,199 @@ impl Cpu {
             | IRegister::E
             | IRegister::H
             | IRegister::L => {
-                panic!("attempted to write a u16 to 8-bit register {}", reg)
+                return Err(format!(
+                    "attempted to write a u16 to 8-bit register {}",
+                    reg
+                ))
             }
         }
+
+        Ok(())
+    }
+
+    /// Address for register-indirect access; (C) lives in the 0xFF00 page
+    fn indirect_address(&mut self, reg: IRegister) -> u16 {
+        match reg {
+            IRegister::C => 0xFF00 | self.reg.c() as u16,
+            // Get as u16 to access full addressing space
+            _ => self.read_register_u16(reg),
+        }
     }
 
     /// Returns u16 though some may only be u8; will never be more than u16
-    fn read_location(&mut self, inst: &DecodedInstruction, loc: ILocation) -> u8 {
-        match loc {
-            ILocation::Register(reg) => self.read_register(reg),
+    fn read_location(&mut self, inst: &DecodedInstruction, loc: ILocation) -> Result<u8, String> {
+        let value = match loc {
+            ILocation::Register(reg) => self.read_register(reg)?,
             ILocation::RegisterIndirectByte(reg) => {
-                // Get as u16 to access full addressing space
-                let addr = self.read_register_u16(reg);
-                self.mmu.rb(addr as usize)
+                let addr = self.indirect_address(reg);
+                self.read_memory(addr)
             }
             ILocation::ImmediateByte => inst.operands_as_u8(),
-            ILocation::ImmediateByteIndirectByte => self.mmu.rb(inst.operands_as_u8() as usize),
-            ILocation::ImmediateWordIndirectByte => self.mmu.rb(inst.operands_as_u16() as usize),
+            ILocation::ImmediateByteIndirectByte => {
+                self.read_memory(0xFF00 | inst.operands_as_u8() as u16)
+            }
+            ILocation::ImmediateWordIndirectByte => self.read_memory(inst.operands_as_u16()),
             ILocation::ImmediateWord
             | ILocation::ImmediateByteIndirectWord
             | ILocation::ImmediateWordIndirectWord
+            | ILocation::ImmediateSignedByte
+            | ILocation::StackPointerOffset
             | ILocation::RegisterIndirectWord(_) => {
-                panic!("attempted to read 16-bit location {} as a u8!", loc)
+                return Err(format!("attempted to read 16-bit location {} as a u8", loc))
             }
-        }
+        };
+
+        Ok(value)
     }
 
     /// Returns u16 though some may only be u8; will never be more than u16
-    fn read_location_u16(&mut self, inst: &DecodedInstruction, loc: ILocation) -> u16 {
-        match loc {
+    fn read_location_u16(
+        &mut self,
+        inst: &DecodedInstruction,
+        loc: ILocation,
+    ) -> Result<u16, String> {
+        let value = match loc {
             ILocation::Register(reg) => self.read_register_u16(reg),
             ILocation::ImmediateWord => inst.operands_as_u16(),
-            ILocation::ImmediateByteIndirectWord => self.mmu.rw(inst.operands_as_u8() as usize),
-            ILocation::ImmediateWordIndirectWord => self.mmu.rw(inst.operands_as_u16() as usize),
+            ILocation::ImmediateByteIndirectWord => {
+                self.read_memory_u16(0xFF00 | inst.operands_as_u8() as u16)
+            }
+            ILocation::ImmediateWordIndirectWord => self.read_memory_u16(inst.operands_as_u16()),
             ILocation::RegisterIndirectWord(reg) => {
-                let addr = self.read_register(reg);
-                self.mmu.rw(addr as usize)
+                let addr = self.read_register(reg)?;
+                self.read_memory_u16(addr as u16)
             }
+            ILocation::StackPointerOffset => self.offset_sp(inst),
             ILocation::RegisterIndirectByte(_)
             | ILocation::ImmediateByte
+            | ILocation::ImmediateSignedByte
             | ILocation::ImmediateByteIndirectByte
             | ILocation::ImmediateWordIndirectByte => {
-                panic!("attempted to read 8-bit location {} as a u16!", loc)
+                return Err(format!("attempted to read 8-bit location {} as a u16", loc))
             }
-        }
+        };
+
+        Ok(value)
     }
 
     /// Function signature takes u16, but some may only write u8
-    fn write_location(&mut self, inst: &DecodedInstruction, loc: ILocation, value: u8) {
+    fn write_location(
+        &mut self,
+        inst: &DecodedInstruction,
+        loc: ILocation,
+        value: u8,
+    ) -> Result<(), String> {
         match loc {
-            ILocation::Register(reg) => self.write_register(reg, value),
+            ILocation::Register(reg) => self.write_register(reg, value)?,
             ILocation::RegisterIndirectByte(reg) => {
-                // Get as u16 to access full addressing space
-                let addr = self.read_register_u16(reg);
-                self.mmu.wb(addr as usize, value)
+                let addr = self.indirect_address(reg);
+                self.write_memory(addr, value)
             }
             ILocation::ImmediateByteIndirectByte => {
-                self.mmu.wb(inst.operands_as_u8() as usize, value)
+                self.write_memory(0xFF00 | inst.operands_as_u8() as u16, value)
             }
             ILocation::ImmediateWordIndirectByte => {
-                self.mmu.wb(inst.operands_as_u16() as usize, value)
+                self.write_memory(inst.operands_as_u16(), value)
             }
-            ILocation::ImmediateByte | ILocation::ImmediateWord => {
-                panic!("cannot write to immediate value {}!", loc)
+            ILocation::ImmediateByte
+            | ILocation::ImmediateWord
+            | ILocation::ImmediateSignedByte
+            | ILocation::StackPointerOffset => {
+                return Err(format!("cannot write to immediate value {}", loc))
             }
             ILocation::RegisterIndirectWord(_)
             | ILocation::ImmediateByteIndirectWord
             | ILocation::ImmediateWordIndirectWord => {
-                panic!("attempted to write a u8 to 16-bit location {}", loc)
+                return Err(format!(
+                    "attempted to write a u8 to 16-bit location {}",
+                    loc
+                ))
             }
         }
+
+        Ok(())
     }
 
     /// Function signature takes u16, but some may only write u8
-    fn write_location_u16(&mut self, inst: &DecodedInstruction, loc: ILocation, value: u16) {
+    fn write_location_u16(
+        &mut self,
+        inst: &DecodedInstruction,
+        loc: ILocation,
+        value: u16,
+    ) -> Result<(), String> {
         match loc {
-            ILocation::Register(reg) => self.write_register_u16(reg, value),
+            ILocation::Register(reg) => self.write_register_u16(reg, value)?,
             ILocation::RegisterIndirectWord(reg) => {
-                let addr = self.read_register(reg);
-                self.mmu.ww(addr as usize, value as u16)
+                let addr = self.read_register(reg)?;
+                self.write_memory_u16(addr as u16, value)
             }
             ILocation::ImmediateByteIndirectWord => {
-                self.mmu.ww(inst.operands_as_u8() as usize, value)
+                self.write_memory_u16(0xFF00 | inst.operands_as_u8() as u16, value)
             }
             ILocation::ImmediateWordIndirectWord => {
-                self.mmu.ww(inst.operands_as_u16() as usize, value)
+                self.write_memory_u16(inst.operands_as_u16(), value)
             }
-            ILocation::ImmediateByte | ILocation::ImmediateWord => {
-                panic!("Cannot write to immediate value {}!", loc)
+            ILocation::ImmediateByte
+            | ILocation::ImmediateWord
+            | ILocation::ImmediateSignedByte
+            | ILocation::StackPointerOffset => {
+                return Err(format!("cannot write to immediate value {}", loc))
             }
             ILocation::RegisterIndirectByte(_)
             | ILocation::ImmediateByteIndirectByte
             | ILocation::ImmediateWordIndirectByte => {
-                panic!("attempted to write a u16 to 8-bit location {}", loc)
+                return Err(format!(
+                    "attempted to write a u16 to 8-bit location {}",
+                    loc
+                ))
             }
         }
+
+        Ok(())
+    }
+
+    /// Clocks the rest of the machine through one M-cycle
+    fn cycle(&mut self) {
+        self.mmu.tick(M_CYCLE);
+    }
+
+    /// Clocks the fetch of the instruction being executed, if that hasn't happened yet
+    fn fetched(&mut self) {
+        for _ in 0..std::mem::take(&mut self.pending_fetch) {
+            self.cycle();
+        }
+    }
+
+    /// An M-cycle spent inside the CPU, without a memory access
+    fn idle(&mut self) {
+        self.fetched();
+        self.cycle();
+    }
+
+    /// Reads a byte at the end of an M-cycle
+    fn read_memory(&mut self, addr: u16) -> u8 {
+        self.idle();
+        self.mmu.read(addr as usize)
+    }
+
+    /// Reads the low byte, then the high byte, an M-cycle each
+    fn read_memory_u16(&mut self, addr: u16) -> u16 {
+        let low = self.read_memory(addr);
+        let high = self.read_memory(addr.wrapping_add(1));
+        bits::pack_u16(high, low)
+    }
+
+    /// Writes a byte at the end of an M-cycle
+    fn write_memory(&mut self, addr: u16, value: u8) {
+        self.idle();
+        self.mmu.write(addr as usize, value);
+    }
+
+    /// Writes the low byte, then the high byte, an M-cycle each
+    fn write_memory_u16(&mut self, addr: u16, value: u16) {
+        let (high, low) = bits::unpack_u16(value);
+        self.write_memory(addr, low);
+        self.write_memory(addr.wrapping_add(1), high);
     }
 
     fn read_flag(&self, flag: IFlag) -> bool {
@@ -412,132 +823,788 @@ impl Cpu {
         }
     }
 
-    fn check_zero(&mut self, value: u16) {
-        self.reg.set_flag(Flags::ZERO, value == 0)
-    }
-
     fn nop(&self, _inst: &DecodedInstruction) {
         log::info!("NOP")
     }
 
-    fn ld(&mut self, inst: &DecodedInstruction, dst: ILocation, src: ILocation) {
-        let value = self.read_location(inst, src);
-        self.write_location(inst, dst, value);
-    }
+    fn ld(
+        &mut self,
+        inst: &DecodedInstruction,
+        dst: ILocation,
+        src: ILocation,
+    ) -> Result<(), String> {
+        let value = self.read_location(inst, src)?;
+        self.write_location(inst, dst, value)
+    }
+
+    fn ld16(
+        &mut self,
+        inst: &DecodedInstruction,
+        dst: ILocation,
+        src: ILocation,
+    ) -> Result<(), String> {
+        let value = self.read_location_u16(inst, src)?;
+
+        // LD SP, HL moves the value over the 16-bit bus, and LD HL, SP+e adds the high byte
+        if let (ILocation::Register(IRegister::SP), ILocation::Register(IRegister::HL))
+        | (_, ILocation::StackPointerOffset) = (dst, src)
+        {
+            self.idle();
+        }
 
-    fn ld16(&mut self, inst: &DecodedInstruction, dst: ILocation, src: ILocation) {
-        let value = self.read_location_u16(inst, src);
-        self.write_location_u16(inst, dst, value);
+        self.write_location_u16(inst, dst, value)
     }
 
     /// JP: Jump to the address specified in `loc` if `flag` is set.
-    fn jp(&mut self, inst: &DecodedInstruction, flag: IFlag, loc: ILocation) {
+    fn jp(&mut self, inst: &DecodedInstruction, flag: IFlag, loc: ILocation) -> Result<(), String> {
+        if self.read_flag(flag) {
+            let target = self.read_location_u16(inst, loc)?;
+
+            // JP HL doesn't need the cycle to load PC from the immediate
+            if let ILocation::ImmediateWord = loc {
+                self.idle();
+            }
+
+            self.pc = target;
+        }
+
+        Ok(())
+    }
+
+    /// JR: Jump by the signed offset in the operand if `flag` is set. Adding it to PC takes a
+    /// cycle, so a jump taken is 12 cycles and one not taken is 8.
+    fn jr(&mut self, inst: &DecodedInstruction, flag: IFlag) {
+        if self.read_flag(flag) {
+            let offset = inst.operands_as_u8() as i8;
+            self.idle();
+            self.pc = self.pc.wrapping_add(offset as u16);
+        }
+    }
+
+    /// Pushes the high byte first, after a cycle decrementing SP
+    fn push_u16(&mut self, value: u16) {
+        let (high, low) = bits::unpack_u16(value);
+
+        self.idle();
+        self.sp = self.sp.wrapping_sub(1);
+        self.write_memory(self.sp, high);
+        self.sp = self.sp.wrapping_sub(1);
+        self.write_memory(self.sp, low);
+    }
+
+    fn pop_u16(&mut self) -> u16 {
+        let low = self.read_memory(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        let high = self.read_memory(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+
+        bits::pack_u16(high, low)
+    }
+
+    /// Pushes the return address and jumps to `target`, recording it on the call stack
+    fn call_address(&mut self, inst: &DecodedInstruction, target: u16) {
+        self.push_u16(self.pc);
+
+        self.call_stack.call(CallFrame {
+            caller: self.pc.wrapping_sub(inst.len() as u16),
+            target,
+            sp: self.sp,
+            bank: self.mmu.bank_at(target),
+            interrupt: false,
+        });
+
+        self.pc = target;
+    }
+
+    fn return_address(&mut self, inst: &DecodedInstruction, reti: bool) {
+        let pc = self.pc.wrapping_sub(inst.len() as u16);
+        self.call_stack.ret(pc, self.sp, reti);
+        self.pc = self.pop_u16();
+        self.idle();
+    }
+
+    /// CALL: Push the next instruction's address and jump to `loc` if `flag` is set.
+    fn call(
+        &mut self,
+        inst: &DecodedInstruction,
+        flag: IFlag,
+        loc: ILocation,
+    ) -> Result<(), String> {
+        if self.read_flag(flag) {
+            let target = self.read_location_u16(inst, loc)?;
+            self.call_address(inst, target);
+        }
+
+        Ok(())
+    }
+
+    /// RET: Pop the return address into PC if `flag` is set.
+    fn ret(&mut self, inst: &DecodedInstruction, flag: IFlag) {
+        // Checking the condition takes a cycle of its own
+        if flag != IFlag::TRUE {
+            self.idle();
+        }
+
         if self.read_flag(flag) {
-            self.pc = self.read_location_u16(inst, loc);
+            self.return_address(inst, false);
         }
     }
 
+    /// RETI: Return and enable interrupts
+    fn reti(&mut self, inst: &DecodedInstruction) {
+        self.return_address(inst, true);
+        self.ime = true;
+    }
+
+    /// RST: Call one of the fixed vectors at 0x00-0x38
+    fn rst(&mut self, inst: &DecodedInstruction, vector: u8) {
+        self.call_address(inst, vector as u16);
+    }
+
+    fn push(&mut self, inst: &DecodedInstruction, loc: ILocation) -> Result<(), String> {
+        let value = self.read_location_u16(inst, loc)?;
+        self.push_u16(value);
+
+        Ok(())
+    }
+
+    /// POP: Pop into a register pair. POP AF drops the low nibble of F.
+    fn pop(&mut self, inst: &DecodedInstruction, loc: ILocation) -> Result<(), String> {
+        let value = self.pop_u16();
+        self.write_location_u16(inst, loc, value)
+    }
+
     /// CPL: One's complement (flip) register A
     fn cpl(&mut self) {
-        self.reg.set_a(!self.reg.a())
+        self.reg.set_a(!self.reg.a());
+        self.reg.set_flag(Flags::SUBTRACT, true);
+        self.reg.set_flag(Flags::HALF_CARRY, true);
     }
 
     /// CCF: One's complement (flip) carry flag
     fn ccf(&mut self) {
         self.reg.flip_flag(Flags::CARRY);
-        self.reg.set_flag(Flags::SUBTRACT, true);
-        self.reg.set_flag(Flags::HALF_CARRY, true);
+        self.reg.set_flag(Flags::SUBTRACT, false);
+        self.reg.set_flag(Flags::HALF_CARRY, false);
+    }
+
+    /// SCF: Set the carry flag
+    fn scf(&mut self) {
+        self.reg.set_flag(Flags::CARRY, true);
+        self.reg.set_flag(Flags::SUBTRACT, false);
+        self.reg.set_flag(Flags::HALF_CARRY, false);
+    }
+
+    /// DAA: Adjust A back to BCD after adding or subtracting two BCD numbers, going by the
+    /// flags that left
+    fn daa(&mut self) {
+        let mut a = self.reg.a();
+        let mut carry = self.reg.has_flag(Flags::CARRY);
+        let half_carry = self.reg.has_flag(Flags::HALF_CARRY);
+
+        if self.reg.has_flag(Flags::SUBTRACT) {
+            if carry {
+                a = a.wrapping_sub(0x60);
+            }
+            if half_carry {
+                a = a.wrapping_sub(0x06);
+            }
+        } else {
+            if carry || a > 0x99 {
+                a = a.wrapping_add(0x60);
+                carry = true;
+            }
+            if half_carry || a & 0x0F > 0x09 {
+                a = a.wrapping_add(0x06);
+            }
+        }
+
+        self.reg.set_a(a);
+        self.reg.set_flag(Flags::ZERO, a == 0);
+        self.reg.set_flag(Flags::HALF_CARRY, false);
+        self.reg.set_flag(Flags::CARRY, carry);
     }
 
-    /// XOR: XOR dst with src, store in dst
-    fn xor(&mut self, inst: &DecodedInstruction, dst: ILocation, src: ILocation) {
-        let src_value = self.read_location(inst, src);
-        let dst_value = self.read_location(inst, dst);
+    /// ADD/ADC: Add src, and the carry flag if `carry`, to dst, store in dst
+    fn add(
+        &mut self,
+        inst: &DecodedInstruction,
+        dst: ILocation,
+        src: ILocation,
+        carry: bool,
+    ) -> Result<(), String> {
+        let src_value = self.read_location(inst, src)?;
+        let dst_value = self.read_location(inst, dst)?;
+        let carry = (carry && self.reg.has_flag(Flags::CARRY)) as u8;
 
-        let value = src_value ^ dst_value;
-        self.write_location(inst, dst, value);
+        let value = dst_value.wrapping_add(src_value).wrapping_add(carry);
+        self.write_location(inst, dst, value)?;
 
         self.reg.set_flag(Flags::ZERO, value == 0);
         self.reg.set_flag(Flags::SUBTRACT, false);
-        self.reg.set_flag(Flags::HALF_CARRY, false);
+        self.reg.set_flag(
+            Flags::HALF_CARRY,
+            (dst_value & 0x0F) + (src_value & 0x0F) + carry > 0x0F,
+        );
+        self.reg.set_flag(
+            Flags::CARRY,
+            dst_value as u16 + src_value as u16 + carry as u16 > 0xFF,
+        );
+
+        Ok(())
+    }
+
+    /// Subtracts src, and the carry flag if `carry`, from dst and sets the flags, for SUB, SBC
+    /// and CP. Returns the result without storing it.
+    fn subtract(
+        &mut self,
+        inst: &DecodedInstruction,
+        dst: ILocation,
+        src: ILocation,
+        carry: bool,
+    ) -> Result<u8, String> {
+        let src_value = self.read_location(inst, src)?;
+        let dst_value = self.read_location(inst, dst)?;
+        let carry = (carry && self.reg.has_flag(Flags::CARRY)) as u8;
+
+        let value = dst_value.wrapping_sub(src_value).wrapping_sub(carry);
+
+        self.reg.set_flag(Flags::ZERO, value == 0);
+        self.reg.set_flag(Flags::SUBTRACT, true);
+        self.reg.set_flag(
+            Flags::HALF_CARRY,
+            (dst_value & 0x0F) < (src_value & 0x0F) + carry,
+        );
+        self.reg.set_flag(
+            Flags::CARRY,
+            (dst_value as u16) < src_value as u16 + carry as u16,
+        );
+
+        Ok(value)
+    }
+
+    /// SUB/SBC: Subtract src, and the carry flag if `carry`, from dst, store in dst
+    fn sub(
+        &mut self,
+        inst: &DecodedInstruction,
+        dst: ILocation,
+        src: ILocation,
+        carry: bool,
+    ) -> Result<(), String> {
+        let value = self.subtract(inst, dst, src, carry)?;
+        self.write_location(inst, dst, value)
+    }
+
+    /// CP: Compare dst with src, setting the flags as SUB would without storing the result
+    fn cp(
+        &mut self,
+        inst: &DecodedInstruction,
+        dst: ILocation,
+        src: ILocation,
+    ) -> Result<(), String> {
+        self.subtract(inst, dst, src, false)?;
+
+        Ok(())
+    }
+
+    /// AND/OR/XOR: Combine dst with src through `op`, store in dst. Only AND sets the
+    /// half-carry flag.
+    fn bitwise(
+        &mut self,
+        inst: &DecodedInstruction,
+        dst: ILocation,
+        src: ILocation,
+        op: fn(u8, u8) -> u8,
+        half_carry: bool,
+    ) -> Result<(), String> {
+        let src_value = self.read_location(inst, src)?;
+        let dst_value = self.read_location(inst, dst)?;
+
+        let value = op(dst_value, src_value);
+        self.write_location(inst, dst, value)?;
+
+        self.reg.set_flag(Flags::ZERO, value == 0);
+        self.reg.set_flag(Flags::SUBTRACT, false);
+        self.reg.set_flag(Flags::HALF_CARRY, half_carry);
         self.reg.set_flag(Flags::CARRY, false);
+
+        Ok(())
     }
 
-    fn dec(&mut self, inst: &DecodedInstruction, loc: ILocation) {
-        let before = self.read_location(inst, loc);
-        let (after, overflowed) = before.overflowing_sub(1);
-        self.write_location(inst, loc, after);
+    fn dec(&mut self, inst: &DecodedInstruction, loc: ILocation) -> Result<(), String> {
+        let before = self.read_location(inst, loc)?;
+        let after = before.wrapping_sub(1);
+        self.write_location(inst, loc, after)?;
 
         self.reg.set_flag(Flags::ZERO, after == 0);
         self.reg.set_flag(Flags::SUBTRACT, true);
-        // A half-carry only occurs when we overflow from 0 to maxint
-        self.reg.set_flag(Flags::HALF_CARRY, overflowed);
+        // Borrowing from bit 4 only happens when the low nibble was 0
+        self.reg.set_flag(Flags::HALF_CARRY, before & 0x0F == 0x00);
+
+        Ok(())
     }
 
-    fn dec16(&mut self, inst: &DecodedInstruction, loc: ILocation) {
-        let before = self.read_location_u16(inst, loc);
+    fn dec16(&mut self, inst: &DecodedInstruction, loc: ILocation) -> Result<(), String> {
+        let before = self.read_location_u16(inst, loc)?;
         let (after, _) = before.overflowing_sub(1);
-        self.write_location_u16(inst, loc, after);
+        self.idle();
+        self.mmu.corrupt_oam(before);
         // Don't update flags for 16-bit DEC
+        self.write_location_u16(inst, loc, after)
     }
 
-    fn inc(&mut self, inst: &DecodedInstruction, loc: ILocation) {
-        let before = self.read_location(inst, loc);
-        let (after, _) = before.overflowing_add(1);
-        self.write_location(inst, loc, after);
+    fn inc(&mut self, inst: &DecodedInstruction, loc: ILocation) -> Result<(), String> {
+        let before = self.read_location(inst, loc)?;
+        let after = before.wrapping_add(1);
+        self.write_location(inst, loc, after)?;
 
         self.reg.set_flag(Flags::ZERO, after == 0);
         self.reg.set_flag(Flags::SUBTRACT, false);
-        // A half-carry only occurs when we go from 0xFF to 0x100
-        self.reg.set_flag(Flags::HALF_CARRY, before == 0xFF);
+        // Carrying into bit 4 only happens when the low nibble was 0xF
+        self.reg.set_flag(Flags::HALF_CARRY, before & 0x0F == 0x0F);
+
+        Ok(())
     }
 
-    fn inc16(&mut self, inst: &DecodedInstruction, loc: ILocation) {
-        let before = self.read_location_u16(inst, loc);
+    fn inc16(&mut self, inst: &DecodedInstruction, loc: ILocation) -> Result<(), String> {
+        let before = self.read_location_u16(inst, loc)?;
         let (after, _) = before.overflowing_add(1);
-        self.write_location_u16(inst, loc, after);
+        self.idle();
+        self.mmu.corrupt_oam(before);
         // Don't update flags for 16-bit INC
+        self.write_location_u16(inst, loc, after)
+    }
+
+    /// ADD HL, rr or ADD SP, e. Adding to HL takes a cycle for the high byte and leaves the
+    /// zero flag alone; adding to SP takes two and clears it.
+    fn add16(
+        &mut self,
+        inst: &DecodedInstruction,
+        dst: ILocation,
+        src: ILocation,
+    ) -> Result<(), String> {
+        if let ILocation::ImmediateSignedByte = src {
+            let value = self.offset_sp(inst);
+            self.idle();
+            self.idle();
+            return self.write_location_u16(inst, dst, value);
+        }
+
+        let src_value = self.read_location_u16(inst, src)?;
+        let dst_value = self.read_location_u16(inst, dst)?;
+
+        let value = dst_value.wrapping_add(src_value);
+        self.idle();
+        self.write_location_u16(inst, dst, value)?;
+
+        self.reg.set_flag(Flags::SUBTRACT, false);
+        self.reg.set_flag(
+            Flags::HALF_CARRY,
+            (dst_value & 0x0FFF) + (src_value & 0x0FFF) > 0x0FFF,
+        );
+        self.reg
+            .set_flag(Flags::CARRY, dst_value as u32 + src_value as u32 > 0xFFFF);
+
+        Ok(())
     }
 
-    fn execute(&mut self, inst: &DecodedInstruction) {
+    /// SP plus the signed operand, for ADD SP, e and LD HL, SP+e. The half-carry and carry
+    /// flags come from adding the operand to SP's low byte as if it were unsigned.
+    fn offset_sp(&mut self, inst: &DecodedInstruction) -> u16 {
+        let offset = inst.operands_as_u8();
+        let value = self.sp.wrapping_add(offset as i8 as u16);
+
+        self.reg.set_flag(Flags::ZERO, false);
+        self.reg.set_flag(Flags::SUBTRACT, false);
+        self.reg.set_flag(
+            Flags::HALF_CARRY,
+            (self.sp & 0x0F) + (offset & 0x0F) as u16 > 0x0F,
+        );
+        self.reg
+            .set_flag(Flags::CARRY, (self.sp & 0xFF) + offset as u16 > 0xFF);
+
+        value
+    }
+
+    /// RLC/RRC/RL/RR/SLA/SRA/SWAP/SRL: Replace loc with what `op` makes of it and the carry
+    /// flag, setting the carry flag to the bit shifted out. Returns the result.
+    fn shift(
+        &mut self,
+        inst: &DecodedInstruction,
+        loc: ILocation,
+        op: fn(u8, bool) -> (u8, bool),
+    ) -> Result<u8, String> {
+        let before = self.read_location(inst, loc)?;
+        let (after, carry) = op(before, self.reg.has_flag(Flags::CARRY));
+        self.write_location(inst, loc, after)?;
+
+        self.reg.set_flag(Flags::ZERO, after == 0);
+        self.reg.set_flag(Flags::SUBTRACT, false);
+        self.reg.set_flag(Flags::HALF_CARRY, false);
+        self.reg.set_flag(Flags::CARRY, carry);
+
+        Ok(after)
+    }
+
+    /// RLCA/RRCA/RLA/RRA: Shift A as the prefixed instructions would, but always clear the
+    /// zero flag
+    fn shift_a(
+        &mut self,
+        inst: &DecodedInstruction,
+        op: fn(u8, bool) -> (u8, bool),
+    ) -> Result<(), String> {
+        self.shift(inst, ILocation::Register(IRegister::A), op)?;
+        self.reg.set_flag(Flags::ZERO, false);
+
+        Ok(())
+    }
+
+    /// BIT: Set the zero flag if `bit` of loc is clear
+    fn bit(&mut self, inst: &DecodedInstruction, bit: u8, loc: ILocation) -> Result<(), String> {
+        let value = self.read_location(inst, loc)?;
+
+        self.reg.set_flag(Flags::ZERO, value & (1 << bit) == 0);
+        self.reg.set_flag(Flags::SUBTRACT, false);
+        self.reg.set_flag(Flags::HALF_CARRY, true);
+
+        Ok(())
+    }
+
+    /// RES/SET: Clear or set `bit` of loc, leaving the flags alone
+    fn set_bit(
+        &mut self,
+        inst: &DecodedInstruction,
+        bit: u8,
+        loc: ILocation,
+        set: bool,
+    ) -> Result<(), String> {
+        let value = self.read_location(inst, loc)?;
+        let value = match set {
+            true => value | (1 << bit),
+            false => value & !(1 << bit),
+        };
+
+        self.write_location(inst, loc, value)
+    }
+
+    /// HALT: Wait for an interrupt to be requested. With IME off and one already pending it
+    /// doesn't wait, and the next opcode is read twice instead.
+    fn halt(&mut self) {
+        if !self.ime && !self.mmu.pending_interrupts().is_empty() {
+            self.halt_bug = true;
+        } else {
+            self.halted = true;
+        }
+    }
+
+    /// STOP: Switch speed if KEY1 asks for it, or otherwise wait for a button to be pressed.
+    /// Either way DIV is reset.
+    fn stop(&mut self) {
+        if self.mmu.switch_speed() {
+            for _ in 0..SPEED_SWITCH_CYCLES {
+                self.idle();
+            }
+        } else {
+            self.mmu.wb(io::DIV as usize, 0);
+            self.stopped = self.mmu.buttons().is_empty();
+        }
+    }
+
+    /// Calls the vector of the highest priority interrupt that's requested and enabled, if IME
+    /// lets it. That takes 5 M-cycles: two waiting, two pushing PC and one jumping. Returns
+    /// whether one was taken.
+    fn service_interrupt(&mut self) -> bool {
+        let pending = self.mmu.pending_interrupts();
+        if !self.ime || pending.is_empty() {
+            return false;
+        }
+
+        let interrupt = Interrupts::from_bits_truncate(1 << pending.bits().trailing_zeros());
+        let vector = 0x0040 + 8 * pending.bits().trailing_zeros() as u16;
+
+        self.ime = false;
+        self.mmu.acknowledge_interrupt(interrupt);
+        log::debug!("servicing {:?} interrupt at {:04x}", interrupt, self.pc);
+
+        self.idle();
+        self.push_u16(self.pc);
+        self.call_stack.call(CallFrame {
+            caller: self.pc,
+            target: vector,
+            sp: self.sp,
+            bank: self.mmu.bank_at(vector),
+            interrupt: true,
+        });
+        self.pc = vector;
+        self.idle();
+
+        true
+    }
+
+    /// Runs `inst`, which was fetched from `pc`
+    fn execute(&mut self, inst: &DecodedInstruction, pc: u16) -> Result<(), EmulatorError> {
+        let opcode = inst.opcode();
+        let invalid = |message| EmulatorError::InvalidOperand {
+            pc,
+            opcode,
+            message,
+        };
+
         match inst.action() {
             IAction::NOP => self.nop(inst),
-            IAction::LD(dst, src) => self.ld(inst, dst, src),
-            IAction::LD16(dst, src) => self.ld16(inst, dst, src),
-            IAction::JP(flag, loc) => self.jp(inst, flag, loc),
+            IAction::LD(dst, src) => self.ld(inst, dst, src).map_err(invalid)?,
+            IAction::LD16(dst, src) => self.ld16(inst, dst, src).map_err(invalid)?,
+            IAction::JP(flag, loc) => self.jp(inst, flag, loc).map_err(invalid)?,
+            IAction::JR(flag, _) => self.jr(inst, flag),
+            IAction::CALL(flag, loc) => self.call(inst, flag, loc).map_err(invalid)?,
+            IAction::RET(flag) => self.ret(inst, flag),
+            IAction::RETI => self.reti(inst),
+            IAction::RST(vector) => self.rst(inst, vector),
+            IAction::PUSH(loc) => self.push(inst, loc).map_err(invalid)?,
+            IAction::POP(loc) => self.pop(inst, loc).map_err(invalid)?,
+            IAction::DI => {
+                self.ime = false;
+                self.ei_delay = false;
+            }
+            IAction::EI => self.ei_delay = true,
             IAction::CPL => self.cpl(),
             IAction::CCF => self.ccf(),
-            IAction::XOR(dst, src) => self.xor(inst, dst, src),
-            IAction::DEC(loc) => self.dec(inst, loc),
-            IAction::DEC16(loc) => self.dec16(inst, loc),
-            IAction::INC(loc) => self.inc(inst, loc),
-            IAction::INC16(loc) => self.inc16(inst, loc),
-            _ => {
-                log::debug!("dumping");
-                log::debug!("af = {:04x}", self.reg.af());
-                log::debug!("bc = {:04x}", self.reg.bc());
-                log::debug!("de = {:04x}", self.reg.de());
-                log::debug!("hl = {:04x}", self.reg.hl());
-                log::debug!("sp = {:04x}", self.sp);
-                log::debug!("pc = {:04x}", self.pc);
-                todo!("instruction 0x{:02x}", inst.opcode())
-            }
-        }
-    }
-
-    pub fn step(&mut self) {
-        let inst = DecodedInstruction::decode(&self.mmu, self.pc as usize);
+            IAction::SCF => self.scf(),
+            IAction::DAA => self.daa(),
+            IAction::ADD(dst, src) => self.add(inst, dst, src, false).map_err(invalid)?,
+            IAction::ADC(dst, src) => self.add(inst, dst, src, true).map_err(invalid)?,
+            IAction::SUB(dst, src) => self.sub(inst, dst, src, false).map_err(invalid)?,
+            IAction::SBC(dst, src) => self.sub(inst, dst, src, true).map_err(invalid)?,
+            IAction::CP(dst, src) => self.cp(inst, dst, src).map_err(invalid)?,
+            IAction::AND(dst, src) => self
+                .bitwise(inst, dst, src, |a, b| a & b, true)
+                .map_err(invalid)?,
+            IAction::OR(dst, src) => self
+                .bitwise(inst, dst, src, |a, b| a | b, false)
+                .map_err(invalid)?,
+            IAction::XOR(dst, src) => self
+                .bitwise(inst, dst, src, |a, b| a ^ b, false)
+                .map_err(invalid)?,
+            IAction::DEC(loc) => self.dec(inst, loc).map_err(invalid)?,
+            IAction::DEC16(loc) => self.dec16(inst, loc).map_err(invalid)?,
+            IAction::INC(loc) => self.inc(inst, loc).map_err(invalid)?,
+            IAction::INC16(loc) => self.inc16(inst, loc).map_err(invalid)?,
+            IAction::ADD16(dst, src) => self.add16(inst, dst, src).map_err(invalid)?,
+            IAction::RLCA => self.shift_a(inst, rlc).map_err(invalid)?,
+            IAction::RRCA => self.shift_a(inst, rrc).map_err(invalid)?,
+            IAction::RLA => self.shift_a(inst, rl).map_err(invalid)?,
+            IAction::RRA => self.shift_a(inst, rr).map_err(invalid)?,
+            IAction::RLC(loc) => self.shift(inst, loc, rlc).map(drop).map_err(invalid)?,
+            IAction::RRC(loc) => self.shift(inst, loc, rrc).map(drop).map_err(invalid)?,
+            IAction::RL(loc) => self.shift(inst, loc, rl).map(drop).map_err(invalid)?,
+            IAction::RR(loc) => self.shift(inst, loc, rr).map(drop).map_err(invalid)?,
+            IAction::SLA(loc) => self.shift(inst, loc, sla).map(drop).map_err(invalid)?,
+            IAction::SRA(loc) => self.shift(inst, loc, sra).map(drop).map_err(invalid)?,
+            IAction::SWAP(loc) => self.shift(inst, loc, swap).map(drop).map_err(invalid)?,
+            IAction::SRL(loc) => self.shift(inst, loc, srl).map(drop).map_err(invalid)?,
+            IAction::BIT(bit, loc) => self.bit(inst, bit, loc).map_err(invalid)?,
+            IAction::RES(bit, loc) => self.set_bit(inst, bit, loc, false).map_err(invalid)?,
+            IAction::SET(bit, loc) => self.set_bit(inst, bit, loc, true).map_err(invalid)?,
+            IAction::HALT => self.halt(),
+            IAction::STOP => self.stop(),
+            IAction::ILLEGAL => {
+                if self.break_on_illegal {
+                    return Err(EmulatorError::IllegalOpcode { pc, opcode });
+                }
+
+                log::warn!(
+                    "illegal opcode {:02x} at {:04x} locked up the CPU",
+                    opcode,
+                    pc
+                );
+                self.pc = pc;
+                self.locked = true;
+            }
+            // Decoding replaces it with the instruction after the prefix, so it never gets here
+            IAction::PREFIX => return Err(EmulatorError::UnimplementedOpcode { pc, opcode }),
+        }
+
+        Ok(())
+    }
+
+    /// Runs one instruction, returning the clock cycles it took. The rest of the machine is
+    /// clocked along with each of its M-cycles, so its memory accesses land when they would on
+    /// hardware. If it can't be run, PC is left on it and it isn't counted in `steps`, traced
+    /// or marked as executed. Once locked up, the clock runs on but nothing is executed.
+    pub fn step(&mut self) -> Result<u64, EmulatorError> {
+        let start = self.cycles();
+        let frame = self.mmu.frame();
+
+        if self.stopped && !self.mmu.buttons().is_empty() {
+            self.stopped = false;
+        }
+        if self.halted && !self.mmu.pending_interrupts().is_empty() {
+            self.halted = false;
+            // Waking up takes a cycle before the interrupt can be taken
+            if self.ime {
+                self.idle();
+            }
+        }
+
+        if self.locked || self.halted || self.stopped {
+            self.cycle();
+            self.stepped(frame);
+            return Ok(M_CYCLE);
+        }
+
+        // Taking an interrupt is a step of its own, so the handler can be stepped into
+        if self.service_interrupt() {
+            self.stepped(frame);
+            return Ok(self.cycles() - start);
+        }
+
+        // Only written once the instruction has run, so one that can't be leaves no trace
+        let trace_line = self.tracer.as_ref().and_then(|tracer| tracer.line(self));
+
+        // Taken out while it runs, as its callbacks get the whole CPU
+        if self.script_ran_at.take() != Some(self.pc) {
+            if let Some(mut script) = self.script.take() {
+                script.before_step(self);
+                self.script = Some(script);
+            }
+        }
+
+        // After the HALT bug, the opcode is read again as the byte after it
+        let halt_bug = std::mem::take(&mut self.halt_bug);
+        let inst = match halt_bug {
+            false => DecodedInstruction::decode(&self.mmu, self.pc as usize),
+            true => {
+                let pc = self.pc as usize;
+                DecodedInstruction::decode_with(|addr| self.mmu.rb((addr - 1).max(pc)), pc)
+            }
+        };
         log::debug!("executing {:02x}: {}", inst.opcode(), inst.action());
-        self.pc += inst.len() as u16;
-        self.execute(&inst);
+
+        // As it was before executing, so a CALL's own cycles go to the caller
+        let location = Location {
+            bank: self.mmu.bank_at(self.pc),
+            addr: self.pc,
+        };
+        let frames = self
+            .profiler
+            .is_some()
+            .then(|| self.call_stack.frames().to_vec());
+
+        let pc = self.pc;
+        let ei_delay = self.ei_delay;
+        self.pc = pc.wrapping_add(inst.len() as u16 - halt_bug as u16);
+        self.pending_fetch = inst.len() as u16;
+        if let Err(e) = self.execute(&inst, pc) {
+            self.pending_fetch = 0;
+            self.pc = pc;
+            self.halt_bug = halt_bug;
+            self.script_ran_at = Some(pc);
+            return Err(e);
+        }
+        self.fetched();
+        self.mmu.fetch(pc as usize, inst.len() as usize);
+        self.steps += 1;
+
+        if let (Some(tracer), Some(line)) = (&mut self.tracer, &trace_line) {
+            if !tracer.write(line) {
+                self.tracer = None;
+            }
+        }
+
+        // EI takes effect once the instruction after it is done, unless that was DI
+        if ei_delay && self.ei_delay {
+            self.ei_delay = false;
+            self.ime = true;
+        }
+
+        let cycles = self.cycles() - start;
+        if let (Some(profiler), Some(frames)) = (&mut self.profiler, &frames) {
+            profiler.record(location, frames, cycles);
+        }
+
+        self.stepped(frame);
+
+        Ok(cycles)
+    }
+
+    /// Lets the script know a step that began in `frame` is done
+    fn stepped(&mut self, frame: u64) {
+        if let Some(mut script) = self.script.take() {
+            let frame_ended = self.mmu.frame() != frame;
+            script.after_step(self, frame_ended);
+            self.script = Some(script);
+        }
     }
 }
 
+// What the rotate and shift instructions make of a value and the carry flag: the result, and
+// the bit shifted out for the new carry flag
+
+fn rlc(value: u8, _carry: bool) -> (u8, bool) {
+    (value.rotate_left(1), value & 0x80 != 0)
+}
+
+fn rrc(value: u8, _carry: bool) -> (u8, bool) {
+    (value.rotate_right(1), value & 0x01 != 0)
+}
+
+fn rl(value: u8, carry: bool) -> (u8, bool) {
+    (value << 1 | carry as u8, value & 0x80 != 0)
+}
+
+fn rr(value: u8, carry: bool) -> (u8, bool) {
+    (value >> 1 | (carry as u8) << 7, value & 0x01 != 0)
+}
+
+fn sla(value: u8, _carry: bool) -> (u8, bool) {
+    (value << 1, value & 0x80 != 0)
+}
+
+fn sra(value: u8, _carry: bool) -> (u8, bool) {
+    (value >> 1 | value & 0x80, value & 0x01 != 0)
+}
+
+fn swap(value: u8, _carry: bool) -> (u8, bool) {
+    (value.rotate_left(4), false)
+}
+
+fn srl(value: u8, _carry: bool) -> (u8, bool) {
+    (value >> 1, value & 0x01 != 0)
+}
+
 #[cfg(test)]
 mod test {
+    use std::{cell::RefCell, rc::Rc};
 
+    use super::Cpu;
     use super::Flags;
     use super::Registers;
+    use crate::{
+        asm,
+        coverage::{Access, AccessLog},
+        error::EmulatorError,
+        instructions::{DecodedInstruction, IAction, IFlag, IRegister},
+        io::{Buttons, Interrupts},
+        model::Model,
+        script::Script,
+        symbols::SymbolTable,
+        trace::{TraceFilter, Tracer},
+    };
+
+    /// Runs `source` up to a HALT added after it
+    fn run(source: &str) -> Cpu {
+        let mut cpu = Cpu::new(asm::assemble(&format!("{}\nhalt", source)).unwrap());
+        while !cpu.halted {
+            cpu.step().unwrap();
+        }
+        cpu
+    }
+
+    /// A and F after running `source`
+    fn a_and_f(source: &str) -> (u8, u8) {
+        let cpu = run(source);
+        (cpu.reg().a(), u8::from(cpu.reg().f()))
+    }
 
     #[test]
     fn flags_to_u8_individual() {
@@ -555,19 +1622,19 @@ mod test {
 
     #[test]
     fn u8_to_flags_individual() {
-        assert!(Flags::from(Flags::ZERO).contains(Flags::ZERO));
-        assert!(Flags::from(Flags::SUBTRACT).contains(Flags::SUBTRACT));
-        assert!(Flags::from(Flags::HALF_CARRY).contains(Flags::HALF_CARRY));
-        assert!(Flags::from(Flags::CARRY).contains(Flags::CARRY));
+        assert!(Flags::from(Flags::ZERO.bits).contains(Flags::ZERO));
+        assert!(Flags::from(Flags::SUBTRACT.bits).contains(Flags::SUBTRACT));
+        assert!(Flags::from(Flags::HALF_CARRY.bits).contains(Flags::HALF_CARRY));
+        assert!(Flags::from(Flags::CARRY.bits).contains(Flags::CARRY));
     }
 
     #[test]
     fn u8_to_flags_all() {
         let flags = Flags::ZERO | Flags::SUBTRACT | Flags::HALF_CARRY | Flags::CARRY;
-        assert!(Flags::from(flags).contains(Flags::ZERO));
-        assert!(Flags::from(flags).contains(Flags::SUBTRACT));
-        assert!(Flags::from(flags).contains(Flags::HALF_CARRY));
-        assert!(Flags::from(flags).contains(Flags::CARRY));
+        assert!(Flags::from(flags.bits).contains(Flags::ZERO));
+        assert!(Flags::from(flags.bits).contains(Flags::SUBTRACT));
+        assert!(Flags::from(flags.bits).contains(Flags::HALF_CARRY));
+        assert!(Flags::from(flags.bits).contains(Flags::CARRY));
     }
 
     #[test]
@@ -656,4 +1723,464 @@ mod test {
             assert!(reg.has_flag(flag));
         }
     }
+
+    #[test]
+    fn high_page_addressing() {
+        let mut rom = vec![0; 0x8000];
+        // LD A, $42; LDH [$80], A; LD C, $81; LD [C], A; XOR A; LD A, [C]
+        rom[0x100..0x10A]
+            .copy_from_slice(&[0x3E, 0x42, 0xE0, 0x80, 0x0E, 0x81, 0xE2, 0xAF, 0xF2, 0x00]);
+        let mut cpu = Cpu::new(rom);
+        for _ in 0..6 {
+            cpu.step().unwrap();
+        }
+
+        assert_eq!(cpu.mmu().rb(0xFF80), 0x42);
+        assert_eq!(cpu.mmu().rb(0xFF81), 0x42);
+        assert_eq!(cpu.reg().a(), 0x42);
+    }
+
+    #[test]
+    fn set_registers() {
+        let mut cpu = Cpu::new(vec![0; 0x8000]);
+
+        cpu.set_register(IRegister::AF, 0x12FF);
+        assert_eq!(cpu.reg().af(), 0x12F0);
+
+        cpu.set_f(0x5A);
+        assert_eq!(u8::from(cpu.reg().f()), 0x50);
+
+        cpu.set_register(IRegister::H, 0xAB);
+        cpu.set_register(IRegister::L, 0xCD);
+        assert_eq!(cpu.reg().hl(), 0xABCD);
+
+        cpu.set_register(IRegister::SP, 0xC100);
+        assert_eq!(cpu.sp(), 0xC100);
+
+        cpu.set_flag(Flags::ZERO, false);
+        assert!(!cpu.reg().has_flag(Flags::ZERO));
+    }
+
+    #[test]
+    fn calls_and_returns() {
+        let rom = asm::assemble(
+            "
+                call Sub
+                rst $38
+            Sub:
+                push bc
+                pop af
+                ret
+            SECTION \"rst\", ROM0[$0038]
+                reti
+            ",
+        )
+        .unwrap();
+        let mut cpu = Cpu::new(rom);
+        cpu.set_register(IRegister::BC, 0x12FF);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc(), 0x0104);
+        assert_eq!(cpu.sp(), 0xFFFC);
+        assert_eq!(cpu.call_stack().frames().len(), 1);
+        assert_eq!(cpu.call_stack().frames()[0].caller, 0x0100);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg().af(), 0x12F0);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc(), 0x0103);
+        assert!(cpu.call_stack().frames().is_empty());
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc(), 0x0038);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc(), 0x0104);
+        assert!(cpu.ime());
+        assert_eq!(cpu.call_stack().anomalies().len(), 1);
+    }
+
+    #[test]
+    fn speed_switch() {
+        let rom = asm::assemble(
+            "
+                ld a, 1
+                ldh [$FF4D], a
+                stop
+            ",
+        )
+        .unwrap();
+
+        let mut cpu = Cpu::with_model(rom.clone(), Model::Cgb);
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert!(cpu.mmu().double_speed());
+        assert_eq!(cpu.mmu().rb(0xFF4D), 0xFE);
+
+        // Nothing to switch to before the CGB, so it stops until a button is pressed
+        let mut cpu = Cpu::with_model(rom, Model::Dmg);
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.mmu().rb(0xFF04), 0x00);
+        assert_eq!(cpu.step(), Ok(4));
+        assert_eq!(cpu.pc(), 0x0106);
+        assert_eq!(cpu.steps(), 3);
+
+        cpu.mmu_mut().set_buttons(Buttons::START);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc(), 0x0107);
+        assert!(!cpu.mmu().double_speed());
+    }
+
+    #[test]
+    fn oam_corruption() {
+        // INC lands in the fifth M-cycle of OAM scan, when the PPU's reading the fifth row
+        let rom = asm::assemble("ld hl, $FE20\ninc hl").unwrap();
+        let rows = [
+            0x78, 0x56, 0x01, 0x02, 0xBC, 0x9A, 0x03, 0x04, 0x34, 0x12, 0xAA, 0xBB, 0xCC, 0xDD,
+            0xEE, 0xFF,
+        ];
+        let oam = |model| {
+            let mut cpu = Cpu::with_model(rom.clone(), model);
+            for (i, &byte) in rows.iter().enumerate() {
+                cpu.mmu_mut().wb(0xFE18 + i, byte);
+            }
+            cpu.step().unwrap();
+            cpu.step().unwrap();
+            cpu.mmu().oam()[0x20..0x28].to_vec()
+        };
+
+        assert_eq!(
+            oam(Model::Dmg),
+            [0x3C, 0x12, 0x01, 0x02, 0xBC, 0x9A, 0x03, 0x04]
+        );
+        assert_eq!(oam(Model::Cgb), rows[8..]);
+    }
+
+    #[test]
+    fn interrupts() {
+        let rom = asm::assemble(
+            "
+                ld a, $04
+                ld [$FFFF], a
+                ld [$FF0F], a
+                ei
+                nop
+                nop
+            SECTION \"timer\", ROM0[$0050]
+                reti
+            ",
+        )
+        .unwrap();
+        let mut cpu = Cpu::new(rom);
+
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert!(!cpu.ime());
+
+        // IME is only set after the instruction following EI
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc(), 0x010A);
+        assert!(cpu.ime());
+
+        assert_eq!(cpu.step(), Ok(20));
+        assert_eq!(cpu.pc(), 0x0050);
+        assert!(!cpu.ime());
+        assert_eq!(cpu.mmu().rb(0xFF0F), 0xE0);
+        assert_eq!(cpu.steps(), 5);
+
+        let frame = cpu.call_stack().frames()[0];
+        assert!(frame.interrupt);
+        assert_eq!((frame.caller, frame.target), (0x010A, 0x0050));
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc(), 0x010A);
+        assert!(cpu.ime());
+        assert!(cpu.call_stack().frames().is_empty());
+        assert!(cpu.call_stack().anomalies().is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100] = 0xD3;
+        let mut cpu = Cpu::new(rom);
+        cpu.set_break_on_illegal(true);
+        assert_eq!(
+            cpu.step(),
+            Err(EmulatorError::IllegalOpcode {
+                pc: 0x0100,
+                opcode: 0xD3
+            })
+        );
+        assert!(!cpu.locked());
+        assert_eq!(cpu.pc(), 0x0100);
+        assert_eq!(cpu.cycles(), 0);
+    }
+
+    #[test]
+    fn errors_leave_no_trace() {
+        struct Shared(Rc<RefCell<Vec<u8>>>);
+        impl std::io::Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut cpu = Cpu::new(asm::assemble("db $D3").unwrap());
+        let out = Rc::new(RefCell::new(Vec::new()));
+        cpu.set_tracer(Some(Tracer::new(
+            Box::new(Shared(out.clone())),
+            TraceFilter::default(),
+        )));
+        cpu.mmu_mut().set_access_log(Some(AccessLog::new(0x8000)));
+        let script = Script::new(
+            "on_exec(0x0100, |addr| print(`exec ${addr}`));",
+            Rc::new(SymbolTable::new()),
+            &mut cpu,
+        )
+        .unwrap();
+        cpu.set_script(Some(script));
+        cpu.set_break_on_illegal(true);
+
+        assert!(cpu.step().is_err());
+        assert!(cpu.step().is_err());
+        assert!(out.borrow().is_empty());
+        let executed = |cpu: &Cpu| {
+            cpu.mmu()
+                .access_log()
+                .unwrap()
+                .count(Access::Execute, 0x0100)
+        };
+        assert_eq!(executed(&cpu), 0);
+
+        cpu.set_break_on_illegal(false);
+        assert_eq!(cpu.step(), Ok(4));
+        assert_eq!(out.borrow().iter().filter(|&&b| b == b'\n').count(), 1);
+        assert_eq!(executed(&cpu), 1);
+        assert_eq!(cpu.script_mut().unwrap().take_output(), vec!["exec 256"]);
+    }
+
+    #[test]
+    fn illegal_opcodes_lock_up() {
+        let rom = asm::assemble("db $D3\nld a, 5").unwrap();
+        let mut cpu = Cpu::new(rom);
+
+        assert_eq!(cpu.step(), Ok(4));
+        assert!(cpu.locked());
+        assert_eq!(cpu.pc(), 0x0100);
+
+        for _ in 0..10 {
+            assert_eq!(cpu.step(), Ok(4));
+        }
+        assert_eq!(cpu.pc(), 0x0100);
+        assert_ne!(cpu.reg().a(), 0x05);
+        assert_eq!(cpu.steps(), 1);
+        assert_eq!(cpu.cycles(), 44);
+    }
+
+    #[test]
+    fn branch_timing() {
+        let rom = asm::assemble(
+            "
+                xor a
+                jp nz, $0000
+                jp z, Taken
+            Taken:
+                jr nz, Taken
+                jr z, .next
+            .next:
+                call Sub
+                push bc
+                inc bc
+                ld sp, hl
+                jp hl
+            Sub:
+                ret nz
+                ret z
+            ",
+        )
+        .unwrap();
+        let mut cpu = Cpu::new(rom);
+        cpu.set_register(IRegister::HL, 0x0000);
+
+        let cycles: Vec<u64> = (0..12).map(|_| cpu.step().unwrap()).collect();
+        assert_eq!(cycles, [4, 12, 16, 8, 12, 24, 8, 20, 16, 8, 8, 4]);
+        assert_eq!(cpu.cycles(), cycles.iter().sum());
+        assert_eq!(cpu.pc(), 0x0000);
+    }
+
+    #[test]
+    fn accesses_land_mid_instruction() {
+        // The read is in the LDH's third M-cycle, 256 cycles after DIV was reset
+        let rom = asm::assemble(&format!("{}ldh a, [$FF04]", "nop\n".repeat(61))).unwrap();
+        let mut cpu = Cpu::new(rom);
+        cpu.mmu_mut().wb(0xFF04, 0);
+
+        for _ in 0..62 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.reg().a(), 0x01);
+        assert_eq!(cpu.mmu().rb(0xFF04), 0x01);
+    }
+
+    #[test]
+    fn arithmetic() {
+        // Half-carry and carry out of bits 3 and 7, with ADC and SBC taking the carry in
+        assert_eq!(a_and_f("ld a, $3A\nld b, $C6\nadd a, b"), (0x00, 0xB0));
+        assert_eq!(a_and_f("scf\nld a, $0F\nadc a, $00"), (0x10, 0x20));
+        assert_eq!(a_and_f("ld a, $3E\nsub a, $3E"), (0x00, 0xC0));
+        assert_eq!(a_and_f("ld a, $10\nsub a, $01"), (0x0F, 0x60));
+        assert_eq!(a_and_f("scf\nld a, $00\nsbc a, $00"), (0xFF, 0x70));
+        assert_eq!(a_and_f("ld a, $3C\ncp a, $40"), (0x3C, 0x50));
+        assert_eq!(a_and_f("ld a, $5A\nand a, $0F"), (0x0A, 0x20));
+        assert_eq!(a_and_f("scf\nld a, $00\nor a, $00"), (0x00, 0x80));
+        assert_eq!(a_and_f("ld a, $FF\nxor a, $0F"), (0xF0, 0x00));
+
+        // INC and DEC carry across the low nibble rather than the byte, and leave C alone
+        assert_eq!(a_and_f("scf\nld a, $0F\ninc a"), (0x10, 0x30));
+        assert_eq!(a_and_f("ld a, $FE\ninc a"), (0xFF, 0x00));
+        assert_eq!(a_and_f("ld a, $10\ndec a"), (0x0F, 0x60));
+        assert_eq!(a_and_f("ld a, $01\ndec a"), (0x00, 0xC0));
+
+        assert_eq!(a_and_f("ld a, $45\nadd a, $38\ndaa"), (0x83, 0x00));
+        assert_eq!(a_and_f("ld a, $83\nsub a, $38\ndaa"), (0x45, 0x40));
+        assert_eq!(a_and_f("ld a, $99\nadd a, $01\ndaa"), (0x00, 0x90));
+
+        // CCF and SCF clear N and H, CPL sets them
+        assert_eq!(a_and_f("ld a, $0F\nadd a, $01\nccf"), (0x10, 0x10));
+        assert_eq!(a_and_f("ld a, $10\nsub a, $01\nscf"), (0x0F, 0x10));
+        assert_eq!(a_and_f("xor a\ncpl"), (0xFF, 0xE0));
+    }
+
+    #[test]
+    fn sixteen_bit_arithmetic() {
+        // ADD HL carries out of bits 11 and 15 and leaves Z alone
+        let cpu = run("xor a\nld hl, $8FFF\nld bc, $8001\nadd hl, bc");
+        assert_eq!(cpu.reg().hl(), 0x1000);
+        assert_eq!(u8::from(cpu.reg().f()), 0xB0);
+
+        let cpu = run("ld h, $12\nld l, $34\nadd hl, hl");
+        assert_eq!(cpu.reg().hl(), 0x2468);
+
+        // ADD SP, e and LD HL, SP+e take their flags from the low byte, unsigned
+        let cpu = run("xor a\nld sp, $FFF8\nadd sp, 8");
+        assert_eq!(cpu.sp(), 0x0000);
+        assert_eq!(u8::from(cpu.reg().f()), 0x30);
+
+        let cpu = run("ld sp, $0100\nld hl, sp-1");
+        assert_eq!(cpu.reg().hl(), 0x00FF);
+        assert_eq!(cpu.sp(), 0x0100);
+        assert_eq!(u8::from(cpu.reg().f()), 0x00);
+    }
+
+    #[test]
+    fn rotates_shifts_and_bits() {
+        // The accumulator rotates always clear Z, the prefixed ones set it from the result
+        assert_eq!(a_and_f("ld a, $80\nrlca"), (0x01, 0x10));
+        assert_eq!(a_and_f("xor a\nrlca"), (0x00, 0x00));
+        assert_eq!(a_and_f("xor a\nrlc a"), (0x00, 0x80));
+        assert_eq!(a_and_f("scf\nld a, $01\nrra"), (0x80, 0x10));
+        assert_eq!(a_and_f("scf\nld a, $80\nrla"), (0x01, 0x10));
+        assert_eq!(a_and_f("ld a, $01\nrrca"), (0x80, 0x10));
+
+        let cpu = run("ld b, $81\nsra b\nld c, $F1\nswap c\nld d, $81\nsla d\nld e, $01\nrr e");
+        assert_eq!(cpu.reg().b(), 0xC0);
+        assert_eq!(cpu.reg().c(), 0x1F);
+        assert_eq!(cpu.reg().d(), 0x02);
+        assert_eq!(cpu.reg().e(), 0x80);
+        assert_eq!(u8::from(cpu.reg().f()), 0x10);
+
+        let cpu = run("ld hl, $C000\nld [hl], $01\nsrl [hl]");
+        assert_eq!(cpu.mmu().rb(0xC000), 0x00);
+        assert_eq!(u8::from(cpu.reg().f()), 0x90);
+
+        // BIT leaves C alone, RES and SET leave all the flags alone
+        assert_eq!(a_and_f("ld a, $80\nscf\nbit 7, a"), (0x80, 0x30));
+        assert_eq!(a_and_f("ld a, $80\nbit 6, a"), (0x80, 0xA0));
+
+        let cpu = run("ld hl, $C000\nld [hl], $FF\nres 3, [hl]\nld b, 0\nset 0, b");
+        assert_eq!(cpu.mmu().rb(0xC000), 0xF7);
+        assert_eq!(cpu.reg().b(), 0x01);
+    }
+
+    #[test]
+    fn halt() {
+        // With IME off, a request wakes it up to carry on after HALT
+        let rom = asm::assemble("ld a, $04\nld [$FFFF], a\nhalt\nld b, 1").unwrap();
+        let mut cpu = Cpu::new(rom);
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.step(), Ok(4));
+        assert_eq!(cpu.pc(), 0x0106);
+
+        cpu.mmu_mut().request_interrupt(Interrupts::TIMER);
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg().b(), 0x01);
+
+        // With IME on it's taken, a cycle later than it would be outside of HALT
+        let rom = asm::assemble(
+            "
+                ld a, $04
+                ld [$FFFF], a
+                ei
+                halt
+            SECTION \"timer\", ROM0[$0050]
+                reti
+            ",
+        )
+        .unwrap();
+        let mut cpu = Cpu::new(rom);
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        cpu.mmu_mut().request_interrupt(Interrupts::TIMER);
+        assert_eq!(cpu.step(), Ok(24));
+        assert_eq!(cpu.pc(), 0x0050);
+
+        // With IME off and one already pending it doesn't halt, and reads INC A twice
+        let cpu =
+            run("ld a, $04\nld [$FFFF], a\nld [$FF0F], a\nhalt\ninc a\nld hl, $FFFF\nld [hl], 0");
+        assert_eq!(cpu.reg().a(), 0x06);
+    }
+
+    #[test]
+    fn instruction_timing() {
+        for bytes in (0..=0xFF).flat_map(|opcode| [[opcode, 0x00, 0xC0], [0xCB, opcode, 0x00]]) {
+            let mut rom = vec![0; 0x8000];
+            rom[0x0100..0x0103].copy_from_slice(&bytes);
+            let mut cpu = Cpu::new(rom);
+
+            let inst = DecodedInstruction::decode(cpu.mmu(), 0x0100);
+            let flag = match inst.action() {
+                IAction::JP(flag, _)
+                | IAction::JR(flag, _)
+                | IAction::CALL(flag, _)
+                | IAction::RET(flag) => flag,
+                // STOP's time depends on what it does, and illegal opcodes take an M-cycle
+                IAction::STOP | IAction::ILLEGAL => continue,
+                _ => IFlag::TRUE,
+            };
+
+            // The table has the time for a branch that's taken
+            let taken = matches!(flag, IFlag::Z | IFlag::CY);
+            cpu.set_f(if taken { 0xF0 } else { 0x00 });
+
+            assert_eq!(
+                cpu.step(),
+                Ok(inst.cycles() as u64),
+                "{:02x?}: {}",
+                inst.raw_bytes(),
+                inst.action()
+            );
+        }
+    }
 }
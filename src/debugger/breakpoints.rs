@@ -0,0 +1,154 @@
+use std::{cell::RefCell, collections::BTreeMap, fmt, rc::Rc};
+
+use yeahboy::{
+    coverage::Access,
+    cpu::Cpu,
+    expr::Expr,
+    mmu::Watchpoint,
+    script::Script,
+    symbols::{SymbolTable, Target},
+    EmulatorError,
+};
+
+/// Breakpoints shared by the widgets that run the CPU
+pub type SharedBreakpoints = Rc<RefCell<Breakpoints>>;
+
+#[derive(Default)]
+pub struct Breakpoints {
+    /// Places to stop, each with an optional condition and the text it was parsed from. Those
+    /// with a bank only stop the CPU while that bank is mapped.
+    entries: BTreeMap<Target, Option<(String, Expr)>>,
+}
+
+impl Breakpoints {
+    /// Adds a breakpoint, replacing any condition already at `target`
+    pub fn insert(&mut self, target: Target, condition: Option<&str>) -> Result<(), String> {
+        let condition = match condition {
+            Some(text) => Some((text.trim().to_string(), Expr::parse(text)?)),
+            None => None,
+        };
+        self.entries.insert(target, condition);
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, target: Target) -> bool {
+        self.entries.remove(&target).is_some()
+    }
+
+    /// Every breakpoint and its condition, in address order
+    pub fn iter(&self) -> impl Iterator<Item = (Target, Option<&str>)> {
+        self.entries
+            .iter()
+            .map(|(&target, condition)| (target, condition.as_ref().map(|(text, _)| text.as_str())))
+    }
+
+    /// Whether a breakpoint stops the CPU where it is now. A condition that can't be evaluated
+    /// stops it too, so the mistake doesn't go unnoticed.
+    fn hit(&self, cpu: &Cpu, symbols: &SymbolTable) -> bool {
+        let pc = cpu.pc();
+        let bank = cpu.mmu().bank_at(pc);
+
+        self.entries
+            .range(
+                Target {
+                    addr: pc,
+                    bank: None,
+                }..,
+            )
+            .take_while(|(target, _)| target.addr == pc)
+            .filter(|(target, _)| target.matches(bank, pc))
+            .any(|(_, condition)| match condition {
+                Some((_, condition)) => condition.eval(cpu, symbols) != Ok(0),
+                None => true,
+            })
+    }
+}
+
+/// Why `run` returned
+pub enum Stop {
+    Breakpoint(u16),
+    Watchpoint(Watchpoint, u16),
+    /// The `until` condition held
+    Condition,
+    /// Ran all the steps asked for
+    Steps,
+    /// The script called `stop()`
+    Script,
+    /// The CPU couldn't run an instruction
+    Fault(EmulatorError),
+    /// An illegal opcode locked up the CPU, so nothing more will happen
+    Locked,
+    Error(String),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Breakpoint(addr) => write!(f, "breakpoint at {:04x}", addr),
+            Stop::Watchpoint(watchpoint, addr) => {
+                let access = match watchpoint.access {
+                    Access::Read => "read",
+                    _ => "write",
+                };
+                write!(f, "watchpoint: {} of {:04x}", access, addr)
+            }
+            Stop::Condition => write!(f, "condition met"),
+            Stop::Steps => write!(f, "stepped"),
+            Stop::Script => write!(f, "stopped by script"),
+            Stop::Fault(e) => write!(f, "{}", e),
+            Stop::Locked => write!(f, "locked up by an illegal opcode"),
+            Stop::Error(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Steps the CPU at least once, until it hits a breakpoint or watchpoint, `until` holds,
+/// `max_steps` have run or it locks up
+pub fn run(
+    cpu: &mut Cpu,
+    breakpoints: &Breakpoints,
+    symbols: &SymbolTable,
+    until: Option<&Expr>,
+    max_steps: Option<u64>,
+) -> Stop {
+    // Whatever was hit before we started has already been reported
+    cpu.mmu_mut().take_watch_hit();
+
+    let mut steps = 0;
+
+    loop {
+        if let Err(e) = cpu.step() {
+            return Stop::Fault(e);
+        }
+        steps += 1;
+
+        if cpu.locked() {
+            return Stop::Locked;
+        }
+
+        if let Some((watchpoint, addr)) = cpu.mmu_mut().take_watch_hit() {
+            return Stop::Watchpoint(watchpoint, addr);
+        }
+
+        if cpu.script_mut().is_some_and(Script::take_stop) {
+            return Stop::Script;
+        }
+
+        if let Some(until) = until {
+            match until.eval(cpu, symbols) {
+                Ok(0) => (),
+                Ok(_) => return Stop::Condition,
+                Err(e) => return Stop::Error(e),
+            }
+        }
+
+        if breakpoints.hit(cpu, symbols) {
+            return Stop::Breakpoint(cpu.pc());
+        }
+
+        if Some(steps) == max_steps {
+            return Stop::Steps;
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::rc::Rc;
+
+use crate::debugger::{DebuggerWidget, Navigation};
+use yeahboy::{cpu::Cpu, symbols::SymbolTable};
+
+pub struct CallStackWidget {
+    symbols: Rc<SymbolTable>,
+    navigation: Navigation,
+}
+
+impl CallStackWidget {
+    pub fn new(symbols: Rc<SymbolTable>, navigation: Navigation) -> Self {
+        Self {
+            symbols,
+            navigation,
+        }
+    }
+
+    fn name(&self, addr: u16, bank: u16) -> String {
+        match self.symbols.label(addr, bank) {
+            Some(label) => format!("{:04x} ({})", addr, label),
+            None => format!("{:04x}", addr),
+        }
+    }
+}
+
+impl DebuggerWidget for CallStackWidget {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+        let call_stack = cpu.call_stack();
+        let rom_bank = cpu.mmu().rom_bank();
+
+        egui::Window::new("Call Stack").show(egui.ctx(), |ui| {
+            egui::Grid::new("call_stack")
+                .num_columns(4)
+                .spacing([20.0, 4.0])
+                .show(ui, |ui| {
+                    for heading in ["Target", "Caller", "SP", "Bank"] {
+                        ui.label(heading);
+                    }
+                    ui.end_row();
+
+                    // Innermost first
+                    for frame in call_stack.frames().iter().rev() {
+                        let target = egui::Label::new(self.name(frame.target, frame.bank))
+                            .monospace()
+                            .text_color(match frame.interrupt {
+                                true => egui::Color32::LIGHT_RED,
+                                false => egui::Color32::LIGHT_BLUE,
+                            })
+                            .sense(egui::Sense::click());
+                        if ui.add(target).clicked() {
+                            self.navigation.set(Some(frame.target));
+                        }
+
+                        let caller = egui::Label::new(self.name(frame.caller, rom_bank))
+                            .monospace()
+                            .sense(egui::Sense::click());
+                        if ui.add(caller).clicked() {
+                            self.navigation.set(Some(frame.caller));
+                        }
+
+                        ui.add(egui::Label::new(format!("{:04x}", frame.sp)).monospace());
+                        ui.add(egui::Label::new(frame.bank.to_string()).monospace());
+                        ui.end_row();
+                    }
+                });
+
+            if !call_stack.anomalies().is_empty() {
+                ui.separator();
+                ui.label("Unbalanced returns");
+
+                for anomaly in call_stack.anomalies().iter().rev() {
+                    let label =
+                        egui::Label::new(format!("{:04x}: {}", anomaly.pc, anomaly.message))
+                            .monospace()
+                            .text_color(egui::Color32::YELLOW)
+                            .sense(egui::Sense::click());
+                    if ui.add(label).clicked() {
+                        self.navigation.set(Some(anomaly.pc));
+                    }
+                }
+            }
+        });
+    }
+}
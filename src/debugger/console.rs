@@ -0,0 +1,500 @@
+use std::rc::Rc;
+
+use crate::debugger::{
+    breakpoints::{self, SharedBreakpoints},
+    DebuggerWidget,
+};
+use yeahboy::{
+    coverage::Access,
+    cpu::Cpu,
+    expr::{self, Expr, Register},
+    mmu::Watchpoint,
+    symbols::{SymbolTable, Target},
+};
+
+/// Most output lines to keep
+const MAX_OUTPUT: usize = 1000;
+
+/// Bytes per line of `x` output
+const DUMP_WIDTH: usize = 16;
+
+const COMMANDS: [&str; 11] = [
+    "b", "continue", "d", "dw", "help", "print", "set", "step", "until", "w", "x",
+];
+
+const HELP: &str = "\
+b [ADDR [if COND]]          list or add breakpoints
+d ADDR                      delete a breakpoint
+w [START..END] read|write   list or add watchpoints, [ADDR] watches one byte
+dw [START..END] read|write  delete a watchpoint
+x[/COUNT] ADDR              dump COUNT bytes, 16 by default
+set REG=VALUE, set [ADDR]=VALUE
+step [COUNT], continue, until COND
+print EXPR
+Expressions take registers, symbols, [addr] for memory, arithmetic and comparisons.
+Numbers are decimal unless written as 0x3F or $3F.";
+
+pub struct ConsoleWidget {
+    symbols: Rc<SymbolTable>,
+    breakpoints: SharedBreakpoints,
+
+    input: String,
+    output: Vec<String>,
+
+    history: Vec<String>,
+    /// Position in `history` while browsing it with the arrow keys
+    history_pos: Option<usize>,
+
+    /// Changing the input's ID resets its cursor to the end, which egui doesn't let us set
+    input_generation: u64,
+}
+
+impl ConsoleWidget {
+    pub fn new(symbols: Rc<SymbolTable>, breakpoints: SharedBreakpoints) -> Self {
+        Self {
+            symbols,
+            breakpoints,
+            input: String::new(),
+            output: Vec::new(),
+            history: Vec::new(),
+            history_pos: None,
+            input_generation: 0,
+        }
+    }
+
+    fn print(&mut self, line: String) {
+        if self.output.len() == MAX_OUTPUT {
+            self.output.remove(0);
+        }
+        self.output.push(line);
+    }
+
+    fn eval(&self, cpu: &Cpu, text: &str) -> Result<i64, String> {
+        Expr::parse(text)?.eval(cpu, &self.symbols)
+    }
+
+    fn eval_address(&self, cpu: &Cpu, text: &str) -> Result<u16, String> {
+        expr::address(self.eval(cpu, text)?)
+    }
+
+    /// A breakpoint location: a label keeps its bank, anything else is evaluated as an address
+    fn eval_target(&self, cpu: &Cpu, text: &str) -> Result<Target, String> {
+        if let Some((bank, addr)) = self.symbols.address(text.trim()) {
+            return Ok(Target {
+                addr,
+                bank: Some(bank),
+            });
+        }
+
+        Ok(Target {
+            addr: self.eval_address(cpu, text)?,
+            bank: None,
+        })
+    }
+
+    /// Runs one command line and prints what it has to say
+    fn execute(&mut self, cpu: &mut Cpu, line: &str) {
+        self.print(format!("> {}", line));
+
+        if let Err(e) = self.command(cpu, line) {
+            self.print(format!("error: {}", e));
+        }
+    }
+
+    fn command(&mut self, cpu: &mut Cpu, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        let (command, args) = match line.split_once(char::is_whitespace) {
+            Some((command, args)) => (command, args.trim()),
+            None => (line, ""),
+        };
+        // `x/16` carries its count in the command
+        let (command, count) = match command.split_once('/') {
+            Some((command, count)) => (command, Some(count)),
+            None => (command, None),
+        };
+
+        match command {
+            "" => (),
+            "help" | "h" => {
+                for line in HELP.lines() {
+                    self.print(line.to_string());
+                }
+            }
+            "b" | "break" if args.is_empty() => {
+                let lines: Vec<String> = self
+                    .breakpoints
+                    .borrow()
+                    .iter()
+                    .map(|(target, condition)| match condition {
+                        Some(condition) => format!("{} if {}", target, condition),
+                        None => target.to_string(),
+                    })
+                    .collect();
+                self.print_all(lines, "no breakpoints");
+            }
+            "b" | "break" => {
+                let (location, condition) = match args.split_once(" if ") {
+                    Some((location, condition)) => (location, Some(condition)),
+                    None => (args, None),
+                };
+                let target = self.eval_target(cpu, location)?;
+                self.breakpoints.borrow_mut().insert(target, condition)?;
+                self.print(format!("breakpoint at {}", target));
+            }
+            "d" | "delete" => {
+                let target = self.eval_target(cpu, args)?;
+                let removed = self.breakpoints.borrow_mut().remove(target);
+                match removed {
+                    true => self.print(format!("deleted breakpoint at {}", target)),
+                    false => return Err(format!("no breakpoint at {}", target)),
+                }
+            }
+            "w" | "watch" if args.is_empty() => {
+                let lines: Vec<String> = cpu
+                    .mmu()
+                    .watchpoints()
+                    .iter()
+                    .map(|w| describe_watchpoint(*w))
+                    .collect();
+                self.print_all(lines, "no watchpoints");
+            }
+            "w" | "watch" => {
+                let watchpoint = self.watchpoint(cpu, args)?;
+                cpu.mmu_mut().add_watchpoint(watchpoint);
+                self.print(format!("watching {}", describe_watchpoint(watchpoint)));
+            }
+            "dw" => {
+                let watchpoint = self.watchpoint(cpu, args)?;
+                cpu.mmu_mut().remove_watchpoint(watchpoint);
+                self.print(format!("deleted {}", describe_watchpoint(watchpoint)));
+            }
+            "x" => {
+                let count = match count {
+                    Some(count) => count
+                        .parse::<usize>()
+                        .map_err(|_| format!("invalid count {}", count))?,
+                    None => DUMP_WIDTH,
+                };
+                let start = self.eval_address(cpu, args)? as usize;
+                let end = (start + count).min(0x10000);
+
+                for line_start in (start..end).step_by(DUMP_WIDTH) {
+                    let bytes: Vec<String> = (line_start..(line_start + DUMP_WIDTH).min(end))
+                        .map(|addr| format!("{:02x}", cpu.mmu().rb(addr)))
+                        .collect();
+                    self.print(format!("{:04x}: {}", line_start, bytes.join(" ")));
+                }
+            }
+            "set" => {
+                let (target, value) = args
+                    .split_once('=')
+                    .ok_or_else(|| "expected set TARGET=VALUE".to_string())?;
+                let (target, value) = (target.trim(), self.eval(cpu, value)?);
+
+                match Expr::parse(target)? {
+                    Expr::Register(reg) => reg.write(cpu, value as u16),
+                    Expr::Deref(addr) => {
+                        let addr = expr::address(addr.eval(cpu, &self.symbols)?)?;
+                        cpu.mmu_mut().wb(addr as usize, value as u8);
+                    }
+                    _ => return Err(format!("can't assign to {}", target)),
+                }
+            }
+            "step" | "s" => {
+                let steps = match args {
+                    "" => 1,
+                    _ => match self.eval(cpu, args)? {
+                        steps if steps > 0 => steps as u64,
+                        steps => return Err(format!("can't step {} times", steps)),
+                    },
+                };
+                self.run(cpu, None, Some(steps));
+            }
+            "continue" | "c" => self.run(cpu, None, None),
+            "until" => {
+                let condition = Expr::parse(args)?;
+                self.run(cpu, Some(&condition), None);
+            }
+            "print" | "p" => {
+                let value = self.eval(cpu, args)?;
+                self.print(format!("{} = ${:x} ({})", args, value, value));
+            }
+            _ => return Err(format!("unknown command {}, try help", command)),
+        }
+
+        Ok(())
+    }
+
+    fn print_all(&mut self, lines: Vec<String>, empty: &str) {
+        if lines.is_empty() {
+            self.print(empty.to_string());
+        }
+        for line in lines {
+            self.print(line);
+        }
+    }
+
+    /// Parses `[START..END] read|write` or `[ADDR] read|write`
+    fn watchpoint(&self, cpu: &Cpu, args: &str) -> Result<Watchpoint, String> {
+        let (range, access) = args
+            .rsplit_once(char::is_whitespace)
+            .ok_or_else(|| "expected [START..END] read|write".to_string())?;
+        let access = match access {
+            "read" | "r" => Access::Read,
+            "write" | "w" => Access::Write,
+            _ => return Err(format!("expected read or write, not {}", access)),
+        };
+
+        let range = range.trim();
+        let range = range
+            .strip_prefix('[')
+            .and_then(|range| range.strip_suffix(']'))
+            .ok_or_else(|| format!("expected [START..END], not {}", range))?;
+
+        let (addr, len) = match range.split_once("..") {
+            Some((start, end)) => {
+                let start = self.eval_address(cpu, start)?;
+                let end = self.eval_address(cpu, end)?;
+                if end <= start {
+                    return Err("empty range".to_string());
+                }
+                (start, end - start)
+            }
+            None => (self.eval_address(cpu, range)?, 1),
+        };
+
+        Ok(Watchpoint { addr, len, access })
+    }
+
+    /// Runs the CPU and says why it stopped
+    fn run(&mut self, cpu: &mut Cpu, until: Option<&Expr>, steps: Option<u64>) {
+        let stop = breakpoints::run(cpu, &self.breakpoints.borrow(), &self.symbols, until, steps);
+        self.print(format!("{}, pc = {:04x}", stop, cpu.pc()));
+    }
+
+    /// Completes the word being typed from the commands, registers and symbols
+    fn complete(&mut self) {
+        let start = self
+            .input
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .map_or(0, |i| i + 1);
+        let prefix = &self.input[start..];
+        if prefix.is_empty() {
+            return;
+        }
+
+        let mut candidates: Vec<String> = match start {
+            0 => COMMANDS.iter().map(|command| command.to_string()).collect(),
+            _ => Register::ALL
+                .iter()
+                .map(|reg| reg.name())
+                .chain(self.symbols.names())
+                .map(|name| name.to_string())
+                .collect(),
+        };
+        candidates.retain(|candidate| candidate.starts_with(prefix));
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let common = match candidates.split_first() {
+            Some((first, rest)) => rest.iter().fold(first.clone(), |common, candidate| {
+                common
+                    .chars()
+                    .zip(candidate.chars())
+                    .take_while(|(a, b)| a == b)
+                    .map(|(a, _)| a)
+                    .collect()
+            }),
+            None => return,
+        };
+
+        if candidates.len() > 1 {
+            let line = candidates.join("  ");
+            self.print(line);
+        }
+
+        let mut completed = format!("{}{}", &self.input[..start], common);
+        if candidates.len() == 1 {
+            completed.push(' ');
+        }
+        self.input = completed;
+    }
+
+    /// Moves through the history, `back` being towards older commands
+    fn browse_history(&mut self, back: bool) {
+        let pos = match (self.history_pos, back) {
+            (None, true) => self.history.len().checked_sub(1),
+            (None, false) => None,
+            (Some(pos), true) => Some(pos.saturating_sub(1)),
+            (Some(pos), false) if pos + 1 < self.history.len() => Some(pos + 1),
+            (Some(_), false) => None,
+        };
+
+        self.history_pos = pos;
+        self.input = match pos {
+            Some(pos) => self.history[pos].clone(),
+            None => String::new(),
+        };
+    }
+}
+
+fn describe_watchpoint(watchpoint: Watchpoint) -> String {
+    let access = match watchpoint.access {
+        Access::Read => "read",
+        _ => "write",
+    };
+
+    match watchpoint.len {
+        1 => format!("[{:04x}] {}", watchpoint.addr, access),
+        len => format!(
+            "[{:04x}..{:04x}] {}",
+            watchpoint.addr,
+            watchpoint.addr as u32 + len as u32,
+            access
+        ),
+    }
+}
+
+impl DebuggerWidget for ConsoleWidget {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+        egui::Window::new("Console").show(egui.ctx(), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .stick_to_bottom()
+                .show(ui, |ui| {
+                    for line in self.output.iter() {
+                        ui.add(egui::Label::new(line).monospace());
+                    }
+                });
+
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.input)
+                    .id(egui::Id::new(("console", self.input_generation)))
+                    .desired_width(f32::INFINITY)
+                    .text_style(egui::TextStyle::Monospace)
+                    .lock_focus(true),
+            );
+
+            let (enter, tab, up, down) = {
+                let input = ui.input();
+                (
+                    input.key_pressed(egui::Key::Enter),
+                    input.key_pressed(egui::Key::Tab),
+                    input.key_pressed(egui::Key::ArrowUp),
+                    input.key_pressed(egui::Key::ArrowDown),
+                )
+            };
+
+            if response.lost_focus() && enter {
+                let line = std::mem::take(&mut self.input);
+                if !line.trim().is_empty() && self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                }
+                self.history_pos = None;
+                self.execute(cpu, &line);
+                response.request_focus();
+            } else if response.has_focus() && (tab || up || down) {
+                match tab {
+                    true => self.complete(),
+                    false => self.browse_history(up),
+                }
+                self.input_generation += 1;
+                ui.memory()
+                    .request_focus(egui::Id::new(("console", self.input_generation)));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::ConsoleWidget;
+    use yeahboy::{asm, cpu::Cpu, symbols::SymbolTable};
+
+    fn console() -> (ConsoleWidget, Cpu) {
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0, 0x0103, "Store");
+        symbols.insert(0, 0xC000, "wValue");
+
+        let cpu = Cpu::new(
+            asm::assemble(
+                "
+                ld a, 5
+                inc a
+                ld [$c000], a
+                ld a, [$c000]
+                nop
+            ",
+            )
+            .unwrap(),
+        );
+
+        (
+            ConsoleWidget::new(Rc::new(symbols), Default::default()),
+            cpu,
+        )
+    }
+
+    fn last(console: &ConsoleWidget) -> &str {
+        console.output.last().unwrap()
+    }
+
+    #[test]
+    fn commands() {
+        let (mut console, mut cpu) = console();
+
+        console.execute(&mut cpu, "set a=0x10");
+        console.execute(&mut cpu, "print a * 2");
+        assert_eq!(last(&console), "a * 2 = $20 (32)");
+
+        console.execute(&mut cpu, "set [wValue]=$AB");
+        console.execute(&mut cpu, "x/2 wValue");
+        assert_eq!(last(&console), "c000: ab 00");
+
+        console.execute(&mut cpu, "b Store if a == 6");
+        console.execute(&mut cpu, "continue");
+        assert_eq!(last(&console), "breakpoint at 0103, pc = 0103");
+
+        console.execute(&mut cpu, "w [wValue..wValue+1] read");
+        console.execute(&mut cpu, "c");
+        assert_eq!(last(&console), "watchpoint: read of c000, pc = 0109");
+
+        console.execute(&mut cpu, "bogus");
+        assert_eq!(last(&console), "error: unknown command bogus, try help");
+    }
+
+    #[test]
+    fn stepping() {
+        let (mut console, mut cpu) = console();
+
+        console.execute(&mut cpu, "step 2");
+        assert_eq!(last(&console), "stepped, pc = 0103");
+
+        console.execute(&mut cpu, "until pc == $0109");
+        assert_eq!(last(&console), "condition met, pc = 0109");
+    }
+
+    #[test]
+    fn completion_and_history() {
+        let (mut console, _) = console();
+
+        console.input = "con".to_string();
+        console.complete();
+        assert_eq!(console.input, "continue ");
+
+        console.input = "print wV".to_string();
+        console.complete();
+        assert_eq!(console.input, "print wValue ");
+
+        console.history = vec!["step".to_string(), "print a".to_string()];
+        console.browse_history(true);
+        assert_eq!(console.input, "print a");
+        console.browse_history(true);
+        assert_eq!(console.input, "step");
+        console.browse_history(false);
+        console.browse_history(false);
+        assert_eq!(console.input, "");
+    }
+}
@@ -1,31 +1,108 @@
-use egui_glium::EguiGlium;
-use epi::NativeTexture;
 use std::rc::Rc;
 
-use crate::{
-    cpu::{Cpu, Flags},
-    debugger::{self, DebuggerWidget},
+use crate::debugger::{
+    breakpoints::{self, SharedBreakpoints},
+    DebuggerWidget,
 };
+use yeahboy::{cpu::Cpu, symbols::SymbolTable};
 
-pub struct ControlWidget {}
+pub struct ControlWidget {
+    symbols: Rc<SymbolTable>,
+    breakpoints: SharedBreakpoints,
+    breakpoint_input: String,
+    /// Why the last Continue stopped
+    stop: Option<String>,
+}
 
 impl ControlWidget {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(symbols: Rc<SymbolTable>, breakpoints: SharedBreakpoints) -> Self {
+        Self {
+            symbols,
+            breakpoints,
+            breakpoint_input: String::new(),
+            stop: None,
+        }
+    }
+
+    /// Adds a breakpoint from `addr` or `addr if condition`
+    fn add_breakpoint(&mut self) -> Result<(), String> {
+        let (location, condition) = match self.breakpoint_input.split_once(" if ") {
+            Some((location, condition)) => (location, Some(condition)),
+            None => (self.breakpoint_input.as_str(), None),
+        };
+
+        let target = self
+            .symbols
+            .resolve(location)
+            .ok_or_else(|| format!("unknown breakpoint {}", location))?;
+
+        self.breakpoints.borrow_mut().insert(target, condition)
     }
 }
 
 impl DebuggerWidget for ControlWidget {
-    fn draw(&self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
         egui::Window::new("Control").show(egui.ctx(), |ui| {
             if ui.button("Step").clicked() {
-                cpu.step()
+                self.stop = cpu.step().err().map(|e| e.to_string());
             }
 
             if ui.button("Continue").clicked() {
-                loop {
-                    cpu.step()
+                let stop =
+                    breakpoints::run(cpu, &self.breakpoints.borrow(), &self.symbols, None, None);
+                self.stop = Some(stop.to_string());
+            }
+
+            if let Some(stop) = &self.stop {
+                ui.label(format!("Stopped: {}", stop));
+            }
+
+            let mut break_on_illegal = cpu.break_on_illegal();
+            if ui
+                .checkbox(&mut break_on_illegal, "Break on illegal opcodes")
+                .changed()
+            {
+                cpu.set_break_on_illegal(break_on_illegal);
+            }
+
+            ui.separator();
+            ui.label("Breakpoints (address or label, then optionally if <condition>)");
+
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.breakpoint_input);
+
+                if ui.button("Add").clicked() {
+                    match self.add_breakpoint() {
+                        Ok(()) => self.breakpoint_input.clear(),
+                        Err(e) => log::warn!("{}", e),
+                    }
                 }
+            });
+
+            let mut removed = None;
+
+            for (target, condition) in self.breakpoints.borrow().iter() {
+                ui.horizontal(|ui| {
+                    let bank = target
+                        .bank
+                        .unwrap_or_else(|| cpu.mmu().bank_at(target.addr));
+                    let mut name = match self.symbols.label(target.addr, bank) {
+                        Some(label) => format!("{} ({})", target, label),
+                        None => target.to_string(),
+                    };
+                    if let Some(condition) = condition {
+                        name.push_str(&format!(" if {}", condition));
+                    }
+                    ui.add(egui::Label::new(name).monospace());
+
+                    if ui.small_button("x").clicked() {
+                        removed = Some(target);
+                    }
+                });
+            }
+
+            if let Some(target) = removed {
+                self.breakpoints.borrow_mut().remove(target);
             }
         });
     }
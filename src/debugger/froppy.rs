@@ -2,10 +2,8 @@ use egui_glium::EguiGlium;
 use epi::NativeTexture;
 use std::rc::Rc;
 
-use crate::{
-    cpu::Cpu,
-    debugger::{self, DebuggerWidget},
-};
+use crate::debugger::{self, DebuggerWidget};
+use yeahboy::cpu::Cpu;
 
 pub struct FroppyWidget {
     image_size: egui::Vec2,
@@ -29,7 +27,7 @@ impl FroppyWidget {
 }
 
 impl DebuggerWidget for FroppyWidget {
-    fn draw(&self, egui: &mut egui_glium::EguiGlium, _cpu: &mut Cpu) {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, _cpu: &mut Cpu) {
         egui::Window::new("Moral Support").show(egui.ctx(), |ui| {
             ui.image(self.texture_id, self.image_size);
         });
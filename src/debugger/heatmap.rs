@@ -0,0 +1,114 @@
+use std::{fs, path::PathBuf};
+
+use crate::debugger::{texture::Texture, DebuggerWidget};
+use yeahboy::{
+    coverage::{Access, AccessLog},
+    cpu::Cpu,
+};
+
+/// Scale the texture up by this much
+const SCALE: usize = 2;
+
+/// Heat lost per redraw, so an access fades out over a few seconds
+const FADE: u8 = 2;
+
+/// Brightness of addresses that were accessed at some point but have gone cold
+const TOUCHED: u8 = 40;
+
+pub struct HeatmapWidget {
+    texture: Texture,
+    /// Where to save coverage, from --coverage
+    output: Option<PathBuf>,
+}
+
+impl HeatmapWidget {
+    pub fn new(output: Option<PathBuf>) -> Self {
+        Self {
+            texture: Texture::new(),
+            output,
+        }
+    }
+
+    /// One pixel per address, 256 to a row: writes in red, reads in green, executes in blue
+    fn pixels(log: &AccessLog) -> Vec<egui::Color32> {
+        (0..=0xFFFF)
+            .map(|addr| {
+                let base = match log.touched(addr) {
+                    true => TOUCHED,
+                    false => 0,
+                };
+                egui::Color32::from_rgb(
+                    log.heat(Access::Write, addr).max(base),
+                    log.heat(Access::Read, addr).max(base),
+                    log.heat(Access::Execute, addr).max(base),
+                )
+            })
+            .collect()
+    }
+
+    fn describe(cpu: &Cpu, log: &AccessLog, addr: u16) -> String {
+        format!(
+            "{:04x} ({})\nReads {}\nWrites {}\nExecutes {}",
+            addr,
+            cpu.mmu().region_name(addr),
+            log.count(Access::Read, addr),
+            log.count(Access::Write, addr),
+            log.count(Access::Execute, addr)
+        )
+    }
+}
+
+impl DebuggerWidget for HeatmapWidget {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+        let texture_id = match cpu.mmu_mut().access_log_mut() {
+            Some(log) => {
+                log.fade(FADE);
+                Some(self.texture.update(egui, 256, Self::pixels(log), SCALE))
+            }
+            None => None,
+        };
+
+        egui::Window::new("Memory Heatmap").show(egui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                let rom_len = cpu.mmu().rom().len();
+                let mut enabled = cpu.mmu().access_log().is_some();
+                if ui.checkbox(&mut enabled, "Recording").changed() {
+                    cpu.mmu_mut()
+                        .set_access_log(enabled.then(|| AccessLog::new(rom_len)));
+                }
+
+                if ui.button("Reset").clicked() && enabled {
+                    cpu.mmu_mut().set_access_log(Some(AccessLog::new(rom_len)));
+                }
+
+                if let (Some(path), Some(log)) = (&self.output, cpu.mmu().access_log()) {
+                    if ui.button("Save coverage").clicked() {
+                        match fs::write(path, log.rom_coverage()) {
+                            Ok(()) => log::info!("saved coverage to {}", path.display()),
+                            Err(e) => log::warn!("couldn't save {}: {}", path.display(), e),
+                        }
+                    }
+                }
+            });
+
+            let (texture_id, log) = match (texture_id, cpu.mmu().access_log()) {
+                (Some(texture_id), Some(log)) => (texture_id, log),
+                _ => return,
+            };
+
+            ui.label("Red: write, green: read, blue: execute. Rows are 256 bytes.");
+
+            let response = ui.image(texture_id, self.texture.size() * SCALE as f32);
+
+            if let Some(pos) = response.hover_pos() {
+                let offset = (pos - response.rect.min) / SCALE as f32;
+                let (x, y) = (offset.x as usize, offset.y as usize);
+
+                if x < 256 && y < 256 {
+                    let addr = (y * 256 + x) as u16;
+                    response.on_hover_text(Self::describe(cpu, log, addr));
+                }
+            }
+        });
+    }
+}
@@ -1,60 +1,233 @@
-use egui_glium::EguiGlium;
-use epi::NativeTexture;
-use std::{collections::BTreeMap, rc::Rc};
+use std::rc::Rc;
 
-use crate::{
-    cpu::{Cpu, Flags},
-    debugger::{self, DebuggerWidget},
+use crate::debugger::{DebuggerWidget, Navigation};
+use yeahboy::{
+    asm,
+    cpu::Cpu,
+    disasm::{Formatter, Syntax},
+    flow::FlowAnalyser,
     instructions::DecodedInstruction,
+    symbols::{SymbolTable, Target},
 };
 
+/// Bytes of context to show above the current instruction
+const BYTES_BEFORE_PC: u16 = 0x10;
+
+/// Instructions to show from the current instruction onwards
+const ROWS_FROM_PC: usize = 20;
+
+enum RowKind {
+    Code(DecodedInstruction),
+    Data(u8),
+}
+
 struct InstructionRow {
     pub address: u16,
-    pub instruction: DecodedInstruction,
+    pub kind: RowKind,
 }
 
 pub struct InstructionsWidget {
-    rows: Vec<InstructionRow>,
-    addr_to_index: BTreeMap<u16, usize>,
+    analyser: FlowAnalyser,
+    formatter: Formatter,
+    symbols: Rc<SymbolTable>,
+
+    /// Address to patch, as typed
+    patch_address: String,
+    /// Instruction to patch in, as typed
+    patch_line: String,
+    /// Result of the last patch
+    patch_status: String,
+
+    /// Requests from other widgets to show an address
+    navigation: Navigation,
+    /// Address to show instead of the PC
+    view: Option<u16>,
 }
 
 impl InstructionsWidget {
-    pub fn new(cpu: &Cpu) -> Self {
-        let mut rows = Vec::new();
-        let mut addr_to_index = BTreeMap::new();
+    pub fn new(cpu: &Cpu, symbols: Rc<SymbolTable>, navigation: Navigation) -> Self {
+        let mut analyser = FlowAnalyser::new(cpu.mmu().rom());
+        analyser.analyse(cpu.mmu().rom(), cpu.mmu().rom_bank(), cpu.pc());
+
+        Self {
+            analyser,
+            formatter: Formatter::with_symbols(Syntax::Rgbds, symbols.clone()),
+            symbols,
+            patch_address: String::new(),
+            patch_line: String::new(),
+            patch_status: String::new(),
+            navigation,
+            view: None,
+        }
+    }
+
+    /// Assembles the patch line and writes it into memory at the patch address
+    fn patch(&mut self, cpu: &mut Cpu) {
+        let target = match self.patch_address.trim() {
+            "" => Target {
+                addr: cpu.pc(),
+                bank: None,
+            },
+            text => match self.symbols.resolve(text) {
+                Some(target) => target,
+                None => {
+                    self.patch_status = format!("unknown address {}", text);
+                    return;
+                }
+            },
+        };
+        let addr = target.addr;
+        let bank = target.bank.unwrap_or_else(|| cpu.mmu().bank_at(addr));
 
-        let mut pc = cpu.pc();
-        let mut index = 0;
+        let bytes = match asm::assemble_line(&self.patch_line, bank, addr, &self.symbols) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                self.patch_status = e.to_string();
+                return;
+            }
+        };
 
-        while pc < 0x8000 {
-            let inst = InstructionRow {
-                address: pc,
-                instruction: DecodedInstruction::decode(cpu.mmu(), pc as usize),
+        // Patch the bank the target is in, even if another one is mapped
+        let mmu = cpu.mmu_mut();
+        let (rom_bank, ram_bank) = match addr {
+            0xA000..=0xBFFF => (mmu.rom_bank(), bank),
+            _ => (bank, mmu.ram_bank()),
+        };
+        for (i, byte) in bytes.iter().enumerate() {
+            mmu.poke_banked(rom_bank, ram_bank, addr.wrapping_add(i as u16), *byte);
+        }
+
+        // The patch can change what's code, so start the analysis again
+        let rom_bank = cpu.mmu().rom_bank();
+        self.analyser = FlowAnalyser::new(cpu.mmu().rom());
+        self.analyser.analyse(cpu.mmu().rom(), rom_bank, cpu.pc());
+
+        log::info!("patched {} byte(s) at {:04x}", bytes.len(), addr);
+        self.patch_status = format!("patched {} byte(s) at {:04x}", bytes.len(), addr);
+    }
+
+    /// Rows around `pc`, using the code/data split found by the analyser
+    fn rom_rows(&mut self, cpu: &Cpu, pc: u16) -> Vec<InstructionRow> {
+        let mmu = cpu.mmu();
+        let rom_bank = mmu.rom_bank();
+
+        if self.analyser.instruction(rom_bank, pc).is_none() {
+            log::debug!("reached unknown code at {:04x}, analysing", pc);
+            self.analyser.analyse(mmu.rom(), rom_bank, pc);
+        }
+
+        // Start on an instruction boundary so the context above `pc` doesn't begin mid-way
+        // through an instruction
+        let start = pc.saturating_sub(BYTES_BEFORE_PC);
+        let mut addr = (start..pc)
+            .find(|&addr| self.analyser.instruction(rom_bank, addr).is_some())
+            .unwrap_or(pc);
+
+        let mut rows = Vec::new();
+        let mut rows_from_pc = 0;
+
+        while rows_from_pc < ROWS_FROM_PC && addr < 0x8000 {
+            let kind = match self.analyser.instruction(rom_bank, addr) {
+                // An instruction that overlaps the PC would hide it, so show its bytes as data
+                Some(inst) if addr >= pc || addr + inst.len() as u16 <= pc => {
+                    RowKind::Code(inst.clone())
+                }
+                _ => RowKind::Data(mmu.rb(addr as usize)),
             };
 
-            addr_to_index.insert(pc, index);
+            let len = match &kind {
+                RowKind::Code(inst) => inst.len() as u16,
+                RowKind::Data(_) => 1,
+            };
 
-            pc += inst.instruction.len() as u16;
+            rows.push(InstructionRow {
+                address: addr,
+                kind,
+            });
 
-            rows.push(inst);
+            if addr >= pc {
+                rows_from_pc += 1;
+            }
 
-            index += 1;
+            addr += len;
         }
 
-        Self {
-            rows,
-            addr_to_index,
+        rows
+    }
+
+    /// Rows from `pc` onwards, for code outside of ROM
+    fn ram_rows(&self, cpu: &Cpu, pc: u16) -> Vec<InstructionRow> {
+        let mut rows = Vec::new();
+        let mut addr = pc;
+
+        while rows.len() < ROWS_FROM_PC {
+            let instruction = DecodedInstruction::decode(cpu.mmu(), addr as usize);
+            let len = instruction.len() as u16;
+
+            rows.push(InstructionRow {
+                address: addr,
+                kind: RowKind::Code(instruction),
+            });
+
+            addr = match addr.checked_add(len) {
+                Some(addr) => addr,
+                None => break,
+            };
         }
+
+        rows
     }
 }
 
 impl DebuggerWidget for InstructionsWidget {
-    fn draw(&self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
         egui::Window::new("Instructions").show(egui.ctx(), |ui| {
-            let mut inst_index = self.addr_to_index[&cpu.pc()];
+            let mut syntax = self.formatter.syntax();
+            ui.horizontal(|ui| {
+                for option in [Syntax::Rgbds, Syntax::NoCash] {
+                    ui.radio_value(&mut syntax, option, option.to_string());
+                }
+            });
+            self.formatter.set_syntax(syntax);
 
-            let disp_rows = &self.rows[inst_index..inst_index + 20];
-            // let disp_rows = &self.rows;
+            ui.horizontal(|ui| {
+                ui.label("Patch");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.patch_address)
+                        .hint_text("PC")
+                        .desired_width(60.0),
+                );
+                ui.add(egui::TextEdit::singleline(&mut self.patch_line).hint_text("ld a, [hl+]"));
+                if ui.button("Assemble").clicked() {
+                    self.patch(cpu);
+                }
+            });
+            if !self.patch_status.is_empty() {
+                ui.label(&self.patch_status);
+            }
+
+            if let Some(addr) = self.navigation.take() {
+                self.view = Some(addr);
+            }
+
+            if let Some(addr) = self.view {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Showing {:04x}", addr));
+                    if ui.button("Follow PC").clicked() {
+                        self.view = None;
+                    }
+                });
+            }
+
+            let rom_bank = cpu.mmu().rom_bank();
+            self.formatter.set_rom_bank(rom_bank);
+
+            let center = self.view.unwrap_or_else(|| cpu.pc());
+            let disp_rows = if center < 0x8000 {
+                self.rom_rows(cpu, center)
+            } else {
+                self.ram_rows(cpu, center)
+            };
 
             // Columns: addr, bytes, action
 
@@ -64,9 +237,35 @@ impl DebuggerWidget for InstructionsWidget {
                     .spacing([40.0, 4.0])
                     .show(ui, |ui| {
                         for row in disp_rows.iter() {
+                            let bank = match row.address {
+                                0x4000..=0x7FFF => rom_bank,
+                                _ => cpu.mmu().bank_at(row.address),
+                            };
+                            for label in self.symbols.labels(row.address, bank) {
+                                ui.add(
+                                    egui::Label::new(format!("{}:", label))
+                                        .monospace()
+                                        .text_color(egui::Color32::YELLOW),
+                                );
+                                ui.end_row();
+                            }
+
+                            let (raw_bytes, text, text_color) = match &row.kind {
+                                RowKind::Code(inst) => (
+                                    inst.raw_bytes().clone(),
+                                    self.formatter.format(inst, row.address),
+                                    egui::Color32::from_rgb(0xCF, 0x9F, 0xFF),
+                                ),
+                                RowKind::Data(byte) => (
+                                    vec![*byte],
+                                    self.formatter.data(&[*byte]),
+                                    egui::Color32::GRAY,
+                                ),
+                            };
+
                             let mut bytestr = String::new();
-                            for byte in row.instruction.raw_bytes().iter() {
-                                bytestr.push_str(&*format!("{:02x} ", byte))
+                            for byte in raw_bytes.iter() {
+                                bytestr.push_str(&format!("{:02x} ", byte))
                             }
 
                             let mut addr = egui::Label::new(format!("{:04x}", row.address))
@@ -80,16 +279,12 @@ impl DebuggerWidget for InstructionsWidget {
                             ui.add(addr);
 
                             ui.add(
-                                egui::Label::new(format!("{}", bytestr))
+                                egui::Label::new(bytestr)
                                     .monospace()
                                     .text_color(egui::Color32::LIGHT_GRAY),
                             );
 
-                            ui.add(
-                                egui::Label::new(format!("{}", row.instruction.action()))
-                                    .monospace()
-                                    .text_color(egui::Color32::from_rgb(0xCF, 0x9F, 0xFF)),
-                            );
+                            ui.add(egui::Label::new(text).monospace().text_color(text_color));
 
                             ui.end_row();
                         }
@@ -0,0 +1,78 @@
+use crate::debugger::DebuggerWidget;
+use yeahboy::{cpu::Cpu, io};
+
+pub struct IoWidget {
+    /// Register being edited and the text typed so far
+    editing: Option<(u16, String)>,
+}
+
+impl IoWidget {
+    pub fn new() -> Self {
+        Self { editing: None }
+    }
+}
+
+impl DebuggerWidget for IoWidget {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+        let registers = (0xFF00..=0xFF7F)
+            .chain(std::iter::once(0xFFFF))
+            .filter_map(|addr| io::register_name(addr).map(|name| (addr, name)));
+
+        egui::Window::new("IO Registers").show(egui.ctx(), |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("io")
+                    .num_columns(4)
+                    .spacing([20.0, 4.0])
+                    .show(ui, |ui| {
+                        for (addr, name) in registers {
+                            let value = cpu.mmu().rb(addr as usize);
+
+                            ui.add(
+                                egui::Label::new(format!("{:04x}", addr))
+                                    .monospace()
+                                    .text_color(egui::Color32::LIGHT_BLUE),
+                            );
+                            ui.add(egui::Label::new(name).monospace());
+
+                            match &mut self.editing {
+                                Some((editing, text)) if *editing == addr => {
+                                    let response = ui.add(
+                                        egui::TextEdit::singleline(text)
+                                            .desired_width(60.0)
+                                            .text_style(egui::TextStyle::Monospace),
+                                    );
+                                    response.request_focus();
+
+                                    if response.lost_focus() {
+                                        // Goes through the normal write path, side effects and all
+                                        match u8::from_str_radix(text.trim(), 16) {
+                                            Ok(value) => cpu.mmu_mut().wb(addr as usize, value),
+                                            Err(_) => log::warn!("invalid byte {}", text),
+                                        }
+                                        self.editing = None;
+                                    }
+                                }
+                                _ => {
+                                    let label =
+                                        egui::Label::new(format!("{:02x} {:08b}", value, value))
+                                            .monospace()
+                                            .sense(egui::Sense::click());
+
+                                    if ui.add(label).clicked() {
+                                        self.editing = Some((addr, format!("{:02x}", value)));
+                                    }
+                                }
+                            }
+
+                            ui.add(
+                                egui::Label::new(io::describe(addr, value).unwrap_or_default())
+                                    .text_color(egui::Color32::GRAY),
+                            );
+
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+    }
+}
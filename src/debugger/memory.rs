@@ -0,0 +1,239 @@
+use std::rc::Rc;
+
+use crate::debugger::DebuggerWidget;
+use yeahboy::{cpu::Cpu, symbols::SymbolTable};
+
+const BYTES_PER_ROW: usize = 16;
+
+const ROW_COUNT: usize = 0x10000 / BYTES_PER_ROW;
+
+/// Start of each region, for the jump buttons
+const REGIONS: [(&str, u16); 9] = [
+    ("ROM0", 0x0000),
+    ("ROMX", 0x4000),
+    ("VRAM", 0x8000),
+    ("SRAM", 0xA000),
+    ("WRAM", 0xC000),
+    ("OAM", 0xFE00),
+    ("IO", 0xFF00),
+    ("HRAM", 0xFF80),
+    ("IE", 0xFFFF),
+];
+
+pub struct MemoryWidget {
+    symbols: Rc<SymbolTable>,
+
+    /// ROM bank shown at 0x4000-0x7FFF, or the mapped one if `None`
+    rom_bank: Option<u16>,
+    /// RAM bank shown at 0xA000-0xBFFF, or the mapped one if `None`
+    ram_bank: Option<u16>,
+
+    /// Memory as of the last frame
+    snapshot: Vec<u8>,
+    /// Bytes that changed in the last step
+    changed: Vec<bool>,
+    /// CPU step count the snapshot was taken at
+    snapshot_steps: u64,
+
+    goto_input: String,
+    /// Row to scroll to on the next frame
+    scroll_to: Option<usize>,
+
+    selected: Option<u16>,
+    edit_input: String,
+}
+
+impl MemoryWidget {
+    pub fn new(symbols: Rc<SymbolTable>) -> Self {
+        Self {
+            symbols,
+            rom_bank: None,
+            ram_bank: None,
+            snapshot: vec![0; 0x10000],
+            changed: vec![false; 0x10000],
+            snapshot_steps: 0,
+            goto_input: String::new(),
+            scroll_to: None,
+            selected: None,
+            edit_input: String::new(),
+        }
+    }
+
+    fn banks(&self, cpu: &Cpu) -> (u16, u16) {
+        let mmu = cpu.mmu();
+        (
+            self.rom_bank.unwrap_or_else(|| mmu.rom_bank()),
+            self.ram_bank.unwrap_or_else(|| mmu.ram_bank()),
+        )
+    }
+
+    /// Reads the whole address space as shown, and works out what changed since the last step
+    fn update_snapshot(&mut self, cpu: &Cpu) {
+        let (rom_bank, ram_bank) = self.banks(cpu);
+        let memory: Vec<u8> = (0..=0xFFFF)
+            .map(|addr| cpu.mmu().peek_banked(rom_bank, ram_bank, addr))
+            .collect();
+
+        if cpu.steps() != self.snapshot_steps {
+            for (changed, (old, new)) in self
+                .changed
+                .iter_mut()
+                .zip(self.snapshot.iter().zip(memory.iter()))
+            {
+                *changed = old != new;
+            }
+            self.snapshot_steps = cpu.steps();
+        }
+
+        self.snapshot = memory;
+    }
+
+    fn goto(&mut self, addr: u16) {
+        self.scroll_to = Some(addr as usize / BYTES_PER_ROW);
+        self.select(addr);
+    }
+
+    fn select(&mut self, addr: u16) {
+        self.selected = Some(addr);
+        self.edit_input = format!("{:02x}", self.snapshot[addr as usize]);
+    }
+
+    fn bank_selector(ui: &mut egui::Ui, label: &str, bank: &mut Option<u16>, count: u16) {
+        let mut mapped = bank.is_none();
+        ui.checkbox(&mut mapped, format!("{} mapped", label));
+
+        match mapped {
+            true => *bank = None,
+            false => {
+                let mut value = bank.unwrap_or(0);
+                ui.add(egui::DragValue::new(&mut value).clamp_range(0..=count.saturating_sub(1)));
+                *bank = Some(value);
+            }
+        }
+    }
+}
+
+impl DebuggerWidget for MemoryWidget {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+        self.update_snapshot(cpu);
+
+        egui::Window::new("Memory").show(egui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                for (name, addr) in REGIONS {
+                    if ui.small_button(name).clicked() {
+                        self.goto(addr);
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Go to (address or label)");
+                ui.text_edit_singleline(&mut self.goto_input);
+
+                if ui.button("Go").clicked() {
+                    match self.symbols.resolve(&self.goto_input) {
+                        Some(target) => {
+                            // Show the bank the label is in rather than whatever is mapped
+                            match (target.addr, target.bank) {
+                                (0x4000..=0x7FFF, Some(bank)) => self.rom_bank = Some(bank),
+                                (0xA000..=0xBFFF, Some(bank)) => self.ram_bank = Some(bank),
+                                _ => (),
+                            }
+                            self.goto(target.addr)
+                        }
+                        None => log::warn!("unknown address {}", self.goto_input),
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let mmu = cpu.mmu();
+                let (rom_count, ram_count) = (mmu.rom_bank_count(), mmu.ram_bank_count());
+                Self::bank_selector(ui, "ROMX", &mut self.rom_bank, rom_count);
+                Self::bank_selector(ui, "SRAM", &mut self.ram_bank, ram_count);
+            });
+
+            if let Some(addr) = self.selected {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::Label::new(format!("{:04x} ({})", addr, cpu.mmu().region_name(addr)))
+                            .monospace(),
+                    );
+                    ui.add(egui::TextEdit::singleline(&mut self.edit_input).desired_width(30.0));
+
+                    if ui.button("Write").clicked() {
+                        match u8::from_str_radix(self.edit_input.trim(), 16) {
+                            Ok(value) => {
+                                let (rom_bank, ram_bank) = self.banks(cpu);
+                                cpu.mmu_mut().poke_banked(rom_bank, ram_bank, addr, value);
+                                self.snapshot[addr as usize] = value;
+                            }
+                            Err(_) => log::warn!("invalid byte {}", self.edit_input),
+                        }
+                    }
+                });
+            }
+
+            ui.separator();
+
+            let row_height = ui.fonts()[egui::TextStyle::Monospace].row_height();
+            let mut scroll = egui::ScrollArea::vertical();
+            if let Some(row) = self.scroll_to.take() {
+                scroll =
+                    scroll.scroll_offset(row as f32 * (row_height + ui.spacing().item_spacing.y));
+            }
+
+            let mut clicked = None;
+
+            scroll.show_rows(ui, row_height, ROW_COUNT, |ui, rows| {
+                for row in rows {
+                    let start = row * BYTES_PER_ROW;
+                    let bytes = &self.snapshot[start..start + BYTES_PER_ROW];
+
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::Label::new(format!("{:04x}", start))
+                                .monospace()
+                                .text_color(egui::Color32::LIGHT_BLUE),
+                        );
+
+                        for (i, byte) in bytes.iter().enumerate() {
+                            let addr = (start + i) as u16;
+                            let mut label = egui::Label::new(format!("{:02x}", byte))
+                                .monospace()
+                                .sense(egui::Sense::click());
+
+                            if self.changed[start + i] {
+                                label = label.text_color(egui::Color32::RED);
+                            }
+                            if self.selected == Some(addr) {
+                                label = label.background_color(egui::Color32::DARK_BLUE);
+                            }
+
+                            if ui.add(label).clicked() {
+                                clicked = Some(addr);
+                            }
+                        }
+
+                        let ascii: String = bytes
+                            .iter()
+                            .map(|&byte| match byte {
+                                0x20..=0x7E => byte as char,
+                                _ => '.',
+                            })
+                            .collect();
+                        ui.add(
+                            egui::Label::new(ascii)
+                                .monospace()
+                                .text_color(egui::Color32::GRAY),
+                        );
+                    });
+                }
+            });
+
+            if let Some(addr) = clicked {
+                self.select(addr);
+            }
+        });
+    }
+}
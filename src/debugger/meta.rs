@@ -1,13 +1,5 @@
-use egui_glium::EguiGlium;
-use epi::NativeTexture;
-use std::rc::Rc;
-
-use crate::{
-    bits,
-    cpu::Cpu,
-    debugger::{self, DebuggerWidget},
-    mmu::Mmu,
-};
+use crate::debugger::DebuggerWidget;
+use yeahboy::{bits, cpu::Cpu, header, mmu::Mmu, Model};
 
 pub struct MetadataWidget {
     title: String,
@@ -15,12 +7,13 @@ pub struct MetadataWidget {
     cart_type: u8,
     rom_size: u8,
     ram_size: u8,
+    model: Model,
 }
 
 impl MetadataWidget {
     pub fn new(mmu: &Mmu) -> Self {
         let mut title = String::new();
-        for addr in (0x0134..=0x0142) {
+        for addr in 0x0134..=0x0142 {
             let byte = mmu.rb(addr);
             if byte == 0 {
                 break;
@@ -31,6 +24,7 @@ impl MetadataWidget {
         let cart_type = mmu.rb(0x147);
         let rom_size = mmu.rb(0x148);
         let ram_size = mmu.rb(0x149);
+        let model = mmu.model();
 
         Self {
             title,
@@ -38,19 +32,15 @@ impl MetadataWidget {
             cart_type,
             rom_size,
             ram_size,
+            model,
         }
     }
 }
 
 impl DebuggerWidget for MetadataWidget {
-    fn draw(&self, egui: &mut egui_glium::EguiGlium, _cpu: &mut Cpu) {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, _cpu: &mut Cpu) {
         egui::Window::new("Metadata").show(egui.ctx(), |ui| {
-            let cart_type_str = match self.cart_type {
-                0 => "ROM",
-                1..=3 => "ROM+MBC1",
-                5..=6 => "ROM+MBC2",
-                _ => todo!(),
-            };
+            let cart_type_str = header::cart_type_name(self.cart_type).unwrap_or("<UNKNOWN>");
             let rom_size_str = format!("{} Banks", 2_u8.pow(self.rom_size as u32 + 1));
             let ram_size_str = match self.ram_size {
                 0 => "None",
@@ -75,6 +65,7 @@ impl DebuggerWidget for MetadataWidget {
                     "RAM Size",
                     format!("{:02x} ({})", self.ram_size, ram_size_str),
                 ),
+                ("Model", self.model.to_string()),
             ];
 
             for (name, value) in rows.iter() {
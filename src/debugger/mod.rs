@@ -1,24 +1,46 @@
 //! Example how to use [epi::NativeTexture] with glium.
 use egui_glium::EguiGlium;
-use epi::NativeTexture;
 use glium::glutin;
-use std::rc::Rc;
+use std::{
+    cell::{Cell, RefCell},
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
-use crate::cpu::Cpu;
+use yeahboy::{cpu::Cpu, symbols::SymbolTable, GameBoy};
 
 use self::{
-    control::ControlWidget, froppy::FroppyWidget, instructions::InstructionsWidget,
-    meta::MetadataWidget, registers::RegistersWidget,
+    breakpoints::Breakpoints, callstack::CallStackWidget, console::ConsoleWidget,
+    control::ControlWidget, froppy::FroppyWidget, heatmap::HeatmapWidget,
+    instructions::InstructionsWidget, io::IoWidget, memory::MemoryWidget, meta::MetadataWidget,
+    profiler::ProfilerWidget, registers::RegistersWidget, script::ScriptWidget,
+    sprites::SpritesWidget, tilemap::TileMapWidget, tiles::TilesWidget,
 };
 
+mod breakpoints;
+mod callstack;
+mod console;
 mod control;
 mod froppy;
+mod heatmap;
 mod instructions;
+mod io;
+mod memory;
 mod meta;
+mod profiler;
 mod registers;
+mod script;
+mod sprites;
+mod texture;
+mod tilemap;
+mod tiles;
+
+/// An address for the instructions view to show, set by other widgets
+pub type Navigation = Rc<Cell<Option<u16>>>;
 
 pub trait DebuggerWidget {
-    fn draw(&self, egui: &mut EguiGlium, cpu: &mut Cpu);
+    fn draw(&mut self, egui: &mut EguiGlium, cpu: &mut Cpu);
 }
 
 fn create_display(event_loop: &glutin::event_loop::EventLoop<()>) -> glium::Display {
@@ -40,7 +62,7 @@ fn create_display(event_loop: &glutin::event_loop::EventLoop<()>) -> glium::Disp
     glium::Display::new(window_builder, context_builder, event_loop).unwrap()
 }
 
-fn load_glium_image(image_data: &[u8]) -> glium::texture::RawImage2d<u8> {
+fn load_glium_image(image_data: &[u8]) -> glium::texture::RawImage2d<'_, u8> {
     // Load image using the image crate:
     let image = image::load_from_memory(image_data).unwrap().to_rgba8();
     let image_dimensions = image.dimensions();
@@ -57,8 +79,38 @@ fn load_glium_image(image_data: &[u8]) -> glium::texture::RawImage2d<u8> {
     glium::texture::RawImage2d::from_raw_rgba(pixels, image_dimensions)
 }
 
-pub fn run(rom: Vec<u8>) {
-    let mut cpu = Cpu::new(rom);
+/// Saves what `cpu` profiled to `profile` and the ROM coverage it recorded to `coverage`
+pub fn save_results(
+    cpu: &Cpu,
+    symbols: &SymbolTable,
+    profile: Option<&Path>,
+    coverage: Option<&Path>,
+) {
+    if let (Some(path), Some(profiler)) = (profile, cpu.profiler()) {
+        match profiler.save_folded(path, symbols) {
+            Ok(()) => log::info!("saved folded stacks to {}", path.display()),
+            Err(e) => log::warn!("couldn't save {}: {}", path.display(), e),
+        }
+    }
+
+    if let (Some(path), Some(log)) = (coverage, cpu.mmu().access_log()) {
+        match fs::write(path, log.rom_coverage()) {
+            Ok(()) => log::info!("saved coverage to {}", path.display()),
+            Err(e) => log::warn!("couldn't save {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Runs the debugger on `gameboy`, which may already be profiling or recording memory
+/// accesses. If so the results are saved to `profile` or `coverage` on exit.
+pub fn run(
+    mut gameboy: GameBoy,
+    symbols: Rc<SymbolTable>,
+    profile: Option<PathBuf>,
+    coverage: Option<PathBuf>,
+) {
+    let navigation = Navigation::default();
+    let breakpoints = Rc::new(RefCell::new(Breakpoints::default()));
 
     let event_loop = glutin::event_loop::EventLoop::with_user_event();
 
@@ -75,20 +127,38 @@ pub fn run(rom: Vec<u8>) {
     //     .insert(egui::TextStyle::Heading, (egui::FontFamily::Prop, 32.0));
     egui.ctx().set_fonts(fonts);
 
-    let widgets: Vec<Box<dyn DebuggerWidget>> = vec![
+    let mut widgets: Vec<Box<dyn DebuggerWidget>> = vec![
         Box::new(FroppyWidget::new(&mut egui, &display)),
         Box::new(RegistersWidget::new()),
-        Box::new(InstructionsWidget::new(&cpu)),
-        Box::new(MetadataWidget::new(cpu.mmu())),
-        Box::new(ControlWidget::new()),
+        Box::new(InstructionsWidget::new(
+            gameboy.cpu(),
+            symbols.clone(),
+            navigation.clone(),
+        )),
+        Box::new(MetadataWidget::new(gameboy.cpu().mmu())),
+        Box::new(MemoryWidget::new(symbols.clone())),
+        Box::new(TilesWidget::new()),
+        Box::new(TileMapWidget::new()),
+        Box::new(SpritesWidget::new()),
+        Box::new(IoWidget::new()),
+        Box::new(CallStackWidget::new(symbols.clone(), navigation.clone())),
+        Box::new(ProfilerWidget::new(
+            symbols.clone(),
+            navigation,
+            profile.clone(),
+        )),
+        Box::new(HeatmapWidget::new(coverage.clone())),
+        Box::new(ControlWidget::new(symbols.clone(), breakpoints.clone())),
+        Box::new(ConsoleWidget::new(symbols.clone(), breakpoints)),
+        Box::new(ScriptWidget::new()),
     ];
 
     event_loop.run(move |event, _, control_flow| {
         let mut redraw = || {
             egui.begin_frame(&display);
 
-            for widget in widgets.iter() {
-                widget.draw(&mut egui, &mut cpu)
+            for widget in widgets.iter_mut() {
+                widget.draw(&mut egui, gameboy.cpu_mut())
             }
 
             let (needs_repaint, shapes) = egui.end_frame(&display);
@@ -131,6 +201,13 @@ pub fn run(rom: Vec<u8>) {
 
             glutin::event::Event::WindowEvent { event, .. } => {
                 if egui.is_quit_event(&event) {
+                    save_results(
+                        gameboy.cpu(),
+                        &symbols,
+                        profile.as_deref(),
+                        coverage.as_deref(),
+                    );
+
                     *control_flow = glium::glutin::event_loop::ControlFlow::Exit;
                 }
 
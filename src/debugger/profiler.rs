@@ -0,0 +1,122 @@
+use std::{path::PathBuf, rc::Rc};
+
+use crate::debugger::{DebuggerWidget, Navigation};
+use yeahboy::{
+    cpu::Cpu,
+    mmu::CYCLES_PER_FRAME,
+    profiler::{self, Location, Profiler},
+    symbols::SymbolTable,
+};
+
+/// Rows to show in each table
+const ROWS: usize = 20;
+
+pub struct ProfilerWidget {
+    symbols: Rc<SymbolTable>,
+    navigation: Navigation,
+    /// Where to save folded stacks, from --profile
+    output: Option<PathBuf>,
+}
+
+impl ProfilerWidget {
+    pub fn new(symbols: Rc<SymbolTable>, navigation: Navigation, output: Option<PathBuf>) -> Self {
+        Self {
+            symbols,
+            navigation,
+            output,
+        }
+    }
+
+    fn table(&self, ui: &mut egui::Ui, id: &str, rows: Vec<(Location, u64)>, frames: f64) {
+        egui::Grid::new(id)
+            .num_columns(4)
+            .spacing([20.0, 4.0])
+            .show(ui, |ui| {
+                for heading in ["Address", "Cycles", "Per frame", "% of frame"] {
+                    ui.label(heading);
+                }
+                ui.end_row();
+
+                for (location, cycles) in rows {
+                    let name = format!(
+                        "{:04x} ({})",
+                        location.addr,
+                        profiler::name(&self.symbols, location)
+                    );
+                    let label = egui::Label::new(name)
+                        .monospace()
+                        .text_color(egui::Color32::LIGHT_BLUE)
+                        .sense(egui::Sense::click());
+                    if ui.add(label).clicked() {
+                        self.navigation.set(Some(location.addr));
+                    }
+
+                    let per_frame = cycles as f64 / frames.max(1.0);
+                    ui.add(egui::Label::new(cycles.to_string()).monospace());
+                    ui.add(egui::Label::new(format!("{:.0}", per_frame)).monospace());
+                    ui.add(
+                        egui::Label::new(format!(
+                            "{:.1}%",
+                            per_frame / CYCLES_PER_FRAME as f64 * 100.0
+                        ))
+                        .monospace(),
+                    );
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+impl DebuggerWidget for ProfilerWidget {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+        egui::Window::new("Profiler").show(egui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                let mut enabled = cpu.profiler().is_some();
+                if ui.checkbox(&mut enabled, "Enabled").changed() {
+                    cpu.set_profiler(enabled.then(Profiler::new));
+                }
+
+                if ui.button("Reset").clicked() && enabled {
+                    cpu.set_profiler(Some(Profiler::new()));
+                }
+
+                if let (Some(path), Some(profiler)) = (&self.output, cpu.profiler()) {
+                    if ui.button("Save folded stacks").clicked() {
+                        match profiler.save_folded(path, &self.symbols) {
+                            Ok(()) => log::info!("saved folded stacks to {}", path.display()),
+                            Err(e) => log::warn!("couldn't save {}: {}", path.display(), e),
+                        }
+                    }
+                }
+            });
+
+            let profiler = match cpu.profiler() {
+                Some(profiler) => profiler,
+                None => return,
+            };
+
+            ui.add(
+                egui::Label::new(format!(
+                    "{} cycles, {:.1} frames",
+                    profiler.total(),
+                    profiler.frames()
+                ))
+                .monospace(),
+            );
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::CollapsingHeader::new("Routines (inclusive)")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        self.table(ui, "routines", profiler.routines(ROWS), profiler.frames())
+                    });
+
+                egui::CollapsingHeader::new("Hotspots")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        self.table(ui, "hotspots", profiler.hotspots(ROWS), profiler.frames())
+                    });
+            });
+        });
+    }
+}
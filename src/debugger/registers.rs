@@ -1,83 +1,147 @@
-use egui_glium::EguiGlium;
-use epi::NativeTexture;
-use std::rc::Rc;
-
-use crate::{
+use crate::debugger::DebuggerWidget;
+use yeahboy::{
     cpu::{Cpu, Flags},
-    debugger::{self, DebuggerWidget},
+    instructions::IRegister,
 };
 
-pub struct RegistersWidget {}
+/// Something the widget can edit
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    Register(IRegister),
+    F,
+    Pc,
+}
+
+pub struct RegistersWidget {
+    /// Register being edited and the text typed so far
+    editing: Option<(Target, String)>,
+}
 
 impl RegistersWidget {
     pub fn new() -> Self {
-        Self {}
+        Self { editing: None }
+    }
+
+    /// A register row that turns into a text field when clicked
+    fn value_row(
+        &mut self,
+        ui: &mut egui::Ui,
+        cpu: &mut Cpu,
+        name: &str,
+        target: Target,
+        value: u16,
+        width: usize,
+    ) {
+        ui.horizontal(|ui| {
+            ui.add(egui::Label::new(format!("{}:", name)).monospace());
+
+            match &mut self.editing {
+                Some((editing, text)) if *editing == target => {
+                    let response = ui.add(
+                        egui::TextEdit::singleline(text)
+                            .desired_width(60.0)
+                            .text_style(egui::TextStyle::Monospace),
+                    );
+                    response.request_focus();
+
+                    if response.lost_focus() {
+                        let text = text.trim().trim_start_matches("0x").trim_start_matches('$');
+                        match u16::from_str_radix(text, 16) {
+                            Ok(value) if width == 2 && value > 0xFF => {
+                                log::warn!("{} is 8-bit, ignoring {:04x}", name, value)
+                            }
+                            Ok(value) => match target {
+                                Target::Register(reg) => cpu.set_register(reg, value),
+                                Target::F => cpu.set_f(value as u8),
+                                Target::Pc => cpu.set_pc(value),
+                            },
+                            Err(_) => log::warn!("invalid value {} for {}", text, name),
+                        }
+                        self.editing = None;
+                    }
+                }
+                _ => {
+                    let label = egui::Label::new(format!("0x{:0width$x}", value, width = width))
+                        .monospace()
+                        .sense(egui::Sense::click());
+
+                    if ui.add(label).clicked() {
+                        self.editing = Some((target, format!("{:0width$x}", value, width = width)));
+                    }
+                }
+            }
+        });
     }
 }
 
 impl DebuggerWidget for RegistersWidget {
-    fn draw(&self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
         egui::Window::new("Registers").show(egui.ctx(), |ui| {
             let reg = cpu.reg();
+
+            let single_registers = [
+                ("a", Target::Register(IRegister::A), reg.a()),
+                ("f", Target::F, reg.f().into()),
+                ("b", Target::Register(IRegister::B), reg.b()),
+                ("c", Target::Register(IRegister::C), reg.c()),
+                ("d", Target::Register(IRegister::D), reg.d()),
+                ("e", Target::Register(IRegister::E), reg.e()),
+                ("h", Target::Register(IRegister::H), reg.h()),
+                ("l", Target::Register(IRegister::L), reg.l()),
+            ];
+
+            let double_registers = [
+                ("af", Target::Register(IRegister::AF), reg.af()),
+                ("bc", Target::Register(IRegister::BC), reg.bc()),
+                ("de", Target::Register(IRegister::DE), reg.de()),
+                ("hl", Target::Register(IRegister::HL), reg.hl()),
+            ];
+
+            let pc_sp = [
+                ("sp", Target::Register(IRegister::SP), cpu.sp()),
+                ("pc", Target::Pc, cpu.pc()),
+            ];
+
+            let flags = [
+                ("Z", Flags::ZERO),
+                ("N", Flags::SUBTRACT),
+                ("H", Flags::HALF_CARRY),
+                ("C", Flags::CARRY),
+            ];
+            let f = reg.f();
+
             ui.columns(3, |uis| {
                 let single_ui = &mut uis[0];
                 single_ui.heading("Single");
                 single_ui.separator();
-                let single_registers = [
-                    ("a", reg.a()),
-                    ("f", reg.f().into()),
-                    ("b", reg.b()),
-                    ("c", reg.c()),
-                    ("d", reg.d()),
-                    ("e", reg.e()),
-                    ("h", reg.h()),
-                    ("l", reg.l()),
-                ];
-
-                for (name, value) in single_registers.into_iter() {
-                    single_ui
-                        .add(egui::Label::new(format!("{}: 0x{:02x}", name, value)).monospace());
+
+                for (name, target, value) in single_registers.into_iter() {
+                    self.value_row(single_ui, cpu, name, target, value as u16, 2);
                 }
 
                 let double_ui = &mut uis[1];
                 double_ui.heading("Double");
                 double_ui.separator();
 
-                let double_registers = [
-                    ("af", reg.af()),
-                    ("bc", reg.bc()),
-                    ("de", reg.de()),
-                    ("hl", reg.hl()),
-                ];
-
-                for (name, value) in double_registers.into_iter() {
-                    double_ui
-                        .add(egui::Label::new(format!("{}: 0x{:04x}", name, value)).monospace());
+                for (name, target, value) in double_registers.into_iter() {
+                    self.value_row(double_ui, cpu, name, target, value, 4);
                 }
 
                 double_ui.heading("PC/SP");
                 double_ui.separator();
 
-                let pc_sp = [("sp", cpu.sp()), ("pc", cpu.pc())];
-
-                for (name, value) in pc_sp.into_iter() {
-                    double_ui
-                        .add(egui::Label::new(format!("{}: 0x{:04x}", name, value)).monospace());
+                for (name, target, value) in pc_sp.into_iter() {
+                    self.value_row(double_ui, cpu, name, target, value, 4);
                 }
 
+                double_ui.add(egui::Label::new(format!("ime: {}", cpu.ime() as u8)).monospace());
+
                 let flags_ui = &mut uis[2];
                 flags_ui.heading("Flags");
                 flags_ui.separator();
 
-                let flags = [
-                    ("Z", Flags::ZERO),
-                    ("S", Flags::SUBTRACT),
-                    ("H", Flags::HALF_CARRY),
-                    ("C", Flags::CARRY),
-                ];
-
                 for (name, flag) in flags.into_iter() {
-                    let (color, value) = if reg.f().contains(flag) {
+                    let (color, value) = if f.contains(flag) {
                         (
                             egui::Color32::from_rgba_premultiplied(0x40, 0xd0, 0x60, 0xff),
                             1,
@@ -89,11 +153,15 @@ impl DebuggerWidget for RegistersWidget {
                         )
                     };
 
-                    flags_ui.add(
-                        egui::Label::new(format!("{}: {}", name, value))
-                            .text_color(color)
-                            .monospace(),
-                    );
+                    let label = egui::Label::new(format!("{}: {}", name, value))
+                        .text_color(color)
+                        .monospace()
+                        .sense(egui::Sense::click());
+
+                    // Click to toggle
+                    if flags_ui.add(label).clicked() {
+                        cpu.set_flag(flag, value == 0);
+                    }
                 }
             });
         });
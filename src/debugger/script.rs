@@ -0,0 +1,84 @@
+use crate::debugger::{
+    texture::{Texture, SHADES},
+    DebuggerWidget,
+};
+use yeahboy::{cpu::Cpu, render_screen, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Screen pixels per Game Boy pixel
+const SCALE: usize = 2;
+
+/// Printed lines to keep
+const MAX_LINES: usize = 500;
+
+pub struct ScriptWidget {
+    /// What the script printed, oldest first
+    lines: Vec<String>,
+
+    /// The screen, for drawing the overlay on
+    screen: Texture,
+}
+
+impl ScriptWidget {
+    pub fn new() -> Self {
+        Self {
+            lines: Vec::new(),
+            screen: Texture::new(),
+        }
+    }
+}
+
+impl DebuggerWidget for ScriptWidget {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+        let mut framebuffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        render_screen(cpu.mmu(), &mut framebuffer);
+        let pixels = framebuffer
+            .iter()
+            .map(|&shade| SHADES[shade as usize])
+            .collect();
+        let screen_id = self.screen.update(egui, SCREEN_WIDTH, pixels, SCALE);
+        let screen_size = self.screen.size() * SCALE as f32;
+
+        egui::Window::new("Script").show(egui.ctx(), |ui| {
+            let script = match cpu.script_mut() {
+                Some(script) => script,
+                None => {
+                    ui.label("No script loaded. Start with --script to load one.");
+                    return;
+                }
+            };
+
+            self.lines.extend(script.take_output());
+            let excess = self.lines.len().saturating_sub(MAX_LINES);
+            self.lines.drain(..excess);
+
+            let response = ui.add(egui::Image::new(screen_id, screen_size));
+            let painter = ui.painter_at(response.rect);
+            for text in script.overlay() {
+                let pos = egui::vec2(text.x as f32, text.y as f32) * SCALE as f32;
+                painter.text(
+                    response.rect.min + pos,
+                    egui::Align2::LEFT_TOP,
+                    text.text,
+                    egui::TextStyle::Monospace,
+                    egui::Color32::RED,
+                );
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Output");
+                if ui.small_button("Clear").clicked() {
+                    self.lines.clear();
+                }
+            });
+
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .stick_to_bottom()
+                .show(ui, |ui| {
+                    for line in &self.lines {
+                        ui.add(egui::Label::new(line).monospace());
+                    }
+                });
+        });
+    }
+}
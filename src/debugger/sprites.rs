@@ -0,0 +1,164 @@
+use crate::debugger::{
+    texture::{Texture, SHADES},
+    DebuggerWidget,
+};
+use yeahboy::{
+    cpu::Cpu,
+    io::{self, Lcdc},
+    vram::{self, Palette, Sprite, SpriteFlags},
+};
+
+/// Scale the previews up by this much
+const SCALE: usize = 3;
+
+pub struct SpritesWidget {
+    /// Every sprite's preview side by side, each in a 8x16 cell
+    texture: Texture,
+}
+
+impl SpritesWidget {
+    pub fn new() -> Self {
+        Self {
+            texture: Texture::new(),
+        }
+    }
+
+    fn pixels(cpu: &Cpu, sprites: &[Sprite], tall: bool) -> Vec<egui::Color32> {
+        let mmu = cpu.mmu();
+        let width = sprites.len() * 8;
+        let mut pixels = vec![egui::Color32::TRANSPARENT; width * 16];
+
+        for (entry, sprite) in sprites.iter().enumerate() {
+            let palette = match sprite.flags.contains(SpriteFlags::PALETTE) {
+                true => Palette::Obp1,
+                false => Palette::Obp0,
+            }
+            .value(|addr| mmu.rb(addr as usize));
+
+            for (i, &index) in sprite.pixels(mmu.vram(), tall).iter().enumerate() {
+                // Color 0 is transparent for sprites
+                if index != 0 {
+                    let (x, y) = (entry * 8 + i % 8, i / 8);
+                    pixels[y * width + x] = SHADES[vram::shade(palette, index) as usize];
+                }
+            }
+        }
+
+        pixels
+    }
+
+    fn flags(flags: SpriteFlags, cgb: bool) -> String {
+        let mut names = Vec::new();
+
+        if flags.contains(SpriteFlags::BG_PRIORITY) {
+            names.push("behind BG".to_string());
+        }
+        if flags.contains(SpriteFlags::X_FLIP) {
+            names.push("X flip".to_string());
+        }
+        if flags.contains(SpriteFlags::Y_FLIP) {
+            names.push("Y flip".to_string());
+        }
+
+        match cgb {
+            true => {
+                let palette = (flags & SpriteFlags::CGB_PALETTE).bits();
+                let bank = flags.contains(SpriteFlags::BANK) as u8;
+                names.push(format!("OBP{} bank {}", palette, bank));
+            }
+            false => match flags.contains(SpriteFlags::PALETTE) {
+                true => names.push("OBP1".to_string()),
+                false => names.push("OBP0".to_string()),
+            },
+        }
+
+        names.join(", ")
+    }
+}
+
+impl DebuggerWidget for SpritesWidget {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+        let mmu = cpu.mmu();
+        let lcdc = Lcdc::from_bits_truncate(mmu.rb(io::LCDC as usize));
+        let tall = lcdc.contains(Lcdc::OBJ_SIZE);
+        let cgb = mmu.vram().len() > vram::BANK_SIZE;
+
+        let sprites = Sprite::all(mmu.oam());
+        let dropped = vram::dropped_sprites(&sprites, tall);
+
+        let pixels = Self::pixels(cpu, &sprites, tall);
+        let texture_id = self.texture.update(egui, sprites.len() * 8, pixels, SCALE);
+        let height = vram::height(tall) as f32;
+
+        egui::Window::new("Sprites").show(egui.ctx(), |ui| {
+            ui.label(format!(
+                "8x{} sprites{}",
+                vram::height(tall),
+                match lcdc.contains(Lcdc::OBJ_ENABLE) {
+                    true => "",
+                    false => " (disabled)",
+                }
+            ));
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("sprites")
+                    .num_columns(7)
+                    .spacing([20.0, 4.0])
+                    .show(ui, |ui| {
+                        for heading in ["#", "Y", "X", "Tile", "Flags", "", ""] {
+                            ui.label(heading);
+                        }
+                        ui.end_row();
+
+                        for (entry, sprite) in sprites.iter().enumerate() {
+                            let color = match sprite.visible(tall) {
+                                true => egui::Color32::LIGHT_GREEN,
+                                false => egui::Color32::GRAY,
+                            };
+
+                            let cells = [
+                                format!("{:02}", entry),
+                                format!("{:3}", sprite.y),
+                                format!("{:3}", sprite.x),
+                                format!("{:02x}", sprite.tile),
+                                Self::flags(sprite.flags, cgb),
+                            ];
+                            for cell in cells {
+                                ui.add(egui::Label::new(cell).monospace().text_color(color));
+                            }
+
+                            // Crop this sprite's cell out of the strip
+                            let uv = egui::Rect::from_min_max(
+                                egui::pos2(entry as f32 / sprites.len() as f32, 0.0),
+                                egui::pos2(
+                                    (entry + 1) as f32 / sprites.len() as f32,
+                                    height / 16.0,
+                                ),
+                            );
+                            ui.add(
+                                egui::Image::new(
+                                    texture_id,
+                                    egui::vec2(8.0, height) * SCALE as f32,
+                                )
+                                .uv(uv)
+                                .bg_fill(egui::Color32::DARK_GRAY),
+                            );
+
+                            if dropped[entry] {
+                                ui.add(
+                                    egui::Label::new("dropped")
+                                        .text_color(egui::Color32::RED)
+                                        .monospace(),
+                                )
+                                .on_hover_text("More than 10 sprites on one of its lines");
+                            } else {
+                                ui.label("");
+                            }
+
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+    }
+}
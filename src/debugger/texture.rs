@@ -0,0 +1,70 @@
+use egui_glium::EguiGlium;
+
+/// Shades for DMG colors 0-3, lightest first
+pub const SHADES: [egui::Color32; 4] = [
+    egui::Color32::from_rgb(0xE0, 0xF8, 0xD0),
+    egui::Color32::from_rgb(0x88, 0xC0, 0x70),
+    egui::Color32::from_rgb(0x34, 0x68, 0x56),
+    egui::Color32::from_rgb(0x08, 0x18, 0x20),
+];
+
+/// A texture drawn by a widget, re-uploaded when its pixels change
+pub struct Texture {
+    id: Option<egui::TextureId>,
+    pixels: Vec<egui::Color32>,
+    width: usize,
+    height: usize,
+}
+
+impl Texture {
+    pub fn new() -> Self {
+        Self {
+            id: None,
+            pixels: Vec::new(),
+            width: 0,
+            height: 0,
+        }
+    }
+
+    /// Sets the texture to `pixels`, `width` wide. The painter filters linearly, so the pixels
+    /// are scaled up by `scale` to keep them sharp.
+    pub fn update(
+        &mut self,
+        egui: &mut EguiGlium,
+        width: usize,
+        pixels: Vec<egui::Color32>,
+        scale: usize,
+    ) -> egui::TextureId {
+        let painter = egui.painter_mut();
+        let id = *self.id.get_or_insert_with(|| painter.alloc_user_texture());
+
+        if pixels == self.pixels && width == self.width {
+            return id;
+        }
+
+        let height = pixels.len() / width;
+        let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+        for row in pixels.chunks(width) {
+            let scaled_row: Vec<_> = row
+                .iter()
+                .flat_map(|&pixel| std::iter::repeat_n(pixel, scale))
+                .collect();
+            for _ in 0..scale {
+                scaled.extend_from_slice(&scaled_row);
+            }
+        }
+
+        painter.set_user_texture(id, (width * scale, height * scale), &scaled);
+
+        self.pixels = pixels;
+        self.width = width;
+        self.height = height;
+
+        id
+    }
+
+    /// Size in unscaled pixels
+    pub fn size(&self) -> egui::Vec2 {
+        egui::Vec2::new(self.width as f32, self.height as f32)
+    }
+}
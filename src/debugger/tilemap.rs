@@ -0,0 +1,174 @@
+use crate::debugger::{
+    texture::{Texture, SHADES},
+    DebuggerWidget,
+};
+use yeahboy::{
+    cpu::Cpu,
+    io::{self, Lcdc},
+    vram::{self, Palette},
+};
+
+/// Scale the texture up by this much
+const SCALE: usize = 2;
+
+const SCREEN_WIDTH: f32 = 160.0;
+const SCREEN_HEIGHT: f32 = 144.0;
+
+pub struct TileMapWidget {
+    texture: Texture,
+
+    /// Tilemap to show, or the one LCDC bit 3 selects if `None`
+    map: Option<u16>,
+    /// Whether to use 0x8000 tile data addressing, or what LCDC bit 4 says if `None`
+    unsigned_data: Option<bool>,
+
+    /// Clicked cell, as (column, row)
+    selected: Option<(usize, usize)>,
+}
+
+impl TileMapWidget {
+    pub fn new() -> Self {
+        Self {
+            texture: Texture::new(),
+            map: None,
+            unsigned_data: None,
+            selected: None,
+        }
+    }
+
+    /// LCDC with the manual overrides applied, and the map to draw
+    fn settings(&self, cpu: &Cpu) -> (Lcdc, u16) {
+        let mut lcdc = Lcdc::from_bits_truncate(cpu.mmu().rb(io::LCDC as usize));
+
+        if let Some(unsigned) = self.unsigned_data {
+            lcdc.set(Lcdc::BG_TILE_DATA, unsigned);
+        }
+
+        let map = self.map.unwrap_or(match lcdc.contains(Lcdc::BG_TILE_MAP) {
+            true => 0x9C00,
+            false => 0x9800,
+        });
+
+        (lcdc, map)
+    }
+
+    fn describe(&self, cpu: &Cpu, map: u16, lcdc: Lcdc, col: usize, row: usize) -> String {
+        let vram = cpu.mmu().vram();
+        let addr = map + (row * 32 + col) as u16;
+        let index = vram[addr as usize - 0x8000];
+
+        let mut text = format!(
+            "Cell ({}, {}) at {:04x}\nTile {:02x}, data at {:04x}",
+            col,
+            row,
+            addr,
+            index,
+            vram::tile_data_address(index, lcdc)
+        );
+
+        // CGB keeps the attributes in VRAM bank 1 at the same address
+        if let Some(attributes) = vram.get(vram::BANK_SIZE + addr as usize - 0x8000) {
+            text.push_str(&format!("\nAttributes {:02x}", attributes));
+        }
+
+        text
+    }
+}
+
+impl DebuggerWidget for TileMapWidget {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+        let mmu = cpu.mmu();
+        let (lcdc, map) = self.settings(cpu);
+        let palette = Palette::Bgp.value(|addr| mmu.rb(addr as usize));
+
+        let pixels = vram::render_tile_map(mmu.vram(), map, lcdc)
+            .iter()
+            .map(|&index| SHADES[vram::shade(palette, index) as usize])
+            .collect();
+        let texture_id = self.texture.update(egui, 256, pixels, SCALE);
+        let size = self.texture.size() * SCALE as f32;
+
+        let (scx, scy) = (mmu.rb(io::SCX as usize), mmu.rb(io::SCY as usize));
+        let (wx, wy) = (mmu.rb(io::WX as usize), mmu.rb(io::WY as usize));
+        let window_map = match lcdc.contains(Lcdc::WINDOW_TILE_MAP) {
+            true => 0x9C00,
+            false => 0x9800,
+        };
+
+        egui::Window::new("Tilemap").show(egui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Map");
+                ui.radio_value(&mut self.map, None, "LCDC");
+                ui.radio_value(&mut self.map, Some(0x9800), "9800");
+                ui.radio_value(&mut self.map, Some(0x9C00), "9C00");
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Tile data");
+                ui.radio_value(&mut self.unsigned_data, None, "LCDC");
+                ui.radio_value(&mut self.unsigned_data, Some(true), "8000");
+                ui.radio_value(&mut self.unsigned_data, Some(false), "8800");
+            });
+
+            ui.add(
+                egui::Label::new(format!(
+                    "SCX {} SCY {}, window at ({}, {}) from {:04x}{}",
+                    scx,
+                    scy,
+                    wx as i16 - 7,
+                    wy,
+                    window_map,
+                    match lcdc.contains(Lcdc::WINDOW_ENABLE) {
+                        true => "",
+                        false => " (disabled)",
+                    }
+                ))
+                .monospace(),
+            );
+
+            let response = ui.add(egui::Image::new(texture_id, size).sense(egui::Sense::click()));
+            let painter = ui.painter_at(response.rect);
+            let to_screen = |x: f32, y: f32| response.rect.min + egui::vec2(x, y) * SCALE as f32;
+
+            // The viewport wraps around, so draw it shifted by the map size as well
+            let viewport = egui::Stroke::new(2.0, egui::Color32::RED);
+            for (dx, dy) in [(0.0, 0.0), (-256.0, 0.0), (0.0, -256.0), (-256.0, -256.0)] {
+                let min = to_screen(scx as f32 + dx, scy as f32 + dy);
+                let max = to_screen(
+                    scx as f32 + dx + SCREEN_WIDTH,
+                    scy as f32 + dy + SCREEN_HEIGHT,
+                );
+                painter.rect_stroke(egui::Rect::from_min_max(min, max), 0.0, viewport);
+            }
+
+            // The window always starts at the top left of its map
+            if lcdc.contains(Lcdc::WINDOW_ENABLE) && map == window_map {
+                let width = SCREEN_WIDTH - (wx as f32 - 7.0);
+                let height = SCREEN_HEIGHT - wy as f32;
+                if width > 0.0 && height > 0.0 {
+                    let rect =
+                        egui::Rect::from_min_max(to_screen(0.0, 0.0), to_screen(width, height));
+                    painter.rect_stroke(rect, 0.0, egui::Stroke::new(2.0, egui::Color32::BLUE));
+                }
+            }
+
+            if let Some(pos) = response.interact_pointer_pos() {
+                if response.clicked() {
+                    let offset = (pos - response.rect.min) / (SCALE * 8) as f32;
+                    self.selected =
+                        Some(((offset.x as usize).min(31), (offset.y as usize).min(31)));
+                }
+            }
+
+            if let Some((col, row)) = self.selected {
+                let cell = egui::Rect::from_min_size(
+                    to_screen(col as f32 * 8.0, row as f32 * 8.0),
+                    egui::vec2(8.0, 8.0) * SCALE as f32,
+                );
+                painter.rect_stroke(cell, 0.0, egui::Stroke::new(2.0, egui::Color32::YELLOW));
+
+                ui.add(egui::Label::new(self.describe(cpu, map, lcdc, col, row)).monospace());
+            }
+        });
+    }
+}
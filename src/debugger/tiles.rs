@@ -0,0 +1,125 @@
+use crate::debugger::{
+    texture::{Texture, SHADES},
+    DebuggerWidget,
+};
+use yeahboy::{
+    cpu::Cpu,
+    io::{self, Lcdc},
+    vram::{self, Palette},
+};
+
+/// Tiles per row of the grid
+const TILES_PER_ROW: usize = 16;
+
+/// Scale the texture up by this much
+const SCALE: usize = 3;
+
+pub struct TilesWidget {
+    texture: Texture,
+    palette: Palette,
+}
+
+impl TilesWidget {
+    pub fn new() -> Self {
+        Self {
+            texture: Texture::new(),
+            palette: Palette::Bgp,
+        }
+    }
+
+    /// Every tile in every bank, banks side by side
+    fn pixels(&self, cpu: &Cpu) -> (usize, Vec<egui::Color32>) {
+        let mmu = cpu.mmu();
+        let vram = mmu.vram();
+        let banks = vram.len() / vram::BANK_SIZE;
+        let palette = self.palette.value(|addr| mmu.rb(addr as usize));
+
+        let width = TILES_PER_ROW * 8 * banks;
+        let height = vram::TILES_PER_BANK / TILES_PER_ROW * 8;
+        let mut pixels = vec![SHADES[0]; width * height];
+
+        for bank in 0..banks {
+            for tile in 0..vram::TILES_PER_BANK {
+                let offset = bank * vram::BANK_SIZE + tile * vram::TILE_SIZE;
+                let x0 = (bank * TILES_PER_ROW + tile % TILES_PER_ROW) * 8;
+                let y0 = tile / TILES_PER_ROW * 8;
+
+                for (i, &index) in vram::tile_pixels(vram, offset).iter().enumerate() {
+                    let (x, y) = (x0 + i % 8, y0 + i / 8);
+                    pixels[y * width + x] = SHADES[vram::shade(palette, index) as usize];
+                }
+            }
+        }
+
+        (width, pixels)
+    }
+
+    /// Describes the tile at a point in the grid, for the hover text
+    fn describe(cpu: &Cpu, x: usize, y: usize) -> String {
+        let mmu = cpu.mmu();
+        let bank = x / (TILES_PER_ROW * 8);
+        let tile = y / 8 * TILES_PER_ROW + x % (TILES_PER_ROW * 8) / 8;
+        let addr = 0x8000 + (tile * vram::TILE_SIZE) as u16;
+        let lcdc = Lcdc::from_bits_truncate(mmu.rb(io::LCDC as usize));
+
+        let mut text = format!("Tile {} (bank {})\nAddress {:04x}", tile, bank, addr);
+
+        if let Some(index) = vram::bg_tile_index(addr, lcdc) {
+            text.push_str(&format!("\nBG/window index {:02x}", index));
+        }
+
+        // Tilemaps and OAM only refer to bank 0 on DMG
+        if bank != 0 {
+            return text;
+        }
+
+        let map_uses = vram::tile_map_uses(mmu.vram(), addr, lcdc);
+        if !map_uses.is_empty() {
+            let cells: Vec<_> = map_uses
+                .iter()
+                .take(8)
+                .map(|(map, col, row)| format!("{:04x}:({},{})", map, col, row))
+                .collect();
+            let more = match map_uses.len() > cells.len() {
+                true => format!(" and {} more", map_uses.len() - cells.len()),
+                false => String::new(),
+            };
+            text.push_str(&format!("\nTilemap: {}{}", cells.join(", "), more));
+        }
+
+        let sprites = vram::sprite_uses(mmu.oam(), addr, lcdc);
+        if !sprites.is_empty() {
+            let entries: Vec<_> = sprites.iter().map(|entry| entry.to_string()).collect();
+            text.push_str(&format!("\nSprites: {}", entries.join(", ")));
+        }
+
+        text
+    }
+}
+
+impl DebuggerWidget for TilesWidget {
+    fn draw(&mut self, egui: &mut egui_glium::EguiGlium, cpu: &mut Cpu) {
+        let (width, pixels) = self.pixels(cpu);
+        let texture_id = self.texture.update(egui, width, pixels, SCALE);
+        let size = self.texture.size() * SCALE as f32;
+
+        egui::Window::new("VRAM Tiles").show(egui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                for palette in Palette::ALL {
+                    ui.radio_value(&mut self.palette, palette, palette.to_string());
+                }
+            });
+
+            let response = ui.image(texture_id, size);
+
+            if let Some(pos) = response.hover_pos() {
+                let offset = (pos - response.rect.min) / SCALE as f32;
+                let (x, y) = (offset.x as usize, offset.y as usize);
+
+                if (x as f32) < self.texture.size().x && (y as f32) < self.texture.size().y {
+                    response.on_hover_text(Self::describe(cpu, x, y));
+                }
+            }
+        });
+    }
+}
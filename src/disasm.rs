@@ -0,0 +1,410 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::{
+    instructions::{DecodedInstruction, IAction, IFlag, ILocation, IRegister},
+    io,
+    symbols::SymbolTable,
+};
+
+/// Assembler dialect to print instructions in
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Syntax {
+    /// RGBDS: `LD A, [HL+]`, `LDH [rLCDC], A`, `$`-prefixed hex
+    Rgbds,
+    /// no$gmb: `ldi a,(hl)`, `ld (rLCDC),a`, bare hex
+    NoCash,
+}
+
+impl Display for Syntax {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Syntax::Rgbds => "RGBDS",
+                Syntax::NoCash => "no$gmb",
+            }
+        )
+    }
+}
+
+/// Absolute address that the relative jump `inst`, decoded at `addr`, lands on
+pub fn relative_target(inst: &DecodedInstruction, addr: u16) -> u16 {
+    let offset = inst.operands_as_u8() as i8;
+    addr.wrapping_add(inst.len() as u16)
+        .wrapping_add(offset as u16)
+}
+
+/// Formats decoded instructions with their operands resolved, e.g. `LD A, $3E` instead of
+/// `LD A, u8`.
+pub struct Formatter {
+    syntax: Syntax,
+    symbols: Rc<SymbolTable>,
+    rom_bank: u16,
+}
+
+impl Formatter {
+    pub fn new(syntax: Syntax) -> Self {
+        Self::with_symbols(syntax, Rc::new(SymbolTable::new()))
+    }
+
+    /// Creates a formatter that prints jump and call targets as labels where it can
+    pub fn with_symbols(syntax: Syntax, symbols: Rc<SymbolTable>) -> Self {
+        Self {
+            syntax,
+            symbols,
+            rom_bank: 1,
+        }
+    }
+
+    /// Sets the ROM bank that targets in 0x4000-0x7FFF are looked up in
+    pub fn set_rom_bank(&mut self, rom_bank: u16) {
+        self.rom_bank = rom_bank
+    }
+
+    pub fn syntax(&self) -> Syntax {
+        self.syntax
+    }
+
+    pub fn set_syntax(&mut self, syntax: Syntax) {
+        self.syntax = syntax
+    }
+
+    /// Formats `inst`, which was decoded at `addr`. The address is needed to resolve relative
+    /// jump targets.
+    pub fn format(&self, inst: &DecodedInstruction, addr: u16) -> String {
+        let (mnemonic, operands) = self.parts(inst, addr);
+
+        let mnemonic = match self.syntax {
+            Syntax::Rgbds => mnemonic.to_string(),
+            Syntax::NoCash => mnemonic.to_lowercase(),
+        };
+
+        if operands.is_empty() {
+            return mnemonic;
+        }
+
+        let separator = match self.syntax {
+            Syntax::Rgbds => ", ",
+            Syntax::NoCash => ",",
+        };
+
+        format!("{} {}", mnemonic, operands.join(separator))
+    }
+
+    /// Formats raw bytes as a data directive, e.g. `DB $12, $34`
+    pub fn data(&self, bytes: &[u8]) -> String {
+        let bytes: Vec<String> = bytes.iter().map(|&byte| self.byte(byte)).collect();
+
+        match self.syntax {
+            Syntax::Rgbds => format!("DB {}", bytes.join(", ")),
+            Syntax::NoCash => format!("db {}", bytes.join(",")),
+        }
+    }
+
+    /// Splits an instruction into its mnemonic and formatted operands
+    fn parts(&self, inst: &DecodedInstruction, addr: u16) -> (&'static str, Vec<String>) {
+        let loc = |loc| self.location(inst, loc);
+
+        match inst.action() {
+            IAction::NOP => ("NOP", vec![]),
+            IAction::LD(dst, src) => (self.ld_mnemonic(dst, src), vec![loc(dst), loc(src)]),
+            IAction::LD16(dst, src) => ("LD", vec![loc(dst), loc(src)]),
+            IAction::JP(flag, ILocation::ImmediateWord) => {
+                let target = self.target(inst.operands_as_u16());
+                ("JP", self.conditional(flag, target))
+            }
+            IAction::JP(flag, target) => ("JP", self.conditional(flag, loc(target))),
+            IAction::JR(flag, _) => {
+                let target = self.target(relative_target(inst, addr));
+                ("JR", self.conditional(flag, target))
+            }
+            IAction::CALL(flag, _) => {
+                let target = self.target(inst.operands_as_u16());
+                ("CALL", self.conditional(flag, target))
+            }
+            IAction::RET(IFlag::TRUE) => ("RET", vec![]),
+            IAction::RET(flag) => ("RET", vec![self.condition(flag)]),
+            IAction::RETI => ("RETI", vec![]),
+            IAction::RST(vector) => ("RST", vec![self.byte(vector)]),
+            IAction::PUSH(src) => ("PUSH", vec![loc(src)]),
+            IAction::POP(dst) => ("POP", vec![loc(dst)]),
+            IAction::CPL => ("CPL", vec![]),
+            IAction::CCF => ("CCF", vec![]),
+            IAction::SCF => ("SCF", vec![]),
+            IAction::DAA => ("DAA", vec![]),
+            IAction::RLCA => ("RLCA", vec![]),
+            IAction::RRCA => ("RRCA", vec![]),
+            IAction::RLA => ("RLA", vec![]),
+            IAction::RRA => ("RRA", vec![]),
+            IAction::HALT => ("HALT", vec![]),
+            IAction::STOP => ("STOP", vec![]),
+            IAction::DI => ("DI", vec![]),
+            IAction::EI => ("EI", vec![]),
+            IAction::INC(dst) | IAction::INC16(dst) => ("INC", vec![loc(dst)]),
+            IAction::DEC(dst) | IAction::DEC16(dst) => ("DEC", vec![loc(dst)]),
+            IAction::ADD(dst, src) | IAction::ADD16(dst, src) => ("ADD", vec![loc(dst), loc(src)]),
+            IAction::ADC(dst, src) => ("ADC", vec![loc(dst), loc(src)]),
+            IAction::SUB(dst, src) => ("SUB", vec![loc(dst), loc(src)]),
+            IAction::SBC(dst, src) => ("SBC", vec![loc(dst), loc(src)]),
+            IAction::AND(dst, src) => ("AND", vec![loc(dst), loc(src)]),
+            IAction::XOR(dst, src) => ("XOR", vec![loc(dst), loc(src)]),
+            IAction::OR(dst, src) => ("OR", vec![loc(dst), loc(src)]),
+            IAction::CP(dst, src) => ("CP", vec![loc(dst), loc(src)]),
+            IAction::RLC(dst) => ("RLC", vec![loc(dst)]),
+            IAction::RRC(dst) => ("RRC", vec![loc(dst)]),
+            IAction::RL(dst) => ("RL", vec![loc(dst)]),
+            IAction::RR(dst) => ("RR", vec![loc(dst)]),
+            IAction::SLA(dst) => ("SLA", vec![loc(dst)]),
+            IAction::SRA(dst) => ("SRA", vec![loc(dst)]),
+            IAction::SWAP(dst) => ("SWAP", vec![loc(dst)]),
+            IAction::SRL(dst) => ("SRL", vec![loc(dst)]),
+            IAction::BIT(bit, dst) => ("BIT", vec![bit.to_string(), loc(dst)]),
+            IAction::RES(bit, dst) => ("RES", vec![bit.to_string(), loc(dst)]),
+            IAction::SET(bit, dst) => ("SET", vec![bit.to_string(), loc(dst)]),
+            // Anything we can't decode is emitted as raw data so that it still round-trips
+            IAction::PREFIX | IAction::ILLEGAL => (
+                "DB",
+                inst.raw_bytes()
+                    .iter()
+                    .map(|&byte| self.byte(byte))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn ld_mnemonic(&self, dst: ILocation, src: ILocation) -> &'static str {
+        let high_page = |loc| {
+            matches!(
+                loc,
+                ILocation::ImmediateByteIndirectByte
+                    | ILocation::RegisterIndirectByte(IRegister::C)
+            )
+        };
+        let post_op = |loc| match loc {
+            ILocation::RegisterIndirectByte(IRegister::HL_INC) => Some("LDI"),
+            ILocation::RegisterIndirectByte(IRegister::HL_DEC) => Some("LDD"),
+            _ => None,
+        };
+
+        match self.syntax {
+            Syntax::Rgbds if high_page(dst) || high_page(src) => "LDH",
+            Syntax::NoCash => post_op(dst).or_else(|| post_op(src)).unwrap_or("LD"),
+            _ => "LD",
+        }
+    }
+
+    /// A jump or call target, by label if there is one
+    fn target(&self, addr: u16) -> String {
+        match self.symbols.label(addr, self.rom_bank) {
+            Some(label) => label.to_string(),
+            None => self.word(addr),
+        }
+    }
+
+    fn conditional(&self, flag: IFlag, target: String) -> Vec<String> {
+        match flag {
+            IFlag::TRUE => vec![target],
+            _ => vec![self.condition(flag), target],
+        }
+    }
+
+    fn condition(&self, flag: IFlag) -> String {
+        let condition = match flag {
+            IFlag::TRUE => "",
+            IFlag::Z => "Z",
+            IFlag::NZ => "NZ",
+            IFlag::CY => "C",
+            IFlag::NCY => "NC",
+        };
+
+        self.case(condition)
+    }
+
+    fn register(&self, reg: IRegister) -> String {
+        let name = match reg {
+            // The post-op is carried by the mnemonic in no$gmb syntax
+            IRegister::HL_INC | IRegister::HL_DEC if self.syntax == Syntax::NoCash => {
+                "HL".to_string()
+            }
+            _ => reg.to_string(),
+        };
+
+        self.case(&name)
+    }
+
+    fn location(&self, inst: &DecodedInstruction, loc: ILocation) -> String {
+        match loc {
+            ILocation::Register(reg) => self.register(reg),
+            ILocation::RegisterIndirectByte(IRegister::C) => match self.syntax {
+                Syntax::Rgbds => "[C]".to_string(),
+                Syntax::NoCash => "(ff00+c)".to_string(),
+            },
+            ILocation::RegisterIndirectByte(reg) | ILocation::RegisterIndirectWord(reg) => {
+                self.indirect(&self.register(reg))
+            }
+            ILocation::ImmediateByte => self.byte(inst.operands_as_u8()),
+            ILocation::ImmediateWord => self.word(inst.operands_as_u16()),
+            ILocation::ImmediateByteIndirectByte | ILocation::ImmediateByteIndirectWord => {
+                self.high_page(inst.operands_as_u8())
+            }
+            ILocation::ImmediateWordIndirectByte | ILocation::ImmediateWordIndirectWord => {
+                self.indirect(&self.word(inst.operands_as_u16()))
+            }
+            ILocation::ImmediateSignedByte => self.signed(inst.operands_as_u8() as i8),
+            ILocation::StackPointerOffset => {
+                let offset = self.signed(inst.operands_as_u8() as i8);
+                let sp = self.case("SP");
+                if offset.starts_with('-') {
+                    format!("{}{}", sp, offset)
+                } else {
+                    format!("{}+{}", sp, offset)
+                }
+            }
+        }
+    }
+
+    /// An address in 0xFF00-0xFFFF, by name if it's a known IO register
+    fn high_page(&self, offset: u8) -> String {
+        let addr = 0xFF00 | offset as u16;
+
+        match (io::register_name(addr), self.syntax) {
+            (Some(name), _) => self.indirect(name),
+            (None, Syntax::Rgbds) => self.indirect(&format!("$FF00+{}", self.byte(offset))),
+            (None, Syntax::NoCash) => self.indirect(&format!("ff00+{}", self.byte(offset))),
+        }
+    }
+
+    fn indirect(&self, inner: &str) -> String {
+        match self.syntax {
+            Syntax::Rgbds => format!("[{}]", inner),
+            Syntax::NoCash => format!("({})", inner),
+        }
+    }
+
+    fn case(&self, text: &str) -> String {
+        match self.syntax {
+            Syntax::Rgbds => text.to_string(),
+            Syntax::NoCash => text.to_lowercase(),
+        }
+    }
+
+    pub fn byte(&self, value: u8) -> String {
+        match self.syntax {
+            Syntax::Rgbds => format!("${:02X}", value),
+            Syntax::NoCash => format!("{:02X}", value),
+        }
+    }
+
+    pub fn word(&self, value: u16) -> String {
+        match self.syntax {
+            Syntax::Rgbds => format!("${:04X}", value),
+            Syntax::NoCash => format!("{:04X}", value),
+        }
+    }
+
+    fn signed(&self, value: i8) -> String {
+        let magnitude = self.byte(value.unsigned_abs());
+        if value < 0 {
+            format!("-{}", magnitude)
+        } else {
+            magnitude
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::{Formatter, Syntax};
+    use crate::{instructions::DecodedInstruction, mmu::Mmu, symbols::SymbolTable};
+
+    fn disassemble(bytes: &[u8], syntax: Syntax) -> String {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0150..0x0150 + bytes.len()].copy_from_slice(bytes);
+        let mmu = Mmu::new(rom);
+
+        let inst = DecodedInstruction::decode(&mmu, 0x0150);
+        Formatter::new(syntax).format(&inst, 0x0150)
+    }
+
+    #[test]
+    fn immediates() {
+        assert_eq!(disassemble(&[0x3E, 0x3E], Syntax::Rgbds), "LD A, $3E");
+        assert_eq!(
+            disassemble(&[0x21, 0x00, 0xC0], Syntax::Rgbds),
+            "LD HL, $C000"
+        );
+        assert_eq!(
+            disassemble(&[0xEA, 0x34, 0x12], Syntax::Rgbds),
+            "LD [$1234], A"
+        );
+        assert_eq!(disassemble(&[0x3E, 0x3E], Syntax::NoCash), "ld a,3E");
+        assert_eq!(
+            disassemble(&[0xEA, 0x34, 0x12], Syntax::NoCash),
+            "ld (1234),a"
+        );
+    }
+
+    #[test]
+    fn relative_jumps() {
+        assert_eq!(disassemble(&[0x18, 0xFE], Syntax::Rgbds), "JR $0150");
+        assert_eq!(disassemble(&[0x20, 0x10], Syntax::Rgbds), "JR NZ, $0162");
+        assert_eq!(disassemble(&[0x38, 0x80], Syntax::NoCash), "jr c,00D2");
+    }
+
+    #[test]
+    fn signed_offsets() {
+        assert_eq!(disassemble(&[0xE8, 0xFE], Syntax::Rgbds), "ADD SP, -$02");
+        assert_eq!(disassemble(&[0xF8, 0x05], Syntax::Rgbds), "LD HL, SP+$05");
+        assert_eq!(disassemble(&[0xF8, 0xFB], Syntax::NoCash), "ld hl,sp-05");
+    }
+
+    #[test]
+    fn high_page() {
+        assert_eq!(disassemble(&[0xE0, 0x40], Syntax::Rgbds), "LDH [rLCDC], A");
+        assert_eq!(
+            disassemble(&[0xF0, 0x80], Syntax::Rgbds),
+            "LDH A, [$FF00+$80]"
+        );
+        assert_eq!(disassemble(&[0xE2], Syntax::Rgbds), "LDH [C], A");
+        assert_eq!(disassemble(&[0xF0, 0x44], Syntax::NoCash), "ld a,(rLY)");
+        assert_eq!(disassemble(&[0xE2], Syntax::NoCash), "ld (ff00+c),a");
+    }
+
+    #[test]
+    fn post_increment() {
+        assert_eq!(disassemble(&[0x22], Syntax::Rgbds), "LD [HL+], A");
+        assert_eq!(disassemble(&[0x3A], Syntax::Rgbds), "LD A, [HL-]");
+        assert_eq!(disassemble(&[0x22], Syntax::NoCash), "ldi (hl),a");
+        assert_eq!(disassemble(&[0x3A], Syntax::NoCash), "ldd a,(hl)");
+    }
+
+    #[test]
+    fn symbol_targets() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0150..0x0155].copy_from_slice(&[0xCD, 0x00, 0x40, 0x18, 0xFB]);
+        let mmu = Mmu::new(rom);
+
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0, 0x0150, "Main");
+        symbols.insert(1, 0x4000, "LoadTiles");
+        let formatter = Formatter::with_symbols(Syntax::Rgbds, Rc::new(symbols));
+
+        let call = DecodedInstruction::decode(&mmu, 0x0150);
+        assert_eq!(formatter.format(&call, 0x0150), "CALL LoadTiles");
+
+        let jr = DecodedInstruction::decode(&mmu, 0x0153);
+        assert_eq!(formatter.format(&jr, 0x0153), "JR Main");
+    }
+
+    #[test]
+    fn prefixed_and_illegal() {
+        assert_eq!(disassemble(&[0xCB, 0x7C], Syntax::Rgbds), "BIT 7, H");
+        assert_eq!(disassemble(&[0xCB, 0x36], Syntax::Rgbds), "SWAP [HL]");
+        assert_eq!(disassemble(&[0xD3], Syntax::Rgbds), "DB $D3");
+        assert_eq!(disassemble(&[0xFF], Syntax::Rgbds), "RST $38");
+        assert_eq!(disassemble(&[0xE9], Syntax::Rgbds), "JP HL");
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+
+/// Why the emulator can't carry on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EmulatorError {
+    /// An instruction the CPU doesn't implement yet
+    UnimplementedOpcode { pc: u16, opcode: u8 },
+    /// One of the opcodes the SM83 doesn't have
+    IllegalOpcode { pc: u16, opcode: u8 },
+    /// An instruction's decoding names an operand it can't use, like a write to an immediate.
+    /// This is a bug in the instruction table rather than the ROM.
+    InvalidOperand {
+        pc: u16,
+        opcode: u8,
+        message: String,
+    },
+    /// The ROM can't be a cartridge, e.g. it's too short to have a header
+    InvalidRom(String),
+    /// The cartridge header names a memory bank controller we don't know
+    UnsupportedMapper(u8),
+    /// A boot ROM that can't be used, e.g. it's the wrong size for the model
+    InvalidBootRom(String),
+    /// A save state that can't be loaded
+    InvalidState(String),
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::UnimplementedOpcode { pc, opcode } => {
+                write!(f, "unimplemented opcode {:02x} at {:04x}", opcode, pc)
+            }
+            EmulatorError::IllegalOpcode { pc, opcode } => {
+                write!(f, "illegal opcode {:02x} at {:04x}", opcode, pc)
+            }
+            EmulatorError::InvalidOperand {
+                pc,
+                opcode,
+                message,
+            } => write!(f, "opcode {:02x} at {:04x}: {}", opcode, pc, message),
+            EmulatorError::InvalidRom(message) => write!(f, "invalid ROM: {}", message),
+            EmulatorError::UnsupportedMapper(cart_type) => {
+                write!(f, "unsupported cartridge type {:02x}", cart_type)
+            }
+            EmulatorError::InvalidBootRom(message) => write!(f, "invalid boot ROM: {}", message),
+            EmulatorError::InvalidState(message) => write!(f, "invalid save state: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
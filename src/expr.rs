@@ -0,0 +1,426 @@
+use crate::{cpu::Cpu, instructions::IRegister, symbols::SymbolTable};
+
+/// A register expressions can name, including the ones instructions can't
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
+impl Register {
+    pub const ALL: [Register; 14] = [
+        Register::A,
+        Register::F,
+        Register::B,
+        Register::C,
+        Register::D,
+        Register::E,
+        Register::H,
+        Register::L,
+        Register::AF,
+        Register::BC,
+        Register::DE,
+        Register::HL,
+        Register::SP,
+        Register::PC,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Register::A => "a",
+            Register::F => "f",
+            Register::B => "b",
+            Register::C => "c",
+            Register::D => "d",
+            Register::E => "e",
+            Register::H => "h",
+            Register::L => "l",
+            Register::AF => "af",
+            Register::BC => "bc",
+            Register::DE => "de",
+            Register::HL => "hl",
+            Register::SP => "sp",
+            Register::PC => "pc",
+        }
+    }
+
+    /// Case-insensitive
+    pub fn from_name(name: &str) -> Option<Register> {
+        Register::ALL
+            .iter()
+            .copied()
+            .find(|reg| reg.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn read(self, cpu: &Cpu) -> u16 {
+        let reg = cpu.reg();
+
+        match self {
+            Register::A => reg.a() as u16,
+            Register::F => u8::from(reg.f()) as u16,
+            Register::B => reg.b() as u16,
+            Register::C => reg.c() as u16,
+            Register::D => reg.d() as u16,
+            Register::E => reg.e() as u16,
+            Register::H => reg.h() as u16,
+            Register::L => reg.l() as u16,
+            Register::AF => reg.af(),
+            Register::BC => reg.bc(),
+            Register::DE => reg.de(),
+            Register::HL => reg.hl(),
+            Register::SP => cpu.sp(),
+            Register::PC => cpu.pc(),
+        }
+    }
+
+    /// Sets the register, truncating `value` to its size
+    pub fn write(self, cpu: &mut Cpu, value: u16) {
+        let reg = match self {
+            Register::F => return cpu.set_f(value as u8),
+            Register::PC => return cpu.set_pc(value),
+            Register::A => IRegister::A,
+            Register::B => IRegister::B,
+            Register::C => IRegister::C,
+            Register::D => IRegister::D,
+            Register::E => IRegister::E,
+            Register::H => IRegister::H,
+            Register::L => IRegister::L,
+            Register::AF => IRegister::AF,
+            Register::BC => IRegister::BC,
+            Register::DE => IRegister::DE,
+            Register::HL => IRegister::HL,
+            Register::SP => IRegister::SP,
+        };
+
+        cpu.set_register(reg, value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    Complement,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinaryOp {
+    /// Operators by their token, loosest binding first
+    const PRECEDENCE: [&'static [(&'static str, BinaryOp)]; 9] = [
+        &[("||", BinaryOp::Or)],
+        &[("&&", BinaryOp::And)],
+        &[
+            ("==", BinaryOp::Eq),
+            ("!=", BinaryOp::Ne),
+            ("<=", BinaryOp::Le),
+            (">=", BinaryOp::Ge),
+            ("<", BinaryOp::Lt),
+            (">", BinaryOp::Gt),
+        ],
+        &[("|", BinaryOp::BitOr)],
+        &[("^", BinaryOp::BitXor)],
+        &[("&", BinaryOp::BitAnd)],
+        &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+        &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+        &[
+            ("*", BinaryOp::Mul),
+            ("/", BinaryOp::Div),
+            ("%", BinaryOp::Rem),
+        ],
+    ];
+
+    fn apply(self, a: i64, b: i64) -> Result<i64, String> {
+        Ok(match self {
+            BinaryOp::Or => (a != 0 || b != 0) as i64,
+            BinaryOp::And => (a != 0 && b != 0) as i64,
+            BinaryOp::Eq => (a == b) as i64,
+            BinaryOp::Ne => (a != b) as i64,
+            BinaryOp::Lt => (a < b) as i64,
+            BinaryOp::Le => (a <= b) as i64,
+            BinaryOp::Gt => (a > b) as i64,
+            BinaryOp::Ge => (a >= b) as i64,
+            BinaryOp::BitOr => a | b,
+            BinaryOp::BitXor => a ^ b,
+            BinaryOp::BitAnd => a & b,
+            BinaryOp::Shl => a.checked_shl(b as u32).unwrap_or(0),
+            BinaryOp::Shr => a.checked_shr(b as u32).unwrap_or(0),
+            BinaryOp::Add => a.wrapping_add(b),
+            BinaryOp::Sub => a.wrapping_sub(b),
+            BinaryOp::Mul => a.wrapping_mul(b),
+            BinaryOp::Div | BinaryOp::Rem if b == 0 => return Err("division by zero".to_string()),
+            BinaryOp::Div => a.wrapping_div(b),
+            BinaryOp::Rem => a.wrapping_rem(b),
+        })
+    }
+}
+
+/// An expression over registers, memory and symbols, e.g. `[hl + 2] == $3F && pc != Main`.
+/// Numbers are decimal unless written as `0x3F` or `$3F` (hex) or `0b101` (binary); `[addr]`
+/// reads a byte. Comparisons and logic operators give 1 or 0.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Register(Register),
+    Symbol(String),
+    Deref(Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let mut parser = Parser { text, pos: 0 };
+        let expr = parser.expr(0)?;
+
+        parser.skip_whitespace();
+        match parser.rest() {
+            "" => Ok(expr),
+            rest => Err(format!("unexpected {}", rest)),
+        }
+    }
+
+    pub fn eval(&self, cpu: &Cpu, symbols: &SymbolTable) -> Result<i64, String> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Register(reg) => Ok(reg.read(cpu) as i64),
+            Expr::Symbol(name) => match symbols.address(name) {
+                Some((_, addr)) => Ok(addr as i64),
+                None => Err(format!("unknown symbol {}", name)),
+            },
+            Expr::Deref(addr) => {
+                let addr = address(addr.eval(cpu, symbols)?)?;
+                Ok(cpu.mmu().rb(addr as usize) as i64)
+            }
+            Expr::Unary(op, value) => {
+                let value = value.eval(cpu, symbols)?;
+                Ok(match op {
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::Not => (value == 0) as i64,
+                    UnaryOp::Complement => !value,
+                })
+            }
+            Expr::Binary(op, a, b) => op.apply(a.eval(cpu, symbols)?, b.eval(cpu, symbols)?),
+        }
+    }
+}
+
+/// Checks that `value` fits in the address space
+pub fn address(value: i64) -> Result<u16, String> {
+    u16::try_from(value).map_err(|_| format!("address {} out of range", value))
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `token` if it comes next
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        match self.rest().starts_with(token) {
+            true => {
+                self.pos += token.len();
+                true
+            }
+            false => false,
+        }
+    }
+
+    /// Binary operators at `level` of `BinaryOp::PRECEDENCE` and tighter, left associative
+    fn expr(&mut self, level: usize) -> Result<Expr, String> {
+        let operators = match BinaryOp::PRECEDENCE.get(level) {
+            Some(operators) => operators,
+            None => return self.unary(),
+        };
+
+        let mut lhs = self.expr(level + 1)?;
+
+        'operators: loop {
+            self.skip_whitespace();
+            for &(token, op) in operators.iter() {
+                // Don't mistake `||` for `|`, or `<<` for `<`
+                let longer =
+                    BinaryOp::PRECEDENCE
+                        .iter()
+                        .flat_map(|ops| ops.iter())
+                        .any(|(other, _)| {
+                            other.len() > token.len()
+                                && other.starts_with(token)
+                                && self.rest().starts_with(other)
+                        });
+
+                if !longer && self.eat(token) {
+                    let rhs = self.expr(level + 1)?;
+                    lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+                    continue 'operators;
+                }
+            }
+
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let op = if self.eat("-") {
+            UnaryOp::Neg
+        } else if self.eat("!") {
+            UnaryOp::Not
+        } else if self.eat("~") {
+            UnaryOp::Complement
+        } else {
+            return self.primary();
+        };
+
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        if self.eat("(") {
+            let expr = self.expr(0)?;
+            return match self.eat(")") {
+                true => Ok(expr),
+                false => Err("missing )".to_string()),
+            };
+        }
+
+        if self.eat("[") {
+            let expr = self.expr(0)?;
+            return match self.eat("]") {
+                true => Ok(Expr::Deref(Box::new(expr))),
+                false => Err("missing ]".to_string()),
+            };
+        }
+
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'))
+            .unwrap_or(rest.len());
+        let word = &rest[..len];
+        self.pos += len;
+
+        if word.is_empty() {
+            return match rest.chars().next() {
+                Some(c) => Err(format!("unexpected {}", c)),
+                None => Err("unexpected end of expression".to_string()),
+            };
+        }
+
+        let number = |digits: &str, radix| {
+            i64::from_str_radix(digits, radix).map_err(|_| format!("invalid number {}", word))
+        };
+
+        if let Some(digits) = word.strip_prefix('$').or_else(|| word.strip_prefix("0x")) {
+            return number(digits, 16).map(Expr::Number);
+        }
+        if let Some(digits) = word.strip_prefix("0b") {
+            return number(digits, 2).map(Expr::Number);
+        }
+        if word.starts_with(|c: char| c.is_ascii_digit()) {
+            return number(word, 10).map(Expr::Number);
+        }
+
+        match Register::from_name(word) {
+            Some(reg) => Ok(Expr::Register(reg)),
+            None => Ok(Expr::Symbol(word.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Expr, Register};
+    use crate::{asm, cpu::Cpu, symbols::SymbolTable};
+
+    fn eval(text: &str) -> Result<i64, String> {
+        let mut cpu = Cpu::new(asm::assemble("nop").unwrap());
+        cpu.mmu_mut().wb(0x014D, 0x42);
+        cpu.mmu_mut().wb(0xC000, 0x07);
+
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0, 0xC000, "wCounter");
+
+        Expr::parse(text)?.eval(&cpu, &symbols)
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("0x10 | $01 << 2"), Ok(0x14));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval("-0b11 + ~0"), Ok(-4));
+        assert_eq!(eval("7 % 4 == 3 && 1 < 2 || 0"), Ok(1));
+        assert_eq!(eval("2 <= 1"), Ok(0));
+        assert!(eval("1 / 0").is_err());
+    }
+
+    #[test]
+    fn cpu_state() {
+        assert_eq!(eval("pc"), Ok(0x0100));
+        assert_eq!(eval("A == 1 && hl == $014D"), Ok(1));
+        assert_eq!(eval("[hl]"), Ok(0x42));
+        assert_eq!(eval("[wCounter] + 1"), Ok(8));
+        assert_eq!(eval("wCounter"), Ok(0xC000));
+
+        assert!(eval("[-1]").is_err());
+        assert!(eval("wMissing").is_err());
+        assert!(eval("1 +").is_err());
+        assert!(eval("(1").is_err());
+        assert!(eval("1 2").is_err());
+    }
+
+    #[test]
+    fn registers() {
+        let mut cpu = Cpu::new(asm::assemble("nop").unwrap());
+
+        Register::BC.write(&mut cpu, 0x1234);
+        Register::F.write(&mut cpu, 0xFF);
+        assert_eq!(Register::B.read(&cpu), 0x12);
+        assert_eq!(Register::F.read(&cpu), 0xF0);
+        assert_eq!(Register::from_name("Hl"), Some(Register::HL));
+    }
+}
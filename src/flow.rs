@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    coverage::Coverage,
+    disasm,
+    instructions::{DecodedInstruction, IAction, IFlag, ILocation},
+};
+
+/// Addresses execution can reach without a jump: the cartridge entry point, the RST vectors and
+/// the interrupt vectors
+pub const ENTRY_POINTS: [u16; 14] = [
+    0x0100, 0x0000, 0x0008, 0x0010, 0x0018, 0x0020, 0x0028, 0x0030, 0x0038, 0x0040, 0x0048, 0x0050,
+    0x0058, 0x0060,
+];
+
+/// Offset into the ROM of `addr`, with `rom_bank` mapped at 0x4000-0x7FFF
+pub fn rom_offset(rom_bank: u16, addr: u16) -> usize {
+    match addr {
+        0x0000..=0x3FFF => addr as usize,
+        _ => rom_bank as usize * 0x4000 + (addr as usize - 0x4000),
+    }
+}
+
+/// Bank and CPU address of a ROM offset. Bank 0 is always mapped at 0x0000-0x3FFF, every
+/// other bank at 0x4000-0x7FFF.
+pub fn rom_address(offset: usize) -> (u16, u16) {
+    let bank = (offset / 0x4000) as u16;
+    let addr = (offset % 0x4000) as u16;
+
+    match bank {
+        0 => (0, addr),
+        _ => (bank, addr + 0x4000),
+    }
+}
+
+/// Where control can go after `inst` at `addr`: an optional jump target, and whether execution
+/// can continue with the next instruction.
+fn successors(inst: &DecodedInstruction, addr: u16) -> (Option<u16>, bool) {
+    let conditional = |flag| !matches!(flag, IFlag::TRUE);
+
+    match inst.action() {
+        IAction::JP(flag, ILocation::ImmediateWord) => {
+            (Some(inst.operands_as_u16()), conditional(flag))
+        }
+        // JP HL: the target is only known at runtime
+        IAction::JP(_, _) => (None, false),
+        IAction::JR(flag, _) => (Some(disasm::relative_target(inst, addr)), conditional(flag)),
+        IAction::CALL(_, _) => (Some(inst.operands_as_u16()), true),
+        IAction::RST(vector) => (Some(vector as u16), true),
+        IAction::RET(flag) => (None, conditional(flag)),
+        IAction::RETI => (None, false),
+        _ => (None, true),
+    }
+}
+
+/// Recursive-descent disassembler. Follows JP/JR/CALL/RST from a set of entry points to find
+/// which parts of the ROM are code; everything it never reaches is treated as data.
+pub struct FlowAnalyser {
+    /// Instructions found so far, keyed by ROM offset
+    instructions: BTreeMap<usize, DecodedInstruction>,
+
+    /// Whether each byte of the ROM is part of an instruction
+    code: Vec<bool>,
+}
+
+impl FlowAnalyser {
+    /// Creates an analyser and walks the ROM from all of the fixed entry points
+    pub fn new(rom: &[u8]) -> Self {
+        let mut analyser = Self {
+            instructions: BTreeMap::new(),
+            code: vec![false; rom.len()],
+        };
+
+        for entry in ENTRY_POINTS {
+            analyser.analyse(rom, 1, entry);
+        }
+
+        analyser
+    }
+
+    /// Disassembles everything reachable from `entry`, with `rom_bank` mapped at 0x4000-0x7FFF.
+    /// Code that has already been analysed isn't visited again, so this is cheap to call
+    /// whenever execution reaches an address we haven't seen yet.
+    pub fn analyse(&mut self, rom: &[u8], rom_bank: u16, entry: u16) {
+        let read = |addr: usize| match addr {
+            0x0000..=0x7FFF => rom
+                .get(rom_offset(rom_bank, addr as u16))
+                .copied()
+                .unwrap_or(0xFF),
+            _ => 0xFF,
+        };
+
+        let mut pending = vec![entry];
+
+        while let Some(addr) = pending.pop() {
+            // Code in RAM can change under us, so we only analyse the ROM
+            if addr >= 0x8000 {
+                continue;
+            }
+
+            let offset = rom_offset(rom_bank, addr);
+            if offset >= rom.len() || self.instructions.contains_key(&offset) {
+                continue;
+            }
+
+            let inst = DecodedInstruction::decode_with(read, addr as usize);
+
+            // Real code never executes an illegal opcode, so we've wandered into data
+            if let IAction::ILLEGAL = inst.action() {
+                continue;
+            }
+
+            let (target, falls_through) = successors(&inst, addr);
+
+            pending.extend(target);
+            if falls_through {
+                pending.push(addr.wrapping_add(inst.len() as u16));
+            }
+
+            for byte in offset..(offset + inst.len() as usize).min(rom.len()) {
+                self.code[byte] = true;
+            }
+
+            self.instructions.insert(offset, inst);
+        }
+    }
+
+    /// Uses a coverage file from the access log as extra entry points, so code only reached
+    /// through jump tables or `JP HL` is found too
+    pub fn add_coverage(&mut self, rom: &[u8], coverage: &[u8]) {
+        for (offset, &flags) in coverage.iter().enumerate().take(rom.len()) {
+            // Operand bytes are marked executed as well, but analysing from the opcode first
+            // already turned them into code
+            if Coverage::from_bits_truncate(flags).contains(Coverage::EXECUTED)
+                && !self.code[offset]
+            {
+                let (bank, addr) = rom_address(offset);
+                self.analyse(rom, bank.max(1), addr);
+            }
+        }
+    }
+
+    /// The instruction starting at `addr`, if analysis found one there
+    pub fn instruction(&self, rom_bank: u16, addr: u16) -> Option<&DecodedInstruction> {
+        match addr {
+            0x0000..=0x7FFF => self.instructions.get(&rom_offset(rom_bank, addr)),
+            _ => None,
+        }
+    }
+
+    /// Whether the byte at `addr` is part of an instruction. Bytes that aren't are data.
+    pub fn is_code(&self, rom_bank: u16, addr: u16) -> bool {
+        match addr {
+            0x0000..=0x7FFF => self
+                .code
+                .get(rom_offset(rom_bank, addr))
+                .copied()
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    /// All instructions found so far, keyed by ROM offset
+    pub fn instructions(&self) -> &BTreeMap<usize, DecodedInstruction> {
+        &self.instructions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{rom_address, rom_offset, FlowAnalyser};
+    use crate::coverage::Coverage;
+
+    fn rom(code: &[(usize, &[u8])]) -> Vec<u8> {
+        // Fill with an illegal opcode so that stray analysis stops immediately
+        let mut rom = vec![0xD3; 0x10000];
+        for (offset, bytes) in code {
+            rom[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+        rom
+    }
+
+    #[test]
+    fn follows_jumps_and_calls() {
+        let rom = rom(&[
+            // JP $0150
+            (0x0100, &[0xC3, 0x50, 0x01]),
+            // CALL $0200; JR NZ, +2; RET; <data>; DI; RET
+            (
+                0x0150,
+                &[0xCD, 0x00, 0x02, 0x20, 0x02, 0xC9, 0x12, 0xF3, 0xC9],
+            ),
+            // RET
+            (0x0200, &[0xC9]),
+        ]);
+
+        let analyser = FlowAnalyser::new(&rom);
+
+        assert!(analyser.instruction(1, 0x0100).is_some());
+        assert!(analyser.instruction(1, 0x0150).is_some());
+        assert!(analyser.instruction(1, 0x0153).is_some());
+        assert!(analyser.instruction(1, 0x0157).is_some());
+        assert!(analyser.instruction(1, 0x0200).is_some());
+
+        // The bytes after JP and between RET and the JR target are never executed
+        assert!(!analyser.is_code(1, 0x0103));
+        assert!(!analyser.is_code(1, 0x0156));
+
+        // Operand bytes are code even though they aren't instruction starts
+        assert!(analyser.is_code(1, 0x0151));
+        assert!(analyser.instruction(1, 0x0151).is_none());
+    }
+
+    #[test]
+    fn incremental_and_mid_instruction() {
+        // LD HL, $00C9 hides a RET in its operand
+        let rom = rom(&[(0x0100, &[0x21, 0xC9, 0x00, 0x18, 0xFB])]);
+
+        let mut analyser = FlowAnalyser::new(&rom);
+        assert!(analyser.instruction(1, 0x0101).is_none());
+
+        analyser.analyse(&rom, 1, 0x0101);
+        assert!(analyser.instruction(1, 0x0100).is_some());
+        assert!(analyser.instruction(1, 0x0101).is_some());
+    }
+
+    #[test]
+    fn banked_code() {
+        // Bank 0 calls into 0x4000, which is bank 2 at the time
+        let rom = rom(&[(0x0100, &[0xC3, 0x00, 0x40]), (0x8000, &[0x00, 0xC9])]);
+
+        let mut analyser = FlowAnalyser::new(&rom);
+        assert!(analyser.instruction(2, 0x4000).is_none());
+
+        // Execution reached 0x4000 with bank 2 mapped
+        analyser.analyse(&rom, 2, 0x4000);
+        assert!(analyser.instruction(2, 0x4001).is_some());
+        assert!(analyser.instruction(1, 0x4001).is_none());
+    }
+
+    #[test]
+    fn coverage_entry_points() {
+        // LD HL, $0300; JP HL
+        let rom = rom(&[(0x0100, &[0x21, 0x00, 0x03, 0xE9]), (0x0300, &[0x00, 0xC9])]);
+
+        let mut analyser = FlowAnalyser::new(&rom);
+        assert!(analyser.instruction(1, 0x0300).is_none());
+
+        let mut coverage = vec![0; rom.len()];
+        coverage[0x0300] = Coverage::EXECUTED.bits();
+        analyser.add_coverage(&rom, &coverage);
+        assert!(analyser.instruction(1, 0x0300).is_some());
+        assert!(analyser.instruction(1, 0x0301).is_some());
+    }
+
+    #[test]
+    fn offsets_round_trip() {
+        assert_eq!(rom_offset(5, 0x4123), 0x14123);
+        assert_eq!(rom_address(0x14123), (5, 0x4123));
+        assert_eq!(rom_address(rom_offset(7, 0x0150)), (0, 0x0150));
+    }
+}
@@ -0,0 +1,318 @@
+use crate::{
+    cpu::Cpu,
+    error::EmulatorError,
+    header::Header,
+    io::{self, Buttons, Lcdc},
+    mmu::Mmu,
+    model::Model,
+    state, vram,
+};
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// The whole machine, for embedding the emulator in other tools. Anything more detailed than
+/// this is on the `Cpu`.
+pub struct GameBoy {
+    cpu: Cpu,
+
+    /// Shades of the last frame (0 = lightest, 3 = darkest), row by row
+    framebuffer: Vec<u8>,
+
+    /// The hardware to emulate, or `None` to pick it for each cartridge
+    model: Option<Model>,
+
+    /// Run at power on, instead of starting in the state it would leave
+    boot_rom: Option<Vec<u8>>,
+}
+
+impl GameBoy {
+    /// A Game Boy with no cartridge in, so everything in the cartridge's space reads 0xFF
+    pub fn new() -> Self {
+        Self {
+            cpu: Cpu::new(Vec::new()),
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            model: None,
+            boot_rom: None,
+        }
+    }
+
+    /// The hardware being emulated now
+    pub fn model(&self) -> Model {
+        self.cpu.model()
+    }
+
+    /// Sets the hardware to emulate from the next `load_rom`. With `None`, a boot ROM's size
+    /// picks between DMG and CGB, and without one the cartridge header picks.
+    pub fn set_model(&mut self, model: Option<Model>) {
+        self.model = model;
+    }
+
+    /// Sets the boot ROM to run from the next `load_rom`, or skips it if `boot_rom` is `None`.
+    /// Fails if it isn't the size of a DMG or CGB boot ROM.
+    pub fn set_boot_rom(&mut self, boot_rom: Option<Vec<u8>>) -> Result<(), EmulatorError> {
+        if let Some(boot_rom) = &boot_rom {
+            if boot_rom.len() != Model::Dmg.boot_rom_len()
+                && boot_rom.len() != Model::Cgb.boot_rom_len()
+            {
+                return Err(EmulatorError::InvalidBootRom(format!(
+                    "it's {} bytes, but should be 256 (DMG) or 2304 (CGB)",
+                    boot_rom.len()
+                )));
+            }
+        }
+
+        self.boot_rom = boot_rom;
+        Ok(())
+    }
+
+    /// Swaps the cartridge for `rom` and powers on. Anything attached to the CPU, like a tracer
+    /// or script, goes with the old one. Fails, leaving the old cartridge in, if `rom` doesn't
+    /// have a header we understand, or the boot ROM doesn't suit the model.
+    pub fn load_rom(&mut self, rom: Vec<u8>) -> Result<(), EmulatorError> {
+        let header = Header::parse(&rom)?;
+
+        self.cpu = match (&self.boot_rom, self.model) {
+            (Some(boot_rom), model) => {
+                let model = model.unwrap_or(if boot_rom.len() == Model::Cgb.boot_rom_len() {
+                    Model::Cgb
+                } else {
+                    Model::Dmg
+                });
+                Cpu::with_boot_rom(rom, model, boot_rom.clone())?
+            }
+            (None, model) => {
+                let model = model.unwrap_or_else(|| Model::from_header(&header));
+                Cpu::with_model(rom, model)
+            }
+        };
+        self.framebuffer.fill(0);
+
+        Ok(())
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    /// Frames since power on
+    pub fn frame(&self) -> u64 {
+        self.cpu.mmu().frame()
+    }
+
+    /// Runs one instruction, returning the clock cycles it took
+    pub fn step_instruction(&mut self) -> Result<u64, EmulatorError> {
+        let frame = self.frame();
+        let cycles = self.cpu.step()?;
+
+        if self.frame() != frame {
+            self.render();
+        }
+
+        Ok(cycles)
+    }
+
+    /// Runs until the next frame starts, or an instruction can't be run
+    pub fn run_frame(&mut self) -> Result<(), EmulatorError> {
+        let frame = self.frame();
+
+        while self.frame() == frame {
+            self.step_instruction()?;
+        }
+
+        Ok(())
+    }
+
+    /// The last frame, `SCREEN_WIDTH` by `SCREEN_HEIGHT` shades row by row
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /// Audio generated since this was last called, as interleaved stereo samples. There's no
+    /// APU yet, so for now this is always empty.
+    pub fn audio_samples(&mut self) -> Vec<f32> {
+        // TODO: sound
+        Vec::new()
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        self.cpu.mmu().buttons()
+    }
+
+    /// Sets the joypad buttons held down
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.cpu.mmu_mut().set_buttons(buttons);
+    }
+
+    /// Snapshots the machine. The cartridge ROM isn't included, so the state can only be
+    /// loaded with the same ROM.
+    pub fn save_state(&self) -> Vec<u8> {
+        state::save(&self.cpu)
+    }
+
+    /// Restores a snapshot from `save_state`. Nothing changes if it can't be read.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), EmulatorError> {
+        state::load(&mut self.cpu, data)?;
+        self.render();
+
+        Ok(())
+    }
+
+    /// Draws the screen as it is now into the framebuffer
+    fn render(&mut self) {
+        render_screen(self.cpu.mmu(), &mut self.framebuffer);
+    }
+}
+
+/// Draws the background as it is in `mmu` now into `framebuffer`, `SCREEN_WIDTH` by
+/// `SCREEN_HEIGHT` shades row by row
+// TODO: the window and sprites, the CGB's colors and VRAM bank 1 attributes, and mid-frame
+// changes, once there's a PPU
+pub fn render_screen(mmu: &Mmu, framebuffer: &mut [u8]) {
+    let lcdc = Lcdc::from_bits_truncate(mmu.rb(io::LCDC as usize));
+
+    if !lcdc.contains(Lcdc::LCD_ENABLE | Lcdc::BG_ENABLE) {
+        framebuffer.fill(0);
+        return;
+    }
+
+    let map = vram::TILE_MAPS[lcdc.contains(Lcdc::BG_TILE_MAP) as usize];
+    let background = vram::render_tile_map(mmu.vram(), map, lcdc);
+    let scx = mmu.rb(io::SCX as usize) as usize;
+    let scy = mmu.rb(io::SCY as usize) as usize;
+    let bgp = mmu.rb(io::BGP as usize);
+
+    for y in 0..SCREEN_HEIGHT {
+        let row = (y + scy) % 256 * 256;
+
+        for x in 0..SCREEN_WIDTH {
+            let color = background[row + (x + scx) % 256];
+            framebuffer[y * SCREEN_WIDTH + x] = vram::shade(bgp, color);
+        }
+    }
+}
+
+impl Default for GameBoy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GameBoy, SCREEN_WIDTH};
+    use crate::{asm, error::EmulatorError, io::Buttons, mmu::CYCLES_PER_FRAME, model::Model};
+
+    #[test]
+    fn frames() {
+        let mut gameboy = GameBoy::new();
+        assert!(gameboy.cpu().mmu().rb(0x0100) == 0xFF);
+
+        // A black line along the top of the top left tile, scrolled one pixel left
+        let rom = asm::assemble(
+            "
+            ld a, $FF
+            ld [$8010], a
+            ld [$8011], a
+            ld a, 1
+            ld [$9800], a
+            ld [$FF43], a
+            ",
+        )
+        .unwrap();
+        gameboy.load_rom(rom).unwrap();
+
+        assert!(gameboy.step_instruction() == Ok(8));
+        gameboy.run_frame().unwrap();
+
+        assert!(gameboy.frame() == 1);
+        assert!(gameboy.cpu().cycles() >= CYCLES_PER_FRAME);
+        assert!(gameboy.framebuffer()[..8] == [3, 3, 3, 3, 3, 3, 3, 0]);
+        assert!(gameboy.framebuffer()[SCREEN_WIDTH] == 0);
+        assert!(gameboy.audio_samples().is_empty());
+    }
+
+    #[test]
+    fn save_states() {
+        let mut gameboy = GameBoy::new();
+        let rom = asm::assemble("ld bc, $1234\nld [$C000], a").unwrap();
+        gameboy.load_rom(rom).unwrap();
+        gameboy.set_buttons(Buttons::START);
+        gameboy.step_instruction().unwrap();
+
+        let state = gameboy.save_state();
+
+        gameboy.step_instruction().unwrap();
+        gameboy.set_buttons(Buttons::empty());
+        assert!(gameboy.cpu().mmu().rb(0xC000) == 0x01);
+
+        gameboy.load_state(&state).unwrap();
+        assert!(gameboy.cpu().pc() == 0x0103);
+        assert!(gameboy.cpu().reg().bc() == 0x1234);
+        assert!(gameboy.cpu().mmu().rb(0xC000) == 0x00);
+        assert!(gameboy.buttons() == Buttons::START);
+
+        assert!(gameboy.load_state(b"nope").is_err());
+        assert!(gameboy.load_state(&state[..state.len() - 1]).is_err());
+        assert!(gameboy.cpu().pc() == 0x0103);
+    }
+
+    #[test]
+    fn models_and_boot_roms() {
+        let mut rom = asm::assemble("nop").unwrap();
+        let mut gameboy = GameBoy::new();
+
+        // Picked from the header, which CGB-only registers depend on
+        gameboy.load_rom(rom.clone()).unwrap();
+        assert!(gameboy.model() == Model::Dmg);
+        assert!(gameboy.cpu().reg().a() == 0x01);
+        gameboy.cpu_mut().mmu_mut().wb(0xFF70, 0x02);
+        assert!(gameboy.cpu().mmu().rb(0xFF70) == 0xFF);
+
+        rom[0x0143] = 0x80;
+        gameboy.load_rom(rom.clone()).unwrap();
+        assert!(gameboy.model() == Model::Cgb);
+        assert!(gameboy.cpu().reg().a() == 0x11);
+        assert!(gameboy.cpu().pc() == 0x0100);
+        gameboy.cpu_mut().mmu_mut().wb(0xFF70, 0x02);
+        assert!(gameboy.cpu().mmu().rb(0xFF70) == 0xFA);
+
+        gameboy.set_model(Some(Model::Mgb));
+        gameboy.load_rom(rom.clone()).unwrap();
+        assert!(gameboy.model() == Model::Mgb);
+        assert!(gameboy.cpu().reg().a() == 0xFF);
+
+        assert!(matches!(
+            gameboy.set_boot_rom(Some(vec![0; 42])),
+            Err(EmulatorError::InvalidBootRom(_))
+        ));
+
+        // LD A, 1; LDH [$50], A
+        let mut boot_rom = vec![0; 0x100];
+        boot_rom[..4].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+        gameboy.set_boot_rom(Some(boot_rom)).unwrap();
+
+        gameboy.set_model(Some(Model::Agb));
+        assert!(matches!(
+            gameboy.load_rom(rom.clone()),
+            Err(EmulatorError::InvalidBootRom(_))
+        ));
+
+        gameboy.set_model(None);
+        gameboy.load_rom(rom.clone()).unwrap();
+        assert!(gameboy.model() == Model::Dmg);
+        assert!(gameboy.cpu().pc() == 0x0000);
+        assert!(gameboy.cpu().mmu().rb(0x0000) == 0x3E);
+        assert!(gameboy.cpu().mmu().rb(0x0100) == rom[0x0100]);
+
+        gameboy.step_instruction().unwrap();
+        gameboy.step_instruction().unwrap();
+        assert!(gameboy.cpu().pc() == 0x0004);
+        assert!(!gameboy.cpu().mmu().boot_rom_mapped());
+        assert!(gameboy.cpu().mmu().rb(0x0000) == rom[0x0000]);
+    }
+}
@@ -0,0 +1,592 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{coverage::Access, gameboy::GameBoy, instructions::IRegister, mmu::Watchpoint};
+
+/// Register set for GDB, in the order the `g` and `G` packets use
+const TARGET_XML: &str = include_str!("../resources/sm83-target.xml");
+
+/// Steps to run between checks for an interrupt from GDB while continuing
+const INTERRUPT_CHECK_STEPS: u64 = 4096;
+
+/// Ctrl-C from GDB, sent outside of a packet
+const INTERRUPT: u8 = 0x03;
+
+/// What to do after handling a packet
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    Packet(String),
+    /// Run until something stops the CPU, then send a stop reply
+    Resume {
+        step: bool,
+    },
+    /// Stop serving after sending OK
+    Detach,
+    /// Stop serving without replying
+    Kill,
+}
+
+/// Everything about the remote protocol except the socket
+struct Stub {
+    gameboy: GameBoy,
+    breakpoints: BTreeSet<u16>,
+}
+
+impl Stub {
+    fn new(gameboy: GameBoy) -> Self {
+        Self {
+            gameboy,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    fn handle(&mut self, packet: &str) -> Reply {
+        let reply = |text: &str| Reply::Packet(text.to_string());
+
+        match packet.as_bytes().first() {
+            Some(b'?') => reply("S05"),
+            Some(b'g') => Reply::Packet(self.read_registers()),
+            Some(b'G') => self.write_registers(&packet[1..]),
+            Some(b'p') => self.read_register(&packet[1..]),
+            Some(b'P') => self.write_register(&packet[1..]),
+            Some(b'm') => self.read_memory(&packet[1..]),
+            Some(b'M') => self.write_memory(&packet[1..]),
+            Some(b'Z') => self.set_breakpoint(&packet[1..], true),
+            Some(b'z') => self.set_breakpoint(&packet[1..], false),
+            Some(b's') => Reply::Resume { step: true },
+            Some(b'c') => Reply::Resume { step: false },
+            Some(b'H') | Some(b'T') => reply("OK"),
+            Some(b'D') => Reply::Detach,
+            Some(b'k') => Reply::Kill,
+            _ if packet == "vCont?" => reply("vCont;c;C;s;S"),
+            // We only have one thread, so the first action applies
+            _ if packet.starts_with("vCont;s") || packet.starts_with("vCont;S") => {
+                Reply::Resume { step: true }
+            }
+            _ if packet.starts_with("vCont;c") || packet.starts_with("vCont;C") => {
+                Reply::Resume { step: false }
+            }
+            _ if packet.starts_with("qSupported") => {
+                reply("PacketSize=4000;qXfer:features:read+;swbreak+;QStartNoAckMode+")
+            }
+            _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+                self.read_target_xml(&packet["qXfer:features:read:target.xml:".len()..])
+            }
+            _ if packet == "qAttached" => reply("1"),
+            _ if packet == "qC" => reply("QC1"),
+            _ if packet == "qfThreadInfo" => reply("m1"),
+            _ if packet == "qsThreadInfo" => reply("l"),
+            // An empty reply tells GDB we don't support the packet
+            _ => reply(""),
+        }
+    }
+
+    /// Value of register `n` in `g` packet order, and its size in bytes
+    fn register(&self, n: usize) -> Option<(u16, usize)> {
+        let reg = self.gameboy.cpu().reg();
+
+        match n {
+            0 => Some((reg.a() as u16, 1)),
+            1 => Some((u8::from(reg.f()) as u16, 1)),
+            2 => Some((reg.b() as u16, 1)),
+            3 => Some((reg.c() as u16, 1)),
+            4 => Some((reg.d() as u16, 1)),
+            5 => Some((reg.e() as u16, 1)),
+            6 => Some((reg.h() as u16, 1)),
+            7 => Some((reg.l() as u16, 1)),
+            8 => Some((self.gameboy.cpu().sp(), 2)),
+            9 => Some((self.gameboy.cpu().pc(), 2)),
+            _ => None,
+        }
+    }
+
+    fn set_register(&mut self, n: usize, value: u16) {
+        match n {
+            0 => self.gameboy.cpu_mut().set_register(IRegister::A, value),
+            1 => self.gameboy.cpu_mut().set_f(value as u8),
+            2 => self.gameboy.cpu_mut().set_register(IRegister::B, value),
+            3 => self.gameboy.cpu_mut().set_register(IRegister::C, value),
+            4 => self.gameboy.cpu_mut().set_register(IRegister::D, value),
+            5 => self.gameboy.cpu_mut().set_register(IRegister::E, value),
+            6 => self.gameboy.cpu_mut().set_register(IRegister::H, value),
+            7 => self.gameboy.cpu_mut().set_register(IRegister::L, value),
+            8 => self.gameboy.cpu_mut().set_register(IRegister::SP, value),
+            9 => self.gameboy.cpu_mut().set_pc(value),
+            _ => (),
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        (0..10)
+            .filter_map(|n| self.register(n))
+            .map(|(value, size)| hex(&value.to_le_bytes()[..size]))
+            .collect()
+    }
+
+    fn write_registers(&mut self, data: &str) -> Reply {
+        let bytes = match unhex(data) {
+            Some(bytes) if bytes.len() >= 12 => bytes,
+            _ => return error(),
+        };
+
+        for (n, &byte) in bytes[..8].iter().enumerate() {
+            self.set_register(n, byte as u16);
+        }
+        self.set_register(8, u16::from_le_bytes([bytes[8], bytes[9]]));
+        self.set_register(9, u16::from_le_bytes([bytes[10], bytes[11]]));
+
+        ok()
+    }
+
+    fn read_register(&self, args: &str) -> Reply {
+        match usize::from_str_radix(args, 16)
+            .ok()
+            .and_then(|n| self.register(n))
+        {
+            Some((value, size)) => Reply::Packet(hex(&value.to_le_bytes()[..size])),
+            None => error(),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> Reply {
+        let (n, data) = match args.split_once('=') {
+            Some((n, data)) => (n, data),
+            None => return error(),
+        };
+
+        match (usize::from_str_radix(n, 16), unhex(data)) {
+            (Ok(n), Some(bytes)) if n < 10 && !bytes.is_empty() => {
+                let value = bytes
+                    .iter()
+                    .rev()
+                    .fold(0, |value, &b| (value << 8) | b as u16);
+                self.set_register(n, value);
+                ok()
+            }
+            _ => error(),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> Reply {
+        let (addr, len) = match address_and_length(args) {
+            Some(range) => range,
+            None => return error(),
+        };
+
+        let bytes: Vec<u8> = (addr..addr + len)
+            .map(|addr| self.gameboy.cpu().mmu().rb(addr))
+            .collect();
+        Reply::Packet(hex(&bytes))
+    }
+
+    fn write_memory(&mut self, args: &str) -> Reply {
+        let (range, data) = match args.split_once(':') {
+            Some(parts) => parts,
+            None => return error(),
+        };
+
+        match (address_and_length(range), unhex(data)) {
+            (Some((addr, len)), Some(bytes)) if bytes.len() == len => {
+                for (i, byte) in bytes.into_iter().enumerate() {
+                    self.gameboy.cpu_mut().mmu_mut().wb(addr + i, byte);
+                }
+                ok()
+            }
+            _ => error(),
+        }
+    }
+
+    /// `Z`/`z` packets: `type,addr,kind`
+    fn set_breakpoint(&mut self, args: &str, insert: bool) -> Reply {
+        let mut parts = args.split(',');
+        let (kind, addr, len) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(kind), Some(addr), Some(len)) => (kind, addr, len),
+            _ => return error(),
+        };
+        let (addr, len) = match (u16::from_str_radix(addr, 16), u16::from_str_radix(len, 16)) {
+            (Ok(addr), Ok(len)) => (addr, len.max(1)),
+            _ => return error(),
+        };
+
+        let access = match kind {
+            "0" | "1" => {
+                match insert {
+                    true => self.breakpoints.insert(addr),
+                    false => self.breakpoints.remove(&addr),
+                };
+                return ok();
+            }
+            "2" => Access::Write,
+            "3" => Access::Read,
+            // Access watchpoints would need two entries to report back as one
+            _ => return Reply::Packet(String::new()),
+        };
+
+        let watchpoint = Watchpoint { addr, len, access };
+        match insert {
+            true => self.gameboy.cpu_mut().mmu_mut().add_watchpoint(watchpoint),
+            false => self
+                .gameboy
+                .cpu_mut()
+                .mmu_mut()
+                .remove_watchpoint(watchpoint),
+        }
+
+        ok()
+    }
+
+    fn read_target_xml(&self, args: &str) -> Reply {
+        let range = args.split_once(',').and_then(|(offset, len)| {
+            let offset = usize::from_str_radix(offset, 16).ok()?;
+            let len = usize::from_str_radix(len, 16).ok()?;
+            Some((offset, offset.checked_add(len)?))
+        });
+
+        match range {
+            Some((offset, end)) if offset <= TARGET_XML.len() => {
+                let chunk = &TARGET_XML[offset..end.min(TARGET_XML.len())];
+                let more = match end < TARGET_XML.len() {
+                    true => "m",
+                    false => "l",
+                };
+                Reply::Packet(format!("{}{}", more, chunk))
+            }
+            _ => error(),
+        }
+    }
+
+    /// Steps the CPU until it stops and returns the stop reply. `interrupted` is polled every so
+    /// often while continuing.
+    fn resume<F: FnMut() -> bool>(&mut self, step: bool, mut interrupted: F) -> String {
+        // Discard anything left over from before we were stopped
+        self.gameboy.cpu_mut().mmu_mut().take_watch_hit();
+
+        let mut steps: u64 = 0;
+
+        loop {
+            if let Err(e) = self.gameboy.step_instruction() {
+                log::warn!("{}", e);
+                // SIGILL, which is near enough for anything the CPU can't run
+                return "S04".to_string();
+            }
+            steps += 1;
+
+            if let Some((watchpoint, addr)) = self.gameboy.cpu_mut().mmu_mut().take_watch_hit() {
+                let kind = match watchpoint.access {
+                    Access::Read => "rwatch",
+                    _ => "watch",
+                };
+                return format!("T05{}:{:04x};", kind, addr);
+            }
+
+            if step {
+                return "S05".to_string();
+            }
+
+            if self.breakpoints.contains(&self.gameboy.cpu().pc()) {
+                return "T05swbreak:;".to_string();
+            }
+
+            if steps.is_multiple_of(INTERRUPT_CHECK_STEPS) && interrupted() {
+                return "S02".to_string();
+            }
+        }
+    }
+}
+
+fn ok() -> Reply {
+    Reply::Packet("OK".to_string())
+}
+
+fn error() -> Reply {
+    Reply::Packet("E01".to_string())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// `addr,length` in hex, limited to the 16-bit address space
+fn address_and_length(args: &str) -> Option<(usize, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+
+    match addr.checked_add(len)? <= 0x10000 {
+        true => Some((addr, len)),
+        false => None,
+    }
+}
+
+/// Frames `data` as `$data#checksum`, escaping the characters the protocol reserves
+fn frame(data: &str) -> Vec<u8> {
+    let mut body = Vec::with_capacity(data.len());
+    for &byte in data.as_bytes() {
+        match byte {
+            b'$' | b'#' | b'}' | b'*' => body.extend([b'}', byte ^ 0x20]),
+            _ => body.push(byte),
+        }
+    }
+
+    let checksum = body.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+
+    let mut framed = vec![b'$'];
+    framed.extend(body);
+    framed.extend(format!("#{:02x}", checksum).bytes());
+    framed
+}
+
+/// What arrived from GDB
+enum Incoming {
+    Packet(String),
+    Interrupt,
+}
+
+/// Reads and writes packets on the GDB connection
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// Whether we still acknowledge packets, until GDB asks for no-ack mode
+    ack: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            ack: true,
+        })
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn receive(&mut self) -> io::Result<Incoming> {
+        loop {
+            match self.read_byte()? {
+                INTERRUPT => return Ok(Incoming::Interrupt),
+                b'$' => (),
+                // Acks from GDB, and anything else between packets
+                _ => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let checksum = [self.read_byte()?, self.read_byte()?];
+
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+            let actual = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+
+            if self.ack {
+                let valid = expected == Some(actual);
+                self.writer.write_all(if valid { b"+" } else { b"-" })?;
+                if !valid {
+                    continue;
+                }
+            }
+
+            return Ok(Incoming::Packet(
+                String::from_utf8_lossy(&data).into_owned(),
+            ));
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        log::debug!("gdb <- {}", data);
+        self.writer.write_all(&frame(data))?;
+        self.writer.flush()
+    }
+
+    /// Whether GDB sent an interrupt, without blocking
+    fn interrupted(&mut self) -> bool {
+        if let Some(&byte) = self.reader.buffer().first() {
+            return byte == INTERRUPT;
+        }
+
+        let mut byte = [0];
+        let _ = self.writer.set_nonblocking(true);
+        let read = self.writer.peek(&mut byte);
+        let _ = self.writer.set_nonblocking(false);
+
+        matches!(read, Ok(1) if byte[0] == INTERRUPT)
+    }
+}
+
+/// Serves the GDB remote protocol on localhost until the debugger detaches or kills us
+pub fn serve(gameboy: GameBoy, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    log::info!("waiting for gdb on port {}", port);
+
+    let (stream, peer) = listener.accept()?;
+    log::info!("gdb connected from {}", peer);
+    stream.set_nodelay(true)?;
+
+    let mut connection = Connection::new(stream)?;
+    let mut stub = Stub::new(gameboy);
+
+    loop {
+        let packet = match connection.receive()? {
+            Incoming::Packet(packet) => packet,
+            // We're already stopped
+            Incoming::Interrupt => {
+                connection.send("S02")?;
+                continue;
+            }
+        };
+        log::debug!("gdb -> {}", packet);
+
+        if packet == "QStartNoAckMode" {
+            connection.send("OK")?;
+            connection.ack = false;
+            continue;
+        }
+
+        match stub.handle(&packet) {
+            Reply::Packet(reply) => connection.send(&reply)?,
+            Reply::Resume { step } => {
+                let reply = stub.resume(step, || connection.interrupted());
+                if reply == "S02" {
+                    // Consume the interrupt
+                    connection.read_byte()?;
+                }
+                connection.send(&reply)?;
+            }
+            Reply::Detach => {
+                connection.send("OK")?;
+                break;
+            }
+            Reply::Kill => break,
+        }
+    }
+
+    log::info!("gdb disconnected");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{frame, Reply, Stub};
+    use crate::{asm, gameboy::GameBoy};
+
+    fn stub(source: &str) -> Stub {
+        let mut gameboy = GameBoy::new();
+        gameboy.load_rom(asm::assemble(source).unwrap()).unwrap();
+        Stub::new(gameboy)
+    }
+
+    fn packet(text: &str) -> Reply {
+        Reply::Packet(text.to_string())
+    }
+
+    #[test]
+    fn framing() {
+        assert_eq!(frame("OK"), b"$OK#9a");
+        assert_eq!(frame("a#b"), b"$a}\x03b#43");
+    }
+
+    #[test]
+    fn registers() {
+        let mut stub = stub("nop");
+
+        // F is 0x80 as the assembled ROM's header checksum is zero
+        assert_eq!(stub.handle("g"), packet("0180001300d8014dfeff0001"));
+        assert_eq!(stub.handle("p9"), packet("0001"));
+
+        assert_eq!(stub.handle("P0=42"), packet("OK"));
+        assert_eq!(stub.handle("P8=f0df"), packet("OK"));
+        assert_eq!(stub.handle("p0"), packet("42"));
+        assert_eq!(stub.gameboy.cpu().sp(), 0xDFF0);
+
+        assert_eq!(stub.handle("G0000000000000000feff5001"), packet("OK"));
+        assert_eq!(stub.gameboy.cpu().pc(), 0x0150);
+        assert_eq!(stub.handle("Gff"), packet("E01"));
+    }
+
+    #[test]
+    fn memory() {
+        let mut stub = stub("ld a, $12");
+
+        assert_eq!(stub.handle("m100,2"), packet("3e12"));
+        assert_eq!(stub.handle("Mc000,2:abcd"), packet("OK"));
+        assert_eq!(stub.handle("mc000,3"), packet("abcd00"));
+        assert_eq!(stub.handle("mffff,2"), packet("E01"));
+        assert_eq!(stub.handle("m1,ffffffffffffffff"), packet("E01"));
+        assert_eq!(stub.handle("Mffffffffffffffff,1:00"), packet("E01"));
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let mut stub = stub(
+            "
+            nop
+            nop
+            ld a, $01
+            ld [$c000], a
+            ld a, [$c000]
+            nop
+        ",
+        );
+
+        assert_eq!(stub.handle("s"), Reply::Resume { step: true });
+        assert_eq!(stub.resume(true, || false), "S05");
+        assert_eq!(stub.gameboy.cpu().pc(), 0x0101);
+
+        assert_eq!(stub.handle("Z0,102,1"), packet("OK"));
+        assert_eq!(stub.handle("Z2,c000,1"), packet("OK"));
+        assert_eq!(stub.handle("Z3,c000,1"), packet("OK"));
+
+        assert_eq!(stub.resume(false, || false), "T05swbreak:;");
+        assert_eq!(stub.gameboy.cpu().pc(), 0x0102);
+
+        assert_eq!(stub.handle("vCont;c"), Reply::Resume { step: false });
+        assert_eq!(stub.resume(false, || false), "T05watch:c000;");
+        assert_eq!(stub.resume(false, || false), "T05rwatch:c000;");
+
+        assert_eq!(stub.handle("z2,c000,1"), packet("OK"));
+        assert_eq!(stub.handle("Z4,c000,1"), packet(""));
+    }
+
+    #[test]
+    fn target_description() {
+        let mut stub = stub("nop");
+
+        match stub.handle("qXfer:features:read:target.xml:0,10") {
+            Reply::Packet(reply) => assert_eq!(reply, "m<?xml version=\"1"),
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+        match stub.handle("qXfer:features:read:target.xml:0,fff") {
+            Reply::Packet(reply) => assert!(reply.starts_with('l') && reply.contains("code_ptr")),
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+        assert_eq!(
+            stub.handle("qXfer:features:read:target.xml:10,ffffffffffffffff"),
+            packet("E01")
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{bits, error::EmulatorError};
+
+/// The cartridge header, at 0x0100-0x014F in the ROM
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub title: String,
+    /// 0x80 if the game supports the CGB's features, 0xC0 if it needs them
+    pub cgb_flag: u8,
+    pub licensee: u16,
+    /// 0x03 if the game supports the SGB's features
+    pub sgb_flag: u8,
+    pub cart_type: u8,
+    pub rom_size: u8,
+    pub ram_size: u8,
+    /// 0x33 if the licensee is in `licensee` instead. The SGB only uses its features then.
+    pub old_licensee: u8,
+}
+
+impl Header {
+    /// Fails if the ROM is too short to have a header, or the header names hardware we don't
+    /// know of. Mappers the `Mmu` can't switch yet only get a warning, and run with bank 1
+    /// fixed at 0x4000.
+    pub fn parse(rom: &[u8]) -> Result<Header, EmulatorError> {
+        if rom.len() < 0x0150 {
+            return Err(EmulatorError::InvalidRom(format!(
+                "{} bytes is too short to have a header",
+                rom.len()
+            )));
+        }
+
+        let title = rom[0x0134..=0x0142]
+            .iter()
+            .take_while(|&&byte| byte != 0)
+            .map(|&byte| byte as char)
+            .collect();
+
+        let header = Header {
+            title,
+            cgb_flag: rom[0x0143],
+            licensee: bits::pack_u16(rom[0x0144], rom[0x0145]),
+            sgb_flag: rom[0x0146],
+            cart_type: rom[0x0147],
+            rom_size: rom[0x0148],
+            ram_size: rom[0x0149],
+            old_licensee: rom[0x014B],
+        };
+
+        match cart_type_name(header.cart_type) {
+            None => return Err(EmulatorError::UnsupportedMapper(header.cart_type)),
+            // TODO: switch banks for the mapper types
+            Some(name) if header.cart_type != 0x00 => log::warn!(
+                "{} has no bank switching yet, so only banks 0 and 1 are mapped",
+                name
+            ),
+            Some(_) => (),
+        }
+
+        Ok(header)
+    }
+}
+
+/// What's in a cartridge of the type in the header's 0x0147 byte
+pub fn cart_type_name(cart_type: u8) -> Option<&'static str> {
+    let name = match cart_type {
+        0x00 => "ROM",
+        0x01 => "ROM+MBC1",
+        0x02 => "ROM+MBC1+RAM",
+        0x03 => "ROM+MBC1+RAM+BATTERY",
+        0x05 => "ROM+MBC2",
+        0x06 => "ROM+MBC2+BATTERY",
+        0x08 => "ROM+RAM",
+        0x09 => "ROM+RAM+BATTERY",
+        0x0B => "ROM+MMM01",
+        0x0C => "ROM+MMM01+RAM",
+        0x0D => "ROM+MMM01+RAM+BATTERY",
+        0x0F => "ROM+MBC3+TIMER+BATTERY",
+        0x10 => "ROM+MBC3+TIMER+RAM+BATTERY",
+        0x11 => "ROM+MBC3",
+        0x12 => "ROM+MBC3+RAM",
+        0x13 => "ROM+MBC3+RAM+BATTERY",
+        0x19 => "ROM+MBC5",
+        0x1A => "ROM+MBC5+RAM",
+        0x1B => "ROM+MBC5+RAM+BATTERY",
+        0x1C => "ROM+MBC5+RUMBLE",
+        0x1D => "ROM+MBC5+RUMBLE+RAM",
+        0x1E => "ROM+MBC5+RUMBLE+RAM+BATTERY",
+        0x20 => "ROM+MBC6",
+        0x22 => "ROM+MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+        0xFC => "POCKET CAMERA",
+        0xFD => "BANDAI TAMA5",
+        0xFE => "HuC3",
+        0xFF => "HuC1+RAM+BATTERY",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Header;
+    use crate::error::EmulatorError;
+
+    #[test]
+    fn parse() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x0138].copy_from_slice(b"TEST");
+        rom[0x0148] = 0x01;
+
+        let header = Header::parse(&rom).unwrap();
+        assert_eq!(header.title, "TEST");
+        assert_eq!(header.cart_type, 0x00);
+        assert_eq!(header.rom_size, 0x01);
+
+        // Known mappers load, even though the Mmu can't switch their banks yet
+        for cart_type in [0x01, 0x08, 0x13, 0x1B] {
+            rom[0x0147] = cart_type;
+            assert_eq!(Header::parse(&rom).unwrap().cart_type, cart_type);
+        }
+
+        rom[0x0147] = 0x42;
+        assert_eq!(
+            Header::parse(&rom),
+            Err(EmulatorError::UnsupportedMapper(0x42))
+        );
+        assert!(matches!(
+            Header::parse(&rom[..0x0100]),
+            Err(EmulatorError::InvalidRom(_))
+        ));
+    }
+}
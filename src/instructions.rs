@@ -2,7 +2,7 @@ use std::fmt::Display;
 
 use crate::{bits, mmu::Mmu};
 
-pub use opcodes::UNPREFIXED_INSTRUCTIONS;
+pub use opcodes::{CB_PREFIXED_INSTRUCTIONS, UNPREFIXED_INSTRUCTIONS};
 
 mod opcodes {
 
@@ -13,6 +13,8 @@ mod opcodes {
     use super::Instruction;
 
     lazy_static! {
+        /// Indexed by opcode. 0x88, 0x98, 0xA8 and 0xB8 start the ADC, SBC, XOR and CP rows;
+        /// they were once copies of the row above, so executing them ran the wrong operation.
         pub static ref UNPREFIXED_INSTRUCTIONS: Vec<Instruction> = vec![
             Instruction::new(NOP, 1, 4), // 0x00
             Instruction::new(LD16(Register(BC), ImmediateWord), 3, 12), // 0x01
@@ -21,7 +23,7 @@ mod opcodes {
             Instruction::new(INC(Register(B)), 1, 4), // 0x04
             Instruction::new(DEC(Register(B)), 1, 4), // 0x05
             Instruction::new(LD(Register(B), ImmediateByte), 2, 8), // 0x06
-            Instruction::new(UNIMPLEMENTED, 1, 4), // 0x07
+            Instruction::new(RLCA, 1, 4), // 0x07
             Instruction::new(LD16(ImmediateWordIndirectWord, Register(SP)), 3, 20), // 0x08
             Instruction::new(ADD16(Register(HL), Register(BC)), 1, 8), // 0x09
             Instruction::new(LD(Register(A), RegisterIndirectByte(BC)), 1, 8), // 0x0a
@@ -29,32 +31,32 @@ mod opcodes {
             Instruction::new(INC(Register(C)), 1, 4), // 0x0c
             Instruction::new(DEC(Register(C)), 1, 4), // 0x0d
             Instruction::new(LD(Register(C), ImmediateByte), 2, 8), // 0x0e
-            Instruction::new(UNIMPLEMENTED, 1, 4), // 0x0f
-            Instruction::new(UNIMPLEMENTED, 1, 4), // 0x10
+            Instruction::new(RRCA, 1, 4), // 0x0f
+            Instruction::new(STOP, 2, 4), // 0x10
             Instruction::new(LD16(Register(DE), ImmediateWord), 3, 12), // 0x11
             Instruction::new(LD(RegisterIndirectByte(DE), Register(A)), 1, 8), // 0x12
             Instruction::new(INC16(Register(DE)), 1, 8), // 0x13
             Instruction::new(INC(Register(D)), 1, 4), // 0x14
             Instruction::new(DEC(Register(D)), 1, 4), // 0x15
             Instruction::new(LD(Register(D), ImmediateByte), 2, 8), // 0x16
-            Instruction::new(UNIMPLEMENTED, 1, 4), // 0x17
-            Instruction::new(UNIMPLEMENTED, 2, 12), // 0x18
+            Instruction::new(RLA, 1, 4), // 0x17
+            Instruction::new(JR(TRUE, ImmediateSignedByte), 2, 12), // 0x18
             Instruction::new(ADD16(Register(HL), Register(DE)), 1, 8), // 0x19
             Instruction::new(LD(Register(A), RegisterIndirectByte(DE)), 1, 8), // 0x1a
             Instruction::new(DEC16(Register(DE)), 1, 8), // 0x1b
             Instruction::new(INC(Register(E)), 1, 4), // 0x1c
             Instruction::new(DEC(Register(E)), 1, 4), // 0x1d
             Instruction::new(LD(Register(E), ImmediateByte), 2, 8), // 0x1e
-            Instruction::new(UNIMPLEMENTED, 1, 4), // 0x1f
-            Instruction::new(UNIMPLEMENTED, 2, 12), // 0x20
+            Instruction::new(RRA, 1, 4), // 0x1f
+            Instruction::new(JR(NZ, ImmediateSignedByte), 2, 12), // 0x20
             Instruction::new(LD16(Register(HL), ImmediateWord), 3, 12), // 0x21
             Instruction::new(LD(RegisterIndirectByte(HL_INC), Register(A)), 1, 8), // 0x22
             Instruction::new(INC16(Register(HL)), 1, 8), // 0x23
             Instruction::new(INC(Register(H)), 1, 4), // 0x24
             Instruction::new(DEC(Register(H)), 1, 4), // 0x25
             Instruction::new(LD(Register(H), ImmediateByte), 2, 8), // 0x26
-            Instruction::new(UNIMPLEMENTED, 1, 4), // 0x27
-            Instruction::new(UNIMPLEMENTED, 2, 12), // 0x28
+            Instruction::new(DAA, 1, 4), // 0x27
+            Instruction::new(JR(Z, ImmediateSignedByte), 2, 12), // 0x28
             Instruction::new(ADD16(Register(HL), Register(HL)), 1, 8), // 0x29
             Instruction::new(LD(Register(A), RegisterIndirectByte(HL_INC)), 1, 8), // 0x2a
             Instruction::new(DEC16(Register(HL)), 1, 8), // 0x2b
@@ -62,15 +64,15 @@ mod opcodes {
             Instruction::new(DEC(Register(L)), 1, 4), // 0x2d
             Instruction::new(LD(Register(L), ImmediateByte), 2, 8), // 0x2e
             Instruction::new(CPL, 1, 4), // 0x2f
-            Instruction::new(UNIMPLEMENTED, 2, 12), // 0x30
+            Instruction::new(JR(NCY, ImmediateSignedByte), 2, 12), // 0x30
             Instruction::new(LD16(Register(SP), ImmediateWord), 3, 12), // 0x31
             Instruction::new(LD(RegisterIndirectByte(HL_DEC), Register(A)), 1, 8), // 0x32
             Instruction::new(INC16(Register(SP)), 1, 8), // 0x33
             Instruction::new(INC(RegisterIndirectByte(HL)), 1, 12), // 0x34
             Instruction::new(DEC(RegisterIndirectByte(HL)), 1, 12), // 0x35
             Instruction::new(LD(RegisterIndirectByte(HL), ImmediateByte), 2, 12), // 0x36
-            Instruction::new(UNIMPLEMENTED, 1, 4), // 0x37
-            Instruction::new(UNIMPLEMENTED, 2, 12), // 0x38
+            Instruction::new(SCF, 1, 4), // 0x37
+            Instruction::new(JR(CY, ImmediateSignedByte), 2, 12), // 0x38
             Instruction::new(ADD16(Register(HL), Register(SP)), 1, 8), // 0x39
             Instruction::new(LD(Register(A), RegisterIndirectByte(HL_DEC)), 1, 8), // 0x3a
             Instruction::new(DEC16(Register(SP)), 1, 8), // 0x3b
@@ -132,7 +134,7 @@ mod opcodes {
             Instruction::new(LD(RegisterIndirectByte(HL), Register(E)), 1, 8), // 0x73
             Instruction::new(LD(RegisterIndirectByte(HL), Register(H)), 1, 8), // 0x74
             Instruction::new(LD(RegisterIndirectByte(HL), Register(L)), 1, 8), // 0x75
-            Instruction::new(UNIMPLEMENTED, 1, 4), // 0x76
+            Instruction::new(HALT, 1, 4), // 0x76
             Instruction::new(LD(RegisterIndirectByte(HL), Register(A)), 1, 8), // 0x77
             Instruction::new(LD(Register(A), Register(B)), 1, 4), // 0x78
             Instruction::new(LD(Register(A), Register(C)), 1, 4), // 0x79
@@ -150,7 +152,7 @@ mod opcodes {
             Instruction::new(ADD(Register(A), Register(L)), 1, 4), // 0x85
             Instruction::new(ADD(Register(A), RegisterIndirectByte(HL)), 1, 8), // 0x86
             Instruction::new(ADD(Register(A), Register(A)), 1, 4), // 0x87
-            Instruction::new(ADD(Register(A), Register(B)), 1, 4), // 0x88
+            Instruction::new(ADC(Register(A), Register(B)), 1, 4), // 0x88
             Instruction::new(ADC(Register(A), Register(C)), 1, 4), // 0x89
             Instruction::new(ADC(Register(A), Register(D)), 1, 4), // 0x8a
             Instruction::new(ADC(Register(A), Register(E)), 1, 4), // 0x8b
@@ -166,7 +168,7 @@ mod opcodes {
             Instruction::new(SUB(Register(A), Register(L)), 1, 4), // 0x95
             Instruction::new(SUB(Register(A), RegisterIndirectByte(HL)), 1, 8), // 0x96
             Instruction::new(SUB(Register(A), Register(A)), 1, 4), // 0x97
-            Instruction::new(SUB(Register(A), Register(B)), 1, 4), // 0x98
+            Instruction::new(SBC(Register(A), Register(B)), 1, 4), // 0x98
             Instruction::new(SBC(Register(A), Register(C)), 1, 4), // 0x99
             Instruction::new(SBC(Register(A), Register(D)), 1, 4), // 0x9a
             Instruction::new(SBC(Register(A), Register(E)), 1, 4), // 0x9b
@@ -182,7 +184,7 @@ mod opcodes {
             Instruction::new(AND(Register(A), Register(L)), 1, 4), // 0xa5
             Instruction::new(AND(Register(A), RegisterIndirectByte(HL)), 1, 8), // 0xa6
             Instruction::new(AND(Register(A), Register(A)), 1, 4), // 0xa7
-            Instruction::new(AND(Register(A), Register(B)), 1, 4), // 0xa8
+            Instruction::new(XOR(Register(A), Register(B)), 1, 4), // 0xa8
             Instruction::new(XOR(Register(A), Register(C)), 1, 4), // 0xa9
             Instruction::new(XOR(Register(A), Register(D)), 1, 4), // 0xaa
             Instruction::new(XOR(Register(A), Register(E)), 1, 4), // 0xab
@@ -198,7 +200,7 @@ mod opcodes {
             Instruction::new(OR(Register(A), Register(L)), 1, 4), // 0xb5
             Instruction::new(OR(Register(A), RegisterIndirectByte(HL)), 1, 8), // 0xb6
             Instruction::new(OR(Register(A), Register(A)), 1, 4), // 0xb7
-            Instruction::new(OR(Register(A), Register(B)), 1, 4), // 0xb8
+            Instruction::new(CP(Register(A), Register(B)), 1, 4), // 0xb8
             Instruction::new(CP(Register(A), Register(C)), 1, 4), // 0xb9
             Instruction::new(CP(Register(A), Register(D)), 1, 4), // 0xba
             Instruction::new(CP(Register(A), Register(E)), 1, 4), // 0xbb
@@ -206,75 +208,334 @@ mod opcodes {
             Instruction::new(CP(Register(A), Register(L)), 1, 4), // 0xbd
             Instruction::new(CP(Register(A), RegisterIndirectByte(HL)), 1, 8), // 0xbe
             Instruction::new(CP(Register(A), Register(A)), 1, 4), // 0xbf
-            Instruction::new(UNIMPLEMENTED, 1, 20), // 0xc0
-            Instruction::new(UNIMPLEMENTED, 1, 12), // 0xc1
-            Instruction::new(UNIMPLEMENTED, 3, 16), // 0xc2
+            Instruction::new(RET(NZ), 1, 20), // 0xc0
+            Instruction::new(POP(Register(BC)), 1, 12), // 0xc1
+            Instruction::new(JP(NZ, ImmediateWord), 3, 16), // 0xc2
             Instruction::new(JP(TRUE, ImmediateWord), 3, 16), // 0xc3
-            Instruction::new(UNIMPLEMENTED, 3, 24), // 0xc4
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xc5
-            Instruction::new(UNIMPLEMENTED, 2, 8), // 0xc6
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xc7
-            Instruction::new(UNIMPLEMENTED, 1, 20), // 0xc8
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xc9
-            Instruction::new(UNIMPLEMENTED, 3, 16), // 0xca
-            Instruction::new(UNIMPLEMENTED, 1, 4), // 0xcb
-            Instruction::new(UNIMPLEMENTED, 3, 24), // 0xcc
-            Instruction::new(UNIMPLEMENTED, 3, 24), // 0xcd
-            Instruction::new(UNIMPLEMENTED, 2, 8), // 0xce
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xcf
-            Instruction::new(UNIMPLEMENTED, 1, 20), // 0xd0
-            Instruction::new(UNIMPLEMENTED, 1, 12), // 0xd1
-            Instruction::new(UNIMPLEMENTED, 3, 16), // 0xd2
-            Instruction::new(UNIMPLEMENTED, 1, 0), // 0xd3: NOT REAL
-            Instruction::new(UNIMPLEMENTED, 3, 24), // 0xd4
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xd5
-            Instruction::new(UNIMPLEMENTED, 2, 8), // 0xd6
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xd7
-            Instruction::new(UNIMPLEMENTED, 1, 20), // 0xd8
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xd9
-            Instruction::new(UNIMPLEMENTED, 3, 16), // 0xda
-            Instruction::new(UNIMPLEMENTED, 1, 0), // 0xdb: NOT REAL
-            Instruction::new(UNIMPLEMENTED, 3, 24), // 0xdc
-            Instruction::new(UNIMPLEMENTED, 1, 0), // 0xdd: NOT REAL
-            Instruction::new(UNIMPLEMENTED, 2, 8), // 0xde
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xdf
-            Instruction::new(UNIMPLEMENTED, 2, 12), // 0xe0
-            Instruction::new(UNIMPLEMENTED, 1, 12), // 0xe1
-            Instruction::new(UNIMPLEMENTED, 1, 8), // 0xe2
-            Instruction::new(UNIMPLEMENTED, 1, 0), // 0xe3: NOT REAL
-            Instruction::new(UNIMPLEMENTED, 1, 0), // 0xe4: NOT REAL
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xe5
-            Instruction::new(UNIMPLEMENTED, 2, 8), // 0xe6
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xe7
-            Instruction::new(UNIMPLEMENTED, 2, 16), // 0xe8
-            Instruction::new(UNIMPLEMENTED, 1, 4), // 0xe9
-            Instruction::new(UNIMPLEMENTED, 3, 16), // 0xea
-            Instruction::new(UNIMPLEMENTED, 1, 0), // 0xeb: NOT REAL
-            Instruction::new(UNIMPLEMENTED, 1, 0), // 0xec: NOT REAL
-            Instruction::new(UNIMPLEMENTED, 1, 0), // 0xed: NOT REAL
-            Instruction::new(UNIMPLEMENTED, 2, 8), // 0xee
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xef
-            Instruction::new(UNIMPLEMENTED, 2, 12), // 0xf0
-            Instruction::new(UNIMPLEMENTED, 1, 12), // 0xf1
-            Instruction::new(UNIMPLEMENTED, 1, 8), // 0xf2
-            Instruction::new(UNIMPLEMENTED, 1, 4), // 0xf3
-            Instruction::new(UNIMPLEMENTED, 1, 0), // 0xf4: NOT REAL
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xf5
-            Instruction::new(UNIMPLEMENTED, 2, 8), // 0xf6
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xf7
-            Instruction::new(UNIMPLEMENTED, 2, 12), // 0xf8
-            Instruction::new(UNIMPLEMENTED, 1, 8), // 0xf9
-            Instruction::new(UNIMPLEMENTED, 3, 16), // 0xfa
-            Instruction::new(UNIMPLEMENTED, 1, 4), // 0xfb
-            Instruction::new(UNIMPLEMENTED, 1, 0), // 0xfc: NOT REAL
-            Instruction::new(UNIMPLEMENTED, 1, 0), // 0xfd: NOT REAL
-            Instruction::new(UNIMPLEMENTED, 2, 8), // 0xfe
-            Instruction::new(UNIMPLEMENTED, 1, 16), // 0xff
+            Instruction::new(CALL(NZ, ImmediateWord), 3, 24), // 0xc4
+            Instruction::new(PUSH(Register(BC)), 1, 16), // 0xc5
+            Instruction::new(ADD(Register(A), ImmediateByte), 2, 8), // 0xc6
+            Instruction::new(RST(0x00), 1, 16), // 0xc7
+            Instruction::new(RET(Z), 1, 20), // 0xc8
+            Instruction::new(RET(TRUE), 1, 16), // 0xc9
+            Instruction::new(JP(Z, ImmediateWord), 3, 16), // 0xca
+            Instruction::new(PREFIX, 1, 4), // 0xcb
+            Instruction::new(CALL(Z, ImmediateWord), 3, 24), // 0xcc
+            Instruction::new(CALL(TRUE, ImmediateWord), 3, 24), // 0xcd
+            Instruction::new(ADC(Register(A), ImmediateByte), 2, 8), // 0xce
+            Instruction::new(RST(0x08), 1, 16), // 0xcf
+            Instruction::new(RET(NCY), 1, 20), // 0xd0
+            Instruction::new(POP(Register(DE)), 1, 12), // 0xd1
+            Instruction::new(JP(NCY, ImmediateWord), 3, 16), // 0xd2
+            Instruction::new(ILLEGAL, 1, 4), // 0xd3: NOT REAL
+            Instruction::new(CALL(NCY, ImmediateWord), 3, 24), // 0xd4
+            Instruction::new(PUSH(Register(DE)), 1, 16), // 0xd5
+            Instruction::new(SUB(Register(A), ImmediateByte), 2, 8), // 0xd6
+            Instruction::new(RST(0x10), 1, 16), // 0xd7
+            Instruction::new(RET(CY), 1, 20), // 0xd8
+            Instruction::new(RETI, 1, 16), // 0xd9
+            Instruction::new(JP(CY, ImmediateWord), 3, 16), // 0xda
+            Instruction::new(ILLEGAL, 1, 4), // 0xdb: NOT REAL
+            Instruction::new(CALL(CY, ImmediateWord), 3, 24), // 0xdc
+            Instruction::new(ILLEGAL, 1, 4), // 0xdd: NOT REAL
+            Instruction::new(SBC(Register(A), ImmediateByte), 2, 8), // 0xde
+            Instruction::new(RST(0x18), 1, 16), // 0xdf
+            Instruction::new(LD(ImmediateByteIndirectByte, Register(A)), 2, 12), // 0xe0
+            Instruction::new(POP(Register(HL)), 1, 12), // 0xe1
+            Instruction::new(LD(RegisterIndirectByte(C), Register(A)), 1, 8), // 0xe2
+            Instruction::new(ILLEGAL, 1, 4), // 0xe3: NOT REAL
+            Instruction::new(ILLEGAL, 1, 4), // 0xe4: NOT REAL
+            Instruction::new(PUSH(Register(HL)), 1, 16), // 0xe5
+            Instruction::new(AND(Register(A), ImmediateByte), 2, 8), // 0xe6
+            Instruction::new(RST(0x20), 1, 16), // 0xe7
+            Instruction::new(ADD16(Register(SP), ImmediateSignedByte), 2, 16), // 0xe8
+            Instruction::new(JP(TRUE, Register(HL)), 1, 4), // 0xe9
+            Instruction::new(LD(ImmediateWordIndirectByte, Register(A)), 3, 16), // 0xea
+            Instruction::new(ILLEGAL, 1, 4), // 0xeb: NOT REAL
+            Instruction::new(ILLEGAL, 1, 4), // 0xec: NOT REAL
+            Instruction::new(ILLEGAL, 1, 4), // 0xed: NOT REAL
+            Instruction::new(XOR(Register(A), ImmediateByte), 2, 8), // 0xee
+            Instruction::new(RST(0x28), 1, 16), // 0xef
+            Instruction::new(LD(Register(A), ImmediateByteIndirectByte), 2, 12), // 0xf0
+            Instruction::new(POP(Register(AF)), 1, 12), // 0xf1
+            Instruction::new(LD(Register(A), RegisterIndirectByte(C)), 1, 8), // 0xf2
+            Instruction::new(DI, 1, 4), // 0xf3
+            Instruction::new(ILLEGAL, 1, 4), // 0xf4: NOT REAL
+            Instruction::new(PUSH(Register(AF)), 1, 16), // 0xf5
+            Instruction::new(OR(Register(A), ImmediateByte), 2, 8), // 0xf6
+            Instruction::new(RST(0x30), 1, 16), // 0xf7
+            Instruction::new(LD16(Register(HL), StackPointerOffset), 2, 12), // 0xf8
+            Instruction::new(LD16(Register(SP), Register(HL)), 1, 8), // 0xf9
+            Instruction::new(LD(Register(A), ImmediateWordIndirectByte), 3, 16), // 0xfa
+            Instruction::new(EI, 1, 4), // 0xfb
+            Instruction::new(ILLEGAL, 1, 4), // 0xfc: NOT REAL
+            Instruction::new(ILLEGAL, 1, 4), // 0xfd: NOT REAL
+            Instruction::new(CP(Register(A), ImmediateByte), 2, 8), // 0xfe
+            Instruction::new(RST(0x38), 1, 16), // 0xff
+        ];
+
+        pub static ref CB_PREFIXED_INSTRUCTIONS: Vec<Instruction> = vec![
+            Instruction::new(RLC(Register(B)), 2, 8), // 0xcb 0x00
+            Instruction::new(RLC(Register(C)), 2, 8), // 0xcb 0x01
+            Instruction::new(RLC(Register(D)), 2, 8), // 0xcb 0x02
+            Instruction::new(RLC(Register(E)), 2, 8), // 0xcb 0x03
+            Instruction::new(RLC(Register(H)), 2, 8), // 0xcb 0x04
+            Instruction::new(RLC(Register(L)), 2, 8), // 0xcb 0x05
+            Instruction::new(RLC(RegisterIndirectByte(HL)), 2, 16), // 0xcb 0x06
+            Instruction::new(RLC(Register(A)), 2, 8), // 0xcb 0x07
+            Instruction::new(RRC(Register(B)), 2, 8), // 0xcb 0x08
+            Instruction::new(RRC(Register(C)), 2, 8), // 0xcb 0x09
+            Instruction::new(RRC(Register(D)), 2, 8), // 0xcb 0x0a
+            Instruction::new(RRC(Register(E)), 2, 8), // 0xcb 0x0b
+            Instruction::new(RRC(Register(H)), 2, 8), // 0xcb 0x0c
+            Instruction::new(RRC(Register(L)), 2, 8), // 0xcb 0x0d
+            Instruction::new(RRC(RegisterIndirectByte(HL)), 2, 16), // 0xcb 0x0e
+            Instruction::new(RRC(Register(A)), 2, 8), // 0xcb 0x0f
+            Instruction::new(RL(Register(B)), 2, 8), // 0xcb 0x10
+            Instruction::new(RL(Register(C)), 2, 8), // 0xcb 0x11
+            Instruction::new(RL(Register(D)), 2, 8), // 0xcb 0x12
+            Instruction::new(RL(Register(E)), 2, 8), // 0xcb 0x13
+            Instruction::new(RL(Register(H)), 2, 8), // 0xcb 0x14
+            Instruction::new(RL(Register(L)), 2, 8), // 0xcb 0x15
+            Instruction::new(RL(RegisterIndirectByte(HL)), 2, 16), // 0xcb 0x16
+            Instruction::new(RL(Register(A)), 2, 8), // 0xcb 0x17
+            Instruction::new(RR(Register(B)), 2, 8), // 0xcb 0x18
+            Instruction::new(RR(Register(C)), 2, 8), // 0xcb 0x19
+            Instruction::new(RR(Register(D)), 2, 8), // 0xcb 0x1a
+            Instruction::new(RR(Register(E)), 2, 8), // 0xcb 0x1b
+            Instruction::new(RR(Register(H)), 2, 8), // 0xcb 0x1c
+            Instruction::new(RR(Register(L)), 2, 8), // 0xcb 0x1d
+            Instruction::new(RR(RegisterIndirectByte(HL)), 2, 16), // 0xcb 0x1e
+            Instruction::new(RR(Register(A)), 2, 8), // 0xcb 0x1f
+            Instruction::new(SLA(Register(B)), 2, 8), // 0xcb 0x20
+            Instruction::new(SLA(Register(C)), 2, 8), // 0xcb 0x21
+            Instruction::new(SLA(Register(D)), 2, 8), // 0xcb 0x22
+            Instruction::new(SLA(Register(E)), 2, 8), // 0xcb 0x23
+            Instruction::new(SLA(Register(H)), 2, 8), // 0xcb 0x24
+            Instruction::new(SLA(Register(L)), 2, 8), // 0xcb 0x25
+            Instruction::new(SLA(RegisterIndirectByte(HL)), 2, 16), // 0xcb 0x26
+            Instruction::new(SLA(Register(A)), 2, 8), // 0xcb 0x27
+            Instruction::new(SRA(Register(B)), 2, 8), // 0xcb 0x28
+            Instruction::new(SRA(Register(C)), 2, 8), // 0xcb 0x29
+            Instruction::new(SRA(Register(D)), 2, 8), // 0xcb 0x2a
+            Instruction::new(SRA(Register(E)), 2, 8), // 0xcb 0x2b
+            Instruction::new(SRA(Register(H)), 2, 8), // 0xcb 0x2c
+            Instruction::new(SRA(Register(L)), 2, 8), // 0xcb 0x2d
+            Instruction::new(SRA(RegisterIndirectByte(HL)), 2, 16), // 0xcb 0x2e
+            Instruction::new(SRA(Register(A)), 2, 8), // 0xcb 0x2f
+            Instruction::new(SWAP(Register(B)), 2, 8), // 0xcb 0x30
+            Instruction::new(SWAP(Register(C)), 2, 8), // 0xcb 0x31
+            Instruction::new(SWAP(Register(D)), 2, 8), // 0xcb 0x32
+            Instruction::new(SWAP(Register(E)), 2, 8), // 0xcb 0x33
+            Instruction::new(SWAP(Register(H)), 2, 8), // 0xcb 0x34
+            Instruction::new(SWAP(Register(L)), 2, 8), // 0xcb 0x35
+            Instruction::new(SWAP(RegisterIndirectByte(HL)), 2, 16), // 0xcb 0x36
+            Instruction::new(SWAP(Register(A)), 2, 8), // 0xcb 0x37
+            Instruction::new(SRL(Register(B)), 2, 8), // 0xcb 0x38
+            Instruction::new(SRL(Register(C)), 2, 8), // 0xcb 0x39
+            Instruction::new(SRL(Register(D)), 2, 8), // 0xcb 0x3a
+            Instruction::new(SRL(Register(E)), 2, 8), // 0xcb 0x3b
+            Instruction::new(SRL(Register(H)), 2, 8), // 0xcb 0x3c
+            Instruction::new(SRL(Register(L)), 2, 8), // 0xcb 0x3d
+            Instruction::new(SRL(RegisterIndirectByte(HL)), 2, 16), // 0xcb 0x3e
+            Instruction::new(SRL(Register(A)), 2, 8), // 0xcb 0x3f
+            Instruction::new(BIT(0, Register(B)), 2, 8), // 0xcb 0x40
+            Instruction::new(BIT(0, Register(C)), 2, 8), // 0xcb 0x41
+            Instruction::new(BIT(0, Register(D)), 2, 8), // 0xcb 0x42
+            Instruction::new(BIT(0, Register(E)), 2, 8), // 0xcb 0x43
+            Instruction::new(BIT(0, Register(H)), 2, 8), // 0xcb 0x44
+            Instruction::new(BIT(0, Register(L)), 2, 8), // 0xcb 0x45
+            Instruction::new(BIT(0, RegisterIndirectByte(HL)), 2, 12), // 0xcb 0x46
+            Instruction::new(BIT(0, Register(A)), 2, 8), // 0xcb 0x47
+            Instruction::new(BIT(1, Register(B)), 2, 8), // 0xcb 0x48
+            Instruction::new(BIT(1, Register(C)), 2, 8), // 0xcb 0x49
+            Instruction::new(BIT(1, Register(D)), 2, 8), // 0xcb 0x4a
+            Instruction::new(BIT(1, Register(E)), 2, 8), // 0xcb 0x4b
+            Instruction::new(BIT(1, Register(H)), 2, 8), // 0xcb 0x4c
+            Instruction::new(BIT(1, Register(L)), 2, 8), // 0xcb 0x4d
+            Instruction::new(BIT(1, RegisterIndirectByte(HL)), 2, 12), // 0xcb 0x4e
+            Instruction::new(BIT(1, Register(A)), 2, 8), // 0xcb 0x4f
+            Instruction::new(BIT(2, Register(B)), 2, 8), // 0xcb 0x50
+            Instruction::new(BIT(2, Register(C)), 2, 8), // 0xcb 0x51
+            Instruction::new(BIT(2, Register(D)), 2, 8), // 0xcb 0x52
+            Instruction::new(BIT(2, Register(E)), 2, 8), // 0xcb 0x53
+            Instruction::new(BIT(2, Register(H)), 2, 8), // 0xcb 0x54
+            Instruction::new(BIT(2, Register(L)), 2, 8), // 0xcb 0x55
+            Instruction::new(BIT(2, RegisterIndirectByte(HL)), 2, 12), // 0xcb 0x56
+            Instruction::new(BIT(2, Register(A)), 2, 8), // 0xcb 0x57
+            Instruction::new(BIT(3, Register(B)), 2, 8), // 0xcb 0x58
+            Instruction::new(BIT(3, Register(C)), 2, 8), // 0xcb 0x59
+            Instruction::new(BIT(3, Register(D)), 2, 8), // 0xcb 0x5a
+            Instruction::new(BIT(3, Register(E)), 2, 8), // 0xcb 0x5b
+            Instruction::new(BIT(3, Register(H)), 2, 8), // 0xcb 0x5c
+            Instruction::new(BIT(3, Register(L)), 2, 8), // 0xcb 0x5d
+            Instruction::new(BIT(3, RegisterIndirectByte(HL)), 2, 12), // 0xcb 0x5e
+            Instruction::new(BIT(3, Register(A)), 2, 8), // 0xcb 0x5f
+            Instruction::new(BIT(4, Register(B)), 2, 8), // 0xcb 0x60
+            Instruction::new(BIT(4, Register(C)), 2, 8), // 0xcb 0x61
+            Instruction::new(BIT(4, Register(D)), 2, 8), // 0xcb 0x62
+            Instruction::new(BIT(4, Register(E)), 2, 8), // 0xcb 0x63
+            Instruction::new(BIT(4, Register(H)), 2, 8), // 0xcb 0x64
+            Instruction::new(BIT(4, Register(L)), 2, 8), // 0xcb 0x65
+            Instruction::new(BIT(4, RegisterIndirectByte(HL)), 2, 12), // 0xcb 0x66
+            Instruction::new(BIT(4, Register(A)), 2, 8), // 0xcb 0x67
+            Instruction::new(BIT(5, Register(B)), 2, 8), // 0xcb 0x68
+            Instruction::new(BIT(5, Register(C)), 2, 8), // 0xcb 0x69
+            Instruction::new(BIT(5, Register(D)), 2, 8), // 0xcb 0x6a
+            Instruction::new(BIT(5, Register(E)), 2, 8), // 0xcb 0x6b
+            Instruction::new(BIT(5, Register(H)), 2, 8), // 0xcb 0x6c
+            Instruction::new(BIT(5, Register(L)), 2, 8), // 0xcb 0x6d
+            Instruction::new(BIT(5, RegisterIndirectByte(HL)), 2, 12), // 0xcb 0x6e
+            Instruction::new(BIT(5, Register(A)), 2, 8), // 0xcb 0x6f
+            Instruction::new(BIT(6, Register(B)), 2, 8), // 0xcb 0x70
+            Instruction::new(BIT(6, Register(C)), 2, 8), // 0xcb 0x71
+            Instruction::new(BIT(6, Register(D)), 2, 8), // 0xcb 0x72
+            Instruction::new(BIT(6, Register(E)), 2, 8), // 0xcb 0x73
+            Instruction::new(BIT(6, Register(H)), 2, 8), // 0xcb 0x74
+            Instruction::new(BIT(6, Register(L)), 2, 8), // 0xcb 0x75
+            Instruction::new(BIT(6, RegisterIndirectByte(HL)), 2, 12), // 0xcb 0x76
+            Instruction::new(BIT(6, Register(A)), 2, 8), // 0xcb 0x77
+            Instruction::new(BIT(7, Register(B)), 2, 8), // 0xcb 0x78
+            Instruction::new(BIT(7, Register(C)), 2, 8), // 0xcb 0x79
+            Instruction::new(BIT(7, Register(D)), 2, 8), // 0xcb 0x7a
+            Instruction::new(BIT(7, Register(E)), 2, 8), // 0xcb 0x7b
+            Instruction::new(BIT(7, Register(H)), 2, 8), // 0xcb 0x7c
+            Instruction::new(BIT(7, Register(L)), 2, 8), // 0xcb 0x7d
+            Instruction::new(BIT(7, RegisterIndirectByte(HL)), 2, 12), // 0xcb 0x7e
+            Instruction::new(BIT(7, Register(A)), 2, 8), // 0xcb 0x7f
+            Instruction::new(RES(0, Register(B)), 2, 8), // 0xcb 0x80
+            Instruction::new(RES(0, Register(C)), 2, 8), // 0xcb 0x81
+            Instruction::new(RES(0, Register(D)), 2, 8), // 0xcb 0x82
+            Instruction::new(RES(0, Register(E)), 2, 8), // 0xcb 0x83
+            Instruction::new(RES(0, Register(H)), 2, 8), // 0xcb 0x84
+            Instruction::new(RES(0, Register(L)), 2, 8), // 0xcb 0x85
+            Instruction::new(RES(0, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0x86
+            Instruction::new(RES(0, Register(A)), 2, 8), // 0xcb 0x87
+            Instruction::new(RES(1, Register(B)), 2, 8), // 0xcb 0x88
+            Instruction::new(RES(1, Register(C)), 2, 8), // 0xcb 0x89
+            Instruction::new(RES(1, Register(D)), 2, 8), // 0xcb 0x8a
+            Instruction::new(RES(1, Register(E)), 2, 8), // 0xcb 0x8b
+            Instruction::new(RES(1, Register(H)), 2, 8), // 0xcb 0x8c
+            Instruction::new(RES(1, Register(L)), 2, 8), // 0xcb 0x8d
+            Instruction::new(RES(1, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0x8e
+            Instruction::new(RES(1, Register(A)), 2, 8), // 0xcb 0x8f
+            Instruction::new(RES(2, Register(B)), 2, 8), // 0xcb 0x90
+            Instruction::new(RES(2, Register(C)), 2, 8), // 0xcb 0x91
+            Instruction::new(RES(2, Register(D)), 2, 8), // 0xcb 0x92
+            Instruction::new(RES(2, Register(E)), 2, 8), // 0xcb 0x93
+            Instruction::new(RES(2, Register(H)), 2, 8), // 0xcb 0x94
+            Instruction::new(RES(2, Register(L)), 2, 8), // 0xcb 0x95
+            Instruction::new(RES(2, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0x96
+            Instruction::new(RES(2, Register(A)), 2, 8), // 0xcb 0x97
+            Instruction::new(RES(3, Register(B)), 2, 8), // 0xcb 0x98
+            Instruction::new(RES(3, Register(C)), 2, 8), // 0xcb 0x99
+            Instruction::new(RES(3, Register(D)), 2, 8), // 0xcb 0x9a
+            Instruction::new(RES(3, Register(E)), 2, 8), // 0xcb 0x9b
+            Instruction::new(RES(3, Register(H)), 2, 8), // 0xcb 0x9c
+            Instruction::new(RES(3, Register(L)), 2, 8), // 0xcb 0x9d
+            Instruction::new(RES(3, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0x9e
+            Instruction::new(RES(3, Register(A)), 2, 8), // 0xcb 0x9f
+            Instruction::new(RES(4, Register(B)), 2, 8), // 0xcb 0xa0
+            Instruction::new(RES(4, Register(C)), 2, 8), // 0xcb 0xa1
+            Instruction::new(RES(4, Register(D)), 2, 8), // 0xcb 0xa2
+            Instruction::new(RES(4, Register(E)), 2, 8), // 0xcb 0xa3
+            Instruction::new(RES(4, Register(H)), 2, 8), // 0xcb 0xa4
+            Instruction::new(RES(4, Register(L)), 2, 8), // 0xcb 0xa5
+            Instruction::new(RES(4, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0xa6
+            Instruction::new(RES(4, Register(A)), 2, 8), // 0xcb 0xa7
+            Instruction::new(RES(5, Register(B)), 2, 8), // 0xcb 0xa8
+            Instruction::new(RES(5, Register(C)), 2, 8), // 0xcb 0xa9
+            Instruction::new(RES(5, Register(D)), 2, 8), // 0xcb 0xaa
+            Instruction::new(RES(5, Register(E)), 2, 8), // 0xcb 0xab
+            Instruction::new(RES(5, Register(H)), 2, 8), // 0xcb 0xac
+            Instruction::new(RES(5, Register(L)), 2, 8), // 0xcb 0xad
+            Instruction::new(RES(5, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0xae
+            Instruction::new(RES(5, Register(A)), 2, 8), // 0xcb 0xaf
+            Instruction::new(RES(6, Register(B)), 2, 8), // 0xcb 0xb0
+            Instruction::new(RES(6, Register(C)), 2, 8), // 0xcb 0xb1
+            Instruction::new(RES(6, Register(D)), 2, 8), // 0xcb 0xb2
+            Instruction::new(RES(6, Register(E)), 2, 8), // 0xcb 0xb3
+            Instruction::new(RES(6, Register(H)), 2, 8), // 0xcb 0xb4
+            Instruction::new(RES(6, Register(L)), 2, 8), // 0xcb 0xb5
+            Instruction::new(RES(6, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0xb6
+            Instruction::new(RES(6, Register(A)), 2, 8), // 0xcb 0xb7
+            Instruction::new(RES(7, Register(B)), 2, 8), // 0xcb 0xb8
+            Instruction::new(RES(7, Register(C)), 2, 8), // 0xcb 0xb9
+            Instruction::new(RES(7, Register(D)), 2, 8), // 0xcb 0xba
+            Instruction::new(RES(7, Register(E)), 2, 8), // 0xcb 0xbb
+            Instruction::new(RES(7, Register(H)), 2, 8), // 0xcb 0xbc
+            Instruction::new(RES(7, Register(L)), 2, 8), // 0xcb 0xbd
+            Instruction::new(RES(7, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0xbe
+            Instruction::new(RES(7, Register(A)), 2, 8), // 0xcb 0xbf
+            Instruction::new(SET(0, Register(B)), 2, 8), // 0xcb 0xc0
+            Instruction::new(SET(0, Register(C)), 2, 8), // 0xcb 0xc1
+            Instruction::new(SET(0, Register(D)), 2, 8), // 0xcb 0xc2
+            Instruction::new(SET(0, Register(E)), 2, 8), // 0xcb 0xc3
+            Instruction::new(SET(0, Register(H)), 2, 8), // 0xcb 0xc4
+            Instruction::new(SET(0, Register(L)), 2, 8), // 0xcb 0xc5
+            Instruction::new(SET(0, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0xc6
+            Instruction::new(SET(0, Register(A)), 2, 8), // 0xcb 0xc7
+            Instruction::new(SET(1, Register(B)), 2, 8), // 0xcb 0xc8
+            Instruction::new(SET(1, Register(C)), 2, 8), // 0xcb 0xc9
+            Instruction::new(SET(1, Register(D)), 2, 8), // 0xcb 0xca
+            Instruction::new(SET(1, Register(E)), 2, 8), // 0xcb 0xcb
+            Instruction::new(SET(1, Register(H)), 2, 8), // 0xcb 0xcc
+            Instruction::new(SET(1, Register(L)), 2, 8), // 0xcb 0xcd
+            Instruction::new(SET(1, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0xce
+            Instruction::new(SET(1, Register(A)), 2, 8), // 0xcb 0xcf
+            Instruction::new(SET(2, Register(B)), 2, 8), // 0xcb 0xd0
+            Instruction::new(SET(2, Register(C)), 2, 8), // 0xcb 0xd1
+            Instruction::new(SET(2, Register(D)), 2, 8), // 0xcb 0xd2
+            Instruction::new(SET(2, Register(E)), 2, 8), // 0xcb 0xd3
+            Instruction::new(SET(2, Register(H)), 2, 8), // 0xcb 0xd4
+            Instruction::new(SET(2, Register(L)), 2, 8), // 0xcb 0xd5
+            Instruction::new(SET(2, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0xd6
+            Instruction::new(SET(2, Register(A)), 2, 8), // 0xcb 0xd7
+            Instruction::new(SET(3, Register(B)), 2, 8), // 0xcb 0xd8
+            Instruction::new(SET(3, Register(C)), 2, 8), // 0xcb 0xd9
+            Instruction::new(SET(3, Register(D)), 2, 8), // 0xcb 0xda
+            Instruction::new(SET(3, Register(E)), 2, 8), // 0xcb 0xdb
+            Instruction::new(SET(3, Register(H)), 2, 8), // 0xcb 0xdc
+            Instruction::new(SET(3, Register(L)), 2, 8), // 0xcb 0xdd
+            Instruction::new(SET(3, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0xde
+            Instruction::new(SET(3, Register(A)), 2, 8), // 0xcb 0xdf
+            Instruction::new(SET(4, Register(B)), 2, 8), // 0xcb 0xe0
+            Instruction::new(SET(4, Register(C)), 2, 8), // 0xcb 0xe1
+            Instruction::new(SET(4, Register(D)), 2, 8), // 0xcb 0xe2
+            Instruction::new(SET(4, Register(E)), 2, 8), // 0xcb 0xe3
+            Instruction::new(SET(4, Register(H)), 2, 8), // 0xcb 0xe4
+            Instruction::new(SET(4, Register(L)), 2, 8), // 0xcb 0xe5
+            Instruction::new(SET(4, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0xe6
+            Instruction::new(SET(4, Register(A)), 2, 8), // 0xcb 0xe7
+            Instruction::new(SET(5, Register(B)), 2, 8), // 0xcb 0xe8
+            Instruction::new(SET(5, Register(C)), 2, 8), // 0xcb 0xe9
+            Instruction::new(SET(5, Register(D)), 2, 8), // 0xcb 0xea
+            Instruction::new(SET(5, Register(E)), 2, 8), // 0xcb 0xeb
+            Instruction::new(SET(5, Register(H)), 2, 8), // 0xcb 0xec
+            Instruction::new(SET(5, Register(L)), 2, 8), // 0xcb 0xed
+            Instruction::new(SET(5, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0xee
+            Instruction::new(SET(5, Register(A)), 2, 8), // 0xcb 0xef
+            Instruction::new(SET(6, Register(B)), 2, 8), // 0xcb 0xf0
+            Instruction::new(SET(6, Register(C)), 2, 8), // 0xcb 0xf1
+            Instruction::new(SET(6, Register(D)), 2, 8), // 0xcb 0xf2
+            Instruction::new(SET(6, Register(E)), 2, 8), // 0xcb 0xf3
+            Instruction::new(SET(6, Register(H)), 2, 8), // 0xcb 0xf4
+            Instruction::new(SET(6, Register(L)), 2, 8), // 0xcb 0xf5
+            Instruction::new(SET(6, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0xf6
+            Instruction::new(SET(6, Register(A)), 2, 8), // 0xcb 0xf7
+            Instruction::new(SET(7, Register(B)), 2, 8), // 0xcb 0xf8
+            Instruction::new(SET(7, Register(C)), 2, 8), // 0xcb 0xf9
+            Instruction::new(SET(7, Register(D)), 2, 8), // 0xcb 0xfa
+            Instruction::new(SET(7, Register(E)), 2, 8), // 0xcb 0xfb
+            Instruction::new(SET(7, Register(H)), 2, 8), // 0xcb 0xfc
+            Instruction::new(SET(7, Register(L)), 2, 8), // 0xcb 0xfd
+            Instruction::new(SET(7, RegisterIndirectByte(HL)), 2, 16), // 0xcb 0xfe
+            Instruction::new(SET(7, Register(A)), 2, 8), // 0xcb 0xff
         ];
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub enum IFlag {
     /// Represents no flag needed for this action; always evaluates true
     TRUE,
@@ -300,7 +561,8 @@ impl Display for IFlag {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
 pub enum IRegister {
     A,
     B,
@@ -351,7 +613,7 @@ pub enum ILocation {
     /// The direct register
     Register(IRegister),
 
-    /// The memory address pointed to by the register (byte)
+    /// The memory address pointed to by the register (byte). For C this is 0xFF00 + C.
     RegisterIndirectByte(IRegister),
 
     /// The memory address pointed to by the register (word)
@@ -363,19 +625,23 @@ pub enum ILocation {
     /// The immediate operand (word)
     ImmediateWord,
 
-    /// The memory address pointed to by the immediate operand (byte)
+    /// The memory address 0xFF00 + the immediate operand (byte)
     ImmediateByteIndirectByte,
 
-    /// The memory address pointed to by the immediate operand (byte)
+    /// The memory address 0xFF00 + the immediate operand (word)
     ImmediateByteIndirectWord,
 
-    /// The memory address pointed to by the immediate operand (word)
+    /// The memory address pointed to by the immediate operand (byte)
     ImmediateWordIndirectByte,
 
     /// The memory address pointed to by the immediate operand (word)
     ImmediateWordIndirectWord,
-    // /// The immediate operand (signed byte)
-    // ImmediateSignedByte,
+
+    /// The immediate operand (signed byte)
+    ImmediateSignedByte,
+
+    /// SP plus the immediate operand (signed byte)
+    StackPointerOffset,
 }
 
 impl Display for ILocation {
@@ -391,7 +657,8 @@ impl Display for ILocation {
             ILocation::ImmediateByteIndirectWord => write!(f, "(u8)"),
             ILocation::ImmediateWordIndirectByte => write!(f, "(u16)"),
             ILocation::ImmediateWordIndirectWord => write!(f, "(u16)"),
-            // ILocation::ImmediateSignedByte => write!(f, "i8"),
+            ILocation::ImmediateSignedByte => write!(f, "i8"),
+            ILocation::StackPointerOffset => write!(f, "SP+i8"),
         }
     }
 }
@@ -417,7 +684,39 @@ pub enum IAction {
     XOR(ILocation, ILocation),
     OR(ILocation, ILocation),
     CP(ILocation, ILocation),
-    UNIMPLEMENTED,
+    JR(IFlag, ILocation),
+    CALL(IFlag, ILocation),
+    RET(IFlag),
+    RETI,
+    /// Call the fixed vector at the given address
+    RST(u8),
+    PUSH(ILocation),
+    POP(ILocation),
+    RLCA,
+    RRCA,
+    RLA,
+    RRA,
+    DAA,
+    SCF,
+    HALT,
+    STOP,
+    DI,
+    EI,
+    /// Prefix byte for the 0xCB table; replaced by the prefixed action when decoded
+    PREFIX,
+    RLC(ILocation),
+    RRC(ILocation),
+    RL(ILocation),
+    RR(ILocation),
+    SLA(ILocation),
+    SRA(ILocation),
+    SWAP(ILocation),
+    SRL(ILocation),
+    BIT(u8, ILocation),
+    RES(u8, ILocation),
+    SET(u8, ILocation),
+    /// One of the opcodes the SM83 doesn't have, which locks it up until reset
+    ILLEGAL,
 }
 
 impl Display for IAction {
@@ -433,7 +732,7 @@ impl Display for IAction {
             IAction::JP(flag, loc) => write!(f, "JP {}, {}", flag, loc),
             IAction::CPL => write!(f, "CPL (FLIP A)"),
             IAction::CCF => write!(f, "CCF (FLIP CY)"),
-            IAction::UNIMPLEMENTED => write!(f, "<???>"),
+            IAction::ILLEGAL => write!(f, "ILLEGAL"),
             IAction::ADD(dst, src) => write!(f, "ADD {}, {}", dst, src),
             IAction::ADD16(dst, src) => write!(f, "ADD {}, {}", dst, src),
             IAction::ADC(dst, src) => write!(f, "ADC {}, {}", dst, src),
@@ -443,7 +742,35 @@ impl Display for IAction {
             IAction::XOR(dst, src) => write!(f, "XOR {}, {}", dst, src),
             IAction::OR(dst, src) => write!(f, "OR {}, {}", dst, src),
             IAction::CP(dst, src) => write!(f, "CP {}, {}", dst, src),
-            _ => write!(f, "<display not impl.>"),
+            IAction::JR(flag, loc) => write!(f, "JR {}, {}", flag, loc),
+            IAction::CALL(flag, loc) => write!(f, "CALL {}, {}", flag, loc),
+            IAction::RET(flag) => write!(f, "RET {}", flag),
+            IAction::RETI => write!(f, "RETI"),
+            IAction::RST(vector) => write!(f, "RST {:02x}h", vector),
+            IAction::PUSH(loc) => write!(f, "PUSH {}", loc),
+            IAction::POP(loc) => write!(f, "POP {}", loc),
+            IAction::RLCA => write!(f, "RLCA"),
+            IAction::RRCA => write!(f, "RRCA"),
+            IAction::RLA => write!(f, "RLA"),
+            IAction::RRA => write!(f, "RRA"),
+            IAction::DAA => write!(f, "DAA"),
+            IAction::SCF => write!(f, "SCF"),
+            IAction::HALT => write!(f, "HALT"),
+            IAction::STOP => write!(f, "STOP"),
+            IAction::DI => write!(f, "DI"),
+            IAction::EI => write!(f, "EI"),
+            IAction::PREFIX => write!(f, "PREFIX CB"),
+            IAction::RLC(loc) => write!(f, "RLC {}", loc),
+            IAction::RRC(loc) => write!(f, "RRC {}", loc),
+            IAction::RL(loc) => write!(f, "RL {}", loc),
+            IAction::RR(loc) => write!(f, "RR {}", loc),
+            IAction::SLA(loc) => write!(f, "SLA {}", loc),
+            IAction::SRA(loc) => write!(f, "SRA {}", loc),
+            IAction::SWAP(loc) => write!(f, "SWAP {}", loc),
+            IAction::SRL(loc) => write!(f, "SRL {}", loc),
+            IAction::BIT(bit, loc) => write!(f, "BIT {}, {}", bit, loc),
+            IAction::RES(bit, loc) => write!(f, "RES {}, {}", bit, loc),
+            IAction::SET(bit, loc) => write!(f, "SET {}, {}", bit, loc),
         }
     }
 }
@@ -464,6 +791,7 @@ impl Instruction {
     }
 }
 
+#[derive(Clone)]
 pub struct DecodedInstruction {
     action: IAction,
     len: u8,
@@ -474,22 +802,24 @@ pub struct DecodedInstruction {
 
 impl DecodedInstruction {
     pub fn decode(mmu: &Mmu, pc: usize) -> Self {
-        let opcode = mmu.rb(pc);
+        Self::decode_with(|addr| mmu.rb(addr), pc)
+    }
 
-        let instruction = &UNPREFIXED_INSTRUCTIONS[opcode as usize];
+    /// Decodes the instruction at `pc`, reading memory through `read` rather than an `Mmu`
+    pub fn decode_with<F: Fn(usize) -> u8>(read: F, pc: usize) -> Self {
+        let opcode = read(pc);
 
-        let num_operands = instruction.length - 1;
+        let instruction = match UNPREFIXED_INSTRUCTIONS[opcode as usize].action {
+            IAction::PREFIX => &CB_PREFIXED_INSTRUCTIONS[read(pc + 1) as usize],
+            _ => &UNPREFIXED_INSTRUCTIONS[opcode as usize],
+        };
 
-        // TODO: remove operands, keep only raw bytes
-        let mut operands = Vec::with_capacity(instruction.length as usize - 1);
         let mut raw_bytes = Vec::with_capacity(instruction.length as usize);
 
         raw_bytes.push(opcode);
 
-        for i in 1..=num_operands {
-            let byte = mmu.rb(pc + i as usize);
-            operands.push(byte);
-            raw_bytes.push(byte);
+        for i in 1..instruction.length {
+            raw_bytes.push(read(pc + i as usize));
         }
 
         Self {
@@ -508,6 +838,11 @@ impl DecodedInstruction {
         self.len
     }
 
+    /// Always false: every instruction has at least its opcode byte
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     pub fn cycles(&self) -> u8 {
         self.cycles
     }
@@ -545,3 +880,113 @@ impl DecodedInstruction {
         operands[0]
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::DecodedInstruction;
+
+    fn decode(bytes: &[u8]) -> (String, u8, u8) {
+        let instruction = DecodedInstruction::decode_with(|addr| bytes[addr], 0);
+        (
+            instruction.action().to_string(),
+            instruction.len(),
+            instruction.cycles(),
+        )
+    }
+
+    #[test]
+    fn alu_rows() {
+        // The first column of each ALU row once decoded as its neighbour in the row above
+        assert_eq!(decode(&[0x88]), ("ADC A, B".into(), 1, 4));
+        assert_eq!(decode(&[0x98]), ("SBC A, B".into(), 1, 4));
+        assert_eq!(decode(&[0xA8]), ("XOR A, B".into(), 1, 4));
+        assert_eq!(decode(&[0xB8]), ("CP A, B".into(), 1, 4));
+    }
+
+    #[test]
+    fn filled_in_opcodes() {
+        let expected = [
+            (0x07, "RLCA", 1, 4),
+            (0x0F, "RRCA", 1, 4),
+            (0x10, "STOP", 2, 4),
+            (0x17, "RLA", 1, 4),
+            (0x18, "JR , i8", 2, 12),
+            (0x1F, "RRA", 1, 4),
+            (0x20, "JR NZ, i8", 2, 12),
+            (0x27, "DAA", 1, 4),
+            (0x28, "JR Z, i8", 2, 12),
+            (0x30, "JR NCY, i8", 2, 12),
+            (0x37, "SCF", 1, 4),
+            (0x38, "JR CY, i8", 2, 12),
+            (0x76, "HALT", 1, 4),
+            (0xC0, "RET NZ", 1, 20),
+            (0xC1, "POP BC", 1, 12),
+            (0xC2, "JP NZ, u16", 3, 16),
+            (0xC4, "CALL NZ, u16", 3, 24),
+            (0xC5, "PUSH BC", 1, 16),
+            (0xC6, "ADD A, u8", 2, 8),
+            (0xC7, "RST 00h", 1, 16),
+            (0xC8, "RET Z", 1, 20),
+            (0xC9, "RET ", 1, 16),
+            (0xCA, "JP Z, u16", 3, 16),
+            (0xCC, "CALL Z, u16", 3, 24),
+            (0xCD, "CALL , u16", 3, 24),
+            (0xCE, "ADC A, u8", 2, 8),
+            (0xCF, "RST 08h", 1, 16),
+            (0xD0, "RET NCY", 1, 20),
+            (0xD1, "POP DE", 1, 12),
+            (0xD2, "JP NCY, u16", 3, 16),
+            (0xD4, "CALL NCY, u16", 3, 24),
+            (0xD5, "PUSH DE", 1, 16),
+            (0xD6, "SUB A, u8", 2, 8),
+            (0xD7, "RST 10h", 1, 16),
+            (0xD8, "RET CY", 1, 20),
+            (0xD9, "RETI", 1, 16),
+            (0xDA, "JP CY, u16", 3, 16),
+            (0xDC, "CALL CY, u16", 3, 24),
+            (0xDE, "SBC A, u8", 2, 8),
+            (0xDF, "RST 18h", 1, 16),
+            (0xE0, "LD (u8), A", 2, 12),
+            (0xE1, "POP HL", 1, 12),
+            (0xE2, "LD (C), A", 1, 8),
+            (0xE5, "PUSH HL", 1, 16),
+            (0xE6, "AND A, u8", 2, 8),
+            (0xE7, "RST 20h", 1, 16),
+            (0xE8, "ADD SP, i8", 2, 16),
+            (0xE9, "JP , HL", 1, 4),
+            (0xEA, "LD (u16), A", 3, 16),
+            (0xEE, "XOR A, u8", 2, 8),
+            (0xEF, "RST 28h", 1, 16),
+            (0xF0, "LD A, (u8)", 2, 12),
+            (0xF1, "POP AF", 1, 12),
+            (0xF2, "LD A, (C)", 1, 8),
+            (0xF3, "DI", 1, 4),
+            (0xF5, "PUSH AF", 1, 16),
+            (0xF6, "OR A, u8", 2, 8),
+            (0xF7, "RST 30h", 1, 16),
+            (0xF8, "LD HL, SP+i8", 2, 12),
+            (0xF9, "LD SP, HL", 1, 8),
+            (0xFA, "LD A, (u16)", 3, 16),
+            (0xFB, "EI", 1, 4),
+            (0xFE, "CP A, u8", 2, 8),
+            (0xFF, "RST 38h", 1, 16),
+        ];
+
+        for (opcode, action, len, cycles) in expected {
+            let bytes = [opcode, 0x00, 0x00];
+            assert_eq!(
+                decode(&bytes),
+                (action.to_string(), len, cycles),
+                "opcode {:02X}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn prefixed() {
+        assert_eq!(decode(&[0xCB, 0x11]), ("RL C".into(), 2, 8));
+        assert_eq!(decode(&[0xCB, 0x7E]), ("BIT 7, (HL)".into(), 2, 12));
+        assert_eq!(decode(&[0xCB, 0xFF]), ("SET 7, A".into(), 2, 8));
+    }
+}
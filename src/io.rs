@@ -0,0 +1,423 @@
+use bitflags::bitflags;
+
+pub const SB: u16 = 0xFF01;
+pub const SC: u16 = 0xFF02;
+pub const DIV: u16 = 0xFF04;
+pub const TIMA: u16 = 0xFF05;
+pub const TMA: u16 = 0xFF06;
+pub const TAC: u16 = 0xFF07;
+pub const IF: u16 = 0xFF0F;
+pub const LCDC: u16 = 0xFF40;
+pub const STAT: u16 = 0xFF41;
+pub const SCY: u16 = 0xFF42;
+pub const SCX: u16 = 0xFF43;
+pub const LY: u16 = 0xFF44;
+pub const LYC: u16 = 0xFF45;
+pub const DMA: u16 = 0xFF46;
+pub const BGP: u16 = 0xFF47;
+pub const OBP0: u16 = 0xFF48;
+pub const OBP1: u16 = 0xFF49;
+pub const WY: u16 = 0xFF4A;
+pub const WX: u16 = 0xFF4B;
+pub const KEY1: u16 = 0xFF4D;
+pub const VBK: u16 = 0xFF4F;
+pub const HDMA5: u16 = 0xFF55;
+pub const BCPS: u16 = 0xFF68;
+pub const SVBK: u16 = 0xFF70;
+pub const IE: u16 = 0xFFFF;
+
+bitflags! {
+    /// LCD control, 0xFF40
+    pub struct Lcdc: u8 {
+        const LCD_ENABLE = 0b1000_0000;
+        const WINDOW_TILE_MAP = 0b0100_0000;
+        const WINDOW_ENABLE = 0b0010_0000;
+        const BG_TILE_DATA = 0b0001_0000;
+        const BG_TILE_MAP = 0b0000_1000;
+        const OBJ_SIZE = 0b0000_0100;
+        const OBJ_ENABLE = 0b0000_0010;
+        const BG_ENABLE = 0b0000_0001;
+    }
+}
+
+bitflags! {
+    /// LCD status, 0xFF41. The low two bits are the PPU mode: 0 HBlank, 1 VBlank, 2 OAM scan
+    /// and 3 drawing.
+    pub struct Stat: u8 {
+        const LYC_INTERRUPT = 0b0100_0000;
+        const OAM_INTERRUPT = 0b0010_0000;
+        const VBLANK_INTERRUPT = 0b0001_0000;
+        const HBLANK_INTERRUPT = 0b0000_1000;
+        const LYC_EQUAL = 0b0000_0100;
+    }
+}
+
+bitflags! {
+    /// Interrupt sources, as IF (0xFF0F) and IE (0xFFFF) lay them out, highest priority first
+    pub struct Interrupts: u8 {
+        const VBLANK = 0b0000_0001;
+        const STAT = 0b0000_0010;
+        const TIMER = 0b0000_0100;
+        const SERIAL = 0b0000_1000;
+        const JOYPAD = 0b0001_0000;
+    }
+}
+
+bitflags! {
+    /// Joypad buttons held down. The low nibble is the action buttons and the high nibble the
+    /// d-pad, each in the order P1 reports them.
+    pub struct Buttons: u8 {
+        const A = 0b0000_0001;
+        const B = 0b0000_0010;
+        const SELECT = 0b0000_0100;
+        const START = 0b0000_1000;
+        const RIGHT = 0b0001_0000;
+        const LEFT = 0b0010_0000;
+        const UP = 0b0100_0000;
+        const DOWN = 0b1000_0000;
+    }
+}
+
+impl Buttons {
+    /// Case-insensitive, e.g. `a` or `Start`
+    pub fn from_name(name: &str) -> Option<Buttons> {
+        let button = match name.to_ascii_lowercase().as_str() {
+            "a" => Buttons::A,
+            "b" => Buttons::B,
+            "select" => Buttons::SELECT,
+            "start" => Buttons::START,
+            "right" => Buttons::RIGHT,
+            "left" => Buttons::LEFT,
+            "up" => Buttons::UP,
+            "down" => Buttons::DOWN,
+            _ => return None,
+        };
+
+        Some(button)
+    }
+
+    /// What P1 reads with `select` written to bits 4-5. Pressed buttons read as 0.
+    pub fn p1(self, select: u8) -> u8 {
+        let select = select & 0x30;
+        let mut pressed = 0;
+
+        if select & 0x10 == 0 {
+            pressed |= self.bits >> 4;
+        }
+        if select & 0x20 == 0 {
+            pressed |= self.bits & 0x0F;
+        }
+
+        0xC0 | select | (!pressed & 0x0F)
+    }
+}
+
+/// Returns the hardware.inc name (e.g. `rLCDC`) of the IO register at `addr`, if it has one.
+pub fn register_name(addr: u16) -> Option<&'static str> {
+    let name = match addr {
+        0xFF00 => "rP1",
+        0xFF01 => "rSB",
+        0xFF02 => "rSC",
+        0xFF04 => "rDIV",
+        0xFF05 => "rTIMA",
+        0xFF06 => "rTMA",
+        0xFF07 => "rTAC",
+        0xFF0F => "rIF",
+        0xFF10 => "rNR10",
+        0xFF11 => "rNR11",
+        0xFF12 => "rNR12",
+        0xFF13 => "rNR13",
+        0xFF14 => "rNR14",
+        0xFF16 => "rNR21",
+        0xFF17 => "rNR22",
+        0xFF18 => "rNR23",
+        0xFF19 => "rNR24",
+        0xFF1A => "rNR30",
+        0xFF1B => "rNR31",
+        0xFF1C => "rNR32",
+        0xFF1D => "rNR33",
+        0xFF1E => "rNR34",
+        0xFF20 => "rNR41",
+        0xFF21 => "rNR42",
+        0xFF22 => "rNR43",
+        0xFF23 => "rNR44",
+        0xFF24 => "rNR50",
+        0xFF25 => "rNR51",
+        0xFF26 => "rNR52",
+        0xFF40 => "rLCDC",
+        0xFF41 => "rSTAT",
+        0xFF42 => "rSCY",
+        0xFF43 => "rSCX",
+        0xFF44 => "rLY",
+        0xFF45 => "rLYC",
+        0xFF46 => "rDMA",
+        0xFF47 => "rBGP",
+        0xFF48 => "rOBP0",
+        0xFF49 => "rOBP1",
+        0xFF4A => "rWY",
+        0xFF4B => "rWX",
+        0xFF4D => "rKEY1",
+        0xFF4F => "rVBK",
+        0xFF50 => "rBANK",
+        0xFF51 => "rHDMA1",
+        0xFF52 => "rHDMA2",
+        0xFF53 => "rHDMA3",
+        0xFF54 => "rHDMA4",
+        0xFF55 => "rHDMA5",
+        0xFF56 => "rRP",
+        0xFF68 => "rBCPS",
+        0xFF69 => "rBCPD",
+        0xFF6A => "rOCPS",
+        0xFF6B => "rOCPD",
+        0xFF70 => "rSVBK",
+        0xFFFF => "rIE",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// Returns the address of the IO register called `name`, the inverse of `register_name`
+pub fn register_address(name: &str) -> Option<u16> {
+    (0xFF00..=0xFFFF).find(|&addr| register_name(addr) == Some(name))
+}
+
+fn on_off(value: u8, bit: u8) -> &'static str {
+    match value & (1 << bit) != 0 {
+        true => "on",
+        false => "off",
+    }
+}
+
+/// Names of the set bits of IF/IE
+fn interrupts(value: u8) -> String {
+    let names = ["VBlank", "STAT", "Timer", "Serial", "Joypad"];
+    let set: Vec<_> = (0..5)
+        .filter(|bit| value & (1 << bit) != 0)
+        .map(|bit| names[bit])
+        .collect();
+
+    match set.is_empty() {
+        true => "none".to_string(),
+        false => set.join(", "),
+    }
+}
+
+/// Shades for color indices 0-3 of a palette register
+fn palette(value: u8) -> String {
+    let shades: Vec<_> = (0..4)
+        .map(|index| ((value >> (index * 2)) & 0b11).to_string())
+        .collect();
+
+    format!("shades {}", shades.join(" "))
+}
+
+/// Decodes the bitfields of the IO register at `addr`, for registers that have any
+pub fn describe(addr: u16, value: u8) -> Option<String> {
+    let description = match addr {
+        0xFF00 => {
+            let group = match (value >> 4) & 0b11 {
+                0b00 => "buttons and d-pad",
+                0b01 => "buttons",
+                0b10 => "d-pad",
+                _ => "nothing",
+            };
+            format!("select {}, pressed {:04b}", group, !value & 0x0F)
+        }
+        0xFF02 => format!(
+            "transfer {}, {} clock",
+            on_off(value, 7),
+            match value & 1 != 0 {
+                true => "internal",
+                false => "external",
+            }
+        ),
+        0xFF07 => {
+            let clock = match value & 0b11 {
+                0b00 => "4 KHz",
+                0b01 => "256 KHz",
+                0b10 => "64 KHz",
+                _ => "16 KHz",
+            };
+            match value & 0b100 != 0 {
+                true => format!("enabled, {}", clock),
+                false => format!("disabled, {}", clock),
+            }
+        }
+        0xFF0F | 0xFFFF => interrupts(value),
+        0xFF10 => format!(
+            "sweep time {}, {}, shift {}",
+            (value >> 4) & 0b111,
+            match value & 0b1000 != 0 {
+                true => "down",
+                false => "up",
+            },
+            value & 0b111
+        ),
+        0xFF11 | 0xFF16 => format!(
+            "duty {}%, length {}",
+            [12.5, 25.0, 50.0, 75.0][(value >> 6) as usize],
+            value & 0b11_1111
+        ),
+        0xFF12 | 0xFF17 | 0xFF21 => format!(
+            "volume {}, {}, pace {}",
+            value >> 4,
+            match value & 0b1000 != 0 {
+                true => "up",
+                false => "down",
+            },
+            value & 0b111
+        ),
+        0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => format!(
+            "trigger {}, length {}",
+            on_off(value, 7),
+            match value & 0b0100_0000 != 0 {
+                true => "enabled",
+                false => "disabled",
+            }
+        ),
+        0xFF1A => format!("DAC {}", on_off(value, 7)),
+        0xFF1C => {
+            let level = match (value >> 5) & 0b11 {
+                0b00 => "mute",
+                0b01 => "100%",
+                0b10 => "50%",
+                _ => "25%",
+            };
+            format!("output {}", level)
+        }
+        0xFF24 => format!("left {}, right {}", (value >> 4) & 0b111, value & 0b111),
+        0xFF25 => {
+            let channels = |bits: u8| {
+                let set: Vec<_> = (0..4)
+                    .filter(|ch| bits & (1 << ch) != 0)
+                    .map(|ch| (ch + 1).to_string())
+                    .collect();
+                match set.is_empty() {
+                    true => "none".to_string(),
+                    false => set.join(""),
+                }
+            };
+            format!("left {}, right {}", channels(value >> 4), channels(value))
+        }
+        0xFF26 => format!(
+            "sound {}, CH1 {}, CH2 {}, CH3 {}, CH4 {}",
+            on_off(value, 7),
+            on_off(value, 0),
+            on_off(value, 1),
+            on_off(value, 2),
+            on_off(value, 3)
+        ),
+        0xFF40 => {
+            let lcdc = Lcdc::from_bits_truncate(value);
+            let map = |flag| match lcdc.contains(flag) {
+                true => "9C00",
+                false => "9800",
+            };
+            format!(
+                "LCD {}, window {}, window map {}, tiles {}, BG map {}, OBJ {}, OBJ {}, BG {}",
+                on_off(value, 7),
+                on_off(value, 5),
+                map(Lcdc::WINDOW_TILE_MAP),
+                match lcdc.contains(Lcdc::BG_TILE_DATA) {
+                    true => "8000",
+                    false => "8800",
+                },
+                map(Lcdc::BG_TILE_MAP),
+                match lcdc.contains(Lcdc::OBJ_SIZE) {
+                    true => "8x16",
+                    false => "8x8",
+                },
+                on_off(value, 1),
+                on_off(value, 0)
+            )
+        }
+        0xFF41 => {
+            let mode = match value & 0b11 {
+                0 => "HBlank",
+                1 => "VBlank",
+                2 => "OAM scan",
+                _ => "drawing",
+            };
+            let sources: Vec<_> = [(6, "LYC"), (5, "OAM"), (4, "VBlank"), (3, "HBlank")]
+                .iter()
+                .filter(|(bit, _)| value & (1 << bit) != 0)
+                .map(|(_, name)| *name)
+                .collect();
+            format!(
+                "mode {}, LYC=LY {}, interrupts on {}",
+                mode,
+                on_off(value, 2),
+                match sources.is_empty() {
+                    true => "none".to_string(),
+                    false => sources.join(", "),
+                }
+            )
+        }
+        0xFF46 => format!("copy from {:02x}00", value),
+        0xFF47..=0xFF49 => palette(value),
+        0xFF4D => format!(
+            "{} speed, switch {}",
+            match value & 0x80 != 0 {
+                true => "double",
+                false => "normal",
+            },
+            match value & 1 != 0 {
+                true => "armed",
+                false => "not armed",
+            }
+        ),
+        0xFF4F => format!("VRAM bank {}", value & 1),
+        0xFF50 => format!(
+            "boot ROM {}",
+            match value != 0 {
+                true => "unmapped",
+                false => "mapped",
+            }
+        ),
+        0xFF70 => format!("WRAM bank {}", (value & 0b111).max(1)),
+        _ => return None,
+    };
+
+    Some(description)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{describe, register_address, register_name, Buttons};
+
+    #[test]
+    fn names() {
+        assert_eq!(register_name(0xFF40), Some("rLCDC"));
+        assert_eq!(register_address("rIE"), Some(0xFFFF));
+        assert_eq!(register_name(0xFF03), None);
+    }
+
+    #[test]
+    fn bitfields() {
+        assert_eq!(
+            describe(0xFF40, 0x91).unwrap(),
+            "LCD on, window off, window map 9800, tiles 8000, BG map 9800, OBJ 8x8, OBJ off, BG on"
+        );
+        assert_eq!(describe(0xFF07, 0b111).unwrap(), "enabled, 16 KHz");
+        assert_eq!(describe(0xFF07, 0b000).unwrap(), "disabled, 4 KHz");
+        assert_eq!(
+            describe(0xFF26, 0xF1).unwrap(),
+            "sound on, CH1 on, CH2 off, CH3 off, CH4 off"
+        );
+        assert_eq!(describe(0xFFFF, 0b101).unwrap(), "VBlank, Timer");
+        assert_eq!(describe(0xFF47, 0xE4).unwrap(), "shades 0 1 2 3");
+        assert_eq!(describe(0xFF05, 0x12), None);
+    }
+
+    #[test]
+    fn joypad() {
+        let buttons = Buttons::from_name("Start").unwrap() | Buttons::UP;
+
+        assert_eq!(buttons.p1(0x10), 0xD7);
+        assert_eq!(buttons.p1(0x20), 0xEB);
+        assert_eq!(buttons.p1(0x00), 0xC3);
+        assert_eq!(buttons.p1(0x30), 0xFF);
+        assert_eq!(Buttons::from_name("turbo"), None);
+    }
+}
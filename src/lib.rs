@@ -0,0 +1,35 @@
+//! A Game Boy emulator, along with the tools for taking games apart: a disassembler and
+//! assembler, tracer, profiler, GDB stub and scripting. `GameBoy` is the place to start.
+
+#[macro_use]
+extern crate lazy_static;
+
+pub mod asm;
+pub mod bits;
+pub mod callstack;
+pub mod coverage;
+pub mod cpu;
+pub mod disasm;
+pub mod error;
+pub mod expr;
+pub mod flow;
+mod gameboy;
+pub mod gdb;
+pub mod header;
+pub mod instructions;
+pub mod io;
+pub mod listing;
+pub mod mmu;
+pub mod model;
+pub mod profiler;
+pub mod scheduler;
+pub mod script;
+pub mod sgb;
+pub mod state;
+pub mod symbols;
+pub mod trace;
+pub mod vram;
+
+pub use error::EmulatorError;
+pub use gameboy::{render_screen, GameBoy, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use model::Model;
@@ -0,0 +1,402 @@
+use std::{collections::BTreeSet, fmt::Write, rc::Rc};
+
+use crate::{
+    disasm::{self, Formatter, Syntax},
+    flow::{self, FlowAnalyser},
+    instructions::{DecodedInstruction, IAction, ILocation},
+    io,
+    symbols::SymbolTable,
+};
+
+/// Most bytes to put on a single `DB` line
+const BYTES_PER_LINE: usize = 8;
+
+/// Cartridge header fields in 0x0104-0x014F, which are always emitted as data
+const HEADER_FIELDS: [(usize, usize, &str); 13] = [
+    (0x0104, 0x0134, "Nintendo logo"),
+    (0x0134, 0x0143, "Title"),
+    (0x0143, 0x0144, "CGB flag"),
+    (0x0144, 0x0146, "New licensee code"),
+    (0x0146, 0x0147, "SGB flag"),
+    (0x0147, 0x0148, "Cartridge type"),
+    (0x0148, 0x0149, "ROM size"),
+    (0x0149, 0x014A, "RAM size"),
+    (0x014A, 0x014B, "Destination code"),
+    (0x014B, 0x014C, "Old licensee code"),
+    (0x014C, 0x014D, "Mask ROM version"),
+    (0x014D, 0x014E, "Header checksum"),
+    (0x014E, 0x0150, "Global checksum"),
+];
+
+/// What to emit at a ROM offset
+enum Line<'a> {
+    Code(&'a DecodedInstruction),
+    /// Code that has to be written as `DB` to reassemble to the same bytes
+    RawCode(&'a DecodedInstruction),
+    Data,
+}
+
+/// Whether rgbasm is guaranteed to encode `inst` back to the same bytes. Anything that isn't
+/// gets emitted as `DB` instead.
+fn reassembles(inst: &DecodedInstruction) -> bool {
+    match inst.action() {
+        // rgbasm only emits `STOP` as 10 00
+        IAction::STOP => inst.raw_bytes()[1] == 0x00,
+        // Some rgbasm versions insert a NOP after HALT
+        IAction::HALT => false,
+        // Some rgbasm versions shorten these to LDH
+        IAction::LD(ILocation::ImmediateWordIndirectByte, _)
+        | IAction::LD(_, ILocation::ImmediateWordIndirectByte) => inst.operands_as_u16() < 0xFF00,
+        IAction::ILLEGAL | IAction::PREFIX => false,
+        _ => true,
+    }
+}
+
+/// Address a JP/JR/CALL at `addr` jumps to, if it's known statically
+fn branch_target(inst: &DecodedInstruction, addr: u16) -> Option<u16> {
+    match inst.action() {
+        IAction::JP(_, ILocation::ImmediateWord) | IAction::CALL(_, _) => {
+            Some(inst.operands_as_u16())
+        }
+        IAction::JR(_, _) => Some(disasm::relative_target(inst, addr)),
+        _ => None,
+    }
+}
+
+/// Turns a whole ROM into an RGBDS source file that rgbasm/rgblink reassemble byte-for-byte.
+/// Code is found with a `FlowAnalyser`; everything else is written out as `DB`. The constants
+/// are defined with `DEF`, so it needs RGBDS 0.5 or later.
+pub struct Listing<'a> {
+    rom: &'a [u8],
+    analyser: FlowAnalyser,
+    symbols: &'a SymbolTable,
+}
+
+impl<'a> Listing<'a> {
+    pub fn new(rom: &'a [u8], symbols: &'a SymbolTable) -> Self {
+        Self {
+            rom,
+            analyser: FlowAnalyser::new(rom),
+            symbols,
+        }
+    }
+
+    /// Like `new`, but also treats everything `coverage` says was executed as code
+    pub fn with_coverage(rom: &'a [u8], symbols: &'a SymbolTable, coverage: &[u8]) -> Self {
+        let mut listing = Self::new(rom, symbols);
+        listing.analyser.add_coverage(rom, coverage);
+        listing
+    }
+
+    /// Decides what goes on each line, keyed by the ROM offset the line starts at
+    fn lines(&self) -> Vec<(usize, Line<'_>)> {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+
+        while offset < self.rom.len() {
+            let bank_end = (offset / 0x4000 + 1) * 0x4000;
+            let in_header = (0x0104..0x0150).contains(&offset);
+
+            let code = self
+                .analyser
+                .instructions()
+                .get(&offset)
+                .filter(|inst| !in_header && offset + inst.len() as usize <= bank_end);
+
+            match code {
+                Some(inst) => {
+                    match reassembles(inst) {
+                        true => lines.push((offset, Line::Code(inst))),
+                        false => lines.push((offset, Line::RawCode(inst))),
+                    }
+                    offset += inst.len() as usize;
+                }
+                None => {
+                    lines.push((offset, Line::Data));
+                    offset += 1;
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// Labels for every branch target that starts a line, keeping user-supplied names where
+    /// they are valid RGBDS global labels
+    fn labels(&self, lines: &[(usize, Line)]) -> SymbolTable {
+        let line_starts: BTreeSet<usize> = lines.iter().map(|(offset, _)| *offset).collect();
+
+        let mut targets = BTreeSet::new();
+        for (offset, line) in lines {
+            if let Line::Code(inst) | Line::RawCode(inst) = line {
+                let (bank, addr) = flow::rom_address(*offset);
+                if let Some(target) = branch_target(inst, addr).filter(|&target| target < 0x8000) {
+                    // Bank 0 code is assumed to run with bank 1 mapped
+                    targets.insert(flow::rom_offset(bank.max(1), target));
+                }
+            }
+        }
+
+        let mut labels = SymbolTable::new();
+
+        for &offset in line_starts.iter() {
+            let (bank, addr) = flow::rom_address(offset);
+            let user_labels = self.symbols.labels(addr, bank.max(1));
+
+            for label in user_labels.iter().filter(|label| !label.contains('.')) {
+                labels.insert(bank, addr, label);
+            }
+
+            if targets.contains(&offset) && labels.label(addr, bank.max(1)).is_none() {
+                labels.insert(bank, addr, &format!("Jump_{:03X}_{:04X}", bank, addr));
+            }
+        }
+
+        labels
+    }
+
+    pub fn write(&self) -> String {
+        let lines = self.lines();
+        let labels = Rc::new(self.labels(&lines));
+        let mut formatter = Formatter::with_symbols(Syntax::Rgbds, labels.clone());
+
+        let mut out = String::new();
+
+        writeln!(out, "; Disassembled by yeahboy").unwrap();
+        writeln!(out).unwrap();
+
+        for addr in 0xFF00..=0xFFFF {
+            if let Some(name) = io::register_name(addr) {
+                writeln!(out, "DEF {} EQU ${:04X}", name, addr).unwrap();
+            }
+        }
+
+        let mut pending_data: Vec<u8> = Vec::new();
+        let mut pending_comment: Option<String> = None;
+
+        for (index, (offset, line)) in lines.iter().enumerate() {
+            let (bank, addr) = flow::rom_address(*offset);
+            let rom_bank = bank.max(1);
+
+            if offset % 0x4000 == 0 {
+                flush_data(
+                    &mut out,
+                    &formatter,
+                    &mut pending_data,
+                    pending_comment.take(),
+                );
+                formatter.set_rom_bank(rom_bank);
+
+                writeln!(out).unwrap();
+                match bank {
+                    0 => writeln!(out, "SECTION \"ROM Bank $000\", ROM0[$0000]").unwrap(),
+                    _ => writeln!(
+                        out,
+                        "SECTION \"ROM Bank ${:03X}\", ROMX[$4000], BANK[{}]",
+                        bank, bank
+                    )
+                    .unwrap(),
+                }
+                writeln!(out).unwrap();
+            }
+
+            let header_field = HEADER_FIELDS
+                .iter()
+                .find(|(start, end, _)| (*start..*end).contains(offset));
+
+            let line_labels = labels.labels(addr, rom_bank);
+            if !line_labels.is_empty() {
+                flush_data(
+                    &mut out,
+                    &formatter,
+                    &mut pending_data,
+                    pending_comment.take(),
+                );
+                for label in line_labels {
+                    writeln!(out, "{}:", label).unwrap();
+                }
+            }
+
+            if let Some((start, end, name)) = header_field.filter(|(start, _, _)| start == offset) {
+                flush_data(
+                    &mut out,
+                    &formatter,
+                    &mut pending_data,
+                    pending_comment.take(),
+                );
+                pending_comment = Some(header_comment(&self.rom[*start..*end], name));
+            }
+
+            match line {
+                Line::Code(inst) => {
+                    flush_data(
+                        &mut out,
+                        &formatter,
+                        &mut pending_data,
+                        pending_comment.take(),
+                    );
+                    writeln!(out, "    {}", formatter.format(inst, addr)).unwrap();
+                }
+                Line::RawCode(inst) => {
+                    flush_data(
+                        &mut out,
+                        &formatter,
+                        &mut pending_data,
+                        pending_comment.take(),
+                    );
+                    writeln!(
+                        out,
+                        "    {} ; {}",
+                        formatter.data(inst.raw_bytes()),
+                        formatter.format(inst, addr)
+                    )
+                    .unwrap();
+                }
+                Line::Data => {
+                    pending_data.push(self.rom[*offset]);
+
+                    let field_ends = header_field.map(|(_, end, _)| *end) == Some(offset + 1);
+                    let next_is_data = matches!(lines.get(index + 1), Some((_, Line::Data)));
+
+                    if pending_data.len() == BYTES_PER_LINE || field_ends || !next_is_data {
+                        flush_data(
+                            &mut out,
+                            &formatter,
+                            &mut pending_data,
+                            pending_comment.take(),
+                        );
+                    }
+                }
+            }
+        }
+
+        flush_data(
+            &mut out,
+            &formatter,
+            &mut pending_data,
+            pending_comment.take(),
+        );
+
+        out
+    }
+}
+
+/// Describes the value of a cartridge header field for the listing
+fn header_comment(bytes: &[u8], name: &str) -> String {
+    let value = match name {
+        "Title" => {
+            let title: String = bytes
+                .iter()
+                .take_while(|&&byte| byte != 0)
+                .map(|&byte| match byte {
+                    0x20..=0x7E => byte as char,
+                    _ => '.',
+                })
+                .collect();
+            format!("\"{}\"", title)
+        }
+        "CGB flag" => match bytes[0] {
+            0x80 => "CGB enhanced".to_string(),
+            0xC0 => "CGB only".to_string(),
+            _ => "DMG".to_string(),
+        },
+        "SGB flag" => match bytes[0] {
+            0x03 => "SGB supported".to_string(),
+            _ => "no SGB support".to_string(),
+        },
+        "ROM size" => match bytes[0] {
+            0x00..=0x08 => format!("{} KiB", 32 << bytes[0]),
+            _ => "unknown".to_string(),
+        },
+        "RAM size" => match bytes[0] {
+            0x02 => "8 KiB".to_string(),
+            0x03 => "32 KiB".to_string(),
+            0x04 => "128 KiB".to_string(),
+            0x05 => "64 KiB".to_string(),
+            _ => "none".to_string(),
+        },
+        "Destination code" => match bytes[0] {
+            0x00 => "Japan".to_string(),
+            _ => "overseas".to_string(),
+        },
+        "Nintendo logo" => return name.to_string(),
+        _ => bytes
+            .iter()
+            .map(|byte| format!("${:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+
+    format!("{}: {}", name, value)
+}
+
+/// Writes out buffered data bytes as a single `DB` line
+fn flush_data(
+    out: &mut String,
+    formatter: &Formatter,
+    data: &mut Vec<u8>,
+    comment: Option<String>,
+) {
+    if data.is_empty() {
+        return;
+    }
+
+    match comment {
+        Some(comment) => writeln!(out, "    {} ; {}", formatter.data(data), comment).unwrap(),
+        None => writeln!(out, "    {}", formatter.data(data)).unwrap(),
+    }
+
+    data.clear();
+}
+
+#[cfg(test)]
+mod test {
+    use super::Listing;
+    use crate::symbols::SymbolTable;
+
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0x00; 0x10000];
+        // NOP; JP $0150
+        rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[0x0134..0x0138].copy_from_slice(b"TEST");
+        // CALL $0200; HALT; JR -3
+        rom[0x0150..0x0156].copy_from_slice(&[0xCD, 0x00, 0x02, 0x76, 0x18, 0xFD]);
+        // LDH [rLCDC], A; RET
+        rom[0x0200..0x0203].copy_from_slice(&[0xE0, 0x40, 0xC9]);
+        rom
+    }
+
+    #[test]
+    fn sections_per_bank() {
+        let listing = Listing::new(&rom(), &SymbolTable::new()).write();
+
+        assert!(listing.contains("SECTION \"ROM Bank $000\", ROM0[$0000]"));
+        assert!(listing.contains("SECTION \"ROM Bank $001\", ROMX[$4000], BANK[1]"));
+        assert!(listing.contains("SECTION \"ROM Bank $003\", ROMX[$4000], BANK[3]"));
+        assert!(!listing.contains("BANK[4]"));
+    }
+
+    #[test]
+    fn labels_and_code() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0, 0x0150, "Main");
+        symbols.insert(0, 0x0150, "Main.local");
+
+        let listing = Listing::new(&rom(), &symbols).write();
+
+        assert!(listing.contains("Main:\n    CALL Jump_000_0200\n"));
+        assert!(!listing.contains("Main.local"));
+        assert!(listing.contains("    DB $76 ; HALT\n"));
+        assert!(listing.contains("    JR Jump_000_0153\n"));
+        assert!(listing.contains("Jump_000_0200:\n    LDH [rLCDC], A\n    RET\n"));
+        assert!(listing.contains("DEF rLCDC EQU $FF40"));
+    }
+
+    #[test]
+    fn header_comments() {
+        let listing = Listing::new(&rom(), &SymbolTable::new()).write();
+
+        assert!(listing.contains("; Title: \"TEST\""));
+        assert!(listing.contains("    DB $00 ; Cartridge type: $00\n"));
+    }
+}
@@ -1,19 +1,25 @@
-#[macro_use]
-extern crate lazy_static;
-
 use simple_logger::SimpleLogger;
 use std::{
-    fs::File,
-    io::{self, BufReader, Read},
+    fs::{self, File},
+    io::{BufReader, LineWriter, Read},
     path::{Path, PathBuf},
+    rc::Rc,
 };
 use structopt::StructOpt;
+use yeahboy::{
+    asm,
+    coverage::AccessLog,
+    gdb,
+    listing::Listing,
+    mmu::CYCLES_PER_FRAME,
+    profiler::Profiler,
+    script::Script,
+    symbols::SymbolTable,
+    trace::{self, TraceFilter, Tracer},
+    EmulatorError, GameBoy, Model,
+};
 
-mod bits;
-mod cpu;
 mod debugger;
-mod mmu;
-mod instructions;
 
 /// A gameboy emulator.
 ///
@@ -26,7 +32,127 @@ mod instructions;
 struct Opt {
     /// Path to the ROM.
     #[structopt(parse(from_os_str))]
-    rom: PathBuf,
+    rom: Option<PathBuf>,
+
+    /// Run this DMG (256 byte) or CGB (2304 byte) boot ROM at power on, rather than starting
+    /// the cartridge in the state it leaves.
+    #[structopt(long, parse(from_os_str))]
+    boot_rom: Option<PathBuf>,
+
+    /// Hardware to emulate: dmg0, dmg, mgb, sgb, cgb or agb. By default the boot ROM's size
+    /// picks, or without one the cartridge header.
+    #[structopt(long, parse(try_from_str = parse_model))]
+    model: Option<Model>,
+
+    /// Path to an RGBDS or no$gmb symbol file. Defaults to the ROM's path with a .sym extension.
+    #[structopt(long, parse(from_os_str))]
+    sym: Option<PathBuf>,
+
+    /// Write a gameboy-doctor style trace line before every instruction to this file.
+    #[structopt(long, parse(from_os_str))]
+    trace: Option<PathBuf>,
+
+    /// Only trace instructions in this hex address range, e.g. 0150-01FF.
+    #[structopt(long, parse(try_from_str = parse_pc_range))]
+    trace_pc: Option<(u16, u16)>,
+
+    /// Only trace instructions in this window of clock cycles since power on, e.g. 0-70224.
+    #[structopt(long, parse(try_from_str = parse_cycle_range))]
+    trace_cycles: Option<(u64, u64)>,
+
+    /// Profile from power on and save the call stacks to this file on exit, in the folded format
+    /// flamegraph.pl and inferno read.
+    #[structopt(long, parse(from_os_str))]
+    profile: Option<PathBuf>,
+
+    /// Record memory accesses from power on and save which ROM bytes were executed or read to
+    /// this file on exit, for `disasm --coverage`.
+    #[structopt(long, parse(from_os_str))]
+    coverage: Option<PathBuf>,
+
+    /// Instead of opening the debugger, wait for GDB to connect on this local port and serve it
+    /// the remote protocol.
+    #[structopt(long)]
+    gdb: Option<u16>,
+
+    /// Stop at illegal opcodes instead of locking up like hardware does. Headless runs exit with
+    /// an error.
+    #[structopt(long)]
+    break_on_illegal: bool,
+
+    /// Run this Rhai script alongside the emulator. It can hook frames, instructions and memory
+    /// accesses, and read and write the machine from those hooks.
+    #[structopt(long, parse(from_os_str))]
+    script: Option<PathBuf>,
+
+    /// Run without the debugger until the script calls stop(), or --frames have run.
+    #[structopt(long)]
+    headless: bool,
+
+    /// With --headless, stop after this many frames.
+    #[structopt(long)]
+    frames: Option<u64>,
+
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+}
+
+fn parse_model(text: &str) -> Result<Model, String> {
+    Model::from_name(text).ok_or_else(|| format!("unknown model {}", text))
+}
+
+fn parse_pc_range(text: &str) -> Result<(u16, u16), String> {
+    trace::parse_range(text, 16)
+}
+
+fn parse_cycle_range(text: &str) -> Result<(u64, u64), String> {
+    trace::parse_range(text, 10)
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Disassemble a ROM into an RGBDS source file that reassembles to the same ROM.
+    Disasm {
+        /// Path to the ROM.
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+
+        /// Where to write the listing. Defaults to the ROM's path with a .asm extension.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Path to an RGBDS or no$gmb symbol file. Defaults to the ROM's path with a .sym
+        /// extension.
+        #[structopt(long, parse(from_os_str))]
+        sym: Option<PathBuf>,
+
+        /// Coverage file saved by --coverage. Everything it says was executed is disassembled
+        /// as code.
+        #[structopt(long, parse(from_os_str))]
+        coverage: Option<PathBuf>,
+    },
+
+    /// Assemble an RGBDS-style source file into a ROM.
+    Asm {
+        /// Path to the source file.
+        #[structopt(parse(from_os_str))]
+        source: PathBuf,
+
+        /// Where to write the ROM. Defaults to the source's path with a .gb extension.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Report the first line where a trace differs from a reference trace.
+    DiffTrace {
+        /// Our trace, as written by --trace.
+        #[structopt(parse(from_os_str))]
+        trace: PathBuf,
+
+        /// The reference emulator's trace.
+        #[structopt(parse(from_os_str))]
+        reference: PathBuf,
+    },
 }
 
 fn load_rom<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<u8>> {
@@ -39,16 +165,195 @@ fn load_rom<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<u8>> {
     Ok(buffer)
 }
 
+fn load_symbols(rom: &Path, sym: Option<&Path>) -> anyhow::Result<SymbolTable> {
+    let path = match sym {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let path = rom.with_extension("sym");
+            if !path.exists() {
+                return Ok(SymbolTable::new());
+            }
+            path
+        }
+    };
+
+    log::info!("loading symbols from {}", path.display());
+
+    Ok(SymbolTable::parse(&fs::read_to_string(path)?))
+}
+
+fn disasm(
+    rom_path: &Path,
+    output: Option<&Path>,
+    sym: Option<&Path>,
+    coverage: Option<&Path>,
+) -> anyhow::Result<()> {
+    let rom = load_rom(rom_path)?;
+    let symbols = load_symbols(rom_path, sym)?;
+    let listing = match coverage {
+        Some(path) => Listing::with_coverage(&rom, &symbols, &fs::read(path)?),
+        None => Listing::new(&rom, &symbols),
+    };
+
+    let output = match output {
+        Some(path) => path.to_path_buf(),
+        None => rom_path.with_extension("asm"),
+    };
+
+    fs::write(&output, listing.write())?;
+    log::info!("wrote disassembly to {}", output.display());
+
+    Ok(())
+}
+
+fn asm(source_path: &Path, output: Option<&Path>) -> anyhow::Result<()> {
+    let source = fs::read_to_string(source_path)?;
+    let rom = asm::assemble(&source)?;
+
+    let output = match output {
+        Some(path) => path.to_path_buf(),
+        None => source_path.with_extension("gb"),
+    };
+
+    fs::write(&output, rom)?;
+    log::info!("wrote ROM to {}", output.display());
+
+    Ok(())
+}
+
+fn diff_trace(trace: &Path, reference: &Path) -> anyhow::Result<()> {
+    let ours = fs::read_to_string(trace)?;
+    let reference = fs::read_to_string(reference)?;
+
+    match trace::diff(&ours, &reference) {
+        None => println!("traces match"),
+        Some(divergence) => {
+            println!("traces diverge at line {}", divergence.line);
+            println!(
+                "  ours:      {}",
+                divergence.ours.as_deref().unwrap_or("<end of trace>")
+            );
+            println!(
+                "  reference: {}",
+                divergence.reference.as_deref().unwrap_or("<end of trace>")
+            );
+            if !divergence.fields.is_empty() {
+                println!("  differs in: {}", divergence.fields.join(", "));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs without a window, printing what the script prints
+fn headless(gameboy: &mut GameBoy, frames: Option<u64>) -> Result<(), EmulatorError> {
+    let end = frames.map(|frames| frames * CYCLES_PER_FRAME);
+
+    loop {
+        gameboy.step_instruction()?;
+
+        if let Some(script) = gameboy.cpu_mut().script_mut() {
+            for line in script.take_output() {
+                println!("{}", line);
+            }
+            if script.take_stop() {
+                return Ok(());
+            }
+        }
+
+        if end.is_some_and(|end| gameboy.cpu().cycles() >= end) {
+            return Ok(());
+        }
+    }
+}
+
 fn main() -> anyhow::Result<()> {
     let opt = Opt::from_args();
 
     SimpleLogger::new().with_utc_timestamps().init().unwrap();
 
-    let rom = load_rom(opt.rom)?;
+    match &opt.cmd {
+        Some(Command::Disasm {
+            rom,
+            output,
+            sym,
+            coverage,
+        }) => return disasm(rom, output.as_deref(), sym.as_deref(), coverage.as_deref()),
+        Some(Command::Asm { source, output }) => return asm(source, output.as_deref()),
+        Some(Command::DiffTrace { trace, reference }) => return diff_trace(trace, reference),
+        None => (),
+    }
+
+    let rom_path = match &opt.rom {
+        Some(path) => path,
+        None => anyhow::bail!("no ROM given"),
+    };
+
+    let rom = load_rom(rom_path)?;
+    let symbols = Rc::new(load_symbols(rom_path, opt.sym.as_deref())?);
 
     log::warn!("test");
 
-    debugger::run(rom);
+    let tracer = match &opt.trace {
+        Some(path) => {
+            let filter = TraceFilter {
+                pc: opt.trace_pc,
+                cycles: opt.trace_cycles,
+            };
+            // Line buffered, as the event loop never returns to flush a bigger buffer
+            let out = Box::new(LineWriter::new(File::create(path)?));
+            log::info!("tracing to {}", path.display());
+            Some(Tracer::new(out, filter))
+        }
+        None => None,
+    };
+
+    let rom_len = rom.len();
+    let mut gameboy = GameBoy::new();
+    gameboy.set_model(opt.model);
+    if let Some(path) = &opt.boot_rom {
+        gameboy.set_boot_rom(Some(fs::read(path)?))?;
+    }
+    gameboy.load_rom(rom)?;
+    log::info!("emulating {}", gameboy.model());
+
+    let cpu = gameboy.cpu_mut();
+    cpu.set_tracer(tracer);
+    cpu.set_break_on_illegal(opt.break_on_illegal);
+    if opt.profile.is_some() {
+        cpu.set_profiler(Some(Profiler::new()));
+    }
+    if opt.coverage.is_some() {
+        cpu.mmu_mut().set_access_log(Some(AccessLog::new(rom_len)));
+    }
+
+    if let Some(path) = &opt.script {
+        let source = fs::read_to_string(path)?;
+        let script = Script::new(&source, symbols.clone(), cpu)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        log::info!("running script {}", path.display());
+        cpu.set_script(Some(script));
+    }
+
+    if let Some(port) = opt.gdb {
+        gdb::serve(gameboy, port)?;
+        return Ok(());
+    }
+
+    if opt.headless {
+        // Whatever was recorded is still worth saving if the CPU gave up
+        let result = headless(&mut gameboy, opt.frames);
+        debugger::save_results(
+            gameboy.cpu(),
+            &symbols,
+            opt.profile.as_deref(),
+            opt.coverage.as_deref(),
+        );
+        return Ok(result?);
+    }
+
+    debugger::run(gameboy, symbols, opt.profile.clone(), opt.coverage.clone());
 
     Ok(())
 }
@@ -1,14 +1,43 @@
+use std::collections::HashSet;
+
+use crate::{
+    coverage::{Access, AccessLog},
+    error::EmulatorError,
+    flow,
+    io::{self, Buttons, Interrupts, Lcdc, Stat},
+    model::Model,
+    scheduler::{Event, Scheduler},
+    sgb::Sgb,
+    state::{Reader, Writer},
+};
+
+/// Cycles the PPU spends on each line, in OAM scan then drawing then HBlank
+const LINE_CYCLES: u64 = 456;
+const OAM_SCAN_CYCLES: u64 = 80;
+// TODO: drawing takes longer with scrolling, the window and objects
+const DRAWING_CYCLES: u64 = 172;
+
+/// Lines in a frame, including the ones in VBlank
+const LINES: u8 = 154;
+const VISIBLE_LINES: u8 = 144;
+
+/// Clock cycles from one VBlank to the next
+pub const CYCLES_PER_FRAME: u64 = LINE_CYCLES * LINES as u64;
+
+/// Clock cycles an OAM DMA transfer takes, one M-cycle to start and then a byte per M-cycle
+const DMA_CYCLES: u64 = 4 + 0xA0 * 4;
+
 pub struct Mmu {
     /// Cartridge RAM
     cart: Vec<u8>,
 
-    /// Video RAM
+    /// Video RAM, two banks of it on the CGB
     vram: Vec<u8>,
 
     /// Cartridge (external) RAM
     cart_ram: Vec<u8>,
 
-    /// Working (internal) RAM
+    /// Working (internal) RAM, eight banks of it on the CGB
     ram: Vec<u8>,
 
     /// Object attribute memory
@@ -16,22 +45,113 @@ pub struct Mmu {
 
     /// Zero-page RAM
     zpram: Vec<u8>,
+
+    /// Accesses made through the CPU-side methods, if we're recording them
+    access_log: Option<AccessLog>,
+
+    watchpoints: Vec<Watchpoint>,
+
+    /// The first watchpoint the CPU hit since `take_watch_hit`, and the address it accessed
+    watch_hit: Option<(Watchpoint, u16)>,
+
+    /// Accesses a script wants to hear about
+    taps: HashSet<(Access, u16)>,
+
+    /// Tapped accesses the CPU made since `take_tap_hits`, in order
+    tap_hits: Vec<(Access, u16)>,
+
+    /// Joypad buttons held down
+    buttons: Buttons,
+
+    /// The master clock, and what's due to happen on it
+    scheduler: Scheduler,
+
+    /// Clock cycle DIV's counter was last zero. It counts up every cycle and DIV is the high
+    /// byte, so it's worked out when read.
+    div_epoch: u64,
+
+    /// TIMA as of the clock cycle `tima_synced`. Like DIV it's worked out when read, and its
+    /// overflow is a scheduled event.
+    tima: u8,
+    tima_synced: u64,
+
+    /// Whether the CGB's CPU is running at double speed. The PPU's speed doesn't change.
+    double_speed: bool,
+
+    /// The CGB's palette RAM: 8 background palettes then 8 object palettes, each of 4
+    /// little-endian RGB555 colors
+    palettes: Vec<u8>,
+
+    /// Where the CGB's VRAM DMA copies its next block from, and to as an offset into VRAM
+    dma_source: u16,
+    dma_dest: u16,
+
+    /// Clock cycle the PPU started the line it's on
+    line_start: u64,
+
+    /// The Super Game Boy listening on P1, if this is one
+    sgb: Option<Sgb>,
+
+    /// Frames finished since power on
+    frame: u64,
+
+    /// The hardware being emulated
+    model: Model,
+
+    /// Runs at power on, if we were given one
+    boot_rom: Option<Vec<u8>>,
+
+    /// Whether the boot ROM is still over the cartridge. Writing 0xFF50 unmaps it for good.
+    boot_rom_mapped: bool,
+}
+
+/// A range of addresses to stop at when the CPU reads or writes them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: u16,
+    pub len: u16,
+    /// `Access::Read` or `Access::Write`
+    pub access: Access,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: Access, addr: u16) -> bool {
+        access == self.access && addr.wrapping_sub(self.addr) < self.len
+    }
 }
 
 impl Mmu {
+    /// Memory as the DMG boot ROM leaves it, with `cart` in
     pub fn new(cart: Vec<u8>) -> Self {
-        let mut mmu = Self {
-            cart,
-            vram: vec![0; 0x2000],
-            cart_ram: vec![0; 0x2000],
-            ram: vec![0; 0x2000],
-            oam: vec![0; 0x100],
-            zpram: vec![0; 0x100],
+        Self::with_model(cart, Model::Dmg)
+    }
+
+    /// Memory as `model`'s boot ROM leaves it, with `cart` in
+    pub fn with_model(cart: Vec<u8>, model: Model) -> Self {
+        let mut mmu = Self::power_on(cart, model, None);
+
+        // The DIV counter as the boot ROM leaves it. How long the SGB and CGB boot ROMs take
+        // depends on the cartridge, so theirs are only typical.
+        let div = match model {
+            Model::Dmg0 => 0x1800,
+            Model::Dmg | Model::Mgb => 0xABCC,
+            Model::Sgb => 0xD800,
+            Model::Cgb | Model::Agb => 0x1EA0,
+        };
+        mmu.div_epoch = 0u64.wrapping_sub(div);
+
+        // Registers that differ by model: SC, NR52 and DMA
+        let (sc, nr52, dma) = match model {
+            Model::Dmg0 | Model::Dmg | Model::Mgb => (0x7E, 0xF1, 0xFF),
+            Model::Sgb => (0x7E, 0xF0, 0xFF),
+            Model::Cgb | Model::Agb => (0x7F, 0xF1, 0x00),
         };
 
+        mmu.wb(0xFF02, sc);
         mmu.wb(0xFF05, 0x00);
         mmu.wb(0xFF06, 0x00);
         mmu.wb(0xFF07, 0x00);
+        mmu.wb(0xFF0F, 0x01);
         mmu.wb(0xFF10, 0x80);
         mmu.wb(0xFF11, 0xBF);
         mmu.wb(0xFF12, 0xF3);
@@ -49,45 +169,995 @@ impl Mmu {
         mmu.wb(0xFF23, 0xBF);
         mmu.wb(0xFF24, 0x77);
         mmu.wb(0xFF25, 0xF3);
-        mmu.wb(0xFF26, 0xF1);
+        mmu.wb(0xFF26, nr52);
         mmu.wb(0xFF40, 0x91);
         mmu.wb(0xFF42, 0x00);
         mmu.wb(0xFF43, 0x00);
         mmu.wb(0xFF45, 0x00);
+        // Just the register, as no transfer was started
+        mmu.zpram[(io::DMA - 0xFF00) as usize] = dma;
         mmu.wb(0xFF47, 0xFC);
         mmu.wb(0xFF48, 0xFF);
         mmu.wb(0xFF49, 0xFF);
         mmu.wb(0xFF4A, 0x00);
         mmu.wb(0xFF4B, 0x00);
+        if model.is_cgb() {
+            mmu.wb(0xFF4D, 0x7E);
+            mmu.wb(0xFF4F, 0xFE);
+            mmu.wb(0xFF70, 0xF8);
+        }
         mmu.wb(0xFFFF, 0x00);
 
         mmu
     }
 
+    /// Memory at reset, with `model`'s `boot_rom` mapped over `cart`. A DMG-style one is 256
+    /// bytes for 0x0000-0x00FF, and a CGB-style one is 2304 bytes for 0x0000-0x00FF and
+    /// 0x0200-0x08FF.
+    pub fn with_boot_rom(
+        cart: Vec<u8>,
+        model: Model,
+        boot_rom: Vec<u8>,
+    ) -> Result<Self, EmulatorError> {
+        if boot_rom.len() != model.boot_rom_len() {
+            return Err(EmulatorError::InvalidBootRom(format!(
+                "it's {} bytes, but a {} boot ROM is {}",
+                boot_rom.len(),
+                model,
+                model.boot_rom_len()
+            )));
+        }
+
+        Ok(Self::power_on(cart, model, Some(boot_rom)))
+    }
+
+    fn power_on(cart: Vec<u8>, model: Model, boot_rom: Option<Vec<u8>>) -> Self {
+        let mut mmu = Self {
+            cart,
+            vram: vec![0; if model.is_cgb() { 0x4000 } else { 0x2000 }],
+            cart_ram: vec![0; 0x2000],
+            ram: vec![0; if model.is_cgb() { 0x8000 } else { 0x2000 }],
+            oam: vec![0; 0x100],
+            zpram: vec![0; 0x100],
+            access_log: None,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            taps: HashSet::new(),
+            tap_hits: Vec::new(),
+            buttons: Buttons::empty(),
+            scheduler: Scheduler::new(),
+            div_epoch: 0,
+            tima: 0,
+            tima_synced: 0,
+            double_speed: false,
+            palettes: vec![0; 0x80],
+            dma_source: 0,
+            dma_dest: 0,
+            line_start: 0,
+            sgb: (model == Model::Sgb).then(Sgb::new),
+            frame: 0,
+            model,
+            boot_rom_mapped: boot_rom.is_some(),
+            boot_rom,
+        };
+
+        // The LCD starts off, so frames end on their own until it's turned on
+        mmu.scheduler.schedule(CYCLES_PER_FRAME, Event::FrameEnd);
+        // No HBlank DMA is going
+        mmu.zpram[(io::HDMA5 - 0xFF00) as usize] = 0xFF;
+
+        mmu
+    }
+
+    /// The whole cartridge ROM, including banks that aren't mapped
+    pub fn rom(&self) -> &[u8] {
+        &self.cart
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Whether `addr` is one of the registers only the CGB has, which read 0xFF and ignore
+    /// writes on others
+    fn is_cgb_register(&self, addr: u16) -> bool {
+        matches!(addr, 0xFF4D | 0xFF4F | 0xFF51..=0xFF55 | 0xFF68..=0xFF6B | 0xFF70)
+    }
+
+    /// Whether the boot ROM is still mapped over the cartridge
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom_mapped
+    }
+
+    /// The boot ROM byte at `addr`, if the boot ROM is mapped there
+    fn boot_rom_at(&self, addr: usize) -> Option<u8> {
+        let boot_rom = self.boot_rom.as_ref().filter(|_| self.boot_rom_mapped)?;
+
+        match addr {
+            // The CGB boot ROM skips over the cartridge header
+            0x0000..=0x00FF | 0x0200..=0x08FF => boot_rom.get(addr).copied(),
+            _ => None,
+        }
+    }
+
+    /// Video RAM, all banks
+    pub fn vram(&self) -> &[u8] {
+        &self.vram
+    }
+
+    /// Object attribute memory. Only the first 0xA0 bytes are real.
+    pub fn oam(&self) -> &[u8] {
+        &self.oam
+    }
+
+    /// The ROM bank currently mapped at 0x4000-0x7FFF
+    pub fn rom_bank(&self) -> u16 {
+        1 // TODO: ROM bank switching
+    }
+
+    /// Number of 16 KiB banks in the cartridge ROM
+    pub fn rom_bank_count(&self) -> u16 {
+        self.cart.len().div_ceil(0x4000).max(2) as u16
+    }
+
+    /// The cartridge RAM bank currently mapped at 0xA000-0xBFFF
+    pub fn ram_bank(&self) -> u16 {
+        0 // TODO: RAM bank switching
+    }
+
+    /// Number of 8 KiB banks of cartridge RAM
+    pub fn ram_bank_count(&self) -> u16 {
+        (self.cart_ram.len() / 0x2000) as u16
+    }
+
+    /// The bank mapped at `addr`, numbered the way symbol files number them: the ROM bank in
+    /// ROMX, the VRAM bank, the cartridge RAM bank in SRAM, the WRAM bank in WRAMX and 0
+    /// everywhere else
+    pub fn bank_at(&self, addr: u16) -> u16 {
+        match addr {
+            0x4000..=0x7FFF => self.rom_bank(),
+            0x8000..=0x9FFF => self.vram_bank() as u16,
+            0xA000..=0xBFFF => self.ram_bank(),
+            0xD000..=0xDFFF => self.wram_bank() as u16,
+            _ => 0,
+        }
+    }
+
+    /// The VRAM bank VBK maps at 0x8000-0x9FFF, which is always 0 before the CGB
+    pub fn vram_bank(&self) -> usize {
+        match self.model.is_cgb() {
+            true => (self.zpram[(io::VBK - 0xFF00) as usize] & 0x01) as usize,
+            false => 0,
+        }
+    }
+
+    /// The WRAM bank SVBK maps at 0xD000-0xDFFF, where 0 maps bank 1 too. It's always 1
+    /// before the CGB.
+    pub fn wram_bank(&self) -> usize {
+        match self.model.is_cgb() {
+            true => ((self.zpram[(io::SVBK - 0xFF00) as usize] & 0x07) as usize).max(1),
+            false => 1,
+        }
+    }
+
+    /// Index into `vram` of `addr` in 0x8000-0x9FFF, in the mapped bank
+    fn vram_index(&self, addr: usize) -> usize {
+        self.vram_bank() * 0x2000 + (addr & 0x1FFF)
+    }
+
+    /// Index into `ram` of `addr` in WRAM or echo RAM, in the mapped bank
+    fn wram_index(&self, addr: usize) -> usize {
+        match addr & 0x1FFF {
+            offset @ 0x0000..=0x0FFF => offset,
+            offset => self.wram_bank() * 0x1000 + (offset & 0x0FFF),
+        }
+    }
+
+    /// The CGB's palette RAM: 8 background palettes then 8 object palettes, each of 4
+    /// little-endian RGB555 colors
+    pub fn palettes(&self) -> &[u8] {
+        &self.palettes
+    }
+
+    /// Name of the memory region `addr` is in, with the mapped bank for banked regions
+    pub fn region_name(&self, addr: u16) -> String {
+        match addr {
+            0x0000..=0x3FFF => "ROM0".to_string(),
+            0x4000..=0x7FFF => format!("ROMX bank {}", self.rom_bank()),
+            0x8000..=0x9FFF => "VRAM".to_string(),
+            0xA000..=0xBFFF => format!("SRAM bank {}", self.ram_bank()),
+            0xC000..=0xDFFF => "WRAM".to_string(),
+            0xE000..=0xFDFF => "Echo RAM".to_string(),
+            0xFE00..=0xFE9F => "OAM".to_string(),
+            0xFEA0..=0xFEFF => "Unusable".to_string(),
+            0xFF00..=0xFF7F => "IO".to_string(),
+            0xFF80..=0xFFFE => "HRAM".to_string(),
+            0xFFFF => "IE".to_string(),
+        }
+    }
+
+    /// Reads `addr` as if `rom_bank` and `ram_bank` were mapped, without touching the real
+    /// mapping. Addresses outside the banked regions read as normal.
+    pub fn peek_banked(&self, rom_bank: u16, ram_bank: u16, addr: u16) -> u8 {
+        match addr {
+            0x4000..=0x7FFF => {
+                let offset = rom_bank as usize * 0x4000 + (addr as usize - 0x4000);
+                self.cart.get(offset).copied().unwrap_or(0xFF)
+            }
+            0xA000..=0xBFFF => {
+                let offset = ram_bank as usize * 0x2000 + (addr as usize - 0xA000);
+                self.cart_ram.get(offset).copied().unwrap_or(0xFF)
+            }
+            _ => self.rb(addr as usize),
+        }
+    }
+
+    /// Writes `addr` as if `rom_bank` and `ram_bank` were mapped. Unlike `wb` this can write
+    /// to ROM banks that aren't mapped, which is what the debugger wants for patching.
+    pub fn poke_banked(&mut self, rom_bank: u16, ram_bank: u16, addr: u16, value: u8) {
+        match addr {
+            0x4000..=0x7FFF => {
+                let offset = rom_bank as usize * 0x4000 + (addr as usize - 0x4000);
+                if let Some(byte) = self.cart.get_mut(offset) {
+                    *byte = value;
+                }
+            }
+            0xA000..=0xBFFF => {
+                let offset = ram_bank as usize * 0x2000 + (addr as usize - 0xA000);
+                if let Some(byte) = self.cart_ram.get_mut(offset) {
+                    *byte = value;
+                }
+            }
+            _ => self.wb(addr as usize, value),
+        }
+    }
+
+    /// Starts recording accesses, or stops and discards them if `access_log` is `None`
+    pub fn set_access_log(&mut self, access_log: Option<AccessLog>) {
+        self.access_log = access_log;
+    }
+
+    pub fn access_log(&self) -> Option<&AccessLog> {
+        self.access_log.as_ref()
+    }
+
+    pub fn access_log_mut(&mut self) -> Option<&mut AccessLog> {
+        self.access_log.as_mut()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.retain(|&w| w != watchpoint);
+    }
+
+    /// The first watchpoint hit since this was last called, and the address that hit it
+    pub fn take_watch_hit(&mut self) -> Option<(Watchpoint, u16)> {
+        self.watch_hit.take()
+    }
+
+    /// Writes the RAM and IO registers for a save state. The ROM isn't included.
+    pub fn save_state(&self, writer: &mut Writer) {
+        for memory in [
+            &self.vram,
+            &self.cart_ram,
+            &self.ram,
+            &self.oam,
+            &self.zpram,
+        ] {
+            writer.bytes(memory);
+        }
+        writer.u8(self.buttons.bits());
+        writer.u8(self.model as u8);
+        writer.u64(self.div_epoch);
+        writer.u8(self.tima);
+        writer.u64(self.tima_synced);
+        writer.bool(self.double_speed);
+        writer.bytes(&self.palettes);
+        writer.u16(self.dma_source);
+        writer.u16(self.dma_dest);
+        writer.u64(self.line_start);
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(writer);
+        }
+        writer.bool(self.boot_rom_mapped);
+        writer.u64(self.frame);
+        self.scheduler.save_state(writer);
+    }
+
+    /// Reads back what `save_state` wrote, leaving everything as it was if that fails
+    pub fn load_state(&mut self, reader: &mut Reader) -> Result<(), EmulatorError> {
+        let vram = reader.bytes(self.vram.len())?;
+        let cart_ram = reader.bytes(self.cart_ram.len())?;
+        let ram = reader.bytes(self.ram.len())?;
+        let oam = reader.bytes(self.oam.len())?;
+        let zpram = reader.bytes(self.zpram.len())?;
+        let buttons = Buttons::from_bits_truncate(reader.u8()?);
+        let model = reader.u8()?;
+        if model != self.model as u8 {
+            return Err(EmulatorError::InvalidState(format!(
+                "it wasn't saved on a {}",
+                self.model
+            )));
+        }
+        let div_epoch = reader.u64()?;
+        let tima = reader.u8()?;
+        let tima_synced = reader.u64()?;
+        let double_speed = reader.bool()?;
+        let palettes = reader.bytes(self.palettes.len())?;
+        let dma_source = reader.u16()?;
+        let dma_dest = reader.u16()?;
+        let line_start = reader.u64()?;
+        let sgb = match self.sgb {
+            Some(_) => Some(Sgb::load_state(reader)?),
+            None => None,
+        };
+        let boot_rom_mapped = reader.bool()?;
+        if boot_rom_mapped && self.boot_rom.is_none() {
+            return Err(EmulatorError::InvalidState(
+                "it was saved while a boot ROM was running, but there isn't one".to_string(),
+            ));
+        }
+        let frame = reader.u64()?;
+        let scheduler = Scheduler::load_state(reader)?;
+
+        self.vram = vram;
+        self.cart_ram = cart_ram;
+        self.ram = ram;
+        self.oam = oam;
+        self.zpram = zpram;
+        self.buttons = buttons;
+        self.div_epoch = div_epoch;
+        self.tima = tima;
+        self.tima_synced = tima_synced;
+        self.double_speed = double_speed;
+        self.palettes = palettes;
+        self.dma_source = dma_source;
+        self.dma_dest = dma_dest;
+        self.line_start = line_start;
+        self.sgb = sgb;
+        self.boot_rom_mapped = boot_rom_mapped;
+        self.frame = frame;
+        self.scheduler = scheduler;
+
+        Ok(())
+    }
+
+    /// Reports CPU accesses of `addr` through `take_tap_hits`
+    pub fn add_tap(&mut self, access: Access, addr: u16) {
+        self.taps.insert((access, addr));
+    }
+
+    /// Tapped accesses since this was last called, oldest first
+    pub fn take_tap_hits(&mut self) -> Vec<(Access, u16)> {
+        std::mem::take(&mut self.tap_hits)
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        self.buttons
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = buttons;
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Frames finished since power on
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Moves the clock on by `cycles` of the CPU's, handling any events that come due. The CPU
+    /// calls this for every M-cycle, before the memory access in it. At double speed its cycles
+    /// take half as long.
+    pub fn tick(&mut self, cycles: u64) {
+        if !self.scheduler.advance(cycles >> self.double_speed as u32) {
+            return;
+        }
+
+        while let Some((at, event)) = self.scheduler.pop() {
+            match event {
+                // Only scheduled while the LCD is off. Otherwise frames end with the PPU's.
+                Event::FrameEnd => {
+                    self.frame += 1;
+                    self.scheduler
+                        .schedule_at(at + CYCLES_PER_FRAME, Event::FrameEnd);
+                }
+                Event::TimerOverflow => {
+                    self.sync_tima(at);
+                    self.tima = self.zpram[(io::TMA - 0xFF00) as usize];
+                    self.request_interrupt(Interrupts::TIMER);
+                    self.schedule_timer_overflow();
+                }
+                Event::LineStart => {
+                    let ly = (self.zpram[(io::LY - 0xFF00) as usize] + 1) % LINES;
+                    if ly == 0 {
+                        self.frame += 1;
+                    }
+                    self.start_line(at, ly);
+                }
+                Event::Drawing => self.update_stat(|mmu| mmu.set_ppu_mode(3)),
+                Event::HBlank => {
+                    self.update_stat(|mmu| mmu.set_ppu_mode(0));
+                    if self.zpram[(io::HDMA5 - 0xFF00) as usize] & 0x80 == 0 {
+                        self.copy_vram_dma_block();
+                    }
+                }
+                Event::SerialBit => self.shift_serial(),
+                // OAM is the CPU's again, which `dma_active` sees
+                Event::DmaEnd => (),
+            }
+        }
+    }
+
+    /// The counter DIV is the high byte of, which counts up every cycle
+    fn div_counter(&self) -> u64 {
+        self.div_counter_at(self.scheduler.now())
+    }
+
+    /// The DIV counter at clock cycle `at`, if it isn't reset before then. It counts the CPU's
+    /// cycles, so twice as fast at double speed.
+    fn div_counter_at(&self, at: u64) -> u64 {
+        at.wrapping_sub(self.div_epoch) << self.double_speed as u32
+    }
+
+    /// Zeroes the DIV counter. If that clears the bit TIMA counts, TIMA counts that falling
+    /// edge.
+    fn reset_div(&mut self) {
+        let signal = self.timer_signal();
+        self.sync_tima(self.scheduler.now());
+        self.div_epoch = self.scheduler.now();
+        if signal {
+            self.increment_tima();
+        }
+        self.schedule_timer_overflow();
+    }
+
+    /// Whether the CPU's running at double speed
+    pub fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    /// Switches between normal and double speed if this is a CGB and KEY1 asks for it, as STOP
+    /// does. DIV is reset. Returns whether the speed changed.
+    pub fn switch_speed(&mut self) -> bool {
+        let key1 = (io::KEY1 - 0xFF00) as usize;
+        if !self.model.is_cgb() || self.zpram[key1] & 0x01 == 0 {
+            return false;
+        }
+
+        self.zpram[key1] &= !0x01;
+        self.reset_div();
+        self.double_speed = !self.double_speed;
+        self.schedule_timer_overflow();
+
+        true
+    }
+
+    /// The DIV counter bit TAC has TIMA count, if the timer is on
+    fn timer_bit(&self) -> Option<u32> {
+        let tac = self.zpram[(io::TAC - 0xFF00) as usize];
+        (tac & 0x04 != 0).then(|| [9, 3, 5, 7][(tac & 0x03) as usize])
+    }
+
+    /// Whether the bit TIMA counts is set. Writing DIV or TAC can clear it, and TIMA counts
+    /// that falling edge too.
+    fn timer_signal(&self) -> bool {
+        self.timer_bit()
+            .is_some_and(|bit| (self.div_counter() >> bit) & 1 != 0)
+    }
+
+    /// Times TIMA has counted between `tima_synced` and clock cycle `at`. TIMA counts falling
+    /// edges of one of the DIV counter's bits.
+    fn tima_edges(&self, at: u64) -> u64 {
+        match self.timer_bit() {
+            Some(bit) => {
+                (self.div_counter_at(at) >> (bit + 1))
+                    - (self.div_counter_at(self.tima_synced) >> (bit + 1))
+            }
+            None => 0,
+        }
+    }
+
+    /// Brings `tima` up to clock cycle `at`. Anything that changes how TIMA counts does this
+    /// first.
+    fn sync_tima(&mut self, at: u64) {
+        self.tima = self.tima.wrapping_add(self.tima_edges(at) as u8);
+        self.tima_synced = at;
+    }
+
+    /// Replaces the scheduled TIMA overflow with one for how TIMA's counting now. Call after
+    /// `sync_tima`.
+    fn schedule_timer_overflow(&mut self) {
+        self.scheduler.cancel(Event::TimerOverflow);
+
+        if let Some(bit) = self.timer_bit() {
+            let period = 1 << (bit + 1);
+            let counter = self.div_counter_at(self.tima_synced);
+            let edges = 0x100 - self.tima as u64;
+            let overflow = (counter / period + edges) * period;
+            let cycles = (overflow - counter) >> self.double_speed as u32;
+            self.scheduler
+                .schedule_at(self.tima_synced + cycles, Event::TimerOverflow);
+        }
+    }
+
+    /// Counts TIMA up by one outside of its usual edges. When it overflows it's reloaded from
+    /// TMA and the timer interrupt is requested.
+    fn increment_tima(&mut self) {
+        let (value, overflowed) = self.tima.overflowing_add(1);
+
+        self.tima = match overflowed {
+            true => {
+                self.request_interrupt(Interrupts::TIMER);
+                self.zpram[(io::TMA - 0xFF00) as usize]
+            }
+            false => value,
+        };
+    }
+
+    /// Starts a transfer if SC asks for one on the internal clock, or stops the one going. With
+    /// nothing on the other end of the link, 1s are shifted in. The bits are shifted as the
+    /// clock, which comes from the DIV counter, ticks.
+    fn start_serial(&mut self) {
+        self.scheduler.cancel(Event::SerialBit);
+
+        let sc = self.zpram[(io::SC - 0xFF00) as usize];
+        if sc & 0x81 != 0x81 {
+            return;
+        }
+
+        // The CGB can clock it 32 times faster
+        let period = match self.model.is_cgb() && sc & 0x02 != 0 {
+            true => 16,
+            false => 512,
+        };
+        let counter = self.div_counter();
+        for bit in 1..=8 {
+            let at = (counter / period + bit) * period;
+            let cycles = (at - counter) >> self.double_speed as u32;
+            self.scheduler.schedule(cycles, Event::SerialBit);
+        }
+    }
+
+    /// Shifts a bit of the transfer. After the eighth, it's done and the serial interrupt is
+    /// requested.
+    fn shift_serial(&mut self) {
+        let sb = &mut self.zpram[(io::SB - 0xFF00) as usize];
+        *sb = (*sb << 1) | 1;
+
+        if !self.scheduler.is_scheduled(Event::SerialBit) {
+            self.zpram[(io::SC - 0xFF00) as usize] &= !0x80;
+            self.request_interrupt(Interrupts::SERIAL);
+        }
+    }
+
+    /// Copies the 0xA0 bytes at `page` * 0x100 to OAM. The CPU can't get at OAM until the
+    /// transfer's done, so it's all copied at once.
+    fn start_dma(&mut self, page: u8) {
+        let source = (page as usize) << 8;
+        for i in 0..0xA0 {
+            self.oam[i] = self.rb(source + i);
+        }
+
+        self.scheduler.cancel(Event::DmaEnd);
+        self.scheduler
+            .schedule(DMA_CYCLES >> self.double_speed as u32, Event::DmaEnd);
+    }
+
+    /// Whether an OAM DMA transfer is going, so the CPU reads 0xFF from OAM and can't write it
+    pub fn dma_active(&self) -> bool {
+        self.scheduler.is_scheduled(Event::DmaEnd)
+    }
+
+    /// Starts a CGB VRAM DMA transfer of `value` & 0x7F + 1 blocks of 16 bytes, or stops the
+    /// HBlank transfer going if bit 7 is clear. With bit 7 clear, the CPU stops while it's all
+    /// copied. With it set, a block is copied at the start of each HBlank, without the CPU
+    /// stopping.
+    fn start_vram_dma(&mut self, value: u8) {
+        let hdma5 = (io::HDMA5 - 0xFF00) as usize;
+        let blocks = value & 0x7F;
+
+        match (self.zpram[hdma5] & 0x80 == 0, value & 0x80 == 0) {
+            (true, true) => self.zpram[hdma5] |= 0x80,
+            (_, true) => {
+                for _ in 0..=blocks {
+                    self.copy_vram_dma_block();
+                }
+                self.zpram[hdma5] = 0xFF;
+                // 8 M-cycles a block, or 16 at double speed
+                self.tick((32 * (blocks as u64 + 1)) << self.double_speed as u32);
+            }
+            (_, false) => self.zpram[hdma5] = blocks,
+        }
+    }
+
+    /// Copies the next 16 bytes of a VRAM DMA transfer, counting down the blocks left in HDMA5.
+    /// It reads 0xFF once they're all done.
+    fn copy_vram_dma_block(&mut self) {
+        for i in 0..16 {
+            let byte = self.rb(self.dma_source.wrapping_add(i) as usize);
+            let index = self.vram_index(0x8000 | self.dma_dest.wrapping_add(i) as usize);
+            self.vram[index] = byte;
+        }
+        self.dma_source = self.dma_source.wrapping_add(16);
+        self.dma_dest = (self.dma_dest + 16) & 0x1FF0;
+
+        let hdma5 = &mut self.zpram[(io::HDMA5 - 0xFF00) as usize];
+        *hdma5 = match *hdma5 & 0x7F {
+            0 => 0xFF,
+            blocks => blocks - 1,
+        };
+    }
+
+    /// Whether the PPU's drawing, so the CPU can't get at the CGB's palette RAM
+    fn palettes_locked(&self) -> bool {
+        let lcdc = Lcdc::from_bits_truncate(self.zpram[(io::LCDC - 0xFF00) as usize]);
+        lcdc.contains(Lcdc::LCD_ENABLE) && self.zpram[(io::STAT - 0xFF00) as usize] & 0x03 == 3
+    }
+
+    /// Index into `palettes` that BCPD, or OCPD if `objects`, reads and writes
+    fn palette_index(&self, objects: bool) -> usize {
+        let spec = self.zpram[(io::BCPS - 0xFF00) as usize + objects as usize * 2];
+        objects as usize * 0x40 + (spec & 0x3F) as usize
+    }
+
+    /// Starts line `ly` at clock cycle `at`: OAM scan, drawing and HBlank on the visible lines,
+    /// and VBlank on the rest
+    fn start_line(&mut self, at: u64, ly: u8) {
+        let visible = ly < VISIBLE_LINES;
+        self.line_start = at;
+        self.update_stat(|mmu| {
+            mmu.zpram[(io::LY - 0xFF00) as usize] = ly;
+            mmu.set_ppu_mode(if visible { 2 } else { 1 });
+        });
+
+        if visible {
+            self.scheduler
+                .schedule_at(at + OAM_SCAN_CYCLES, Event::Drawing);
+            self.scheduler
+                .schedule_at(at + OAM_SCAN_CYCLES + DRAWING_CYCLES, Event::HBlank);
+        } else if ly == VISIBLE_LINES {
+            self.request_interrupt(Interrupts::VBLANK);
+        }
+        self.scheduler
+            .schedule_at(at + LINE_CYCLES, Event::LineStart);
+    }
+
+    /// Turning the LCD off stops the PPU at the start of line 0 in HBlank, and turning it back
+    /// on starts that line. While it's off, frames end when the PPU's would have.
+    fn set_lcd_enabled(&mut self, enabled: bool) {
+        match enabled {
+            true => {
+                self.scheduler.cancel(Event::FrameEnd);
+                self.start_line(self.scheduler.now(), 0);
+            }
+            false => {
+                let ly = self.zpram[(io::LY - 0xFF00) as usize];
+                let frame_start = self.line_start - ly as u64 * LINE_CYCLES;
+                self.scheduler
+                    .schedule_at(frame_start + CYCLES_PER_FRAME, Event::FrameEnd);
+                for event in [Event::LineStart, Event::Drawing, Event::HBlank] {
+                    self.scheduler.cancel(event);
+                }
+                self.update_stat(|mmu| {
+                    mmu.zpram[(io::LY - 0xFF00) as usize] = 0;
+                    mmu.set_ppu_mode(0);
+                });
+            }
+        }
+    }
+
+    /// Corrupts OAM the way pre-CGB models do when INC or DEC of a register pair puts `addr`
+    /// on the bus in OAM scan. It's for the M-cycle just clocked, in which the PPU was reading
+    /// a row of two objects. That row is mixed with the one before it.
+    // TODO: the other patterns, from reads and from PUSH and POP
+    pub fn corrupt_oam(&mut self, addr: u16) {
+        let lcdc = Lcdc::from_bits_truncate(self.zpram[(io::LCDC - 0xFF00) as usize]);
+        let ly = self.zpram[(io::LY - 0xFF00) as usize];
+        if self.model.is_cgb()
+            || !(0xFE00..=0xFEFF).contains(&addr)
+            || !lcdc.contains(Lcdc::LCD_ENABLE)
+            || ly >= VISIBLE_LINES
+        {
+            return;
+        }
+
+        let elapsed = self.scheduler.now() - self.line_start;
+        if elapsed == 0 || elapsed > OAM_SCAN_CYCLES {
+            return;
+        }
+
+        // The first row is never corrupted
+        let row = (elapsed as usize - 1) / 4 * 8;
+        if row == 0 {
+            return;
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([self.oam[offset], self.oam[offset + 1]]);
+        let (a, b, c) = (word(row), word(row - 8), word(row - 4));
+        let first = ((a ^ c) & (b ^ c)) ^ c;
+
+        self.oam[row..row + 2].copy_from_slice(&first.to_le_bytes());
+        self.oam.copy_within(row - 6..row, row + 2);
+    }
+
+    fn set_ppu_mode(&mut self, mode: u8) {
+        let stat = &mut self.zpram[(io::STAT - 0xFF00) as usize];
+        *stat = (*stat & !0x03) | mode;
+    }
+
+    /// Whether any of the conditions STAT is set to interrupt on hold
+    fn stat_line(&self) -> bool {
+        let value = self.zpram[(io::STAT - 0xFF00) as usize];
+        let stat = Stat::from_bits_truncate(value);
+        let mode_source = match value & 0x03 {
+            0 => Stat::HBLANK_INTERRUPT,
+            1 => Stat::VBLANK_INTERRUPT,
+            2 => Stat::OAM_INTERRUPT,
+            _ => Stat::empty(),
+        };
+
+        (!mode_source.is_empty() && stat.contains(mode_source))
+            || stat.contains(Stat::LYC_EQUAL | Stat::LYC_INTERRUPT)
+    }
+
+    /// Makes `change` to LY, LYC or STAT, then updates the LYC=LY flag. The STAT interrupt is
+    /// requested if that starts one of its conditions holding when none did.
+    fn update_stat(&mut self, change: impl FnOnce(&mut Self)) {
+        let line = self.stat_line();
+        change(self);
+
+        let ly = self.zpram[(io::LY - 0xFF00) as usize];
+        let lyc = self.zpram[(io::LYC - 0xFF00) as usize];
+        let stat = &mut self.zpram[(io::STAT - 0xFF00) as usize];
+        match ly == lyc {
+            true => *stat |= Stat::LYC_EQUAL.bits(),
+            false => *stat &= !Stat::LYC_EQUAL.bits(),
+        }
+
+        if !line && self.stat_line() {
+            self.request_interrupt(Interrupts::STAT);
+        }
+    }
+
+    /// Sets `interrupts` in IF
+    pub fn request_interrupt(&mut self, interrupts: Interrupts) {
+        self.zpram[(io::IF - 0xFF00) as usize] |= interrupts.bits();
+    }
+
+    /// Clears `interrupts` in IF, as the CPU does when it calls one's handler
+    pub fn acknowledge_interrupt(&mut self, interrupts: Interrupts) {
+        self.zpram[(io::IF - 0xFF00) as usize] &= !interrupts.bits();
+    }
+
+    /// Interrupts both requested in IF and enabled in IE
+    pub fn pending_interrupts(&self) -> Interrupts {
+        let requested = self.zpram[(io::IF - 0xFF00) as usize];
+        let enabled = self.zpram[(io::IE - 0xFF00) as usize];
+        Interrupts::from_bits_truncate(requested & enabled)
+    }
+
+    fn record(&mut self, access: Access, addr: usize) {
+        let addr = addr as u16;
+
+        if !self.taps.is_empty() && self.taps.contains(&(access, addr)) {
+            self.tap_hits.push((access, addr));
+        }
+
+        if self.watch_hit.is_none() {
+            if let Some(&watchpoint) = self.watchpoints.iter().find(|w| w.matches(access, addr)) {
+                self.watch_hit = Some((watchpoint, addr));
+            }
+        }
+
+        if self.access_log.is_none() {
+            return;
+        }
+
+        let rom_offset = match addr {
+            0x0000..=0x7FFF if self.boot_rom_at(addr as usize).is_none() => {
+                Some(flow::rom_offset(self.rom_bank(), addr))
+            }
+            _ => None,
+        };
+        if let Some(access_log) = &mut self.access_log {
+            access_log.record(access, addr, rom_offset);
+        }
+    }
+
+    /// Marks the `len` bytes at `addr` as executed. The CPU calls this for every instruction.
+    pub fn fetch(&mut self, addr: usize, len: usize) {
+        for i in 0..len {
+            self.record(Access::Execute, addr + i);
+        }
+    }
+
+    /// `rb` on behalf of the CPU, which gets recorded. The debugger reads through `rb` so it
+    /// doesn't show up in the access log.
+    pub fn read(&mut self, addr: usize) -> u8 {
+        self.record(Access::Read, addr);
+        match self.dma_blocks(addr) {
+            true => 0xFF,
+            false => self.rb(addr),
+        }
+    }
+
+    /// `rw` on behalf of the CPU, which gets recorded
+    pub fn read_word(&mut self, addr: usize) -> u16 {
+        u16::from_le_bytes([self.read(addr), self.read(addr + 1)])
+    }
+
+    /// `wb` on behalf of the CPU, which gets recorded
+    pub fn write(&mut self, addr: usize, value: u8) {
+        self.record(Access::Write, addr);
+        if !self.dma_blocks(addr) {
+            self.wb(addr, value)
+        }
+    }
+
+    /// `ww` on behalf of the CPU, which gets recorded
+    pub fn write_word(&mut self, addr: usize, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.write(addr, low);
+        self.write(addr + 1, high);
+    }
+
+    /// Whether an OAM DMA transfer keeps the CPU from `addr`
+    fn dma_blocks(&self, addr: usize) -> bool {
+        (0xFE00..=0xFE9F).contains(&(addr as u16)) && self.dma_active()
+    }
+
     // pub fn raw_memory(&self) -> &Vec<u8> {
     //     &self.mem
     // }
 
+    /// Writes a byte. Addresses wrap around at 0x10000, like on the 16-bit bus.
     pub fn wb(&mut self, addr: usize, value: u8) {
-        match addr {
+        let addr = addr as u16 as usize;
+
+        match addr as u16 {
             // 0x0000-0x8000: Cartridge memory. For MBC this will need to handle indexing
             //                further into the cart
-            0x0000..=0x7FFF => self.cart[addr] = value, // TODO: ROM bank switching
+            0x0000..=0x7FFF => {
+                // TODO: ROM bank switching
+                if let Some(byte) = self.cart.get_mut(addr) {
+                    *byte = value;
+                }
+            }
             // 0x8000-0xA000: Video RAM
-            0x8000..=0x9FFF => self.vram[addr - 0x8000] = value,
+            0x8000..=0x9FFF => {
+                let index = self.vram_index(addr);
+                self.vram[index] = value;
+            }
             // 0xA000-0xC000: Cartridge (external) RAM
             0xA000..=0xBFFF => self.cart_ram[addr - 0xA000] = value, // TODO: RAM bank switching
             // 0xC000-0xE000: Working (internal) RAM
-            0xC000..=0xDFFF => self.ram[addr - 0xC000] = value,
+            0xC000..=0xDFFF => {
+                let index = self.wram_index(addr);
+                self.ram[index] = value;
+            }
             // 0xE000-0xFE00: Shadow of working rAM
-            0xE000..=0xFDFF => self.ram[addr - 0xE000] = value,
+            0xE000..=0xFDFF => {
+                let index = self.wram_index(addr);
+                self.ram[index] = value;
+            }
             // 0xFE00-0xFEA0: Object attrbute memory
             0xFE00..=0xFE9F => self.oam[addr - 0xFE00] = value,
             // 0xFEA0-0xFF00: All zeroes
             0xFEA0..=0xFEFF => (),
+            // 0xFF04: DIV, which any write resets
+            0xFF04 => self.reset_div(),
+            // 0xFF05: TIMA
+            0xFF05 => {
+                self.sync_tima(self.scheduler.now());
+                self.tima = value;
+                self.schedule_timer_overflow();
+            }
+            // 0xFF07: TAC, which picks TIMA's rate
+            0xFF07 => {
+                let signal = self.timer_signal();
+                self.sync_tima(self.scheduler.now());
+                self.zpram[addr - 0xFF00] = value;
+                if signal && !self.timer_signal() {
+                    self.increment_tima();
+                }
+                self.schedule_timer_overflow();
+            }
+            // 0xFF40: LCDC, whose top bit turns the LCD on and off
+            0xFF40 => {
+                let enabled = Lcdc::from_bits_truncate(value).contains(Lcdc::LCD_ENABLE);
+                let was_enabled = self.zpram[addr - 0xFF00] & Lcdc::LCD_ENABLE.bits() != 0;
+                self.zpram[addr - 0xFF00] = value;
+                if enabled != was_enabled {
+                    self.set_lcd_enabled(enabled);
+                }
+            }
+            // 0xFF41: STAT, where only the interrupt sources can be written
+            0xFF41 => self.update_stat(|mmu| {
+                let stat = &mut mmu.zpram[addr - 0xFF00];
+                *stat = (value & 0x78) | (*stat & 0x07);
+            }),
+            // 0xFF44: LY, which is read-only
+            0xFF44 => (),
+            // 0xFF45: LYC
+            0xFF45 => self.update_stat(|mmu| mmu.zpram[addr - 0xFF00] = value),
+            // 0xFF50: Unmaps the boot ROM
+            0xFF50 => {
+                if value != 0 {
+                    self.boot_rom_mapped = false;
+                }
+                self.zpram[addr - 0xFF00] = value;
+            }
+            // 0xFF00: Joypad, which the SGB also takes packets through
+            0xFF00 => {
+                self.zpram[addr - 0xFF00] = value;
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_p1(value);
+                }
+            }
+            // 0xFF02: SC, which starts and stops serial transfers
+            0xFF02 => {
+                self.zpram[addr - 0xFF00] = value;
+                self.start_serial();
+            }
+            // 0xFF46: DMA, which starts a transfer to OAM
+            0xFF46 => {
+                self.zpram[addr - 0xFF00] = value;
+                self.start_dma(value);
+            }
+            // CGB registers, when there's no CGB hardware
+            0xFF4D..=0xFF70 if !self.model.is_cgb() && self.is_cgb_register(addr as u16) => (),
+            // 0xFF4D: KEY1, where only the switch can be armed
+            0xFF4D => self.zpram[addr - 0xFF00] = value & 0x01,
+            // 0xFF4F: VBK and 0xFF70: SVBK, which pick the VRAM and WRAM banks
+            0xFF4F => self.zpram[addr - 0xFF00] = value & 0x01,
+            0xFF70 => self.zpram[addr - 0xFF00] = value & 0x07,
+            // 0xFF51-0xFF54: HDMA1-4, where the VRAM DMA copies from and to, 16-byte aligned
+            0xFF51 => self.dma_source = (value as u16) << 8 | (self.dma_source & 0x00F0),
+            0xFF52 => self.dma_source = (self.dma_source & 0xFF00) | (value & 0xF0) as u16,
+            0xFF53 => self.dma_dest = ((value & 0x1F) as u16) << 8 | (self.dma_dest & 0x00F0),
+            0xFF54 => self.dma_dest = (self.dma_dest & 0x1F00) | (value & 0xF0) as u16,
+            // 0xFF55: HDMA5, which starts and stops VRAM DMA
+            0xFF55 => self.start_vram_dma(value),
+            // 0xFF68 and 0xFF6A: BCPS and OCPS, which pick the palette byte and whether writes
+            // move on to the next
+            0xFF68 | 0xFF6A => self.zpram[addr - 0xFF00] = value & 0xBF,
+            // 0xFF69 and 0xFF6B: BCPD and OCPD, the palette byte BCPS or OCPS picked
+            0xFF69 | 0xFF6B => {
+                let objects = addr == 0xFF6B;
+                let index = self.palette_index(objects);
+                if !self.palettes_locked() {
+                    self.palettes[index] = value;
+                }
+
+                let spec = &mut self.zpram[addr - 0xFF00 - 1];
+                if *spec & 0x80 != 0 {
+                    *spec = 0x80 | ((*spec + 1) & 0x3F);
+                }
+            }
             // 0xFF00-0x10000: Zero-page RAM
-            0xFF00..=0xFFFF => self.zpram[addr - 0xFF00] = value,
-            _ => todo!(),
+            0xFF01
+            | 0xFF03
+            | 0xFF06
+            | 0xFF08..=0xFF3F
+            | 0xFF42..=0xFF43
+            | 0xFF47..=0xFF4C
+            | 0xFF4E
+            | 0xFF56..=0xFF67
+            | 0xFF6C..=0xFF6F
+            | 0xFF71..=0xFFFF => self.zpram[addr - 0xFF00] = value,
         }
     }
 
@@ -96,26 +1166,73 @@ impl Mmu {
         self.wb(addr + 1, (value >> 8) as u8);
     }
 
+    /// Reads a byte. Addresses wrap around at 0x10000, like on the 16-bit bus.
     pub fn rb(&self, addr: usize) -> u8 {
-        match addr {
+        let addr = addr as u16 as usize;
+
+        match addr as u16 {
             // 0x0000-0x8000: Cartridge memory. For MBC this will need to handle indexing
-            //                further into the cart
-            0x0000..=0x7FFF => self.cart[addr], // TODO: ROM bank switching
+            //                further into the cart. Past its end, or with no cart at all,
+            //                the bus floats high. The boot ROM covers the start until it's
+            //                unmapped.
+            0x0000..=0x7FFF => self
+                .boot_rom_at(addr)
+                .or_else(|| self.cart.get(addr).copied())
+                .unwrap_or(0xFF), // TODO: ROM bank switching
             // 0x8000-0xA000: Video RAM
-            0x8000..=0x9FFF => self.vram[addr - 0x8000],
+            0x8000..=0x9FFF => self.vram[self.vram_index(addr)],
             // 0xA000-0xC000: Cartridge (external) RAM
             0xA000..=0xBFFF => self.cart_ram[addr - 0xA000], // TODO: RAM bank switching
             // 0xC000-0xE000: Working (internal) RAM
-            0xC000..=0xDFFF => self.ram[addr - 0xC000],
+            0xC000..=0xDFFF => self.ram[self.wram_index(addr)],
             // 0xE000-0xFE00: Shadow of working rAM
-            0xE000..=0xFDFF => self.ram[addr - 0xE000],
+            0xE000..=0xFDFF => self.ram[self.wram_index(addr)],
             // 0xFE00-0xFEA0: Object attrbute memory
             0xFE00..=0xFE9F => self.oam[addr - 0xFE00],
             // 0xFEA0-0xFF00: All zeroes
             0xFEA0..=0xFEFF => 0,
-            // 0xFF00-0x10000: Zero-page RAM
-            0xFF00..=0xFFFF => self.zpram[addr - 0xFF00],
-            _ => todo!(),
+            // 0xFF00: Joypad, with the select bits written last
+            0xFF00 => match &self.sgb {
+                Some(sgb) => sgb.p1(self.buttons, self.zpram[0]),
+                None => self.buttons.p1(self.zpram[0]),
+            },
+            // 0xFF04: DIV
+            0xFF04 => (self.div_counter() >> 8) as u8,
+            // 0xFF05: TIMA
+            0xFF05 => {
+                let edges = self.tima_edges(self.scheduler.now());
+                self.tima.wrapping_add(edges as u8)
+            }
+            // 0xFF0F: IF, whose top three bits aren't wired up
+            0xFF0F => self.zpram[addr - 0xFF00] | 0xE0,
+            // 0xFF41: STAT, whose top bit isn't wired up
+            0xFF41 => self.zpram[addr - 0xFF00] | 0x80,
+            // CGB registers, when there's no CGB hardware
+            0xFF4D..=0xFF70 if !self.model.is_cgb() && self.is_cgb_register(addr as u16) => 0xFF,
+            // 0xFF4D: KEY1, with the current speed on top
+            0xFF4D => 0x7E | (self.double_speed as u8) << 7 | self.zpram[addr - 0xFF00],
+            // 0xFF4F: VBK and 0xFF70: SVBK, with the bits that aren't wired up set
+            0xFF4F => 0xFE | self.zpram[addr - 0xFF00],
+            0xFF70 => 0xF8 | self.zpram[addr - 0xFF00],
+            // 0xFF51-0xFF54: HDMA1-4, which can't be read
+            0xFF51..=0xFF54 => 0xFF,
+            // 0xFF68 and 0xFF6A: BCPS and OCPS, with the bit that isn't wired up set
+            0xFF68 | 0xFF6A => 0x40 | self.zpram[addr - 0xFF00],
+            // 0xFF69 and 0xFF6B: BCPD and OCPD
+            0xFF69 | 0xFF6B => match self.palettes_locked() {
+                true => 0xFF,
+                false => self.palettes[self.palette_index(addr == 0xFF6B)],
+            },
+            // 0xFF01-0x10000: Zero-page RAM
+            0xFF01..=0xFF03
+            | 0xFF06..=0xFF0E
+            | 0xFF10..=0xFF40
+            | 0xFF42..=0xFF4C
+            | 0xFF4E
+            | 0xFF50
+            | 0xFF55..=0xFF67
+            | 0xFF6C..=0xFF6F
+            | 0xFF71..=0xFFFF => self.zpram[addr - 0xFF00],
         }
     }
 
@@ -126,7 +1243,8 @@ impl Mmu {
 
 #[cfg(test)]
 mod test {
-    use super::Mmu;
+    use super::{Mmu, Watchpoint, CYCLES_PER_FRAME};
+    use crate::{coverage::Access, model::Model};
 
     #[test]
     fn read_write_bytes() {
@@ -148,4 +1266,340 @@ mod test {
         assert!(mmu.rb(addr + 1) == 0x52);
         assert!(mmu.rw(addr) == 0x5248);
     }
+
+    #[test]
+    fn post_boot_registers() {
+        let registers = |model| {
+            let mmu = Mmu::with_model(vec![0; 0x8000], model);
+            [0xFF02, 0xFF04, 0xFF0F, 0xFF26, 0xFF46, 0xFF4D, 0xFF70].map(|addr| mmu.rb(addr))
+        };
+
+        assert_eq!(
+            registers(Model::Dmg),
+            [0x7E, 0xAB, 0xE1, 0xF1, 0xFF, 0xFF, 0xFF]
+        );
+        assert_eq!(
+            registers(Model::Dmg0),
+            [0x7E, 0x18, 0xE1, 0xF1, 0xFF, 0xFF, 0xFF]
+        );
+        assert_eq!(
+            registers(Model::Sgb),
+            [0x7E, 0xD8, 0xE1, 0xF0, 0xFF, 0xFF, 0xFF]
+        );
+        assert_eq!(
+            registers(Model::Cgb),
+            [0x7F, 0x1E, 0xE1, 0xF1, 0x00, 0x7E, 0xF8]
+        );
+    }
+
+    #[test]
+    fn timer() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.wb(0xFF04, 0);
+        mmu.wb(0xFF05, 0xFE);
+        mmu.wb(0xFF06, 0x42);
+        // On, counting every 16 cycles
+        mmu.wb(0xFF07, 0x05);
+
+        for _ in 0..4 {
+            mmu.tick(4);
+        }
+        assert_eq!(mmu.rb(0xFF05), 0xFF);
+        assert_eq!(mmu.rb(0xFF0F) & 0x04, 0);
+
+        for _ in 0..4 {
+            mmu.tick(4);
+        }
+        assert_eq!(mmu.rb(0xFF05), 0x42);
+        assert_eq!(mmu.rb(0xFF0F) & 0x04, 0x04);
+
+        // Resetting DIV with the counted bit set is a falling edge too
+        mmu.tick(8);
+        mmu.wb(0xFF04, 0);
+        assert_eq!(mmu.rb(0xFF05), 0x43);
+
+        // Overflows keep coming however far the clock moves at once
+        mmu.wb(0xFF05, 0);
+        mmu.wb(0xFF06, 0);
+        mmu.wb(0xFF0F, 0);
+        mmu.tick(16 * 0x300 + 4);
+        assert_eq!(mmu.rb(0xFF05), 0x00);
+        assert_eq!(mmu.rb(0xFF0F) & 0x04, 0x04);
+
+        // And turned off, it stops
+        mmu.wb(0xFF07, 0x01);
+        for _ in 0..16 {
+            mmu.tick(4);
+        }
+        assert_eq!(mmu.rb(0xFF05), 0x00);
+    }
+
+    #[test]
+    fn double_speed() {
+        let mut mmu = Mmu::with_model(vec![0; 0x8000], Model::Cgb);
+        assert!(!mmu.switch_speed());
+
+        mmu.wb(0xFF4D, 0xFF);
+        assert_eq!(mmu.rb(0xFF4D), 0x7F);
+        assert!(mmu.switch_speed());
+        assert_eq!(mmu.rb(0xFF4D), 0xFE);
+
+        // DIV counts the CPU's cycles, which now take half the time
+        let start = mmu.scheduler().now();
+        for _ in 0..64 {
+            mmu.tick(4);
+        }
+        assert_eq!(mmu.rb(0xFF04), 0x01);
+        assert_eq!(mmu.scheduler().now() - start, 128);
+
+        // TIMA too
+        mmu.wb(0xFF05, 0xFF);
+        mmu.wb(0xFF07, 0x05);
+        mmu.wb(0xFF0F, 0);
+        for _ in 0..4 {
+            mmu.tick(4);
+        }
+        assert_eq!(mmu.rb(0xFF0F) & 0x04, 0x04);
+
+        mmu.wb(0xFF4D, 0x01);
+        assert!(mmu.switch_speed());
+        assert_eq!(mmu.rb(0xFF4D), 0x7E);
+        assert_eq!(mmu.rb(0xFF04), 0x00);
+    }
+
+    #[test]
+    fn ppu_modes() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        let mode = |mmu: &Mmu| mmu.rb(0xFF41) & 0x03;
+        assert_eq!((mmu.rb(0xFF44), mode(&mmu)), (0, 2));
+
+        mmu.tick(80);
+        assert_eq!(mode(&mmu), 3);
+        mmu.tick(172);
+        assert_eq!(mode(&mmu), 0);
+        mmu.tick(204);
+        assert_eq!((mmu.rb(0xFF44), mode(&mmu)), (1, 2));
+
+        // Interrupts on LYC=LY
+        mmu.wb(0xFF45, 2);
+        mmu.wb(0xFF41, 0x40);
+        mmu.wb(0xFF0F, 0);
+        mmu.tick(456);
+        assert_eq!(mmu.rb(0xFF41) & 0x04, 0x04);
+        assert_eq!(mmu.rb(0xFF0F) & 0x03, 0x02);
+
+        mmu.wb(0xFF0F, 0);
+        mmu.tick(456 * 142);
+        assert_eq!((mmu.rb(0xFF44), mode(&mmu)), (144, 1));
+        assert_eq!(mmu.rb(0xFF0F) & 0x03, 0x01);
+
+        mmu.tick(456 * 10);
+        assert_eq!((mmu.rb(0xFF44), mode(&mmu)), (0, 2));
+
+        // Off, it waits at the start of line 0
+        mmu.wb(0xFF40, 0x11);
+        mmu.tick(456 * 3);
+        assert_eq!((mmu.rb(0xFF44), mode(&mmu)), (0, 0));
+        mmu.wb(0xFF40, 0x91);
+        mmu.tick(456 * 3);
+        assert_eq!((mmu.rb(0xFF44), mode(&mmu)), (3, 2));
+    }
+
+    #[test]
+    fn frames() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+
+        // Frames end as the PPU gets back to line 0
+        mmu.tick(CYCLES_PER_FRAME - 1);
+        assert_eq!(mmu.frame(), 0);
+        mmu.tick(1);
+        assert_eq!((mmu.frame(), mmu.rb(0xFF44)), (1, 0));
+
+        // With the LCD off, when it would have
+        mmu.tick(456 * 145);
+        mmu.wb(0xFF40, 0x11);
+        mmu.tick(456 * 9 - 1);
+        assert_eq!(mmu.frame(), 1);
+        mmu.tick(1);
+        assert_eq!(mmu.frame(), 2);
+        mmu.tick(CYCLES_PER_FRAME);
+        assert_eq!(mmu.frame(), 3);
+
+        // Back on, the PPU starts a frame that ends when it's done
+        mmu.tick(100);
+        mmu.wb(0xFF40, 0x91);
+        mmu.tick(CYCLES_PER_FRAME - 1);
+        assert_eq!(mmu.frame(), 3);
+        mmu.tick(1);
+        assert_eq!(mmu.frame(), 4);
+    }
+
+    #[test]
+    fn serial() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.wb(0xFF0F, 0);
+        mmu.wb(0xFF04, 0);
+        mmu.wb(0xFF01, 0x0F);
+        mmu.wb(0xFF02, 0x81);
+
+        mmu.tick(512 * 4);
+        assert_eq!(mmu.rb(0xFF01), 0xFF);
+        assert_eq!(mmu.rb(0xFF02) & 0x80, 0x80);
+        mmu.tick(512 * 4 - 1);
+        assert_eq!(mmu.rb(0xFF0F) & 0x08, 0);
+        mmu.tick(1);
+        assert_eq!(mmu.rb(0xFF02) & 0x80, 0);
+        assert_eq!(mmu.rb(0xFF0F) & 0x08, 0x08);
+
+        // On the external clock, nothing's clocking it
+        mmu.wb(0xFF01, 0x0F);
+        mmu.wb(0xFF02, 0x80);
+        mmu.tick(512 * 16);
+        assert_eq!(mmu.rb(0xFF01), 0x0F);
+    }
+
+    #[test]
+    fn oam_dma() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        for i in 0..0xA0 {
+            mmu.wb(0xC100 + i, i as u8);
+        }
+
+        mmu.write(0xFF46, 0xC1);
+        assert!(mmu.dma_active());
+        assert_eq!(mmu.read(0xFE05), 0xFF);
+        mmu.write(0xFE05, 0x42);
+        assert_eq!(mmu.rb(0xFE05), 0x05);
+
+        mmu.tick(644);
+        assert!(!mmu.dma_active());
+        assert_eq!(mmu.read(0xFE9F), 0x9F);
+    }
+
+    #[test]
+    fn cgb_banks() {
+        let mut mmu = Mmu::with_model(vec![0; 0x8000], Model::Cgb);
+        mmu.wb(0x8000, 1);
+        mmu.wb(0xD000, 1);
+        mmu.wb(0xFF4F, 0x01);
+        mmu.wb(0xFF70, 0x03);
+        assert_eq!((mmu.rb(0xFF4F), mmu.rb(0xFF70)), (0xFF, 0xFB));
+        assert_eq!((mmu.rb(0x8000), mmu.rb(0xD000)), (0, 0));
+        mmu.wb(0x8000, 2);
+        mmu.wb(0xF000, 3);
+        assert_eq!((mmu.bank_at(0x8000), mmu.bank_at(0xD000)), (1, 3));
+        assert_eq!((mmu.vram()[0x2000], mmu.rb(0xD000)), (2, 3));
+
+        // Bank 0 in SVBK is bank 1
+        mmu.wb(0xFF4F, 0x00);
+        mmu.wb(0xFF70, 0x00);
+        assert_eq!((mmu.rb(0x8000), mmu.rb(0xD000)), (1, 1));
+        assert_eq!(mmu.rb(0xC000), 0);
+
+        // Before the CGB, there's one of each
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.wb(0xD000, 1);
+        mmu.wb(0xFF70, 0x03);
+        assert_eq!((mmu.rb(0xFF70), mmu.rb(0xD000)), (0xFF, 1));
+    }
+
+    #[test]
+    fn vram_dma() {
+        let mut mmu = Mmu::with_model(vec![0; 0x8000], Model::Cgb);
+        for i in 0..0x40 {
+            mmu.wb(0xC000 + i, i as u8 + 1);
+        }
+        mmu.wb(0xFF51, 0xC0);
+        mmu.wb(0xFF52, 0x00);
+        mmu.wb(0xFF53, 0x81);
+        mmu.wb(0xFF54, 0x00);
+
+        // All at once, with the CPU stopped
+        let now = mmu.scheduler().now();
+        mmu.wb(0xFF55, 0x01);
+        assert_eq!(mmu.scheduler().now() - now, 64);
+        assert_eq!((mmu.rb(0x8100), mmu.rb(0x811F)), (1, 0x20));
+        assert_eq!(mmu.rb(0xFF55), 0xFF);
+
+        // A block each HBlank, carrying on from where that stopped
+        mmu.wb(0xFF55, 0x81);
+        assert_eq!(mmu.rb(0xFF55), 0x01);
+        mmu.tick(456);
+        assert_eq!((mmu.rb(0xFF55), mmu.rb(0x8120)), (0x00, 0x21));
+        assert_eq!(mmu.rb(0x8130), 0);
+        mmu.tick(456);
+        assert_eq!((mmu.rb(0xFF55), mmu.rb(0x813F)), (0xFF, 0x40));
+
+        // Stopped partway
+        mmu.wb(0xFF55, 0x83);
+        mmu.tick(456);
+        mmu.wb(0xFF55, 0x00);
+        assert_eq!(mmu.rb(0xFF55), 0x82);
+    }
+
+    #[test]
+    fn palettes() {
+        let mut mmu = Mmu::with_model(vec![0; 0x8000], Model::Cgb);
+        mmu.wb(0xFF40, 0x00);
+        mmu.wb(0xFF6A, 0x82);
+        mmu.wb(0xFF6B, 0x1F);
+        mmu.wb(0xFF6B, 0x7C);
+        assert_eq!(mmu.rb(0xFF6A), 0xC4);
+        assert_eq!(&mmu.palettes()[0x42..0x44], [0x1F, 0x7C]);
+
+        // Without auto-increment, writes stay put
+        mmu.wb(0xFF68, 0x3F);
+        mmu.wb(0xFF69, 0x12);
+        mmu.wb(0xFF69, 0x34);
+        assert_eq!((mmu.rb(0xFF68), mmu.rb(0xFF69)), (0x7F, 0x34));
+
+        // The PPU has them while it draws
+        mmu.wb(0xFF40, 0x91);
+        mmu.tick(80);
+        mmu.wb(0xFF69, 0x56);
+        assert_eq!(mmu.rb(0xFF69), 0xFF);
+        mmu.tick(172);
+        assert_eq!(mmu.rb(0xFF69), 0x34);
+    }
+
+    #[test]
+    fn banked_access() {
+        let mut rom = vec![0; 0x10000];
+        rom[0x8123] = 0x42;
+        let mut mmu = Mmu::new(rom);
+
+        assert!(mmu.rom_bank_count() == 4);
+        assert!(mmu.peek_banked(2, 0, 0x4123) == 0x42);
+        assert!(mmu.rb(0x4123) == 0);
+
+        mmu.poke_banked(3, 0, 0x4000, 0x99);
+        assert!(mmu.rom()[0xC000] == 0x99);
+
+        assert!(mmu.region_name(0x4000) == "ROMX bank 1");
+        assert!(mmu.region_name(0xFF80) == "HRAM");
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        let watchpoint = Watchpoint {
+            addr: 0xC100,
+            len: 2,
+            access: Access::Write,
+        };
+        mmu.add_watchpoint(watchpoint);
+
+        // Neither the debugger's accesses nor reads trigger a write watchpoint
+        mmu.wb(0xC100, 1);
+        mmu.read(0xC101);
+        assert!(mmu.take_watch_hit().is_none());
+
+        mmu.write_word(0xC0FF, 0x1234);
+        assert!(mmu.take_watch_hit() == Some((watchpoint, 0xC100)));
+        assert!(mmu.take_watch_hit().is_none());
+
+        mmu.remove_watchpoint(watchpoint);
+        mmu.write(0xC100, 1);
+        assert!(mmu.take_watch_hit().is_none());
+    }
 }
@@ -0,0 +1,118 @@
+use std::fmt;
+
+use crate::header::Header;
+
+/// Game Boy hardware revisions. Games tell them apart by the registers their boot ROMs leave
+/// behind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Model {
+    /// The original Game Boy's first boot ROM, only sold in Japan
+    Dmg0,
+    /// The original Game Boy
+    #[default]
+    Dmg,
+    /// Game Boy Pocket and Light
+    Mgb,
+    /// Super Game Boy
+    Sgb,
+    /// Game Boy Color
+    Cgb,
+    /// Game Boy Advance, in Game Boy Color mode
+    Agb,
+}
+
+impl Model {
+    pub const ALL: [Model; 6] = [
+        Model::Dmg0,
+        Model::Dmg,
+        Model::Mgb,
+        Model::Sgb,
+        Model::Cgb,
+        Model::Agb,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Model::Dmg0 => "dmg0",
+            Model::Dmg => "dmg",
+            Model::Mgb => "mgb",
+            Model::Sgb => "sgb",
+            Model::Cgb => "cgb",
+            Model::Agb => "agb",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Model> {
+        Model::ALL
+            .iter()
+            .copied()
+            .find(|model| model.name().eq_ignore_ascii_case(name))
+    }
+
+    /// What the cartridge was made for: a CGB if the header's 0x0143 byte says it supports one,
+    /// an SGB if 0x0146 says it has SGB features, and a DMG otherwise. The SGB ignores 0x0146
+    /// unless the old licensee at 0x014B is 0x33.
+    pub fn from_header(header: &Header) -> Model {
+        if header.cgb_flag & 0x80 != 0 {
+            Model::Cgb
+        } else if header.sgb_flag == 0x03 && header.old_licensee == 0x33 {
+            Model::Sgb
+        } else {
+            Model::Dmg
+        }
+    }
+
+    /// Whether it has the Game Boy Color's hardware: its extra registers, banked VRAM and WRAM,
+    /// and double speed. It also fixed OAM corruption.
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    /// Size of its boot ROM in bytes
+    pub fn boot_rom_len(self) -> usize {
+        if self.is_cgb() {
+            0x900
+        } else {
+            0x100
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name().to_ascii_uppercase())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Model;
+    use crate::header::Header;
+
+    #[test]
+    fn names() {
+        for model in Model::ALL {
+            assert_eq!(Model::from_name(model.name()), Some(model));
+        }
+        assert_eq!(Model::from_name("CGB"), Some(Model::Cgb));
+        assert_eq!(Model::from_name("gba"), None);
+        assert_eq!(Model::Mgb.to_string(), "MGB");
+    }
+
+    #[test]
+    fn from_header() {
+        let mut rom = vec![0; 0x8000];
+        let model = |rom: &[u8]| Model::from_header(&Header::parse(rom).unwrap());
+        assert_eq!(model(&rom), Model::Dmg);
+
+        rom[0x0146] = 0x03;
+        assert_eq!(model(&rom), Model::Dmg);
+        rom[0x014B] = 0x33;
+        assert_eq!(model(&rom), Model::Sgb);
+
+        rom[0x0143] = 0xC0;
+        assert_eq!(model(&rom), Model::Cgb);
+        rom[0x0143] = 0x80;
+        assert_eq!(model(&rom), Model::Cgb);
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::{callstack::CallFrame, mmu::CYCLES_PER_FRAME, symbols::SymbolTable};
+
+/// A code address and the ROM bank mapped there, 0 outside of the switchable area
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Location {
+    pub bank: u16,
+    pub addr: u16,
+}
+
+/// Accumulates where the CPU spends its cycles
+#[derive(Default)]
+pub struct Profiler {
+    /// Cycles spent on the instruction at each location
+    hotspots: HashMap<Location, u64>,
+    /// Cycles spent in each CALL target, including everything it called
+    routines: HashMap<Location, u64>,
+    /// Cycles spent with exactly this chain of call targets, outermost first
+    stacks: HashMap<Vec<Location>, u64>,
+    total: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    /// Records `cycles` spent on the instruction at `location`, while `frames` were on the call
+    /// stack
+    pub fn record(&mut self, location: Location, frames: &[CallFrame], cycles: u64) {
+        *self.hotspots.entry(location).or_default() += cycles;
+
+        let stack: Vec<Location> = frames
+            .iter()
+            .map(|frame| Location {
+                bank: frame.bank,
+                addr: frame.target,
+            })
+            .collect();
+
+        // Recursion puts a routine on the stack more than once, but it only spent the cycles once
+        for (i, routine) in stack.iter().enumerate() {
+            if !stack[..i].contains(routine) {
+                *self.routines.entry(*routine).or_default() += cycles;
+            }
+        }
+
+        *self.stacks.entry(stack).or_default() += cycles;
+        self.total += cycles;
+    }
+
+    /// Cycles recorded so far
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Frames' worth of cycles recorded so far
+    pub fn frames(&self) -> f64 {
+        self.total as f64 / CYCLES_PER_FRAME as f64
+    }
+
+    /// The `count` most expensive instructions, most expensive first
+    pub fn hotspots(&self, count: usize) -> Vec<(Location, u64)> {
+        top(&self.hotspots, count)
+    }
+
+    /// The `count` most expensive routines by inclusive cycles, most expensive first
+    pub fn routines(&self, count: usize) -> Vec<(Location, u64)> {
+        top(&self.routines, count)
+    }
+
+    /// Saves the call stacks in the folded format flamegraph.pl and inferno read
+    pub fn save_folded(&self, path: &Path, symbols: &SymbolTable) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_folded(&mut out, symbols)?;
+        out.flush()
+    }
+
+    /// Writes one `outer;inner cycles` line per call stack
+    fn write_folded(&self, out: &mut dyn Write, symbols: &SymbolTable) -> std::io::Result<()> {
+        let mut stacks: Vec<_> = self.stacks.iter().collect();
+        stacks.sort();
+
+        for (stack, cycles) in stacks {
+            let names: Vec<String> = std::iter::once("top".to_string())
+                .chain(stack.iter().map(|&location| name(symbols, location)))
+                .collect();
+            writeln!(out, "{} {}", names.join(";"), cycles)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The location's label, or `bank:addr` without one
+pub fn name(symbols: &SymbolTable, location: Location) -> String {
+    match symbols.label(location.addr, location.bank) {
+        Some(label) => label.to_string(),
+        None => format!("{:02x}:{:04x}", location.bank, location.addr),
+    }
+}
+
+fn top(cycles: &HashMap<Location, u64>, count: usize) -> Vec<(Location, u64)> {
+    let mut entries: Vec<_> = cycles
+        .iter()
+        .map(|(&location, &cycles)| (location, cycles))
+        .collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    entries.truncate(count);
+
+    entries
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Location, Profiler};
+    use crate::{
+        callstack::{CallFrame, CallStack},
+        symbols::SymbolTable,
+    };
+
+    fn location(addr: u16) -> Location {
+        Location { bank: 0, addr }
+    }
+
+    fn call(stack: &mut CallStack, target: u16, sp: u16) {
+        stack.call(CallFrame {
+            caller: 0x0150,
+            target,
+            sp,
+            bank: 0,
+            interrupt: false,
+        });
+    }
+
+    #[test]
+    fn inclusive_cycles() {
+        let mut profiler = Profiler::new();
+        let mut stack = CallStack::new();
+
+        profiler.record(location(0x0150), stack.frames(), 24);
+        call(&mut stack, 0x0200, 0xFFFC);
+        profiler.record(location(0x0200), stack.frames(), 4);
+        call(&mut stack, 0x0300, 0xFFFA);
+        profiler.record(location(0x0300), stack.frames(), 8);
+        profiler.record(location(0x0300), stack.frames(), 8);
+        // Recursing doesn't count the cycles twice
+        call(&mut stack, 0x0200, 0xFFF8);
+        profiler.record(location(0x0200), stack.frames(), 4);
+
+        assert_eq!(profiler.total(), 48);
+        assert_eq!(
+            profiler.hotspots(2),
+            [(location(0x0150), 24), (location(0x0300), 16)]
+        );
+        assert_eq!(
+            profiler.routines(10),
+            [(location(0x0200), 24), (location(0x0300), 20)]
+        );
+    }
+
+    #[test]
+    fn folded_stacks() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0, 0x0200, "Main");
+
+        let mut profiler = Profiler::new();
+        let mut stack = CallStack::new();
+
+        profiler.record(location(0x0150), stack.frames(), 12);
+        call(&mut stack, 0x0200, 0xFFFC);
+        profiler.record(location(0x0200), stack.frames(), 4);
+        call(&mut stack, 0x0300, 0xFFFA);
+        profiler.record(location(0x0300), stack.frames(), 8);
+
+        let mut out = Vec::new();
+        profiler.write_folded(&mut out, &symbols).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "top 12\ntop;Main 4\ntop;Main;00:0300 8\n"
+        );
+    }
+}
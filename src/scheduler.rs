@@ -0,0 +1,193 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::{
+    error::EmulatorError,
+    state::{Reader, Writer},
+};
+
+/// Something that happens at a known clock cycle. Hardware that can work out its state from the
+/// time, like DIV, catches up when it's read instead.
+// TODO: the APU frame sequencer once there's an APU, and the RTC once there's an MBC3
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Event {
+    /// A frame's worth of cycles has run
+    FrameEnd,
+    /// TIMA counts past 0xFF
+    TimerOverflow,
+    /// The PPU starts the next line, in OAM scan or VBlank
+    LineStart,
+    /// The PPU finishes OAM scan and starts drawing the line
+    Drawing,
+    /// The PPU finishes drawing the line
+    HBlank,
+    /// The serial port shifts a bit out of SB and one in
+    SerialBit,
+    /// An OAM DMA transfer is done
+    DmaEnd,
+}
+
+impl Event {
+    fn from_u8(value: u8) -> Option<Event> {
+        match value {
+            0 => Some(Event::FrameEnd),
+            1 => Some(Event::TimerOverflow),
+            2 => Some(Event::LineStart),
+            3 => Some(Event::Drawing),
+            4 => Some(Event::HBlank),
+            5 => Some(Event::SerialBit),
+            6 => Some(Event::DmaEnd),
+            _ => None,
+        }
+    }
+}
+
+/// The master clock, and the events coming up on it
+#[derive(Debug)]
+pub struct Scheduler {
+    /// Clock cycles since power on
+    now: u64,
+
+    /// Soonest first, and in the order they were scheduled when due at the same cycle
+    queue: BinaryHeap<Reverse<(u64, u64, Event)>>,
+
+    /// Counts the events scheduled, to order those due at the same cycle
+    scheduled: u64,
+
+    /// When the soonest event is due, so `advance` is a comparison when there's nothing to do
+    next: u64,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            now: 0,
+            queue: BinaryHeap::new(),
+            scheduled: 0,
+            next: u64::MAX,
+        }
+    }
+
+    /// Clock cycles since power on
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Queues `event` for `cycles` from now
+    pub fn schedule(&mut self, cycles: u64, event: Event) {
+        self.schedule_at(self.now + cycles, event);
+    }
+
+    /// Queues `event` for the clock cycle `at`, or as soon as possible if that's passed
+    pub fn schedule_at(&mut self, at: u64, event: Event) {
+        self.queue.push(Reverse((at, self.scheduled, event)));
+        self.scheduled += 1;
+        self.next = self.next.min(at);
+    }
+
+    /// Whether `event` is queued
+    pub fn is_scheduled(&self, event: Event) -> bool {
+        self.queue
+            .iter()
+            .any(|Reverse((_, _, queued))| *queued == event)
+    }
+
+    /// Drops every queued `event`
+    pub fn cancel(&mut self, event: Event) {
+        self.queue
+            .retain(|Reverse((_, _, queued))| *queued != event);
+        self.update_next();
+    }
+
+    /// Moves the clock on. Returns whether any events are now due, to be taken with `pop`.
+    pub fn advance(&mut self, cycles: u64) -> bool {
+        self.now += cycles;
+        self.now >= self.next
+    }
+
+    /// Takes the soonest event that's due, and the cycle it was due at
+    pub fn pop(&mut self) -> Option<(u64, Event)> {
+        if self.now < self.next {
+            return None;
+        }
+
+        let Reverse((at, _, event)) = self.queue.pop()?;
+        self.update_next();
+        Some((at, event))
+    }
+
+    fn update_next(&mut self) {
+        self.next = match self.queue.peek() {
+            Some(Reverse((at, _, _))) => *at,
+            None => u64::MAX,
+        };
+    }
+
+    /// Writes the clock and queued events for a save state
+    pub fn save_state(&self, writer: &mut Writer) {
+        let mut events: Vec<_> = self.queue.iter().map(|Reverse(entry)| *entry).collect();
+        events.sort();
+
+        writer.u64(self.now);
+        writer.u64(events.len() as u64);
+        for (at, _, event) in events {
+            writer.u64(at);
+            writer.u8(event as u8);
+        }
+    }
+
+    /// Reads back what `save_state` wrote. The caller replaces the scheduler only if everything
+    /// else loads too.
+    pub fn load_state(reader: &mut Reader) -> Result<Scheduler, EmulatorError> {
+        let mut scheduler = Scheduler::new();
+        scheduler.now = reader.u64()?;
+
+        let len = reader.u64()?;
+        for _ in 0..len {
+            let at = reader.u64()?;
+            let value = reader.u8()?;
+            let event = Event::from_u8(value)
+                .ok_or_else(|| EmulatorError::InvalidState(format!("unknown event {}", value)))?;
+            scheduler.schedule_at(at, event);
+        }
+
+        Ok(scheduler)
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Event, Scheduler};
+
+    #[test]
+    fn events() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(10, Event::FrameEnd);
+        scheduler.schedule_at(4, Event::FrameEnd);
+        scheduler.schedule_at(4, Event::HBlank);
+
+        assert!(!scheduler.advance(3));
+        assert_eq!(scheduler.pop(), None);
+
+        assert!(scheduler.advance(9));
+        assert_eq!(scheduler.now(), 12);
+        assert_eq!(scheduler.pop(), Some((4, Event::FrameEnd)));
+        assert_eq!(scheduler.pop(), Some((4, Event::HBlank)));
+        assert_eq!(scheduler.pop(), Some((10, Event::FrameEnd)));
+        assert_eq!(scheduler.pop(), None);
+
+        scheduler.schedule(1, Event::FrameEnd);
+        scheduler.schedule(2, Event::TimerOverflow);
+        scheduler.cancel(Event::FrameEnd);
+        assert!(!scheduler.is_scheduled(Event::FrameEnd));
+        assert!(scheduler.is_scheduled(Event::TimerOverflow));
+        assert!(scheduler.advance(100));
+        assert_eq!(scheduler.pop(), Some((14, Event::TimerOverflow)));
+        assert_eq!(scheduler.pop(), None);
+    }
+}
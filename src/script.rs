@@ -0,0 +1,514 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fs,
+    ptr::NonNull,
+    rc::Rc,
+};
+
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, AST};
+
+use crate::{
+    coverage::Access,
+    cpu::Cpu,
+    expr::{self, Register},
+    gameboy::{render_screen, SCREEN_HEIGHT, SCREEN_WIDTH},
+    io::Buttons,
+    state,
+    symbols::SymbolTable,
+};
+
+/// The CPU, while script code is running. Scripts only run from `Script`'s methods, which point
+/// this at the CPU they were given for the duration and clear it afterwards.
+type CpuSlot = Rc<Cell<Option<NonNull<Cpu>>>>;
+
+/// Gray levels for shades 0-3 in screenshots
+const SCREENSHOT_SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Text a script drew over the screen, in screen pixels
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Text {
+    pub x: i64,
+    pub y: i64,
+    pub text: String,
+}
+
+/// Callbacks a script registered
+#[derive(Default)]
+struct Hooks {
+    frame: Vec<FnPtr>,
+    exec: HashMap<u16, Vec<FnPtr>>,
+    read: HashMap<u16, Vec<FnPtr>>,
+    write: HashMap<u16, Vec<FnPtr>>,
+}
+
+/// What a script has to show for itself
+#[derive(Default)]
+struct Output {
+    /// Printed lines and errors since `take_output`
+    lines: Vec<String>,
+    /// Redrawn every frame
+    overlay: Vec<Text>,
+    stop: bool,
+}
+
+/// A Rhai script driving the emulator through callbacks
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    hooks: Rc<RefCell<Hooks>>,
+    output: Rc<RefCell<Output>>,
+    cpu: CpuSlot,
+}
+
+impl Script {
+    /// Compiles `source` and runs its top level, which is where it registers callbacks
+    pub fn new(source: &str, symbols: Rc<SymbolTable>, cpu: &mut Cpu) -> Result<Self, String> {
+        let hooks = Rc::new(RefCell::new(Hooks::default()));
+        let output = Rc::new(RefCell::new(Output::default()));
+        let slot = CpuSlot::default();
+
+        let mut engine = Engine::new();
+        register(&mut engine, &hooks, &output, &slot, symbols);
+
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+
+        let script = Self {
+            engine,
+            ast,
+            hooks,
+            output,
+            cpu: slot,
+        };
+
+        script.with_cpu(cpu, |script| {
+            script
+                .engine
+                .run_ast(&script.ast)
+                .map_err(|e| e.to_string())
+        })?;
+
+        Ok(script)
+    }
+
+    /// Lines printed since this was last called, along with any errors from callbacks
+    pub fn take_output(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.output.borrow_mut().lines)
+    }
+
+    /// Text to draw over the current frame
+    pub fn overlay(&self) -> Vec<Text> {
+        self.output.borrow().overlay.clone()
+    }
+
+    /// Whether the script called `stop()` since this was last called
+    pub fn take_stop(&mut self) -> bool {
+        std::mem::take(&mut self.output.borrow_mut().stop)
+    }
+
+    /// Runs the callbacks for executing the instruction at PC, which is about to happen
+    pub fn before_step(&mut self, cpu: &mut Cpu) {
+        let callbacks = match self.hooks.borrow().exec.get(&cpu.pc()) {
+            Some(callbacks) => callbacks.clone(),
+            None => return,
+        };
+        let addr = cpu.pc() as i64;
+
+        self.with_cpu(cpu, |script| {
+            for callback in &callbacks {
+                script.call(callback, (addr,));
+            }
+        });
+    }
+
+    /// Runs the callbacks for the memory the last instruction touched, and the frame callbacks
+    /// if it ended a frame
+    pub fn after_step(&mut self, cpu: &mut Cpu, frame_ended: bool) {
+        let hits = cpu.mmu_mut().take_tap_hits();
+        if hits.is_empty() && !frame_ended {
+            return;
+        }
+
+        // Whatever's there after the instruction, which is what a write wrote
+        let hits: Vec<_> = hits
+            .into_iter()
+            .map(|(access, addr)| (access, addr, cpu.mmu().rb(addr as usize)))
+            .collect();
+        let frame = cpu.mmu().frame() as i64;
+
+        self.with_cpu(cpu, |script| {
+            for (access, addr, value) in hits {
+                let hooks = script.hooks.borrow();
+                let callbacks = match access {
+                    Access::Read => hooks.read.get(&addr),
+                    _ => hooks.write.get(&addr),
+                };
+                let callbacks = callbacks.cloned().unwrap_or_default();
+                drop(hooks);
+
+                for callback in &callbacks {
+                    script.call(callback, (addr as i64, value as i64));
+                }
+            }
+
+            if frame_ended {
+                script.output.borrow_mut().overlay.clear();
+
+                let callbacks = script.hooks.borrow().frame.clone();
+                for callback in &callbacks {
+                    script.call(callback, (frame,));
+                }
+            }
+        });
+    }
+
+    /// Calls a callback, reporting rather than propagating errors so one broken callback doesn't
+    /// stop the emulator
+    fn call(&self, callback: &FnPtr, args: impl rhai::FuncArgs) {
+        if let Err(e) = callback.call::<Dynamic>(&self.engine, &self.ast, args) {
+            log::warn!("script error in {}: {}", callback.fn_name(), e);
+            self.output
+                .borrow_mut()
+                .lines
+                .push(format!("error in {}: {}", callback.fn_name(), e));
+        }
+    }
+
+    /// Lends `cpu` to the script while `f` runs
+    fn with_cpu<T>(&self, cpu: &mut Cpu, f: impl FnOnce(&Self) -> T) -> T {
+        /// Takes the CPU back when dropped, even if `f` panics
+        struct Lent<'a>(&'a CpuSlot);
+
+        impl Drop for Lent<'_> {
+            fn drop(&mut self) {
+                self.0.set(None);
+            }
+        }
+
+        self.cpu.set(Some(NonNull::from(cpu)));
+        let _lent = Lent(&self.cpu);
+
+        f(self)
+    }
+}
+
+/// Runs `f` on the lent CPU
+fn on_cpu<T>(slot: &CpuSlot, f: impl FnOnce(&mut Cpu) -> T) -> ScriptResult<T> {
+    match slot.get() {
+        // SAFETY: the slot is only set inside `with_cpu`, which holds the CPU's `&mut` and
+        // doesn't touch it until the slot's cleared. `f` never calls back into the script, so
+        // this is the only reference to the CPU while it runs.
+        Some(mut cpu) => Ok(f(unsafe { cpu.as_mut() })),
+        None => Err("the emulator isn't available here".into()),
+    }
+}
+
+fn register_by_name(name: &str) -> ScriptResult<Register> {
+    Register::from_name(name).ok_or_else(|| format!("unknown register {}", name).into())
+}
+
+fn button_by_name(name: &str) -> ScriptResult<Buttons> {
+    Buttons::from_name(name).ok_or_else(|| format!("unknown button {}", name).into())
+}
+
+fn address(value: i64) -> ScriptResult<u16> {
+    expr::address(value).map_err(|e| e.into())
+}
+
+/// Registers the functions scripts can call
+fn register(
+    engine: &mut Engine,
+    hooks: &Rc<RefCell<Hooks>>,
+    output: &Rc<RefCell<Output>>,
+    slot: &CpuSlot,
+    symbols: Rc<SymbolTable>,
+) {
+    let lines = output.clone();
+    engine.on_print(move |text| {
+        log::debug!("script: {}", text);
+        lines.borrow_mut().lines.push(text.to_string());
+    });
+
+    // Callbacks
+
+    let frame_hooks = hooks.clone();
+    engine.register_fn("on_frame", move |callback: FnPtr| {
+        frame_hooks.borrow_mut().frame.push(callback);
+    });
+
+    let exec_hooks = hooks.clone();
+    engine.register_fn(
+        "on_exec",
+        move |addr: i64, callback: FnPtr| -> ScriptResult<()> {
+            let addr = address(addr)?;
+            exec_hooks
+                .borrow_mut()
+                .exec
+                .entry(addr)
+                .or_default()
+                .push(callback);
+            Ok(())
+        },
+    );
+
+    for access in [Access::Read, Access::Write] {
+        let name = match access {
+            Access::Read => "on_read",
+            _ => "on_write",
+        };
+        let (hooks, slot) = (hooks.clone(), slot.clone());
+        engine.register_fn(
+            name,
+            move |addr: i64, callback: FnPtr| -> ScriptResult<()> {
+                let addr = address(addr)?;
+                on_cpu(&slot, |cpu| cpu.mmu_mut().add_tap(access, addr))?;
+
+                let mut hooks = hooks.borrow_mut();
+                let callbacks = match access {
+                    Access::Read => &mut hooks.read,
+                    _ => &mut hooks.write,
+                };
+                callbacks.entry(addr).or_default().push(callback);
+                Ok(())
+            },
+        );
+    }
+
+    // Registers and memory
+
+    let cpu = slot.clone();
+    engine.register_fn("reg", move |name: &str| -> ScriptResult<i64> {
+        let reg = register_by_name(name)?;
+        on_cpu(&cpu, |cpu| reg.read(cpu) as i64)
+    });
+
+    let cpu = slot.clone();
+    engine.register_fn(
+        "set_reg",
+        move |name: &str, value: i64| -> ScriptResult<()> {
+            let reg = register_by_name(name)?;
+            on_cpu(&cpu, |cpu| reg.write(cpu, value as u16))
+        },
+    );
+
+    let cpu = slot.clone();
+    engine.register_fn("peek", move |addr: i64| -> ScriptResult<i64> {
+        let addr = address(addr)?;
+        on_cpu(&cpu, |cpu| cpu.mmu().rb(addr as usize) as i64)
+    });
+
+    let cpu = slot.clone();
+    engine.register_fn("poke", move |addr: i64, value: i64| -> ScriptResult<()> {
+        let addr = address(addr)?;
+        on_cpu(&cpu, |cpu| cpu.mmu_mut().wb(addr as usize, value as u8))
+    });
+
+    engine.register_fn("sym", move |name: &str| -> ScriptResult<i64> {
+        match symbols.address(name) {
+            Some((_, addr)) => Ok(addr as i64),
+            None => Err(format!("unknown symbol {}", name).into()),
+        }
+    });
+
+    let cpu = slot.clone();
+    engine.register_fn("cycles", move || -> ScriptResult<i64> {
+        on_cpu(&cpu, |cpu| cpu.cycles() as i64)
+    });
+
+    let cpu = slot.clone();
+    engine.register_fn("frame", move || -> ScriptResult<i64> {
+        on_cpu(&cpu, |cpu| cpu.mmu().frame() as i64)
+    });
+
+    // Joypad
+
+    let cpu = slot.clone();
+    engine.register_fn("press", move |name: &str| -> ScriptResult<()> {
+        let button = button_by_name(name)?;
+        on_cpu(&cpu, |cpu| {
+            let buttons = cpu.mmu().buttons() | button;
+            cpu.mmu_mut().set_buttons(buttons)
+        })
+    });
+
+    let cpu = slot.clone();
+    engine.register_fn("release", move |name: &str| -> ScriptResult<()> {
+        let button = button_by_name(name)?;
+        on_cpu(&cpu, |cpu| {
+            let buttons = cpu.mmu().buttons() - button;
+            cpu.mmu_mut().set_buttons(buttons)
+        })
+    });
+
+    // Output
+
+    let overlay = output.clone();
+    engine.register_fn("text", move |x: i64, y: i64, text: &str| {
+        overlay.borrow_mut().overlay.push(Text {
+            x,
+            y,
+            text: text.to_string(),
+        });
+    });
+
+    let stop = output.clone();
+    engine.register_fn("stop", move || {
+        stop.borrow_mut().stop = true;
+    });
+
+    let cpu = slot.clone();
+    engine.register_fn("save_state", move |path: &str| -> ScriptResult<()> {
+        let state = on_cpu(&cpu, |cpu| state::save(cpu))?;
+        fs::write(path, state).map_err(|e| format!("couldn't save {}: {}", path, e).into())
+    });
+
+    let cpu = slot.clone();
+    engine.register_fn("load_state", move |path: &str| -> ScriptResult<()> {
+        let state = fs::read(path).map_err(|e| format!("couldn't load {}: {}", path, e))?;
+        on_cpu(&cpu, |cpu| state::load(cpu, &state))?.map_err(|e| e.to_string().into())
+    });
+
+    let cpu = slot.clone();
+    engine.register_fn("screenshot", move |path: &str| -> ScriptResult<()> {
+        let mut framebuffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        on_cpu(&cpu, |cpu| render_screen(cpu.mmu(), &mut framebuffer))?;
+
+        let pixels = framebuffer
+            .iter()
+            .map(|&shade| SCREENSHOT_SHADES[shade as usize])
+            .collect();
+        let image =
+            image::GrayImage::from_raw(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, pixels).unwrap();
+        image
+            .save(path)
+            .map_err(|e| format!("couldn't save {}: {}", path, e).into())
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::{Script, Text};
+    use crate::{asm, cpu::Cpu, io::Buttons, mmu::CYCLES_PER_FRAME, symbols::SymbolTable};
+
+    fn cpu(source: &str) -> Cpu {
+        Cpu::new(asm::assemble(source).unwrap())
+    }
+
+    fn script(source: &str, cpu: &mut Cpu) -> Script {
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0, 0xC000, "Counter");
+
+        Script::new(source, Rc::new(symbols), cpu).unwrap()
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut cpu = cpu("ld a, 1");
+        let mut script = script(
+            r#"
+            set_reg("bc", 0x1234);
+            poke(sym("Counter"), peek(0x0100) + 1);
+            print(reg("b"));
+            "#,
+            &mut cpu,
+        );
+
+        assert_eq!(cpu.reg().bc(), 0x1234);
+        assert_eq!(cpu.mmu().rb(0xC000), 0x3F);
+        assert_eq!(script.take_output(), vec!["18"]);
+        assert!(Script::new("reg(\"q\")", Rc::new(SymbolTable::new()), &mut cpu).is_err());
+    }
+
+    #[test]
+    fn hooks() {
+        let mut cpu = cpu("
+            ld hl, $C000
+            ld a, [hl]
+            inc a
+            ld [hl], a
+            ");
+        let script = script(
+            r#"
+            on_exec(0x0103, |addr| print(`exec ${addr}`));
+            on_read(0xC000, |addr, value| print(`read ${value}`));
+            on_write(0xC000, |addr, value| {
+                print(`write ${value}`);
+                press("start");
+                stop();
+            });
+            "#,
+            &mut cpu,
+        );
+        cpu.mmu_mut().wb(0xC000, 4);
+        cpu.set_script(Some(script));
+
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+
+        let script = cpu.script_mut().unwrap();
+        assert_eq!(script.take_output(), vec!["exec 259", "read 4", "write 5"]);
+        assert!(script.take_stop());
+        assert!(!script.take_stop());
+        assert_eq!(cpu.mmu().buttons(), Buttons::START);
+    }
+
+    #[test]
+    fn screenshot() {
+        let path = std::env::temp_dir().join("yeahboy-script-screenshot.png");
+        let mut cpu = cpu("nop");
+        script(
+            &format!(
+                r#"
+                poke(0xFF47, 0xE4);
+                poke(0x8000, 0xFF);
+                screenshot("{}");
+                "#,
+                path.display()
+            ),
+            &mut cpu,
+        );
+
+        // Tile 0's top row is color 1 and the rest of it color 0
+        let image = image::open(&path).unwrap().to_luma8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image.dimensions(), (160, 144));
+        assert_eq!(image.get_pixel(3, 0).0, [0xAA]);
+        assert_eq!(image.get_pixel(3, 1).0, [0xFF]);
+    }
+
+    #[test]
+    fn frames() {
+        let mut cpu = cpu("nop");
+        let mut script = script(
+            r#"
+            on_frame(|frame| text(8, 16, `frame ${frame}`));
+            text(0, 0, "before");
+            "#,
+            &mut cpu,
+        );
+
+        script.after_step(&mut cpu, false);
+        assert_eq!(script.overlay()[0].text, "before");
+
+        // The padding is all NOPs, so this runs into the next frame
+        cpu.set_script(Some(script));
+        while cpu.cycles() < CYCLES_PER_FRAME {
+            cpu.step().unwrap();
+        }
+
+        let script = cpu.script_mut().unwrap();
+        assert_eq!(
+            script.overlay(),
+            vec![Text {
+                x: 8,
+                y: 16,
+                text: "frame 1".to_string()
+            }]
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{
+    error::EmulatorError,
+    io::Buttons,
+    state::{Reader, Writer},
+};
+
+/// Bytes in a packet, and the bits in them before the stop bit
+const PACKET_LEN: usize = 16;
+const PACKET_LEN_BITS: usize = PACKET_LEN * 8;
+
+/// MLT_REQ, which turns on reading more than one joypad
+const MLT_REQ: u8 = 0x11;
+
+/// The Super Game Boy's side of P1. Games send it packets by pulsing P14 and P15, and read
+/// more than one joypad through it once MLT_REQ has asked for them.
+///
+/// MLT_REQ is the only command that does anything. The rest, including the ones for palettes,
+/// attributes, borders and masking the screen, are logged and dropped. The screen is drawn in
+/// DMG shades without a border, so there's nothing for them to change yet.
+// TODO: the palette, attribute, border and MASK_EN commands, once there's a PPU to show them
+#[derive(Debug, Default)]
+pub struct Sgb {
+    /// The packet coming in and how many of its bits are in, or `None` between packets
+    packet: Option<([u8; PACKET_LEN], usize)>,
+
+    /// Whether P14 and P15 were last both released. A pulse only counts after one of these.
+    released: bool,
+
+    /// Packets of a command that's still coming in
+    command: Vec<[u8; PACKET_LEN]>,
+
+    /// Joypads being read: 1, 2 or 4
+    players: u8,
+
+    /// The joypad P1 reads, counting from 0
+    player: u8,
+
+    /// Whether P15 was pulled low since the player last changed. Releasing both lines after
+    /// that moves on to the next player.
+    next_player: bool,
+}
+
+impl Sgb {
+    pub fn new() -> Self {
+        Self {
+            players: 1,
+            ..Self::default()
+        }
+    }
+
+    /// The joypad P1 reads, counting from 0
+    pub fn player(&self) -> u8 {
+        self.player
+    }
+
+    /// What P1 reads with `select` written to bits 4-5. Only the first joypad has `buttons`
+    /// held, and with both lines released the low nibble says which joypad is next.
+    pub fn p1(&self, buttons: Buttons, select: u8) -> u8 {
+        match (self.player, select & 0x30) {
+            (player, 0x30) => 0xF0 | (0x0F - player),
+            (0, _) => buttons.p1(select),
+            _ => Buttons::empty().p1(select),
+        }
+    }
+
+    /// Takes `select` being written to P1's bits 4-5. Pulling both lines low starts a packet,
+    /// and after that P14 low sends a 0 bit and P15 low a 1, each followed by releasing both.
+    pub fn write_p1(&mut self, select: u8) {
+        match select & 0x30 {
+            0x00 => {
+                self.packet = Some(([0; PACKET_LEN], 0));
+                self.released = false;
+            }
+            0x30 => {
+                if !self.released && self.next_player {
+                    self.player = (self.player + 1) % self.players;
+                    self.next_player = false;
+                }
+                self.released = true;
+            }
+            pulse => {
+                let bit = pulse == 0x10;
+                self.next_player |= bit;
+                if !std::mem::take(&mut self.released) {
+                    return;
+                }
+
+                match &mut self.packet {
+                    // The stop bit after the last byte has to be 0
+                    Some((data, PACKET_LEN_BITS)) => {
+                        let data = *data;
+                        self.packet = None;
+                        if !bit {
+                            self.receive(data);
+                        }
+                    }
+                    Some((data, bits)) => {
+                        data[*bits / 8] |= (bit as u8) << (*bits % 8);
+                        *bits += 1;
+                    }
+                    None => (),
+                }
+            }
+        }
+    }
+
+    /// Takes a whole packet. The first packet of a command says how many it has.
+    fn receive(&mut self, packet: [u8; PACKET_LEN]) {
+        self.command.push(packet);
+
+        let len = (self.command[0][0] & 0x07).max(1) as usize;
+        if self.command.len() < len {
+            return;
+        }
+
+        let command = std::mem::take(&mut self.command);
+        match command[0][0] >> 3 {
+            MLT_REQ => {
+                self.players = match command[0][1] & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+                self.next_player = false;
+            }
+            id => log::debug!("ignoring SGB command {} ({:02x})", command_name(id), id),
+        }
+    }
+
+    /// Writes the joypads being read for a save state. A packet halfway in isn't kept.
+    pub fn save_state(&self, writer: &mut Writer) {
+        writer.u8(self.players);
+        writer.u8(self.player);
+    }
+
+    /// Reads back what `save_state` wrote
+    pub fn load_state(reader: &mut Reader) -> Result<Sgb, EmulatorError> {
+        let players = reader.u8()?;
+        let player = reader.u8()?;
+        if !matches!(players, 1 | 2 | 4) || player >= players {
+            return Err(EmulatorError::InvalidState(format!(
+                "it has joypad {} of {}",
+                player, players
+            )));
+        }
+
+        Ok(Self {
+            players,
+            player,
+            ..Self::new()
+        })
+    }
+}
+
+/// The name of SGB command `id`, as the SGB manual has it
+pub fn command_name(id: u8) -> &'static str {
+    const NAMES: [&str; 0x1A] = [
+        "PAL01", "PAL23", "PAL03", "PAL12", "ATTR_BLK", "ATTR_LIN", "ATTR_DIV", "ATTR_CHR",
+        "SOUND", "SOU_TRN", "PAL_SET", "PAL_TRN", "ATRC_EN", "TEST_EN", "ICON_EN", "DATA_SND",
+        "DATA_TRN", "MLT_REQ", "JUMP", "CHR_TRN", "PCT_TRN", "ATTR_TRN", "ATTR_SET", "MASK_EN",
+        "OBJ_TRN", "PAL_PRI",
+    ];
+
+    NAMES.get(id as usize).copied().unwrap_or("unknown")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{command_name, Sgb};
+    use crate::io::Buttons;
+
+    /// Sends `packet` the way games do, with a reset pulse first and a stop bit last
+    fn send(sgb: &mut Sgb, packet: [u8; 16]) {
+        sgb.write_p1(0x00);
+        sgb.write_p1(0x30);
+        for i in 0..128 {
+            let bit = packet[i / 8] >> (i % 8) & 1 != 0;
+            sgb.write_p1(if bit { 0x10 } else { 0x20 });
+            sgb.write_p1(0x30);
+        }
+        sgb.write_p1(0x20);
+        sgb.write_p1(0x30);
+    }
+
+    #[test]
+    fn multiple_joypads() {
+        let mut sgb = Sgb::new();
+        assert_eq!(sgb.p1(Buttons::A, 0x30), 0xFF);
+
+        let mut packet = [0; 16];
+        packet[0] = 0x11 << 3 | 1;
+        packet[1] = 0x01;
+        send(&mut sgb, packet);
+        assert_eq!(sgb.player(), 0);
+
+        // Reading the buttons then releasing both lines moves on to the next joypad, which
+        // has nothing held
+        assert_eq!(sgb.p1(Buttons::A, 0x10), 0xDE);
+        sgb.write_p1(0x20);
+        sgb.write_p1(0x10);
+        sgb.write_p1(0x30);
+        assert_eq!(sgb.p1(Buttons::A, 0x30), 0xFE);
+        assert_eq!(sgb.p1(Buttons::A, 0x10), 0xDF);
+
+        sgb.write_p1(0x10);
+        sgb.write_p1(0x30);
+        assert_eq!(sgb.player(), 0);
+
+        // Back to one
+        packet[1] = 0x00;
+        send(&mut sgb, packet);
+        assert_eq!(sgb.p1(Buttons::A, 0x30), 0xFF);
+    }
+
+    #[test]
+    fn command_names() {
+        assert_eq!(command_name(0x00), "PAL01");
+        assert_eq!(command_name(0x11), "MLT_REQ");
+        assert_eq!(command_name(0x17), "MASK_EN");
+        assert_eq!(command_name(0x19), "PAL_PRI");
+        assert_eq!(command_name(0x1A), "unknown");
+    }
+}
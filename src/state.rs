@@ -0,0 +1,119 @@
+use crate::{cpu::Cpu, error::EmulatorError};
+
+/// Start of every save state
+const MAGIC: &[u8; 4] = b"YBST";
+
+/// Bumped whenever the layout changes, as old states can't be read
+const VERSION: u8 = 11;
+
+/// Serializes a save state, little endian
+#[derive(Default)]
+pub struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    /// Writes the length first, so `Reader::bytes` can check it
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+/// Reads back what a `Writer` wrote, in the same order
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], EmulatorError> {
+        if self.data.len() < len {
+            return Err(invalid("it's truncated".to_string()));
+        }
+
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, EmulatorError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, EmulatorError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, EmulatorError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, EmulatorError> {
+        Ok(self.u8()? != 0)
+    }
+
+    /// Reads bytes written by `Writer::bytes`, which must be `len` of them
+    pub fn bytes(&mut self, len: usize) -> Result<Vec<u8>, EmulatorError> {
+        let written = self.u64()?;
+        if written != len as u64 {
+            return Err(invalid(format!(
+                "it has {} bytes where {} were expected",
+                written, len
+            )));
+        }
+
+        Ok(self.take(len)?.to_vec())
+    }
+}
+
+fn invalid(message: String) -> EmulatorError {
+    EmulatorError::InvalidState(message)
+}
+
+/// Snapshots everything `cpu` needs to carry on from where it is. The cartridge ROM isn't
+/// included, so the state can only be loaded with the same ROM.
+pub fn save(cpu: &Cpu) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.data.extend_from_slice(MAGIC);
+    writer.u8(VERSION);
+    cpu.save_state(&mut writer);
+
+    writer.data
+}
+
+/// Restores a snapshot taken by `save`. Nothing changes if it can't be read.
+pub fn load(cpu: &mut Cpu, data: &[u8]) -> Result<(), EmulatorError> {
+    let mut reader = Reader { data };
+
+    if reader.take(MAGIC.len()) != Ok(MAGIC) {
+        return Err(invalid(
+            "it doesn't start with the magic number".to_string(),
+        ));
+    }
+
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(invalid(format!(
+            "it's version {}, but only version {} can be loaded",
+            version, VERSION
+        )));
+    }
+
+    cpu.load_state(&mut reader)
+}
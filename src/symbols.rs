@@ -0,0 +1,237 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+/// An address the user asked for, and its bank if they gave one by label or as `bank:addr`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Target {
+    // Ordered by address first, so sorted targets read in address order
+    pub addr: u16,
+    pub bank: Option<u16>,
+}
+
+impl Target {
+    /// Whether this is `addr` with `bank` mapped there. Targets without a bank match any bank.
+    pub fn matches(&self, bank: u16, addr: u16) -> bool {
+        self.addr == addr && self.bank.is_none_or(|own| own == region_bank(addr, bank))
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02x}:{:04x}", bank, self.addr),
+            None => write!(f, "{:04x}", self.addr),
+        }
+    }
+}
+
+/// The bank labels at `addr` are filed under, given the bank mapped there. Only ROMX, VRAM,
+/// SRAM and WRAMX are banked, so everywhere else it's 0.
+fn region_bank(addr: u16, bank: u16) -> u16 {
+    match addr {
+        0x4000..=0xBFFF | 0xD000..=0xDFFF => bank,
+        _ => 0,
+    }
+}
+
+/// Labels loaded from an RGBDS or no$gmb symbol file, made up of `bank:addr label` lines
+#[derive(Default)]
+pub struct SymbolTable {
+    labels: BTreeMap<(u16, u16), Vec<String>>,
+    addresses: HashMap<String, (u16, u16)>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut table = Self::new();
+
+        for (number, line) in text.lines().enumerate() {
+            // Everything after a semicolon is a comment
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let location = parts.next().and_then(|location| {
+                let (bank, addr) = location.split_once(':')?;
+                Some((
+                    u16::from_str_radix(bank, 16).ok()?,
+                    u16::from_str_radix(addr, 16).ok()?,
+                ))
+            });
+
+            match (location, parts.next()) {
+                (Some((bank, addr)), Some(label)) => table.insert(bank, addr, label),
+                _ => log::warn!("ignoring malformed symbol on line {}: {}", number + 1, line),
+            }
+        }
+
+        table
+    }
+
+    pub fn insert(&mut self, bank: u16, addr: u16, label: &str) {
+        self.labels
+            .entry((bank, addr))
+            .or_default()
+            .push(label.to_string());
+        self.addresses.insert(label.to_string(), (bank, addr));
+    }
+
+    /// All labels at `addr`, where `bank` is the bank mapped there, as `Mmu::bank_at` gives it
+    pub fn labels(&self, addr: u16, bank: u16) -> &[String] {
+        self.labels
+            .get(&(region_bank(addr, bank), addr))
+            .map(|labels| labels.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// The first label at `addr`, if any
+    pub fn label(&self, addr: u16, bank: u16) -> Option<&str> {
+        self.labels(addr, bank).first().map(|label| label.as_str())
+    }
+
+    /// The nearest global label at or before `addr`, which is what `.local` labels written there
+    /// belong to. `bank` is the bank mapped at `addr`.
+    pub fn scope(&self, addr: u16, bank: u16) -> Option<&str> {
+        let bank = region_bank(addr, bank);
+
+        self.labels
+            .range((bank, 0)..=(bank, addr))
+            .rev()
+            .flat_map(|(_, labels)| labels.iter().rev())
+            .find(|label| !label.contains('.'))
+            .map(|label| label.as_str())
+    }
+
+    /// The bank and address of `label`
+    pub fn address(&self, label: &str) -> Option<(u16, u16)> {
+        self.addresses.get(label).copied()
+    }
+
+    /// Every label, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.addresses.keys().map(|label| label.as_str())
+    }
+
+    /// Resolves user input to an address. Accepts label names, which carry their bank, as well
+    /// as hex addresses written as `$0150`, `0x0150`, `0150` or `02:4000` with a bank.
+    pub fn resolve(&self, text: &str) -> Option<Target> {
+        let text = text.trim();
+
+        if let Some((bank, addr)) = self.address(text) {
+            return Some(Target {
+                bank: Some(bank),
+                addr,
+            });
+        }
+
+        let (bank, text) = match text.split_once(':') {
+            Some((bank, addr)) => (Some(u16::from_str_radix(bank, 16).ok()?), addr),
+            None => (None, text),
+        };
+        let digits = text
+            .strip_prefix('$')
+            .or_else(|| text.strip_prefix("0x"))
+            .unwrap_or(text);
+
+        Some(Target {
+            bank,
+            addr: u16::from_str_radix(digits, 16).ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SymbolTable, Target};
+
+    const SYM_FILE: &str = "; File generated by rgblink
+00:0000 RST_00
+00:0150 Main
+00:0150 Main.start
+00:0158 Main.loop
+02:4000 LoadTiles
+01:4000 Bank1Start
+00:C000 wFrameCounter
+01:D000 wBuffer
+00:A000 sSaveData
+this line is garbage
+";
+
+    #[test]
+    fn parse_sym_file() {
+        let symbols = SymbolTable::parse(SYM_FILE);
+
+        assert_eq!(symbols.labels(0x0150, 1), ["Main", "Main.start"]);
+        assert_eq!(symbols.label(0x0158, 1), Some("Main.loop"));
+        assert_eq!(symbols.label(0xC000, 1), Some("wFrameCounter"));
+        assert_eq!(symbols.label(0x0151, 1), None);
+    }
+
+    #[test]
+    fn banked_lookup() {
+        let symbols = SymbolTable::parse(SYM_FILE);
+
+        assert_eq!(symbols.label(0x4000, 1), Some("Bank1Start"));
+        assert_eq!(symbols.label(0x4000, 2), Some("LoadTiles"));
+        assert_eq!(symbols.address("LoadTiles"), Some((2, 0x4000)));
+    }
+
+    #[test]
+    fn banked_ram_lookup() {
+        let symbols = SymbolTable::parse(SYM_FILE);
+
+        assert_eq!(symbols.label(0xD000, 1), Some("wBuffer"));
+        assert_eq!(symbols.label(0xA000, 0), Some("sSaveData"));
+        assert_eq!(symbols.label(0xA000, 1), None);
+        // Unbanked regions ignore the bank they're given
+        assert_eq!(symbols.label(0xC000, 1), Some("wFrameCounter"));
+    }
+
+    #[test]
+    fn scopes() {
+        let symbols = SymbolTable::parse(SYM_FILE);
+
+        assert_eq!(symbols.scope(0x0158, 1), Some("Main"));
+        assert_eq!(symbols.scope(0x0149, 1), Some("RST_00"));
+        assert_eq!(symbols.scope(0x4010, 2), Some("LoadTiles"));
+        assert_eq!(symbols.scope(0x4010, 3), None);
+    }
+
+    #[test]
+    fn resolve_labels_and_addresses() {
+        let symbols = SymbolTable::parse(SYM_FILE);
+
+        let target = |bank, addr| Some(Target { bank, addr });
+
+        assert_eq!(symbols.resolve("Main.loop"), target(Some(0), 0x0158));
+        assert_eq!(symbols.resolve("LoadTiles"), target(Some(2), 0x4000));
+        assert_eq!(symbols.resolve("$0150"), target(None, 0x0150));
+        assert_eq!(symbols.resolve("0xC000"), target(None, 0xC000));
+        assert_eq!(symbols.resolve("ff80"), target(None, 0xFF80));
+        assert_eq!(symbols.resolve("03:$4100"), target(Some(3), 0x4100));
+        assert_eq!(symbols.resolve("NotALabel"), None);
+        assert_eq!(symbols.resolve("zz:4000"), None);
+    }
+
+    #[test]
+    fn target_matching() {
+        let symbols = SymbolTable::parse(SYM_FILE);
+        let load_tiles = symbols.resolve("LoadTiles").unwrap();
+
+        assert!(load_tiles.matches(2, 0x4000));
+        assert!(!load_tiles.matches(1, 0x4000));
+        assert!(symbols.resolve("4000").unwrap().matches(1, 0x4000));
+        assert!(symbols.resolve("Main").unwrap().matches(1, 0x0150));
+        assert_eq!(load_tiles.to_string(), "02:4000");
+    }
+}
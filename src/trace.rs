@@ -0,0 +1,212 @@
+use std::io::Write;
+
+use crate::cpu::Cpu;
+
+/// Limits which steps get traced. Both ranges are inclusive.
+#[derive(Default, Clone, Copy)]
+pub struct TraceFilter {
+    /// Only trace instructions at these addresses
+    pub pc: Option<(u16, u16)>,
+    /// Only trace instructions starting within this many cycles since power on
+    pub cycles: Option<(u64, u64)>,
+}
+
+impl TraceFilter {
+    fn matches(&self, cpu: &Cpu) -> bool {
+        let pc_matches = match self.pc {
+            Some((start, end)) => (start..=end).contains(&cpu.pc()),
+            None => true,
+        };
+        let cycles_match = match self.cycles {
+            Some((start, end)) => (start..=end).contains(&cpu.cycles()),
+            None => true,
+        };
+
+        pc_matches && cycles_match
+    }
+}
+
+/// Writes the CPU state before every step in the gameboy-doctor format
+pub struct Tracer {
+    out: Box<dyn Write>,
+    filter: TraceFilter,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>, filter: TraceFilter) -> Self {
+        Self { out, filter }
+    }
+
+    /// The line for the instruction the CPU is about to execute, if it passes the filter
+    pub fn line(&self, cpu: &Cpu) -> Option<String> {
+        self.filter.matches(cpu).then(|| line(cpu))
+    }
+
+    /// Writes a line from `line` once its instruction has run. Returns false once writing fails.
+    pub fn write(&mut self, line: &str) -> bool {
+        match writeln!(self.out, "{}", line) {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("stopping trace: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// One trace line, e.g.
+/// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+pub fn line(cpu: &Cpu) -> String {
+    let reg = cpu.reg();
+    let pc = cpu.pc();
+    let pcmem: Vec<_> = (0..4)
+        .map(|i| format!("{:02X}", cpu.mmu().rb(pc.wrapping_add(i) as usize)))
+        .collect();
+
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{}",
+        reg.a(),
+        u8::from(reg.f()),
+        reg.b(),
+        reg.c(),
+        reg.d(),
+        reg.e(),
+        reg.h(),
+        reg.l(),
+        cpu.sp(),
+        pc,
+        pcmem.join(",")
+    )
+}
+
+/// Parses an inclusive range like `0150-01FF` (hex) or `1000-2000` (decimal)
+pub fn parse_range<T: TryFrom<u64>>(text: &str, radix: u32) -> Result<(T, T), String> {
+    let (start, end) = text
+        .split_once('-')
+        .ok_or_else(|| format!("expected START-END, got {}", text))?;
+
+    let parse = |part: &str| {
+        u64::from_str_radix(part.trim().trim_start_matches("0x"), radix)
+            .ok()
+            .and_then(|value| T::try_from(value).ok())
+            .ok_or_else(|| format!("invalid bound {}", part))
+    };
+
+    Ok((parse(start)?, parse(end)?))
+}
+
+/// Where two traces first disagree
+#[derive(Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// 1-based line number
+    pub line: usize,
+    pub ours: Option<String>,
+    pub reference: Option<String>,
+    /// Fields that differ, e.g. `A` or `PCMEM`
+    pub fields: Vec<String>,
+}
+
+/// Finds the first line where `ours` and `reference` differ, ignoring blank lines at the end
+pub fn diff(ours: &str, reference: &str) -> Option<Divergence> {
+    let mut ours_lines = ours.lines().map(str::trim).filter(|line| !line.is_empty());
+    let mut reference_lines = reference
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+
+    let mut line = 0;
+
+    loop {
+        line += 1;
+
+        match (ours_lines.next(), reference_lines.next()) {
+            (None, None) => return None,
+            (Some(a), Some(b)) if a == b => continue,
+            (a, b) => {
+                return Some(Divergence {
+                    line,
+                    ours: a.map(str::to_string),
+                    reference: b.map(str::to_string),
+                    fields: differing_fields(a.unwrap_or(""), b.unwrap_or("")),
+                })
+            }
+        }
+    }
+}
+
+fn differing_fields(a: &str, b: &str) -> Vec<String> {
+    let fields = |line: &str| -> Vec<(String, String)> {
+        line.split_whitespace()
+            .filter_map(|field| field.split_once(':'))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    };
+    let (a, b) = (fields(a), fields(b));
+
+    let keys: Vec<String> = a
+        .iter()
+        .chain(b.iter())
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    let value = |fields: &[(String, String)], key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    };
+
+    let mut differing = Vec::new();
+    for key in keys {
+        if value(&a, &key) != value(&b, &key) && !differing.contains(&key) {
+            differing.push(key);
+        }
+    }
+
+    differing
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diff, line, parse_range};
+    use crate::cpu::Cpu;
+
+    #[test]
+    fn initial_state() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0101..0x0104].copy_from_slice(&[0xC3, 0x13, 0x02]);
+        // Any real header checksum, so the boot ROM sets H and C
+        rom[0x014D] = 0xE7;
+        let cpu = Cpu::new(rom);
+
+        assert_eq!(
+            line(&cpu),
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02"
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range::<u16>("0150-01ff", 16), Ok((0x150, 0x1FF)));
+        assert_eq!(parse_range::<u64>("100-2000", 10), Ok((100, 2000)));
+        assert!(parse_range::<u16>("0150", 16).is_err());
+        assert!(parse_range::<u16>("0-10000", 16).is_err());
+    }
+
+    #[test]
+    fn divergence() {
+        let reference = "A:01 F:B0 PC:0100\nA:01 F:B0 PC:0101\nA:02 F:00 PC:0104\n";
+
+        assert_eq!(diff(reference, reference), None);
+
+        let ours = "A:01 F:B0 PC:0100\nA:01 F:B0 PC:0101\nA:02 F:80 PC:0104\n";
+        let divergence = diff(ours, reference).unwrap();
+        assert_eq!(divergence.line, 3);
+        assert_eq!(divergence.fields, ["F"]);
+
+        let short = "A:01 F:B0 PC:0100\n";
+        let divergence = diff(short, reference).unwrap();
+        assert_eq!(divergence.line, 2);
+        assert_eq!(divergence.ours, None);
+    }
+}
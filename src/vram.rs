@@ -0,0 +1,363 @@
+use bitflags::bitflags;
+use std::fmt::Display;
+
+use crate::io::{self, Lcdc};
+
+/// Bytes in one VRAM bank
+pub const BANK_SIZE: usize = 0x2000;
+
+/// Tiles in one VRAM bank's tile data, 0x8000-0x97FF
+pub const TILES_PER_BANK: usize = 384;
+
+/// Bytes per 8x8 2bpp tile
+pub const TILE_SIZE: usize = 16;
+
+/// The two 32x32 tilemaps
+pub const TILE_MAPS: [u16; 2] = [0x9800, 0x9C00];
+
+/// Entries in OAM
+pub const SPRITE_COUNT: usize = 40;
+
+/// Sprites the PPU will draw on one line
+pub const SPRITES_PER_LINE: usize = 10;
+
+bitflags! {
+    /// Attribute byte of an OAM entry
+    pub struct SpriteFlags: u8 {
+        const BG_PRIORITY = 0b1000_0000;
+        const Y_FLIP = 0b0100_0000;
+        const X_FLIP = 0b0010_0000;
+        /// OBP1 instead of OBP0 (DMG)
+        const PALETTE = 0b0001_0000;
+        /// VRAM bank 1 (CGB)
+        const BANK = 0b0000_1000;
+        /// OBP0-7 (CGB)
+        const CGB_PALETTE = 0b0000_0111;
+    }
+}
+
+/// A decoded OAM entry. Positions are as stored, offset by (8, 16) from the screen.
+#[derive(Clone, Copy)]
+pub struct Sprite {
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub flags: SpriteFlags,
+}
+
+impl Sprite {
+    /// Decodes all OAM entries
+    pub fn all(oam: &[u8]) -> Vec<Sprite> {
+        oam.chunks(4)
+            .take(SPRITE_COUNT)
+            .map(|entry| Sprite {
+                y: entry[0],
+                x: entry[1],
+                tile: entry[2],
+                flags: SpriteFlags::from_bits_truncate(entry[3]),
+            })
+            .collect()
+    }
+
+    /// Whether the sprite covers screen line `ly`
+    pub fn on_line(&self, ly: u8, tall: bool) -> bool {
+        let top = self.y as i16 - 16;
+        (top..top + height(tall) as i16).contains(&(ly as i16))
+    }
+
+    /// Whether any of the sprite is on screen
+    pub fn visible(&self, tall: bool) -> bool {
+        let top = self.y as i16 - 16;
+        top + height(tall) as i16 > 0 && top < 144 && self.x > 0 && self.x < 168
+    }
+
+    /// Color indices of the sprite, 8 wide, with flips applied
+    pub fn pixels(&self, vram: &[u8], tall: bool) -> Vec<u8> {
+        // 8x16 sprites ignore bit 0 of the tile index
+        let first = match tall {
+            true => self.tile & 0xFE,
+            false => self.tile,
+        };
+        let bank = match self.flags.contains(SpriteFlags::BANK) && vram.len() > BANK_SIZE {
+            true => BANK_SIZE,
+            false => 0,
+        };
+
+        let mut pixels = Vec::new();
+        for tile in 0..(height(tall) / 8) {
+            let offset = bank + (first as usize + tile) * TILE_SIZE;
+            pixels.extend_from_slice(&tile_pixels(vram, offset));
+        }
+
+        if self.flags.contains(SpriteFlags::Y_FLIP) {
+            pixels = pixels.chunks(8).rev().flatten().copied().collect();
+        }
+        if self.flags.contains(SpriteFlags::X_FLIP) {
+            pixels = pixels
+                .chunks(8)
+                .flat_map(|row| row.iter().rev().copied())
+                .collect();
+        }
+
+        pixels
+    }
+}
+
+/// Sprite height in pixels
+pub fn height(tall: bool) -> usize {
+    match tall {
+        true => 16,
+        false => 8,
+    }
+}
+
+/// Which sprites lose out to the 10-per-line limit on at least one line they cover. The PPU
+/// picks the first 10 in OAM order, whatever their X position.
+pub fn dropped_sprites(sprites: &[Sprite], tall: bool) -> Vec<bool> {
+    let mut dropped = vec![false; sprites.len()];
+
+    for ly in 0..144 {
+        let on_line = sprites
+            .iter()
+            .enumerate()
+            .filter(|(_, sprite)| sprite.on_line(ly, tall));
+
+        for (entry, _) in on_line.skip(SPRITES_PER_LINE) {
+            dropped[entry] = true;
+        }
+    }
+
+    dropped
+}
+
+/// Decodes the 2bpp tile at `offset` into VRAM into 64 color indices, row by row
+pub fn tile_pixels(vram: &[u8], offset: usize) -> [u8; 64] {
+    let mut pixels = [0; 64];
+
+    for row in 0..8 {
+        let low = vram[offset + row * 2];
+        let high = vram[offset + row * 2 + 1];
+
+        for col in 0..8 {
+            let bit = 7 - col;
+            pixels[row * 8 + col] = ((high >> bit) & 1) << 1 | ((low >> bit) & 1);
+        }
+    }
+
+    pixels
+}
+
+/// Address of the tile data for a BG/window tile index. With LCDC bit 4 clear the index is
+/// signed and relative to 0x9000.
+pub fn tile_data_address(index: u8, lcdc: Lcdc) -> u16 {
+    match lcdc.contains(Lcdc::BG_TILE_DATA) {
+        true => 0x8000 + index as u16 * TILE_SIZE as u16,
+        false => (0x9000 + (index as i8 as i32) * TILE_SIZE as i32) as u16,
+    }
+}
+
+/// BG tile index of the tile at `addr`, if BG/window tiles can use it with this LCDC
+pub fn bg_tile_index(addr: u16, lcdc: Lcdc) -> Option<u8> {
+    let tile = (addr - 0x8000) / TILE_SIZE as u16;
+
+    match lcdc.contains(Lcdc::BG_TILE_DATA) {
+        true if tile < 256 => Some(tile as u8),
+        false if tile >= 128 => Some((tile as i32 - 256) as i8 as u8),
+        _ => None,
+    }
+}
+
+/// A DMG palette register, mapping color indices to shades
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Palette {
+    Bgp,
+    Obp0,
+    Obp1,
+    Grayscale,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 4] = [
+        Palette::Bgp,
+        Palette::Obp0,
+        Palette::Obp1,
+        Palette::Grayscale,
+    ];
+
+    /// The palette's register value: shade for index n in bits 2n+1..2n
+    pub fn value(self, read: impl Fn(u16) -> u8) -> u8 {
+        match self {
+            Palette::Bgp => read(io::BGP),
+            Palette::Obp0 => read(io::OBP0),
+            Palette::Obp1 => read(io::OBP1),
+            Palette::Grayscale => 0b11_10_01_00,
+        }
+    }
+}
+
+impl Display for Palette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Palette::Bgp => "BGP",
+            Palette::Obp0 => "OBP0",
+            Palette::Obp1 => "OBP1",
+            Palette::Grayscale => "Grayscale",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Shade (0 = lightest, 3 = darkest) of a color index under a palette register value
+pub fn shade(palette: u8, index: u8) -> u8 {
+    (palette >> (index * 2)) & 0b11
+}
+
+/// Color indices of the 256x256 background drawn from the tilemap at `map`, row by row
+pub fn render_tile_map(vram: &[u8], map: u16, lcdc: Lcdc) -> Vec<u8> {
+    let mut pixels = vec![0; 256 * 256];
+
+    for cell in 0..0x400usize {
+        let index = vram[map as usize - 0x8000 + cell];
+        let tile = tile_pixels(vram, (tile_data_address(index, lcdc) - 0x8000) as usize);
+        let (x0, y0) = (cell % 32 * 8, cell / 32 * 8);
+
+        for (i, &color) in tile.iter().enumerate() {
+            pixels[(y0 + i / 8) * 256 + x0 + i % 8] = color;
+        }
+    }
+
+    pixels
+}
+
+/// Tilemap cells, as (map address, column, row), that show the tile at `addr`
+pub fn tile_map_uses(vram: &[u8], addr: u16, lcdc: Lcdc) -> Vec<(u16, u8, u8)> {
+    let index = match bg_tile_index(addr, lcdc) {
+        Some(index) => index,
+        None => return Vec::new(),
+    };
+
+    let mut uses = Vec::new();
+
+    for map in TILE_MAPS {
+        for cell in 0..0x400u16 {
+            if vram[(map - 0x8000 + cell) as usize] == index {
+                uses.push((map, (cell % 32) as u8, (cell / 32) as u8));
+            }
+        }
+    }
+
+    uses
+}
+
+/// OAM entries whose sprite shows the tile at `addr`. Sprites always use 0x8000 addressing,
+/// and 8x16 sprites cover an even/odd pair of tiles.
+pub fn sprite_uses(oam: &[u8], addr: u16, lcdc: Lcdc) -> Vec<usize> {
+    let tile = (addr - 0x8000) / TILE_SIZE as u16;
+    if tile >= 256 {
+        return Vec::new();
+    }
+
+    (0..SPRITE_COUNT)
+        .filter(|&entry| {
+            let index = oam[entry * 4 + 2] as u16;
+            match lcdc.contains(Lcdc::OBJ_SIZE) {
+                true => index & 0xFE == tile & 0xFE,
+                false => index == tile,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        bg_tile_index, dropped_sprites, render_tile_map, shade, tile_data_address, tile_pixels,
+        Sprite,
+    };
+    use crate::io::Lcdc;
+
+    #[test]
+    fn decode_tile() {
+        let mut vram = vec![0; 0x2000];
+        // Row 0: low plane 0b1010_0000, high plane 0b1100_0000
+        vram[0x10] = 0b1010_0000;
+        vram[0x11] = 0b1100_0000;
+
+        let pixels = tile_pixels(&vram, 0x10);
+        assert_eq!(&pixels[0..4], [3, 2, 1, 0]);
+        assert!(pixels[8..].iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn addressing_modes() {
+        let unsigned = Lcdc::BG_TILE_DATA;
+        let signed = Lcdc::empty();
+
+        assert_eq!(tile_data_address(0x01, unsigned), 0x8010);
+        assert_eq!(tile_data_address(0x01, signed), 0x9010);
+        assert_eq!(tile_data_address(0xFF, signed), 0x8FF0);
+
+        assert_eq!(bg_tile_index(0x8FF0, signed), Some(0xFF));
+        assert_eq!(bg_tile_index(0x8010, signed), None);
+        assert_eq!(bg_tile_index(0x9010, unsigned), None);
+    }
+
+    #[test]
+    fn tile_map() {
+        let mut vram = vec![0; 0x2000];
+        // Tile 0x80 in signed mode lives at 0x8800, fill its first row with color 3
+        vram[0x0800] = 0xFF;
+        vram[0x0801] = 0xFF;
+        // Cell (1, 2) of the 0x9C00 map
+        vram[0x1C00 + 2 * 32 + 1] = 0x80;
+
+        let pixels = render_tile_map(&vram, 0x9C00, Lcdc::empty());
+        assert!(pixels[16 * 256 + 8..16 * 256 + 16].iter().all(|&p| p == 3));
+        assert_eq!(pixels[17 * 256 + 8], 0);
+        assert_eq!(pixels[16 * 256 + 7], 0);
+    }
+
+    #[test]
+    fn sprites() {
+        let mut oam = vec![0; 0xA0];
+        // 11 sprites on lines 0-7, so the last one gets dropped. The first is off the left of
+        // the screen but still counts towards the limit.
+        for entry in 0..11 {
+            oam[entry * 4] = 16;
+            oam[entry * 4 + 1] = entry as u8;
+        }
+
+        let sprites = Sprite::all(&oam);
+        assert_eq!(sprites.len(), 40);
+        assert!(!sprites[0].visible(false));
+        assert!(sprites[1].visible(false));
+        assert!(!sprites[20].visible(false));
+
+        let dropped = dropped_sprites(&sprites, false);
+        assert!(!dropped[9]);
+        assert!(dropped[10]);
+        assert!(!dropped[11]);
+    }
+
+    #[test]
+    fn sprite_flips() {
+        let mut vram = vec![0; 0x2000];
+        // Tile 1, top-left pixel color 1
+        vram[0x10] = 0b1000_0000;
+        let mut oam = vec![0; 0xA0];
+        oam[2] = 1;
+        oam[3] = 0b0110_0000;
+
+        let pixels = Sprite::all(&oam)[0].pixels(&vram, false);
+        assert_eq!(pixels[63], 1);
+        assert_eq!(pixels[0], 0);
+    }
+
+    #[test]
+    fn palette_shades() {
+        assert_eq!(shade(0xE4, 0), 0);
+        assert_eq!(shade(0xE4, 3), 3);
+        assert_eq!(shade(0x1B, 0), 3);
+    }
+}